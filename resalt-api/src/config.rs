@@ -3,8 +3,9 @@ use log::error;
 use resalt_config::ResaltConfig;
 use resalt_update::{get_update_info, CURRENT_VERSION};
 use serde::Serialize;
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ApiConfig {
     #[serde(rename = "authForwardEnabled")]
     auth_forward_enabled: bool,
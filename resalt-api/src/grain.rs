@@ -1,3 +1,4 @@
+use crate::minion::get_minions;
 use axum::http::StatusCode;
 use log::*;
 use resalt_models::*;
@@ -8,31 +9,15 @@ use std::collections::HashMap;
 pub fn search_grains(
     data: &Storage,
     path: String,
-    filter: Option<String>,
+    filters: Vec<FilterNode>,
+    redaction: Option<&PillarRedaction>,
 ) -> Result<HashMap<String, Value>, StatusCode> {
     let path = path
         .starts_with('$')
         .then(|| path.clone())
         .unwrap_or(format!("$.{}", path));
 
-    let filters: Vec<Filter> = match filter {
-        Some(filter) => match serde_json::from_str(&filter) {
-            Ok(filters) => filters,
-            Err(e) => {
-                error!("Failed to parse filter: {}", e);
-                return Err(StatusCode::BAD_REQUEST);
-            }
-        },
-        None => vec![],
-    };
-
-    let minions = match data.list_minions(filters, None, None) {
-        Ok(minions) => minions,
-        Err(e) => {
-            error!("{:?}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
+    let minions = get_minions(data, filters, None, None, redaction)?;
 
     let mut results: HashMap<String, Value> = HashMap::new();
 
@@ -50,6 +35,11 @@ pub fn search_grains(
                 continue;
             }
         };
+        // Redact before selecting, so secrets can't be reached by selecting them directly
+        let grains = match redaction {
+            Some(redaction) => redaction.redact_value(grains),
+            None => grains,
+        };
 
         let grains: Vec<Value> = match jsonpath_lib::select(&grains, &path) {
             Ok(grains) => grains.into_iter().map(|v| v.to_owned()).collect(),
@@ -24,6 +24,31 @@ pub async fn create_job(
     salt.run_job(salt_token, run_job).await
 }
 
+/// The masters to send a job to, based on the masters its target minions were last seen on.
+pub fn get_job_masters(
+    data: &Storage,
+    masters: &[String],
+    run_job: &SaltRunJob,
+) -> Result<Vec<String>, StatusCode> {
+    if masters.len() == 1 {
+        return Ok(masters.to_vec());
+    }
+    let minions = data
+        .list_minions(Vec::new(), None, Paginate::None)
+        .map_err(|e| {
+            error!("api.get_job_masters {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(run_job.target_masters(&minions, masters))
+}
+
+pub fn set_job_preset(data: &Storage, jid: &str, preset_id: &str) -> Result<(), StatusCode> {
+    data.set_job_preset(jid, preset_id).map_err(|e| {
+        error!("api.set_job_preset {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
 pub fn get_job(data: Storage, jid: &str) -> Result<Option<Job>, StatusCode> {
     data.get_job_by_jid(jid).map_err(|e| {
         error!("api.get_job {:?}", e);
@@ -4,6 +4,7 @@ pub mod grain;
 pub mod job;
 pub mod key;
 pub mod minion;
+pub mod package;
 pub mod permission;
 pub mod preset;
 pub mod setting;
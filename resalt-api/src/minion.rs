@@ -1,20 +1,61 @@
 use axum::http::StatusCode;
-use log::error;
+use log::{error, warn};
+use resalt_config::ResaltConfig;
 use resalt_models::*;
 use resalt_salt::{SaltAPI, SaltError};
 use resalt_storage::Storage;
 
 pub fn get_minions(
     data: &Storage,
-    filters: Vec<Filter>,
+    filters: Vec<FilterNode>,
     sort: Option<MinionSort>,
     paginate: Paginate,
+    redaction: Option<&PillarRedaction>,
 ) -> Result<Vec<Minion>, StatusCode> {
-    data.list_minions(filters, Some(sort.unwrap_or_default()), paginate)
+    validate_filters(&filters).map_err(|e| {
+        warn!("api.get_minions {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+
+    // Filtering on pillars must not reveal redacted values, so filter on redacted pillars
+    let redaction = redaction.filter(|_| {
+        filters
+            .iter()
+            .any(|f| f.uses_field_type(&FilterFieldType::Pillar))
+    });
+    let redaction = match redaction {
+        Some(redaction) => redaction,
+        None => {
+            return data
+                .list_minions(filters, Some(sort.unwrap_or_default()), paginate)
+                .map_err(|e| {
+                    error!("api.get_minions {:?}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })
+        }
+    };
+
+    let mut minions = data
+        .list_minions(Vec::new(), Some(sort.unwrap_or_default()), None)
         .map_err(|e| {
             error!("api.get_minions {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR
-        })
+        })?;
+    for minion in minions.iter_mut() {
+        redaction.redact_minion(minion);
+    }
+    filter_minions(&mut minions, &filters).map_err(|e| {
+        warn!("api.get_minions {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+    if let Some((limit, offset)) = paginate {
+        minions = minions
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect();
+    }
+    Ok(minions)
 }
 
 pub fn get_minion(data: &Storage, minion_id: &str) -> Result<Option<Minion>, StatusCode> {
@@ -24,6 +65,58 @@ pub fn get_minion(data: &Storage, minion_id: &str) -> Result<Option<Minion>, Sta
     })
 }
 
+/// Pillar redaction rules from the configuration.
+pub fn pillar_redaction() -> PillarRedaction {
+    PillarRedaction::new(
+        ResaltConfig::PILLAR_REDACT_KEYS.clone(),
+        ResaltConfig::PILLAR_REDACT_PATHS.clone(),
+    )
+}
+
+pub fn get_minion_status_transitions(
+    data: &Storage,
+    minion_id: &str,
+    paginate: Paginate,
+) -> Result<Vec<MinionStatusTransition>, StatusCode> {
+    data.list_minion_status_transitions(minion_id, paginate)
+        .map_err(|e| {
+            error!("api.get_minion_status_transitions {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+pub fn get_minion_history(
+    data: &Storage,
+    minion_id: &str,
+    kind: MinionSnapshotKind,
+    since: Option<ResaltTime>,
+    paginate: Paginate,
+    redaction: Option<&PillarRedaction>,
+) -> Result<Vec<MinionHistoryEntry>, StatusCode> {
+    let mut snapshots = data.list_minion_snapshots(minion_id, kind).map_err(|e| {
+        error!("api.get_minion_history {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if let (MinionSnapshotKind::Pillars, Some(redaction)) = (kind, redaction) {
+        for snapshot in snapshots.iter_mut() {
+            snapshot.data = redaction.redact_str(&snapshot.data);
+        }
+    }
+
+    let mut history = minion_history(&snapshots);
+    if let Some(since) = since {
+        history.retain(|entry| entry.timestamp >= since);
+    }
+    if let Some((limit, offset)) = paginate {
+        history = history
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect();
+    }
+    Ok(history)
+}
+
 pub async fn refresh_minion(
     salt: &SaltAPI,
     salt_token: &SaltToken,
@@ -36,3 +129,36 @@ pub async fn refresh_minion(
             e
         })
 }
+
+/// The master a minion is managed by, out of `masters`.
+///
+/// Uses `requested` if given, otherwise the only master, otherwise the master the minion was last seen on.
+pub fn get_minion_master(
+    data: &Storage,
+    masters: &[String],
+    minion_id: &str,
+    requested: Option<&str>,
+) -> Result<String, StatusCode> {
+    if let Some(requested) = requested {
+        return match masters.iter().any(|master| master == requested) {
+            true => Ok(requested.to_owned()),
+            false => {
+                warn!("Unknown Salt master {}", requested);
+                Err(StatusCode::BAD_REQUEST)
+            }
+        };
+    }
+    if let [master] = masters {
+        return Ok(master.clone());
+    }
+    match get_minion(data, minion_id)?.and_then(|minion| minion.master) {
+        Some(master) if masters.contains(&master) => Ok(master),
+        _ => {
+            warn!(
+                "Master of minion {} is unknown, it must be given",
+                minion_id
+            );
+            Err(StatusCode::BAD_REQUEST)
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use axum::http::StatusCode;
+use log::*;
+use resalt_models::*;
+use resalt_storage::Storage;
+
+pub fn get_packages(data: &Storage) -> Result<Vec<Package>, StatusCode> {
+    data.list_packages().map_err(|e| {
+        error!("api.get_packages {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+pub fn get_package(
+    data: &Storage,
+    name: &str,
+    constraint: Option<PackageVersionConstraint>,
+) -> Result<Option<Package>, StatusCode> {
+    let package = data.get_package(name).map_err(|e| {
+        error!("api.get_package {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(package.map(|mut package| {
+        if let Some(constraint) = constraint {
+            package.retain_versions(&constraint);
+        }
+        package
+    }))
+}
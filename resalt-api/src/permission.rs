@@ -1,7 +1,10 @@
 use axum::http::StatusCode;
-use log::error;
-use resalt_models::{Paginate, PermissionGroup, User};
+use log::{error, warn};
+use resalt_models::{
+    AuditRecord, Paginate, PermissionGroup, PermissionGroupGrant, ResaltTime, User,
+};
 use resalt_storage::Storage;
+use serde_json::Value;
 
 pub fn get_permission_groups(
     data: &Storage,
@@ -42,6 +45,39 @@ pub fn get_permission_group_users(data: &Storage, group_id: &str) -> Result<Vec<
         })
 }
 
+/// The permissions members get from a group, including those inherited from the groups
+/// which include it.
+pub fn get_permission_group_resolved_perms(
+    data: &Storage,
+    group_id: &str,
+) -> Result<Vec<Value>, StatusCode> {
+    data.permission_group_tree()
+        .and_then(|tree| tree.resolved_perms(group_id))
+        .map_err(|e| {
+            error!("api.get_permission_group_resolved_perms {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+/// Members of a group and of every group it includes, who all get its permissions.
+fn get_transitive_user_ids(data: &Storage, group_id: &str) -> Result<Vec<String>, StatusCode> {
+    data.list_transitive_user_ids_by_permission_group_id(group_id)
+        .map_err(|e| {
+            error!("api.get_transitive_user_ids {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+fn refresh_users_permissions(data: &Storage, user_ids: &[String]) -> Result<(), StatusCode> {
+    for user_id in user_ids {
+        if let Err(e) = data.refresh_user_permissions(user_id) {
+            error!("{:?}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    Ok(())
+}
+
 pub fn create_permission_group(
     data: &Storage,
     id: Option<String>,
@@ -54,32 +90,55 @@ pub fn create_permission_group(
     })
 }
 
+/// Save a group, and update everyone who gets its permissions. Subgroups must exist,
+/// and may not include the group again, or `BAD_REQUEST` is returned.
 pub fn update_permission_group(data: &Storage, group: &PermissionGroup) -> Result<(), StatusCode> {
+    let mut tree = data.permission_group_tree().map_err(|e| {
+        error!("api.update_group.tree {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    tree.insert(group.clone());
+    if let Some(subgroup) = group.subgroups.iter().find(|id| tree.get(id).is_none()) {
+        warn!("Group {} includes unknown group {}", group.id, subgroup);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if tree.has_cycle(&group.id) {
+        warn!("Group {} would include itself", group.id);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // Members of removed subgroups lose the permissions too
+    let mut user_ids = get_transitive_user_ids(data, &group.id)?;
+
     data.update_permission_group(group).map_err(|e| {
         error!("api.update_group {:?}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
-    // Update members
-    match get_permission_group_users(data, &group.id) {
-        Ok(users) => {
-            for user in users {
-                if let Err(e) = data.refresh_user_permissions(&user.id) {
-                    error!("{:?}", e);
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
-                }
-            }
-            Ok(())
-        }
-        Err(e) => {
-            error!("{:?}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+    // Update members, including those of subgroups
+    for user_id in get_transitive_user_ids(data, &group.id)? {
+        if !user_ids.contains(&user_id) {
+            user_ids.push(user_id);
         }
     }
+    refresh_users_permissions(data, &user_ids)
 }
 
 pub fn delete_permission_group(data: &Storage, group_id: &str) -> Result<(), StatusCode> {
     let users = get_permission_group_users(data, group_id)?;
+    let user_ids = get_transitive_user_ids(data, group_id)?;
+
+    // Remove the group from the groups which include it
+    for mut parent in get_permission_groups(data, None)? {
+        if !parent.subgroups.iter().any(|id| id == group_id) {
+            continue;
+        }
+        parent.subgroups.retain(|id| id != group_id);
+        data.update_permission_group(&parent).map_err(|e| {
+            error!("api.delete_group.parent {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    }
 
     for user in &users {
         data.delete_permission_group_user(&user.id, group_id)
@@ -89,20 +148,19 @@ pub fn delete_permission_group(data: &Storage, group_id: &str) -> Result<(), Sta
             })?;
     }
 
+    for grant in get_permission_group_grants(data)? {
+        if grant.group_id == group_id {
+            delete_permission_group_grant(data, &grant.id)?;
+        }
+    }
+
     data.delete_permission_group(group_id).map_err(|e| {
         error!("api.delete_group {:?}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
-    // Update ex-members
-    for user in users {
-        if let Err(e) = data.refresh_user_permissions(&user.id) {
-            error!("{:?}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    }
-
-    Ok(())
+    // Update ex-members, including those of subgroups
+    refresh_users_permissions(data, &user_ids)
 }
 
 pub fn is_user_member_of_group(
@@ -144,6 +202,10 @@ pub fn remove_user_from_group(
             error!("api.remove_user_from_group {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
+    // Removing a temporary membership early
+    if let Some(grant) = get_permission_group_grant(data, user_id, group_id)? {
+        delete_permission_group_grant(data, &grant.id)?;
+    }
 
     // Update user-cached permissions
     match data.refresh_user_permissions(user_id) {
@@ -154,3 +216,102 @@ pub fn remove_user_from_group(
         }
     }
 }
+
+/// All temporary memberships which have not been revoked yet, soonest expiring first.
+pub fn get_permission_group_grants(
+    data: &Storage,
+) -> Result<Vec<PermissionGroupGrant>, StatusCode> {
+    data.list_permission_group_grants().map_err(|e| {
+        error!("api.get_permission_group_grants {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+pub fn get_permission_group_grant(
+    data: &Storage,
+    user_id: &str,
+    group_id: &str,
+) -> Result<Option<PermissionGroupGrant>, StatusCode> {
+    data.get_permission_group_grant(user_id, group_id)
+        .map_err(|e| {
+            error!("api.get_permission_group_grant {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+pub fn delete_permission_group_grant(data: &Storage, grant_id: &str) -> Result<(), StatusCode> {
+    data.delete_permission_group_grant(grant_id).map_err(|e| {
+        error!("api.delete_permission_group_grant {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Add a user to a group until `expires_at`, or extend their temporary membership.
+/// Returns `BAD_REQUEST` if the user is a permanent member already.
+pub fn grant_user_group(
+    data: &Storage,
+    user_id: &str,
+    group_id: &str,
+    expires_at: ResaltTime,
+    reason: &str,
+    granted_by: &str,
+) -> Result<PermissionGroupGrant, StatusCode> {
+    let existing = get_permission_group_grant(data, user_id, group_id)?;
+    if existing.is_none() && is_user_member_of_group(data, user_id, group_id)? {
+        warn!("User {} is a permanent member of {}", user_id, group_id);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let result = match existing {
+        Some(mut grant) => {
+            grant.reason = reason.to_string();
+            grant.granted_by = granted_by.to_string();
+            grant.granted_at = ResaltTime::now();
+            grant.expires_at = expires_at;
+            data.set_permission_group_grant(&grant).map(|_| grant)
+        }
+        None => {
+            data.insert_permission_group_grant(user_id, group_id, reason, granted_by, expires_at)
+        }
+    };
+    let grant = result.map_err(|e| {
+        error!("api.grant_user_group {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    add_user_to_group(data, user_id, group_id)?;
+
+    add_audit_record(
+        data,
+        "grant.created",
+        Some(granted_by),
+        Some(user_id),
+        Some(group_id),
+        format!("Membership granted until {}, for: {}", expires_at, reason),
+    )?;
+    Ok(grant)
+}
+
+pub fn add_audit_record(
+    data: &Storage,
+    action: &str,
+    actor_id: Option<&str>,
+    user_id: Option<&str>,
+    group_id: Option<&str>,
+    message: String,
+) -> Result<(), StatusCode> {
+    data.insert_audit_record(action, actor_id, user_id, group_id, message)
+        .map(|_| ())
+        .map_err(|e| {
+            error!("api.add_audit_record {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+pub fn get_audit_records(
+    data: &Storage,
+    paginate: Paginate,
+) -> Result<Vec<AuditRecord>, StatusCode> {
+    data.list_audit_records(paginate).map_err(|e| {
+        error!("api.get_audit_records {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
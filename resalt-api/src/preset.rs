@@ -1,6 +1,8 @@
 use axum::http::StatusCode;
-use log::error;
-use resalt_models::MinionPreset;
+use log::{error, warn};
+use resalt_models::{
+    dedupe_nodegroup_names, filter_minions, FilterNode, MinionPreset, Nodegroup, PillarRedaction,
+};
 use resalt_storage::Storage;
 
 pub fn create_minion_preset(
@@ -29,6 +31,58 @@ pub fn get_minion_preset(data: &Storage, id: &str) -> Result<Option<MinionPreset
     })
 }
 
+/// The filters of a preset, `NOT_FOUND` if the preset does not exist.
+pub fn get_minion_preset_filters(data: &Storage, id: &str) -> Result<Vec<FilterNode>, StatusCode> {
+    let preset = match get_minion_preset(data, id)? {
+        Some(preset) => preset,
+        None => return Err(StatusCode::NOT_FOUND),
+    };
+    preset.filters().map_err(|e| {
+        error!("api.get_minion_preset_filters {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Render presets as Salt nodegroups, evaluated against the currently known minions.
+/// Pillars are redacted before filtering on them if `redaction` is given.
+pub fn get_nodegroups(
+    data: &Storage,
+    presets: &[MinionPreset],
+    redaction: Option<&PillarRedaction>,
+) -> Result<Vec<Nodegroup>, StatusCode> {
+    let mut minions = data.list_minions(Vec::new(), None, None).map_err(|e| {
+        error!("api.get_nodegroups {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    if let Some(redaction) = redaction {
+        for minion in minions.iter_mut() {
+            redaction.redact_minion(minion);
+        }
+    }
+
+    let mut nodegroups: Vec<Nodegroup> = Vec::with_capacity(presets.len());
+    for preset in presets {
+        let filters = preset.filters().map_err(|e| {
+            error!("api.get_nodegroups {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let mut matched = minions.clone();
+        filter_minions(&mut matched, &filters).map_err(|e| {
+            warn!("api.get_nodegroups preset {}: {}", preset.id, e);
+            StatusCode::BAD_REQUEST
+        })?;
+        nodegroups.push(Nodegroup::build(
+            &preset.id,
+            &preset.name,
+            &filters,
+            &minions,
+            &matched,
+        ));
+    }
+    dedupe_nodegroup_names(&mut nodegroups);
+    Ok(nodegroups)
+}
+
 pub fn update_minion_preset(
     data: &Storage,
     minion_preset: &MinionPreset,
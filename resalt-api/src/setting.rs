@@ -1,183 +1,392 @@
+use std::collections::HashMap;
+
 use axum::http::StatusCode;
-use log::error;
+use log::{error, warn};
 use resalt_models::*;
 use resalt_storage::Storage;
-use serde::{Deserialize, Serialize};
-
-#[allow(non_snake_case)]
-#[derive(Debug, Serialize, Deserialize)]
-pub struct DataDump {
-    pub users: Vec<User>,
-    pub groups: Vec<PermissionGroup>,
-    pub memberships: std::collections::HashMap<String, Vec<String>>,
-    pub minions: Vec<Minion>,
-    #[serde(rename = "minionPresets")]
-    pub minion_presets: Vec<MinionPreset>,
+use serde_json::Value;
+
+enum ImportAction {
+    Create,
+    Update,
+    Skip,
 }
 
-pub fn import_backup(data: &Storage, config: &DataDump) -> Result<(), StatusCode> {
+/// Decide what to do with an object from a backup, and count it in the section summary.
+fn import_action<T: PartialEq>(
+    summary: &mut DataDumpSectionSummary,
+    existing: Option<&T>,
+    object: &T,
+) -> ImportAction {
+    match existing {
+        None => {
+            summary.created += 1;
+            ImportAction::Create
+        }
+        Some(existing) if existing == object => {
+            summary.skipped += 1;
+            ImportAction::Skip
+        }
+        Some(_) => {
+            summary.updated += 1;
+            ImportAction::Update
+        }
+    }
+}
+
+fn import_error(e: String) -> StatusCode {
+    error!("api.import_backup {:?}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Import the sections included in the backup, creating missing objects and updating
+/// changed ones. Nothing is written on a dry-run, but the summary is the same.
+pub fn import_backup(
+    data: &Storage,
+    dump: &DataDump,
+    dry_run: bool,
+) -> Result<DataDumpImportSummary, StatusCode> {
+    let mut summary = DataDumpImportSummary {
+        dry_run,
+        ..Default::default()
+    };
+
+    // Users and groups from the backup which exist after importing, even in a dry run
+    let mut imported_users: Vec<&str> = Vec::new();
+    let mut imported_groups: Vec<&str> = Vec::new();
+
     // Import users
-    for user in &config.users {
-        // Check if user exists
-        let user_exists = match data.get_user_by_username(&user.username) {
-            Ok(Some(_)) => true,
-            Ok(None) => false,
-            Err(e) => {
-                error!("route_settings_import_post get_user_by_username {:?}", e);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        };
-        if user_exists {
-            match data.set_user(user) {
-                Ok(_) => {}
-                Err(e) => {
-                    error!("route_settings_import_post update_user {:?}", e);
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
-                }
-            };
-        } else {
-            // Create user
-            match data.create_user_hashed(
-                Some(user.id.clone()),
-                user.username.clone(),
-                user.password.clone(),
-                user.perms.clone(),
-                user.last_login,
-                user.email.clone(),
-            ) {
-                Ok(_) => {}
-                Err(e) => {
-                    error!("route_settings_import_post create_user_hashed {:?}", e);
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    if let Some(users) = &dump.users {
+        let section = summary.sections.entry(DataDumpSection::Users).or_default();
+        for user in users {
+            let existing = data.get_user_by_id(&user.id).map_err(import_error)?;
+            if existing.is_none() {
+                // Usernames must stay unique
+                if let Some(other) = data
+                    .get_user_by_username(&user.username)
+                    .map_err(import_error)?
+                {
+                    warn!(
+                        "Skipping user {} from backup, username {} is taken by {}",
+                        user.id, user.username, other.id
+                    );
+                    section.skipped += 1;
+                    continue;
                 }
-            };
+            }
+            match import_action(section, existing.as_ref(), user) {
+                ImportAction::Create if !dry_run => data
+                    .create_user_hashed(
+                        Some(user.id.clone()),
+                        user.username.clone(),
+                        user.password.clone(),
+                        user.perms.clone(),
+                        user.last_login,
+                        user.email.clone(),
+                    )
+                    .map(|_| ()),
+                ImportAction::Update if !dry_run => data.set_user(user),
+                _ => Ok(()),
+            }
+            .map_err(import_error)?;
+            imported_users.push(&user.id);
         }
     }
 
     // Import groups
-    for group in &config.groups {
-        // Check if group exists
-        let group_exists = match data.get_permission_group_by_id(&group.id) {
-            Ok(Some(_)) => true,
-            Ok(None) => false,
-            Err(e) => {
-                error!(
-                    "route_settings_import_post get_permission_group_by_id {:?}",
-                    e
-                );
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        };
-        if group_exists {
-            match data.update_permission_group(group) {
-                Ok(_) => {}
-                Err(e) => {
-                    error!("route_settings_import_post update_permission_group {:?}", e);
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
-                }
-            };
-        } else {
-            // Create group
-            match data.create_permission_group(
-                Some(group.id.clone()),
-                &group.name.clone(),
-                Some(group.perms.clone()),
-            ) {
-                Ok(_) => {}
-                Err(e) => {
-                    error!("route_settings_import_post create_permission_group {:?}", e);
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
-                }
-            };
+    if let Some(groups) = &dump.groups {
+        let section = summary.sections.entry(DataDumpSection::Groups).or_default();
+        for group in groups {
+            let existing = data
+                .get_permission_group_by_id(&group.id)
+                .map_err(import_error)?;
+            match import_action(section, existing.as_ref(), group) {
+                ImportAction::Create if !dry_run => data
+                    .create_permission_group(
+                        Some(group.id.clone()),
+                        &group.name,
+                        Some(group.perms.clone()),
+                    )
+                    .map(|_| ()),
+                ImportAction::Update if !dry_run => data.update_permission_group(group),
+                _ => Ok(()),
+            }
+            .map_err(import_error)?;
+            imported_groups.push(&group.id);
         }
     }
 
     // Import memberships
-    for (group_id, user_ids) in &config.memberships {
-        for user_id in user_ids {
-            match data.insert_permission_group_user(user_id, group_id) {
-                Ok(_) => {}
-                Err(e) => {
-                    error!(
-                        "route_settings_import_post update_permission_group_memberships {:?}",
-                        e
+    if let Some(memberships) = &dump.memberships {
+        let section = summary
+            .sections
+            .entry(DataDumpSection::Memberships)
+            .or_default();
+        let mut affected_users: Vec<&str> = Vec::new();
+        for (group_id, user_ids) in memberships {
+            let group_exists = imported_groups.contains(&group_id.as_str())
+                || data
+                    .get_permission_group_by_id(group_id)
+                    .map_err(import_error)?
+                    .is_some();
+            for user_id in user_ids {
+                // Users skipped above, e.g. for a taken username, are not imported
+                let user_exists = match dump.users.iter().flatten().any(|u| &u.id == user_id) {
+                    true => imported_users.contains(&user_id.as_str()),
+                    false => data
+                        .get_user_by_id(user_id)
+                        .map_err(import_error)?
+                        .is_some(),
+                };
+                if !group_exists || !user_exists {
+                    warn!(
+                        "Skipping membership of user {} in group {} from backup, user or group does not exist",
+                        user_id, group_id
                     );
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                    section.skipped += 1;
+                    continue;
+                }
+                if data
+                    .is_user_member_of_group(user_id, group_id)
+                    .map_err(import_error)?
+                {
+                    section.skipped += 1;
+                    continue;
                 }
-            };
+                section.created += 1;
+                if !dry_run {
+                    data.insert_permission_group_user(user_id, group_id)
+                        .map_err(import_error)?;
+                    if !affected_users.contains(&user_id.as_str()) {
+                        affected_users.push(user_id);
+                    }
+                }
+            }
+        }
+        // Permissions are stored per user, so include those of the new groups
+        for user_id in affected_users {
+            data.refresh_user_permissions(user_id)
+                .map_err(import_error)?;
         }
     }
 
     // Import minions
-    for minion in &config.minions {
-        match data.set_minion(minion.clone()) {
-            Ok(_) => {}
-            Err(e) => {
-                error!("route_settings_import_post update_minion {:?}", e);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        };
+    if let Some(minions) = &dump.minions {
+        let section = summary
+            .sections
+            .entry(DataDumpSection::Minions)
+            .or_default();
+        for minion in minions {
+            let existing = data.get_minion_by_id(&minion.id).map_err(import_error)?;
+            let minion = restore_redacted_pillars(minion, existing.as_ref());
+            match import_action(section, existing.as_ref(), &minion) {
+                ImportAction::Create | ImportAction::Update if !dry_run => {
+                    data.set_minion(minion.clone())
+                }
+                _ => Ok(()),
+            }
+            .map_err(import_error)?;
+        }
     }
 
     // Import minion presets
-    for preset in &config.minion_presets {
-        match data.insert_minion_preset(Some(preset.id.clone()), &preset.name, &preset.filter) {
-            Ok(_) => {}
-            Err(e) => {
-                error!("route_settings_import_post update_minion_preset {:?}", e);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        };
+    if let Some(presets) = &dump.minion_presets {
+        let section = summary
+            .sections
+            .entry(DataDumpSection::MinionPresets)
+            .or_default();
+        for preset in presets {
+            let existing = data
+                .get_minion_preset_by_id(&preset.id)
+                .map_err(import_error)?;
+            match import_action(section, existing.as_ref(), preset) {
+                ImportAction::Create if !dry_run => data
+                    .insert_minion_preset(Some(preset.id.clone()), &preset.name, &preset.filter)
+                    .map(|_| ()),
+                ImportAction::Update if !dry_run => data.update_minion_preset(preset),
+                _ => Ok(()),
+            }
+            .map_err(import_error)?;
+        }
+    }
+
+    // Import preferences
+    if let Some(preferences) = &dump.preferences {
+        let section = summary
+            .sections
+            .entry(DataDumpSection::Preferences)
+            .or_default();
+        for (user_id, preferences) in preferences {
+            let existing = data.get_preferences(user_id).map_err(import_error)?;
+            match import_action(section, existing.as_ref(), preferences) {
+                ImportAction::Create | ImportAction::Update if !dry_run => {
+                    data.set_preferences(user_id, preferences)
+                }
+                _ => Ok(()),
+            }
+            .map_err(import_error)?;
+        }
     }
 
-    Ok(())
+    // Import jobs, counting their returns in the same section
+    if let Some(jobs) = &dump.jobs {
+        let section = summary.sections.entry(DataDumpSection::Jobs).or_default();
+        for job in jobs {
+            let existing = data.get_job_by_jid(&job.jid).map_err(import_error)?;
+            match import_action(section, existing.as_ref(), job) {
+                ImportAction::Create | ImportAction::Update if !dry_run => data.set_job(job),
+                _ => Ok(()),
+            }
+            .map_err(import_error)?;
+        }
+        for job_return in dump.job_returns.iter().flatten() {
+            let existing = data.get_job_return(&job_return.id).map_err(import_error)?;
+            match import_action(section, existing.as_ref(), job_return) {
+                ImportAction::Create | ImportAction::Update if !dry_run => {
+                    data.set_job_return(job_return)
+                }
+                _ => Ok(()),
+            }
+            .map_err(import_error)?;
+        }
+    }
+
+    // Import events
+    if let Some(events) = &dump.events {
+        let section = summary.sections.entry(DataDumpSection::Events).or_default();
+        for event in events {
+            let existing = data.get_event(&event.id).map_err(import_error)?;
+            match import_action(section, existing.as_ref(), event) {
+                ImportAction::Create | ImportAction::Update if !dry_run => data.set_event(event),
+                _ => Ok(()),
+            }
+            .map_err(import_error)?;
+        }
+    }
+
+    // Import auth tokens
+    if let Some(auth_tokens) = &dump.auth_tokens {
+        let section = summary
+            .sections
+            .entry(DataDumpSection::AuthTokens)
+            .or_default();
+        for auth_token in auth_tokens {
+            let existing = data
+                .get_authtoken_by_id(&auth_token.id)
+                .map_err(import_error)?;
+            match import_action(section, existing.as_ref(), auth_token) {
+                ImportAction::Create | ImportAction::Update if !dry_run => {
+                    data.set_authtoken(auth_token)
+                }
+                _ => Ok(()),
+            }
+            .map_err(import_error)?;
+        }
+    }
+
+    Ok(summary)
 }
 
-pub fn export_backup(data: &Storage) -> Result<DataDump, StatusCode> {
-    // Get all users (Warning: This will include passwords!)
-    let users = data.list_users(None).map_err(|e| {
-        error!("route_settings_export_get list_users {:?}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    // Get all permission groups
-    let groups = data.list_permission_groups(None).map_err(|e| {
-        error!("route_settings_export_get list_permission_groups {:?}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    // Get all permission group memberships
-    let mut memberships: std::collections::HashMap<String, Vec<String>> =
-        std::collections::HashMap::new();
-    for group in &groups {
-        let users = data
-            .list_users_by_permission_group_id(&group.id)
-            .map_err(|e| {
-                error!(
-                    "route_settings_export_get list_users_by_permission_group_id {:?}",
-                    e
-                );
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
-        let users: Vec<String> = users.iter().map(|u| u.id.clone()).collect();
-        memberships.insert(group.id.clone(), users);
+/// Pillars from a redacted export must not overwrite the real values, so redacted values
+/// are taken from the stored pillars. If any can not be, the stored pillars are kept as is.
+fn restore_redacted_pillars(minion: &Minion, existing: Option<&Minion>) -> Minion {
+    let mut minion = minion.clone();
+    let mut pillars: Value = match minion.pillars.as_deref() {
+        Some(pillars) if pillars.contains(REDACTED) => match serde_json::from_str(pillars) {
+            Ok(pillars) => pillars,
+            Err(_) => return minion,
+        },
+        _ => return minion,
+    };
+    let stored: Option<Value> = existing
+        .and_then(|m| m.pillars.as_deref())
+        .and_then(|p| serde_json::from_str(p).ok());
+    if restore_redacted(&mut pillars, stored.as_ref()) {
+        minion.pillars = Some(pillars.to_string());
+    } else {
+        minion.pillars = existing.and_then(|m| m.pillars.clone());
+        minion.last_updated_pillars = existing.and_then(|m| m.last_updated_pillars);
+    }
+    minion
+}
+
+fn export_error(e: String) -> StatusCode {
+    error!("api.export_backup {:?}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Export the given sections. Users are included with their password hashes!
+pub fn export_backup(
+    data: &Storage,
+    sections: &[DataDumpSection],
+    redaction: Option<&PillarRedaction>,
+) -> Result<DataDump, StatusCode> {
+    let mut dump = DataDump::new();
+    for section in sections {
+        match section {
+            DataDumpSection::Users => {
+                dump.users = Some(data.list_users(None).map_err(export_error)?);
+            }
+            DataDumpSection::Groups => {
+                dump.groups = Some(data.list_permission_groups(None).map_err(export_error)?);
+            }
+            DataDumpSection::Memberships => {
+                // Temporary memberships would be permanent once imported, so leave them out
+                let grants = data.list_permission_group_grants().map_err(export_error)?;
+                let mut memberships: HashMap<String, Vec<String>> = HashMap::new();
+                for group in data.list_permission_groups(None).map_err(export_error)? {
+                    let users = data
+                        .list_users_by_permission_group_id(&group.id)
+                        .map_err(export_error)?;
+                    let users: Vec<String> = users
+                        .iter()
+                        .filter(|u| {
+                            !grants
+                                .iter()
+                                .any(|grant| grant.user_id == u.id && grant.group_id == group.id)
+                        })
+                        .map(|u| u.id.clone())
+                        .collect();
+                    memberships.insert(group.id.clone(), users);
+                }
+                dump.memberships = Some(memberships);
+            }
+            DataDumpSection::Minions => {
+                let mut minions = data
+                    .list_minions(Vec::new(), None, Paginate::None)
+                    .map_err(export_error)?;
+                if let Some(redaction) = redaction {
+                    for minion in minions.iter_mut() {
+                        redaction.redact_minion(minion);
+                    }
+                }
+                dump.minions = Some(minions);
+            }
+            DataDumpSection::MinionPresets => {
+                dump.minion_presets = Some(data.list_minion_presets().map_err(export_error)?);
+            }
+            DataDumpSection::Preferences => {
+                let mut preferences: HashMap<String, Preferences> = HashMap::new();
+                for user in data.list_users(None).map_err(export_error)? {
+                    if let Some(user_preferences) =
+                        data.get_preferences(&user.id).map_err(export_error)?
+                    {
+                        preferences.insert(user.id, user_preferences);
+                    }
+                }
+                dump.preferences = Some(preferences);
+            }
+            DataDumpSection::Jobs => {
+                dump.jobs = Some(data.list_jobs(None, None).map_err(export_error)?);
+                dump.job_returns = Some(data.list_job_returns().map_err(export_error)?);
+            }
+            DataDumpSection::Events => {
+                dump.events = Some(data.list_events(None).map_err(export_error)?);
+            }
+            DataDumpSection::AuthTokens => {
+                dump.auth_tokens = Some(data.list_authtokens().map_err(export_error)?);
+            }
+        }
     }
-    // Get all minions
-    let minions = data
-        .list_minions(Vec::new(), None, Paginate::None)
-        .map_err(|e| {
-            error!("route_settings_export_get list_minions {:?}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-    // Get all minion presets
-    let minion_presets = data.list_minion_presets().map_err(|e| {
-        error!("route_settings_export_get list_minion_presets {:?}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-
-    Ok(DataDump {
-        users,
-        groups,
-        memberships,
-        minions,
-        minion_presets,
-    })
+    Ok(dump)
 }
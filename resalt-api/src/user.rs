@@ -1,6 +1,6 @@
 use axum::http::StatusCode;
 use log::error;
-use resalt_models::{Paginate, Preferences, User};
+use resalt_models::{Paginate, Preferences, ResaltTime, User};
 use resalt_security::hash_password;
 use resalt_storage::Storage;
 
@@ -76,3 +76,23 @@ pub fn delete_user(data: &Storage, user_id: &str) -> Result<(), StatusCode> {
         StatusCode::INTERNAL_SERVER_ERROR
     })
 }
+
+/// Disable or enable a user, and set when they expire. Users who can no longer log in
+/// also lose their existing sessions.
+pub fn set_user_access(
+    data: &Storage,
+    user: &mut User,
+    disabled: bool,
+    expires_at: Option<ResaltTime>,
+) -> Result<(), StatusCode> {
+    user.disabled = disabled;
+    user.expires_at = expires_at;
+    update_user(data, user)?;
+    if !user.is_active() {
+        data.delete_authtokens_by_user_id(&user.id).map_err(|e| {
+            error!("api.set_user_access {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    }
+    Ok(())
+}
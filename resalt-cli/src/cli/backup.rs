@@ -0,0 +1,128 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+};
+
+use clap::Subcommand;
+use resalt_api::{
+    minion::pillar_redaction,
+    setting::{export_backup, import_backup},
+};
+use resalt_models::{DataDump, DataDumpSection};
+use resalt_salt::SaltAPI;
+use resalt_storage::Storage;
+use serde_json::to_string_pretty;
+
+#[derive(Subcommand, Debug)]
+pub enum BackupCommands {
+    #[clap(about = "Export a backup", aliases = &["e"])]
+    Export {
+        /// File to write to, "-" for stdout
+        #[clap(short, long, default_value = "-")]
+        output: String,
+        /// Compress with gzip, implied by an output file ending in .gz
+        #[clap(short = 'z', long)]
+        gzip: bool,
+        /// Sections to include, all if none are given
+        #[clap(short, long, value_delimiter = ',')]
+        sections: Vec<DataDumpSection>,
+        /// Redact pillar secrets of minions, like the export in the settings
+        #[clap(long)]
+        redact_secrets: bool,
+    },
+    #[clap(about = "Import a backup of any version, plain or gzip-compressed", aliases = &["i"])]
+    Import {
+        /// File to read from, "-" for stdin
+        input: String,
+        /// Only print what would be imported
+        #[clap(short = 'n', long)]
+        dry_run: bool,
+        #[clap(short, long)]
+        raw: bool,
+    },
+}
+
+pub async fn cli_backup(
+    data: Storage,
+    _salt_api: SaltAPI,
+    cmd: BackupCommands,
+) -> Result<(), String> {
+    match cmd {
+        BackupCommands::Export {
+            output,
+            gzip,
+            sections,
+            redact_secrets,
+        } => {
+            let sections = match sections.is_empty() {
+                true => DataDumpSection::ALL.to_vec(),
+                false => sections,
+            };
+            let redaction = match redact_secrets {
+                true => Some(pillar_redaction()),
+                false => None,
+            };
+            let dump = export_backup(&data, &sections, redaction.as_ref())
+                .map_err(|e| format!("Failed to export backup: {}", e))?;
+            let bytes = dump.to_bytes(gzip || output.ends_with(".gz"))?;
+            match output.as_str() {
+                "-" => io::stdout()
+                    .write_all(&bytes)
+                    .map_err(|e| format!("Failed to write backup: {}", e))?,
+                path => {
+                    fs::write(path, bytes)
+                        .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+                    eprintln!(
+                        "Exported {} to {}",
+                        sections
+                            .iter()
+                            .map(|s| s.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", "),
+                        path
+                    );
+                }
+            }
+        }
+        BackupCommands::Import {
+            input,
+            dry_run,
+            raw,
+        } => {
+            let bytes = match input.as_str() {
+                "-" => {
+                    let mut bytes = Vec::new();
+                    io::stdin()
+                        .read_to_end(&mut bytes)
+                        .map_err(|e| format!("Failed to read backup: {}", e))?;
+                    bytes
+                }
+                path => fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?,
+            };
+            let dump = DataDump::from_bytes(&bytes)?;
+            let summary = import_backup(&data, &dump, dry_run)
+                .map_err(|e| format!("Failed to import backup: {}", e))?;
+            if raw {
+                println!("{}", to_string_pretty(&summary).unwrap());
+            } else {
+                println!(
+                    "{0: <16} {1: <9} {2: <9} {3: <9}",
+                    "Section", "Created", "Updated", "Skipped"
+                );
+                for (section, counts) in &summary.sections {
+                    println!(
+                        "{0: <16} {1: <9} {2: <9} {3: <9}",
+                        section.to_string(),
+                        counts.created,
+                        counts.updated,
+                        counts.skipped
+                    );
+                }
+                if dry_run {
+                    println!("Dry-run, nothing was imported");
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use clap::Subcommand;
+use resalt_config::validate_config;
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    #[clap(about = "Validate the config and print the effective values, with secrets masked")]
+    Validate,
+}
+
+/// Runs without a database connection, so it also works when the database config is broken.
+pub fn cli_config_validate() -> Result<(), String> {
+    match validate_config() {
+        Ok(entries) => {
+            let key_width = entries.iter().map(|e| e.key.len()).max().unwrap_or(0);
+            for entry in entries {
+                println!(
+                    "{:key_width$}  {} ({})",
+                    entry.key,
+                    entry.value,
+                    entry.source,
+                    key_width = key_width
+                );
+            }
+            println!("Config is valid");
+            Ok(())
+        }
+        Err(errors) => {
+            for e in &errors {
+                eprintln!("Invalid config: {}", e);
+            }
+            Err(format!("{} invalid config value(s)", errors.len()))
+        }
+    }
+}
@@ -1,13 +1,24 @@
+mod backup;
+mod config;
 mod permission;
 mod permission_group;
+mod preset;
+mod remote;
+mod storage;
 mod user;
 
+pub use self::config::{cli_config_validate, ConfigCommands};
+pub use self::remote::{cli_remote, RemoteCommands};
+pub use self::storage::{cli_storage, StorageCommands};
 use self::{
+    backup::{cli_backup, BackupCommands},
     permission::{run_cli_permission, PermissionCommands},
+    preset::{cli_preset, PresetCommands},
     user::{cli_user, UserCommands},
 };
-use clap::{command, Parser, Subcommand};
+use clap::{Parser, Subcommand};
 use resalt_api::config::get_config;
+use resalt_routes::openapi::openapi_json;
 use resalt_salt::SaltAPI;
 use resalt_storage::Storage;
 use serde_json::to_string_pretty;
@@ -22,13 +33,38 @@ pub struct Cli {
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
+    #[clap(about = "Export and import backups", aliases = &["b"])]
+    Backup {
+        #[clap(subcommand)]
+        subcmd: BackupCommands,
+    },
     #[clap(about = "Print the current config", aliases = &["c"])]
-    Config,
+    Config {
+        #[clap(subcommand)]
+        subcmd: Option<ConfigCommands>,
+    },
+    #[clap(about = "Print the OpenAPI document of the REST API")]
+    Openapi,
     #[clap(about = "Manage permissions", aliases = &["p", "perms"])]
     Permission {
         #[clap(subcommand)]
         subcmd: PermissionCommands,
     },
+    #[clap(about = "Work with minion presets", aliases = &["ps"])]
+    Preset {
+        #[clap(subcommand)]
+        subcmd: PresetCommands,
+    },
+    #[clap(about = "Work with a Resalt server over HTTP", aliases = &["r"])]
+    Remote {
+        #[clap(subcommand)]
+        subcmd: RemoteCommands,
+    },
+    #[clap(about = "Work with the database", aliases = &["s"])]
+    Storage {
+        #[clap(subcommand)]
+        subcmd: StorageCommands,
+    },
     #[clap(about = "Manage users", aliases = &["u"])]
     User {
         #[clap(subcommand)]
@@ -40,13 +76,21 @@ pub enum Commands {
 
 pub async fn run_cli(data: Storage, salt_api: SaltAPI, cmd: Commands) -> Result<(), String> {
     match cmd {
-        Commands::Config => {
+        Commands::Backup { subcmd } => cli_backup(data, salt_api, subcmd).await?,
+        Commands::Config {
+            subcmd: Some(ConfigCommands::Validate),
+        } => cli_config_validate()?,
+        Commands::Config { subcmd: None } => {
             let config = get_config(false)
                 .await
                 .map_err(|e| format!("Failed to get config: {}", e))?;
             println!("Config: {}", to_string_pretty(&config).unwrap());
         }
+        Commands::Openapi => cli_openapi()?,
         Commands::Permission { subcmd } => run_cli_permission(data, salt_api, subcmd).await?,
+        Commands::Preset { subcmd } => cli_preset(data, salt_api, subcmd).await?,
+        Commands::Remote { subcmd } => cli_remote(subcmd).await?,
+        Commands::Storage { subcmd } => cli_storage(subcmd).await?,
         Commands::User { subcmd } => cli_user(data, salt_api, subcmd).await?,
         Commands::Version => {
             println!("Version: {}", env!("CARGO_PKG_VERSION"));
@@ -55,3 +99,8 @@ pub async fn run_cli(data: Storage, salt_api: SaltAPI, cmd: Commands) -> Result<
 
     Ok(())
 }
+
+pub fn cli_openapi() -> Result<(), String> {
+    println!("{}", openapi_json());
+    Ok(())
+}
@@ -1,24 +1,39 @@
+use std::{
+    fs,
+    io::{self, Read},
+};
+
 use clap::Subcommand;
 use log::*;
 use resalt_api::permission::{
-    delete_permission_group, get_permission_group_by_id, get_permission_groups,
+    create_permission_group, delete_permission_group, get_permission_group_by_id,
+    get_permission_group_resolved_perms, get_permission_group_users, get_permission_groups,
+    update_permission_group,
 };
-use resalt_models::Paginate;
+use resalt_models::{Paginate, PermissionGroup};
 use resalt_salt::SaltAPI;
 use resalt_storage::Storage;
-use serde_json::to_string_pretty;
+use serde_json::{to_string_pretty, Value};
 
 #[derive(Subcommand, Debug)]
 pub enum PermissionGroupCommands {
-    // #[clap(about = "Create a permission group", aliases = &["c"])]
-    // Create {
-    //     #[clap(short, long)]
-    //     name: String,
-    //     #[clap(short, long)]
-    //     description: Option<String>,
-    //     #[clap(short, long)]
-    //     permissions: Option<Vec<String>>,
-    // },
+    #[clap(about = "Create a permission group", aliases = &["c"])]
+    Create {
+        name: String,
+        /// JSON file with an array of permissions, "-" for stdin
+        #[clap(short, long)]
+        perms_file: Option<String>,
+    },
+    #[clap(about = "Rename a permission group or replace its permissions", aliases = &["e"])]
+    Edit {
+        /// Group ID or name
+        group: String,
+        #[clap(short, long)]
+        name: Option<String>,
+        /// JSON file with an array of permissions, "-" for stdin
+        #[clap(short, long)]
+        perms_file: Option<String>,
+    },
     #[clap(about = "Delete a permission group", aliases = &["d"])]
     Delete { id: String },
     #[clap(about = "List permission groups", aliases = &["l", "ls"])]
@@ -26,20 +41,112 @@ pub enum PermissionGroupCommands {
         #[clap(short, long)]
         raw: bool,
     },
-    // #[clap(about = "Add a permission to a group", aliases = &["a"])]
-    // AddPermission {
-    //     #[clap(short, long)]
-    //     group_id: String,
-    //     #[clap(short, long)]
-    //     permission: String,
-    // },
-    // #[clap(about = "Remove a permission from a group", aliases = &["r"])]
-    // RemovePermission {
-    //     #[clap(short, long)]
-    //     group_id: String,
-    //     #[clap(short, long)]
-    //     permission: String,
-    // },
+    #[clap(about = "Show the permissions and members of a group", aliases = &["s"])]
+    Show {
+        /// Group ID or name
+        group: String,
+        #[clap(short, long)]
+        raw: bool,
+    },
+    #[clap(about = "Add a permission to a group", aliases = &["a"])]
+    AddPermission {
+        /// Group ID or name
+        group: String,
+        /// A minion target like ".*", or JSON like '{"@resalt": ["admin.user"]}'
+        permission: String,
+    },
+    #[clap(about = "Remove a permission from a group", aliases = &["r"])]
+    RemovePermission {
+        /// Group ID or name
+        group: String,
+        /// As given to add-permission
+        permission: String,
+    },
+    #[clap(
+        about = "Include a group in another, so its members inherit the permissions of both",
+        aliases = &["as"]
+    )]
+    AddSubgroup {
+        /// Group ID or name
+        group: String,
+        /// Group ID or name to include
+        subgroup: String,
+    },
+    #[clap(about = "Stop including a group in another", aliases = &["rs"])]
+    RemoveSubgroup {
+        /// Group ID or name
+        group: String,
+        /// Group ID or name to no longer include
+        subgroup: String,
+    },
+}
+
+/// Find a group by ID, or else by name.
+pub(super) fn find_group(data: &Storage, group: &str) -> Result<PermissionGroup, String> {
+    if let Some(found) = get_permission_group_by_id(data, group)
+        .map_err(|e| format!("Failed to get group: {}", e))?
+    {
+        return Ok(found);
+    }
+    get_permission_groups(data, Paginate::None)
+        .map_err(|e| format!("Failed to get groups: {}", e))?
+        .into_iter()
+        .find(|found| found.name == group)
+        .ok_or_else(|| format!("Group not found: {}", group))
+}
+
+/// Permissions are an array of minion targets, and objects of functions by target.
+fn parse_perms(json: &str) -> Result<Vec<Value>, String> {
+    let perms: Vec<Value> = serde_json::from_str(json)
+        .map_err(|e| format!("Permissions must be a JSON array: {}", e))?;
+    match perms
+        .iter()
+        .find(|perm| !perm.is_string() && !perm.is_object())
+    {
+        Some(perm) => Err(format!("Invalid permission: {}", perm)),
+        None => Ok(perms),
+    }
+}
+
+fn read_perms_file(path: &str) -> Result<String, String> {
+    let json = match path {
+        "-" => {
+            let mut json = String::new();
+            io::stdin()
+                .read_to_string(&mut json)
+                .map_err(|e| format!("Failed to read permissions: {}", e))?;
+            json
+        }
+        path => fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?,
+    };
+    let perms = parse_perms(&json)?;
+    Ok(Value::Array(perms).to_string())
+}
+
+/// A permission argument is JSON, or else a plain minion target.
+fn parse_permission(permission: &str) -> Value {
+    match serde_json::from_str(permission) {
+        Ok(Value::Object(perm)) => Value::Object(perm),
+        _ => Value::String(permission.to_string()),
+    }
+}
+
+fn save_perms(
+    data: &Storage,
+    group: &mut PermissionGroup,
+    perms: Vec<Value>,
+) -> Result<(), String> {
+    group.perms = Value::Array(perms).to_string();
+    update_permission_group(data, group).map_err(|e| format!("Failed to update group: {}", e))
+}
+
+fn print_perms(perms: &Value) {
+    for perm in perms.as_array().into_iter().flatten() {
+        match perm {
+            Value::String(perm) => println!("\t{}", perm),
+            perm => println!("\t{}", perm),
+        }
+    }
 }
 
 pub async fn cli_permission_group(
@@ -48,6 +155,34 @@ pub async fn cli_permission_group(
     cmd: PermissionGroupCommands,
 ) -> Result<(), String> {
     match cmd {
+        PermissionGroupCommands::Create { name, perms_file } => {
+            if find_group(&data, &name).is_ok() {
+                return Err(format!("Group already exists: {}", name));
+            }
+            let perms = match perms_file {
+                Some(path) => Some(read_perms_file(&path)?),
+                None => None,
+            };
+            let id = create_permission_group(&data, None, &name, perms)
+                .map_err(|e| format!("Failed to create group: {}", e))?;
+            println!("Created group: {} ({})", name, id);
+        }
+        PermissionGroupCommands::Edit {
+            group,
+            name,
+            perms_file,
+        } => {
+            let mut group = find_group(&data, &group)?;
+            if let Some(name) = name {
+                group.name = name;
+            }
+            if let Some(path) = perms_file {
+                group.perms = read_perms_file(&path)?;
+            }
+            update_permission_group(&data, &group)
+                .map_err(|e| format!("Failed to update group: {}", e))?;
+            println!("Updated group: {}", group.name);
+        }
         PermissionGroupCommands::Delete { id } => {
             let group = get_permission_group_by_id(&data, &id)
                 .map_err(|e| format!("Failed to get group: {}", e))?;
@@ -72,6 +207,86 @@ pub async fn cli_permission_group(
                 }
             }
         }
+        PermissionGroupCommands::Show { group, raw } => {
+            let group = find_group(&data, &group)?;
+            let users = get_permission_group_users(&data, &group.id)
+                .map_err(|e| format!("Failed to get group members: {}", e))?;
+            let resolved_perms = get_permission_group_resolved_perms(&data, &group.id)
+                .map_err(|e| format!("Failed to resolve permissions: {}", e))?;
+            let group = group.public(users, resolved_perms);
+            if raw {
+                println!("{}", to_string_pretty(&group).unwrap());
+            } else {
+                println!("Group: {} ({})", group.name, group.id);
+                println!("Permissions:");
+                print_perms(&group.perms);
+                println!("Including inherited:");
+                print_perms(&group.resolved_perms);
+                println!("Subgroups:");
+                for subgroup in &group.subgroups {
+                    match get_permission_group_by_id(&data, subgroup) {
+                        Ok(Some(subgroup)) => println!("\t{} ({})", subgroup.name, subgroup.id),
+                        _ => println!("\t{}", subgroup),
+                    }
+                }
+                println!("Members:");
+                for user in &group.users {
+                    println!("\t{} ({})", user.username, user.id);
+                }
+            }
+        }
+        PermissionGroupCommands::AddPermission { group, permission } => {
+            let mut group = find_group(&data, &group)?;
+            let mut perms = parse_perms(&group.perms)?;
+            let permission = parse_permission(&permission);
+            if perms.contains(&permission) {
+                return Err(format!("{} already has {}", group.name, permission));
+            }
+            perms.push(permission.clone());
+            save_perms(&data, &mut group, perms)?;
+            println!("Added {} to {}", permission, group.name);
+        }
+        PermissionGroupCommands::RemovePermission { group, permission } => {
+            let mut group = find_group(&data, &group)?;
+            let mut perms = parse_perms(&group.perms)?;
+            let permission = parse_permission(&permission);
+            let count = perms.len();
+            perms.retain(|perm| perm != &permission);
+            if perms.len() == count {
+                return Err(format!("{} does not have {}", group.name, permission));
+            }
+            save_perms(&data, &mut group, perms)?;
+            println!("Removed {} from {}", permission, group.name);
+        }
+        PermissionGroupCommands::AddSubgroup { group, subgroup } => {
+            let mut group = find_group(&data, &group)?;
+            let subgroup = find_group(&data, &subgroup)?;
+            if group.subgroups.contains(&subgroup.id) {
+                return Err(format!("{} already includes {}", group.name, subgroup.name));
+            }
+            group.subgroups.push(subgroup.id.clone());
+            let mut tree = data
+                .permission_group_tree()
+                .map_err(|e| format!("Failed to get groups: {}", e))?;
+            tree.insert(group.clone());
+            if tree.has_cycle(&group.id) {
+                return Err(format!("{} already includes {}", subgroup.name, group.name));
+            }
+            update_permission_group(&data, &group)
+                .map_err(|e| format!("Failed to update group: {}", e))?;
+            println!("{} now includes {}", group.name, subgroup.name);
+        }
+        PermissionGroupCommands::RemoveSubgroup { group, subgroup } => {
+            let mut group = find_group(&data, &group)?;
+            let subgroup = find_group(&data, &subgroup)?;
+            if !group.subgroups.contains(&subgroup.id) {
+                return Err(format!("{} does not include {}", group.name, subgroup.name));
+            }
+            group.subgroups.retain(|id| id != &subgroup.id);
+            update_permission_group(&data, &group)
+                .map_err(|e| format!("Failed to update group: {}", e))?;
+            println!("{} no longer includes {}", group.name, subgroup.name);
+        }
     }
     Ok(())
 }
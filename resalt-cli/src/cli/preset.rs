@@ -0,0 +1,53 @@
+use clap::Subcommand;
+use resalt_api::preset::{get_minion_preset, get_minion_presets, get_nodegroups};
+use resalt_models::{render_nodegroups_yaml, MinionPreset};
+use resalt_salt::SaltAPI;
+use resalt_storage::Storage;
+use serde_json::to_string_pretty;
+
+#[derive(Subcommand, Debug)]
+pub enum PresetCommands {
+    #[clap(about = "Render presets as a Salt nodegroups config", aliases = &["ng"])]
+    Nodegroups {
+        /// Preset IDs to render, all presets if none are given
+        #[clap(long = "id")]
+        ids: Vec<String>,
+        #[clap(short, long)]
+        raw: bool,
+    },
+}
+
+pub async fn cli_preset(
+    data: Storage,
+    _salt_api: SaltAPI,
+    cmd: PresetCommands,
+) -> Result<(), String> {
+    match cmd {
+        PresetCommands::Nodegroups { ids, raw } => {
+            let presets: Vec<MinionPreset> = match ids.is_empty() {
+                true => get_minion_presets(&data)
+                    .map_err(|e| format!("Failed to get presets: {}", e))?,
+                false => {
+                    let mut presets = Vec::new();
+                    for id in ids {
+                        match get_minion_preset(&data, &id)
+                            .map_err(|e| format!("Failed to get preset: {}", e))?
+                        {
+                            Some(preset) => presets.push(preset),
+                            None => return Err(format!("Preset not found: {}", id)),
+                        }
+                    }
+                    presets
+                }
+            };
+            let nodegroups = get_nodegroups(&data, &presets, None)
+                .map_err(|e| format!("Failed to render nodegroups: {}", e))?;
+            if raw {
+                println!("{}", to_string_pretty(&nodegroups).unwrap());
+            } else {
+                print!("{}", render_nodegroups_yaml(&nodegroups));
+            }
+        }
+    }
+    Ok(())
+}
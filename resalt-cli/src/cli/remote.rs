@@ -0,0 +1,642 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use clap::Subcommand;
+use resalt_client::{JobRunRequest, MinionsFilter, ResaltClient, ResaltClientError};
+use resalt_models::{
+    Event, FilterNode, JobSort, MinionSort, Paginate, ResaltTime, SaltClientType, SaltMinionKey,
+    SaltTgtType,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{to_string_pretty, Value};
+
+/// Events fetched per request when following events.
+const FOLLOW_PAGE_SIZE: usize = 100;
+/// Already printed events fetched again when following, so none are missed if the
+/// event list shifted in between.
+const FOLLOW_OVERLAP: usize = 10;
+
+#[derive(Subcommand, Debug)]
+pub enum RemoteCommands {
+    #[clap(about = "Log in to a Resalt server, and cache the session")]
+    Login {
+        /// Where Resalt is served, e.g. https://resalt.example.com
+        #[clap(long)]
+        url: String,
+        #[clap(short, long)]
+        username: Option<String>,
+        /// Prompted for on stdin if not given
+        #[clap(short, long)]
+        password: Option<String>,
+        /// Use an existing API token instead of a username and password
+        #[clap(long, conflicts_with_all = &["username", "password"])]
+        token: Option<String>,
+    },
+    #[clap(about = "Log out, and remove the cached session")]
+    Logout,
+    #[clap(about = "List minions", aliases = &["m"])]
+    Minions {
+        /// Filters as JSON, in the same format as the web UI
+        #[clap(long)]
+        filter: Option<String>,
+        /// Text query, e.g. "os:Ubuntu and not pkg.nginx"
+        #[clap(short, long)]
+        query: Option<String>,
+        /// E.g. "id.asc" or "lastSeen.desc"
+        #[clap(long, value_parser = parse_serde_enum::<MinionSort>)]
+        sort: Option<MinionSort>,
+        #[clap(long)]
+        limit: Option<i64>,
+        #[clap(long, default_value = "0")]
+        offset: i64,
+        #[clap(short, long)]
+        raw: bool,
+    },
+    #[clap(about = "Run and inspect jobs", aliases = &["j"])]
+    Job {
+        #[clap(subcommand)]
+        subcmd: RemoteJobCommands,
+    },
+    #[clap(about = "Manage minion keys", aliases = &["k"])]
+    Key {
+        #[clap(subcommand)]
+        subcmd: RemoteKeyCommands,
+    },
+    #[clap(about = "Print the latest events", aliases = &["e"])]
+    Events {
+        /// Number of events to print
+        #[clap(short = 'n', long, default_value = "20")]
+        lines: usize,
+        /// Keep printing new events as they arrive
+        #[clap(short, long)]
+        follow: bool,
+        /// Seconds between polls when following
+        #[clap(long, default_value = "2")]
+        interval: u64,
+        /// Print each event as a line of JSON
+        #[clap(short, long)]
+        raw: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RemoteJobCommands {
+    #[clap(about = "List jobs", aliases = &["l", "ls"])]
+    List {
+        /// E.g. "timestamp.desc"
+        #[clap(long, value_parser = parse_serde_enum::<JobSort>)]
+        sort: Option<JobSort>,
+        #[clap(long)]
+        limit: Option<i64>,
+        #[clap(long, default_value = "0")]
+        offset: i64,
+        #[clap(short, long)]
+        raw: bool,
+    },
+    #[clap(about = "Run a job, and print what Salt returned")]
+    Run {
+        tgt: String,
+        fun: String,
+        /// Positional arguments, parsed as JSON where possible
+        arg: Vec<String>,
+        /// Keyword argument as key=value, may be repeated
+        #[clap(long, value_parser = parse_kwarg)]
+        kwarg: Vec<(String, String)>,
+        #[clap(long, default_value = "glob", value_parser = parse_serde_enum::<SaltTgtType>)]
+        tgt_type: SaltTgtType,
+        #[clap(long, default_value = "local", value_parser = parse_serde_enum::<SaltClientType>)]
+        client: SaltClientType,
+        /// Only used with the "local_batch" client
+        #[clap(long, default_value = "")]
+        batch_size: String,
+        /// Target the minions currently matching this preset, instead of `tgt`
+        #[clap(long)]
+        preset: Option<String>,
+    },
+    #[clap(about = "Print a job and its returns", aliases = &["g"])]
+    Get {
+        jid: String,
+        #[clap(short, long)]
+        raw: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RemoteKeyCommands {
+    #[clap(about = "List minion keys", aliases = &["l", "ls"])]
+    List {
+        #[clap(short, long)]
+        raw: bool,
+    },
+    #[clap(about = "Accept a minion key")]
+    Accept {
+        id: String,
+        /// Required if the minion has a key on more than one master
+        #[clap(long)]
+        master: Option<String>,
+    },
+    #[clap(about = "Reject a minion key")]
+    Reject {
+        id: String,
+        #[clap(long)]
+        master: Option<String>,
+    },
+    #[clap(about = "Delete a minion key", aliases = &["d"])]
+    Delete {
+        id: String,
+        #[clap(long)]
+        master: Option<String>,
+    },
+}
+
+/// Cached between invocations, so only `remote login` needs credentials.
+#[derive(Serialize, Deserialize, Debug)]
+struct RemoteSession {
+    url: String,
+    token: String,
+    /// Unix timestamp at which the token expires, unknown for API tokens.
+    expiry: Option<u64>,
+}
+
+/// Runs without a database connection, everything goes through the HTTP API.
+pub async fn cli_remote(cmd: RemoteCommands) -> Result<(), String> {
+    match cmd {
+        RemoteCommands::Login {
+            url,
+            username,
+            password,
+            token,
+        } => {
+            let mut client = ResaltClient::new(&url);
+            let session = match token {
+                Some(token) => {
+                    client = client.with_token(&token);
+                    RemoteSession {
+                        url,
+                        token,
+                        expiry: None,
+                    }
+                }
+                None => {
+                    let username = match username {
+                        Some(username) => username,
+                        None => return Err("Either --username or --token is required".to_string()),
+                    };
+                    let password = match password {
+                        Some(password) => password,
+                        None => prompt("Password: ")?,
+                    };
+                    let login = client
+                        .login(&username, &password)
+                        .await
+                        .map_err(|e| format!("Failed to log in: {}", e))?;
+                    RemoteSession {
+                        url,
+                        token: login.token,
+                        expiry: Some(login.expiry),
+                    }
+                }
+            };
+            // Also validates API tokens before they are cached
+            let user = client
+                .myself()
+                .await
+                .map_err(|e| format!("Failed to log in: {}", e))?;
+            save_session(&session)?;
+            println!("Logged in to {} as {}", session.url, user.username);
+        }
+        RemoteCommands::Logout => {
+            let mut client = load_client()?;
+            // The cached session is removed even if the server already forgot it
+            if let Err(e) = client.logout().await {
+                eprintln!("{}", remote_error("Failed to log out", e));
+            }
+            remove_session()?;
+            println!("Logged out");
+        }
+        RemoteCommands::Minions {
+            filter,
+            query,
+            sort,
+            limit,
+            offset,
+            raw,
+        } => {
+            let client = load_client()?;
+            let filters: Vec<FilterNode> = match filter {
+                Some(filter) => {
+                    serde_json::from_str(&filter).map_err(|e| format!("Invalid filter: {}", e))?
+                }
+                None => Vec::new(),
+            };
+            let filter = MinionsFilter { filters, query };
+            let minions = client
+                .minions(&filter, sort, paginate(limit, offset))
+                .await
+                .map_err(|e| remote_error("Failed to get minions", e))?;
+            if raw {
+                println!("{}", to_string_pretty(&minions).unwrap());
+            } else {
+                println!(
+                    "{0: <32} {1: <8} {2: <28} {3: <12} {4: <14} {5: <12}",
+                    "ID", "Status", "Last Seen", "OS", "Conformity", "Master"
+                );
+                for minion in minions {
+                    let conformity = match minion.last_updated_conformity {
+                        Some(_) => format!(
+                            "{}/{}/{}",
+                            minion.conformity_success.unwrap_or(0),
+                            minion.conformity_incorrect.unwrap_or(0),
+                            minion.conformity_error.unwrap_or(0)
+                        ),
+                        None => "None".to_string(),
+                    };
+                    println!(
+                        "{0: <32} {1: <8} {2: <28} {3: <12} {4: <14} {5: <12}",
+                        minion.id,
+                        minion.status.to_string(),
+                        minion.last_seen.to_string(),
+                        minion.os_type.unwrap_or("None".to_string()),
+                        conformity,
+                        minion.master.unwrap_or("None".to_string())
+                    );
+                }
+            }
+        }
+        RemoteCommands::Job { subcmd } => cli_remote_job(subcmd).await?,
+        RemoteCommands::Key { subcmd } => cli_remote_key(subcmd).await?,
+        RemoteCommands::Events {
+            lines,
+            follow,
+            interval,
+            raw,
+        } => {
+            let client = load_client()?;
+            // Events are listed oldest first
+            let events = client
+                .events(None)
+                .await
+                .map_err(|e| remote_error("Failed to get events", e))?;
+            let skip = events.len().saturating_sub(lines);
+            let mut last: Option<(ResaltTime, Vec<String>)> = None;
+            if !raw {
+                println!("{0: <28} {1: <12} {2: <60}", "Timestamp", "Master", "Tag");
+            }
+            let mut count = events.len();
+            print_events(events, skip, &mut last, raw);
+            if follow {
+                loop {
+                    tokio::time::sleep(Duration::from_secs(interval)).await;
+                    // Only fetch the events after those already seen
+                    loop {
+                        let offset = count.saturating_sub(FOLLOW_OVERLAP);
+                        let events = client
+                            .events(Some((FOLLOW_PAGE_SIZE as i64, offset as i64)))
+                            .await
+                            .map_err(|e| remote_error("Failed to get events", e))?;
+                        let fetched = events.len();
+                        count = offset + fetched;
+                        print_events(events, 0, &mut last, raw);
+                        if fetched < FOLLOW_PAGE_SIZE {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn cli_remote_job(cmd: RemoteJobCommands) -> Result<(), String> {
+    let client = load_client()?;
+    match cmd {
+        RemoteJobCommands::List {
+            sort,
+            limit,
+            offset,
+            raw,
+        } => {
+            let jobs = client
+                .jobs(sort, paginate(limit, offset))
+                .await
+                .map_err(|e| remote_error("Failed to get jobs", e))?;
+            if raw {
+                println!("{}", to_string_pretty(&jobs).unwrap());
+            } else {
+                println!(
+                    "{0: <22} {1: <28} {2: <22} {3: <12}",
+                    "JID", "Timestamp", "User", "Master"
+                );
+                for job in jobs {
+                    println!(
+                        "{0: <22} {1: <28} {2: <22} {3: <12}",
+                        job.jid,
+                        job.timestamp.to_string(),
+                        job.user.unwrap_or("None".to_string()),
+                        job.master.unwrap_or("None".to_string())
+                    );
+                }
+            }
+        }
+        RemoteJobCommands::Run {
+            tgt,
+            fun,
+            arg,
+            kwarg,
+            tgt_type,
+            client: client_type,
+            batch_size,
+            preset,
+        } => {
+            let job = JobRunRequest {
+                client: client_type,
+                tgt_type,
+                tgt,
+                preset_id: preset,
+                fun,
+                // Like the salt CLI, "true" or "5" are passed as JSON values
+                arg: arg
+                    .into_iter()
+                    .map(|a| serde_json::from_str(&a).unwrap_or(Value::String(a)))
+                    .collect(),
+                kwarg: kwarg.into_iter().collect::<HashMap<String, String>>(),
+                batch_size,
+            };
+            let results = client
+                .run_job(&job)
+                .await
+                .map_err(|e| remote_error("Failed to run job", e))?;
+            // Print the results by master, and fail if any master failed
+            let mut jobs = serde_json::Map::new();
+            let mut errors = Vec::new();
+            for (master, result) in results {
+                match (result.job, result.error) {
+                    (Some(job), _) => {
+                        jobs.insert(master, job);
+                    }
+                    (None, error) => errors.push(format!(
+                        "{}: {}",
+                        master,
+                        error.unwrap_or("Unknown error".to_string())
+                    )),
+                }
+            }
+            println!("{}", to_string_pretty(&jobs).unwrap());
+            if !errors.is_empty() {
+                errors.sort();
+                return Err(format!("Failed to run job on {}", errors.join(", ")));
+            }
+        }
+        RemoteJobCommands::Get { jid, raw } => {
+            let job = client
+                .job(&jid)
+                .await
+                .map_err(|e| remote_error("Failed to get job", e))?;
+            if raw {
+                println!(
+                    "{}",
+                    to_string_pretty(&serde_json::json!({
+                        "job": job.job,
+                        "returns": job.returns,
+                    }))
+                    .unwrap()
+                );
+            } else {
+                println!("JID:       {}", job.job.jid);
+                println!("Timestamp: {}", job.job.timestamp);
+                println!("User:      {}", job.job.user.unwrap_or("None".to_string()));
+                println!(
+                    "Master:    {}",
+                    job.job.master.unwrap_or("None".to_string())
+                );
+                println!();
+                println!("{0: <32} {1: <28}", "Minion", "Returned");
+                for job_return in job.returns {
+                    println!(
+                        "{0: <32} {1: <28}",
+                        job_return.minion_id,
+                        job_return.timestamp.to_string()
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn cli_remote_key(cmd: RemoteKeyCommands) -> Result<(), String> {
+    let client = load_client()?;
+    let keys = client
+        .keys()
+        .await
+        .map_err(|e| remote_error("Failed to get keys", e))?;
+    match cmd {
+        RemoteKeyCommands::List { raw } => {
+            if raw {
+                println!("{}", to_string_pretty(&keys).unwrap());
+            } else {
+                println!(
+                    "{0: <32} {1: <18} {2: <12} {3: <50}",
+                    "ID", "State", "Master", "Fingerprint"
+                );
+                for key in keys {
+                    println!(
+                        "{0: <32} {1: <18} {2: <12} {3: <50}",
+                        key.id,
+                        key.state.to_string(),
+                        key.master,
+                        key.finger
+                    );
+                }
+            }
+        }
+        RemoteKeyCommands::Accept { id, master } => {
+            let key = find_key(keys, &id, master.as_deref())?;
+            client
+                .accept_key(&key)
+                .await
+                .map_err(|e| remote_error("Failed to accept key", e))?;
+            println!("Accepted key: {}", key.id);
+        }
+        RemoteKeyCommands::Reject { id, master } => {
+            let key = find_key(keys, &id, master.as_deref())?;
+            client
+                .reject_key(&key)
+                .await
+                .map_err(|e| remote_error("Failed to reject key", e))?;
+            println!("Rejected key: {}", key.id);
+        }
+        RemoteKeyCommands::Delete { id, master } => {
+            let key = find_key(keys, &id, master.as_deref())?;
+            client
+                .delete_key(&key)
+                .await
+                .map_err(|e| remote_error("Failed to delete key", e))?;
+            println!("Deleted key: {}", key.id);
+        }
+    }
+    Ok(())
+}
+
+fn find_key(
+    keys: Vec<SaltMinionKey>,
+    id: &str,
+    master: Option<&str>,
+) -> Result<SaltMinionKey, String> {
+    let mut keys: Vec<SaltMinionKey> = keys
+        .into_iter()
+        .filter(|key| key.id == id && master.map_or(true, |master| key.master == master))
+        .collect();
+    match keys.len() {
+        0 => Err(format!("Key not found: {}", id)),
+        1 => Ok(keys.remove(0)),
+        _ => Err(format!(
+            "Key {} exists on several masters, select one with --master",
+            id
+        )),
+    }
+}
+
+/// Print the events after `skip` that were not printed yet, and remember the
+/// newest ones in `last`.
+fn print_events(
+    events: Vec<Event>,
+    skip: usize,
+    last: &mut Option<(ResaltTime, Vec<String>)>,
+    raw: bool,
+) {
+    for event in events.into_iter().skip(skip) {
+        // Events sharing a timestamp are told apart by their ID
+        if let Some((timestamp, ids)) = last.as_mut() {
+            if event.timestamp < *timestamp
+                || (event.timestamp == *timestamp && ids.contains(&event.id))
+            {
+                continue;
+            }
+            if event.timestamp > *timestamp {
+                *timestamp = event.timestamp;
+                ids.clear();
+            }
+            ids.push(event.id.clone());
+        } else {
+            *last = Some((event.timestamp, vec![event.id.clone()]));
+        }
+        if raw {
+            println!("{}", serde_json::to_string(&event).unwrap());
+        } else {
+            println!(
+                "{0: <28} {1: <12} {2: <60}",
+                event.timestamp.to_string(),
+                event.master.unwrap_or("None".to_string()),
+                event.tag
+            );
+        }
+    }
+}
+
+fn paginate(limit: Option<i64>, offset: i64) -> Paginate {
+    limit.map(|limit| (limit, offset))
+}
+
+fn remote_error(context: &str, e: ResaltClientError) -> String {
+    match e {
+        ResaltClientError::Unauthorized => format!(
+            "{}: {}, log in again with `resalt-cli remote login`",
+            context, e
+        ),
+        e => format!("{}: {}", context, e),
+    }
+}
+
+/// Parse an enum by its serialized name, e.g. "timestamp.desc" for [`JobSort`].
+fn parse_serde_enum<T: DeserializeOwned>(value: &str) -> Result<T, String> {
+    serde_json::from_value(Value::String(value.to_string()))
+        .map_err(|_| format!("Invalid value: {}", value))
+}
+
+fn parse_kwarg(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) => Ok((key.to_string(), value.to_string())),
+        None => Err(format!("Expected key=value, got: {}", value)),
+    }
+}
+
+fn prompt(message: &str) -> Result<String, String> {
+    print!("{}", message);
+    io::stdout().flush().map_err(|e| e.to_string())?;
+    let mut line = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|e| format!("Failed to read stdin: {}", e))?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// `RESALT_CLI_SESSION`, or `$XDG_CONFIG_HOME/resalt/session.json`.
+fn session_path() -> Result<PathBuf, String> {
+    if let Ok(path) = std::env::var("RESALT_CLI_SESSION") {
+        return Ok(PathBuf::from(path));
+    }
+    let config_dir = match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => match std::env::var("HOME") {
+            Ok(home) => PathBuf::from(home).join(".config"),
+            Err(_) => return Err("Neither XDG_CONFIG_HOME nor HOME is set".to_string()),
+        },
+    };
+    Ok(config_dir.join("resalt").join("session.json"))
+}
+
+fn save_session(session: &RemoteSession) -> Result<(), String> {
+    let path = session_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    // The token grants the same access as the password
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(&path)
+        .map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+    file.write_all(to_string_pretty(session).unwrap().as_bytes())
+        .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+fn remove_session() -> Result<(), String> {
+    let path = session_path()?;
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("Failed to remove {:?}: {}", path, e)),
+    }
+}
+
+fn load_client() -> Result<ResaltClient, String> {
+    let path = session_path()?;
+    let session: RemoteSession = match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| format!("Invalid session in {:?}: {}", path, e))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err("Not logged in, run `resalt-cli remote login` first".to_string())
+        }
+        Err(e) => return Err(format!("Failed to read {:?}: {}", path, e)),
+    };
+    if let Some(expiry) = session.expiry {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        if expiry <= now {
+            return Err("Session expired, log in again with `resalt-cli remote login`".to_string());
+        }
+    }
+    Ok(ResaltClient::new(&session.url).with_token(&session.token))
+}
@@ -1,21 +1,39 @@
+use std::io;
+
+use super::permission_group::find_group;
 use clap::Subcommand;
 use log::*;
 use rand::Rng;
 use resalt_api::{
-    permission::{add_user_to_group, create_permission_group},
-    user::{create_user, delete_user, get_user_by_id, get_user_by_username, get_users},
+    permission::{
+        add_user_to_group, create_permission_group, get_permission_groups_by_user_id,
+        is_user_member_of_group, remove_user_from_group,
+    },
+    user::{
+        create_user, delete_user, get_preferences, get_user_by_id, get_user_by_username, get_users,
+        set_user_access, update_user,
+    },
 };
-use resalt_models::Paginate;
+use resalt_models::{Paginate, ResaltTime, User};
 use resalt_salt::SaltAPI;
+use resalt_security::hash_password;
 use resalt_storage::Storage;
-use serde_json::{json, to_string_pretty};
+use serde_json::{json, to_string_pretty, Value};
 
 #[derive(Subcommand, Debug)]
 pub enum UserCommands {
-    // Create {
-    //     #[clap(short, long, default_value = "World")]
-    //     name: String,
-    // },
+    #[clap(about = "Create a user", aliases = &["c"])]
+    Create {
+        username: String,
+        #[clap(short, long)]
+        email: Option<String>,
+        /// Read the password from stdin, instead of generating one
+        #[clap(long, conflicts_with = "no_password")]
+        password_stdin: bool,
+        /// Create the user without a password, e.g. for forward-auth
+        #[clap(long)]
+        no_password: bool,
+    },
     #[clap(about = "Delete a user", aliases = &["d"])]
     Delete { id: String },
     #[clap(about = "List users", aliases = &["l", "ls"])]
@@ -23,12 +41,120 @@ pub enum UserCommands {
         #[clap(short, long)]
         raw: bool,
     },
+    #[clap(about = "Set or reset the password of a user", aliases = &["pw", "passwd"])]
+    SetPassword {
+        /// User ID or username
+        user: String,
+        /// Read the password from stdin, instead of generating one
+        #[clap(long)]
+        password_stdin: bool,
+    },
+    #[clap(about = "Disable a user, ending their sessions", aliases = &["dis"])]
+    Disable {
+        /// User ID or username
+        user: String,
+    },
+    #[clap(about = "Enable a disabled user", aliases = &["en"])]
+    Enable {
+        /// User ID or username
+        user: String,
+    },
+    #[clap(about = "Set or clear when a user expires", aliases = &["exp"])]
+    Expire {
+        /// User ID or username
+        user: String,
+        /// RFC 3339 time, like "2024-12-31T23:59:59Z"
+        #[clap(long, required_unless_present = "never", conflicts_with = "never")]
+        at: Option<String>,
+        /// Never expire
+        #[clap(long)]
+        never: bool,
+    },
+    #[clap(about = "Add a user to a permission group", aliases = &["ag"])]
+    AddGroup {
+        /// User ID or username
+        user: String,
+        /// Group ID or name
+        group: String,
+    },
+    #[clap(about = "Remove a user from a permission group", aliases = &["rg"])]
+    RemoveGroup {
+        /// User ID or username
+        user: String,
+        /// Group ID or name
+        group: String,
+    },
+    #[clap(about = "Show the groups and effective permissions of a user", aliases = &["p"])]
+    Perms {
+        /// User ID or username
+        user: String,
+        #[clap(short, long)]
+        raw: bool,
+    },
     #[clap(about = "Initialize the admin user")]
     InitAdmin,
 }
 
+/// Find a user by ID, or else by username.
+fn find_user(data: &Storage, user: &str) -> Result<User, String> {
+    let found = match get_user_by_id(data, user) {
+        Ok(Some(found)) => Some(found),
+        Ok(None) => {
+            get_user_by_username(data, user).map_err(|e| format!("Failed to get user: {}", e))?
+        }
+        Err(e) => return Err(format!("Failed to get user: {}", e)),
+    };
+    found.ok_or_else(|| format!("User not found: {}", user))
+}
+
+fn generate_password() -> String {
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(15)
+        .map(char::from)
+        .collect::<String>()
+}
+
+/// Read a password from the first line of stdin, so it does not end up in the shell history.
+fn read_password() -> Result<String, String> {
+    let mut password = String::new();
+    io::stdin()
+        .read_line(&mut password)
+        .map_err(|e| format!("Failed to read password: {}", e))?;
+    let password = password.trim_end_matches(['\r', '\n']).to_string();
+    // Same minimum as the API
+    if password.len() < 8 {
+        return Err("Password must be at least 8 characters".to_string());
+    }
+    Ok(password)
+}
+
 pub async fn cli_user(data: Storage, _salt_api: SaltAPI, cmd: UserCommands) -> Result<(), String> {
     match cmd {
+        UserCommands::Create {
+            username,
+            email,
+            password_stdin,
+            no_password,
+        } => {
+            if get_user_by_username(&data, &username)
+                .map_err(|e| format!("Failed to get user: {}", e))?
+                .is_some()
+            {
+                return Err(format!("Username is taken: {}", username));
+            }
+            let (password, generated) = match (no_password, password_stdin) {
+                (true, _) => (None, false),
+                (false, true) => (Some(read_password()?), false),
+                (false, false) => (Some(generate_password()), true),
+            };
+            let user = create_user(&data, username, password.clone(), email)
+                .map_err(|e| format!("Failed to create user: {}", e))?;
+            println!("Created user: {} ({})", user.username, user.id);
+            if let (true, Some(password)) = (generated, password) {
+                println!("\tPassword: {}", password);
+            }
+        }
         UserCommands::Delete { id } => {
             let user =
                 get_user_by_id(&data, &id).map_err(|e| format!("Failed to get user: {}", e))?;
@@ -49,15 +175,19 @@ pub async fn cli_user(data: Storage, _salt_api: SaltAPI, cmd: UserCommands) -> R
                 println!("{}", to_string_pretty(&users).unwrap());
             } else {
                 println!(
-                    "{0: <42} {1: <22} {2: <14} {3: <22} {4: <6}",
-                    "ID", "Username", "Has Password", "Last Login", "Email"
+                    "{0: <42} {1: <22} {2: <14} {3: <9} {4: <22} {5: <22} {6: <6}",
+                    "ID", "Username", "Has Password", "Disabled", "Expires", "Last Login", "Email"
                 );
                 for user in users {
                     println!(
-                        "{0: <42} {1: <22} {2: <14} {3: <22} {4: <6}",
+                        "{0: <42} {1: <22} {2: <14} {3: <9} {4: <22} {5: <22} {6: <6}",
                         user.id,
                         user.username,
                         user.password.is_some(),
+                        user.disabled,
+                        user.expires_at
+                            .map(|d| d.to_string())
+                            .unwrap_or("Never".to_string()),
                         user.last_login
                             .map(|d| d.to_string())
                             .unwrap_or("None".to_string()),
@@ -66,6 +196,108 @@ pub async fn cli_user(data: Storage, _salt_api: SaltAPI, cmd: UserCommands) -> R
                 }
             }
         }
+        UserCommands::SetPassword {
+            user,
+            password_stdin,
+        } => {
+            let mut user = find_user(&data, &user)?;
+            let password = match password_stdin {
+                true => read_password()?,
+                false => generate_password(),
+            };
+            user.password = Some(hash_password(&password));
+            update_user(&data, &user).map_err(|e| format!("Failed to update user: {}", e))?;
+            println!("Set password of user: {}", user.username);
+            if !password_stdin {
+                println!("\tPassword: {}", password);
+            }
+        }
+        UserCommands::Disable { user } => {
+            let mut user = find_user(&data, &user)?;
+            let expires_at = user.expires_at;
+            set_user_access(&data, &mut user, true, expires_at)
+                .map_err(|e| format!("Failed to update user: {}", e))?;
+            println!("Disabled user: {}", user.username);
+        }
+        UserCommands::Enable { user } => {
+            let mut user = find_user(&data, &user)?;
+            let expires_at = user.expires_at;
+            set_user_access(&data, &mut user, false, expires_at)
+                .map_err(|e| format!("Failed to update user: {}", e))?;
+            println!("Enabled user: {}", user.username);
+            if !user.is_active() {
+                warn!(
+                    "User has expired, clear it with: user expire {} --never",
+                    user.username
+                );
+            }
+        }
+        UserCommands::Expire { user, at, never: _ } => {
+            let mut user = find_user(&data, &user)?;
+            let expires_at = match at {
+                Some(at) => Some(
+                    ResaltTime::parse_from_rfc3339(&at)
+                        .map_err(|e| format!("Invalid time {}: {}", at, e))?,
+                ),
+                None => None,
+            };
+            let disabled = user.disabled;
+            set_user_access(&data, &mut user, disabled, expires_at)
+                .map_err(|e| format!("Failed to update user: {}", e))?;
+            match expires_at {
+                Some(expires_at) => println!("User {} expires at {}", user.username, expires_at),
+                None => println!("User {} never expires", user.username),
+            }
+        }
+        UserCommands::AddGroup { user, group } => {
+            let user = find_user(&data, &user)?;
+            let group = find_group(&data, &group)?;
+            if is_user_member_of_group(&data, &user.id, &group.id)
+                .map_err(|e| format!("Failed to get membership: {}", e))?
+            {
+                return Err(format!("{} is already in {}", user.username, group.name));
+            }
+            add_user_to_group(&data, &user.id, &group.id)
+                .map_err(|e| format!("Failed to add user to group: {}", e))?;
+            println!("Added {} to {}", user.username, group.name);
+        }
+        UserCommands::RemoveGroup { user, group } => {
+            let user = find_user(&data, &user)?;
+            let group = find_group(&data, &group)?;
+            if !is_user_member_of_group(&data, &user.id, &group.id)
+                .map_err(|e| format!("Failed to get membership: {}", e))?
+            {
+                return Err(format!("{} is not in {}", user.username, group.name));
+            }
+            remove_user_from_group(&data, &user.id, &group.id)
+                .map_err(|e| format!("Failed to remove user from group: {}", e))?;
+            println!("Removed {} from {}", user.username, group.name);
+        }
+        UserCommands::Perms { user, raw } => {
+            let user = find_user(&data, &user)?;
+            let groups = get_permission_groups_by_user_id(&data, &user.id)
+                .map_err(|e| format!("Failed to get groups: {}", e))?;
+            let preferences = get_preferences(&data, &user.id)
+                .map_err(|e| format!("Failed to get preferences: {}", e))?
+                .unwrap_or_default();
+            let user = user.public(groups, preferences);
+            if raw {
+                println!("{}", to_string_pretty(&user).unwrap());
+            } else {
+                println!("{0: <42} {1: <22}", "Group ID", "Group");
+                for group in &user.permission_groups {
+                    println!("{0: <42} {1: <22}", group.id, group.name);
+                }
+                println!();
+                println!("Effective permissions:");
+                for perm in user.perms.as_array().into_iter().flatten() {
+                    match perm {
+                        Value::String(perm) => println!("\t{}", perm),
+                        perm => println!("\t{}", perm),
+                    }
+                }
+            }
+        }
         UserCommands::InitAdmin => {
             // Check if "admin" user exists
             match get_user_by_username(&data, "admin") {
@@ -93,11 +325,7 @@ pub async fn cli_user(data: Storage, _salt_api: SaltAPI, cmd: UserCommands) -> R
             let group_id = create_permission_group(&data, None, "$superadmins", Some(perms))
                 .map_err(|e| format!("Failed to create group: {}", e))?;
             // Create Admin user
-            let random_password = rand::thread_rng()
-                .sample_iter(&rand::distributions::Alphanumeric)
-                .take(15)
-                .map(|c| c.to_string())
-                .collect::<String>();
+            let random_password = generate_password();
             let user = create_user(
                 &data,
                 ("admin").to_string(),
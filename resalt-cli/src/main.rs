@@ -14,6 +14,17 @@ async fn run() -> Result<(), String> {
     // Logging
     init_from_env(Env::new().default_filter_or("Error"));
 
+    // Config validation, the OpenAPI document, remote mode and migrations must not depend on a working database
+    match cli.subcmd {
+        Commands::Config {
+            subcmd: Some(ConfigCommands::Validate),
+        } => return cli_config_validate(),
+        Commands::Openapi => return cli_openapi(),
+        Commands::Remote { subcmd } => return cli_remote(subcmd).await,
+        Commands::Storage { subcmd } => return cli_storage(subcmd).await,
+        _ => {}
+    }
+
     // Database
     let data: Storage = Storage::init_db().await;
 
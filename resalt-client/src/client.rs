@@ -0,0 +1,719 @@
+use std::collections::HashMap;
+
+use reqwest::{header::COOKIE, Client, Method, RequestBuilder, Response};
+use resalt_models::*;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use urlencoding::encode;
+
+use crate::{
+    ApiConfig, JobGetResponse, JobRunRequest, JobRunResult, LoginResponse, MinionsFilter,
+    ResaltClientError,
+};
+
+/// Client for the Resalt HTTP API.
+///
+/// Authenticate either with [`ResaltClient::login`], or with an existing token
+/// using [`ResaltClient::with_token`].
+#[derive(Clone, Debug)]
+pub struct ResaltClient {
+    url: String,
+    token: Option<String>,
+    client: Client,
+}
+
+impl ResaltClient {
+    /// `url` is where Resalt is served, e.g. `https://resalt.example.com`.
+    pub fn new(url: &str) -> Self {
+        ResaltClient::with_client(url, Client::new())
+    }
+
+    /// Use a preconfigured `reqwest` client, e.g. with a custom CA.
+    pub fn with_client(url: &str, client: Client) -> Self {
+        ResaltClient {
+            url: url.trim_end_matches('/').to_string(),
+            token: None,
+            client,
+        }
+    }
+
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .client
+            .request(method, format!("{}/api{}", self.url, path));
+        match &self.token {
+            Some(token) => request.header(COOKIE, format!("resalt-auth={}", token)),
+            None => request,
+        }
+    }
+
+    async fn send(request: RequestBuilder) -> Result<Response, ResaltClientError> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| ResaltClientError::RequestError(e.to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(ResaltClientError::from_status(status.as_u16(), body))
+    }
+
+    async fn json<T: DeserializeOwned>(request: RequestBuilder) -> Result<T, ResaltClientError> {
+        ResaltClient::send(request)
+            .await?
+            .json::<T>()
+            .await
+            .map_err(|e| ResaltClientError::ResponseParseError(e.to_string()))
+    }
+
+    async fn text(request: RequestBuilder) -> Result<String, ResaltClientError> {
+        ResaltClient::send(request)
+            .await?
+            .text()
+            .await
+            .map_err(|e| ResaltClientError::ResponseParseError(e.to_string()))
+    }
+
+    async fn empty(request: RequestBuilder) -> Result<(), ResaltClientError> {
+        ResaltClient::send(request).await.map(|_| ())
+    }
+
+    /*
+    ===================
+    =    NO AUTH      =
+    ===================
+    */
+
+    pub async fn config(&self) -> Result<ApiConfig, ResaltClientError> {
+        ResaltClient::json(self.request(Method::GET, "/config")).await
+    }
+
+    /// Log in, and use the returned token for all following requests.
+    pub async fn login(
+        &mut self,
+        username: &str,
+        password: &str,
+    ) -> Result<LoginResponse, ResaltClientError> {
+        let request = self.request(Method::POST, "/login").json(&json!({
+            "username": username,
+            "password": password,
+        }));
+        let response: LoginResponse = ResaltClient::json(request).await?;
+        self.token = Some(response.token.clone());
+        Ok(response)
+    }
+
+    pub async fn logout(&mut self) -> Result<(), ResaltClientError> {
+        ResaltClient::empty(self.request(Method::POST, "/logout")).await?;
+        self.token = None;
+        Ok(())
+    }
+
+    /// Prometheus metrics, if enabled on the server.
+    pub async fn metrics(&self) -> Result<String, ResaltClientError> {
+        ResaltClient::text(self.request(Method::GET, "/metrics")).await
+    }
+
+    pub async fn openapi(&self) -> Result<Value, ResaltClientError> {
+        ResaltClient::json(self.request(Method::GET, "/openapi.json")).await
+    }
+
+    /*
+    ===================
+    =     GENERAL     =
+    ===================
+    */
+
+    pub async fn myself(&self) -> Result<PublicUser, ResaltClientError> {
+        ResaltClient::json(self.request(Method::GET, "/myself")).await
+    }
+
+    pub async fn status(&self) -> Result<SystemStatus, ResaltClientError> {
+        ResaltClient::json(self.request(Method::GET, "/status")).await
+    }
+
+    pub async fn events(&self, paginate: Paginate) -> Result<Vec<Event>, ResaltClientError> {
+        let request = self
+            .request(Method::GET, "/events")
+            .query(&paginate_params(paginate));
+        ResaltClient::json(request).await
+    }
+
+    /*
+    ===================
+    =     MINIONS     =
+    ===================
+    */
+
+    pub async fn minions(
+        &self,
+        filter: &MinionsFilter,
+        sort: Option<MinionSort>,
+        paginate: Paginate,
+    ) -> Result<Vec<Minion>, ResaltClientError> {
+        let mut params = filter_params(filter)?;
+        params.extend(sort.map(|sort| ("sort", param_value(&sort))));
+        params.extend(paginate_params(paginate));
+        let request = self.request(Method::GET, "/minions").query(&params);
+        ResaltClient::json(request).await
+    }
+
+    /// Export minions as CSV or NDJSON. The default columns are used if `columns` is empty.
+    pub async fn minions_export(
+        &self,
+        filter: &MinionsFilter,
+        sort: Option<MinionSort>,
+        columns: &[&str],
+        format: ExportFormat,
+    ) -> Result<String, ResaltClientError> {
+        let mut params = filter_params(filter)?;
+        params.extend(sort.map(|sort| ("sort", param_value(&sort))));
+        if !columns.is_empty() {
+            params.push(("columns", encode(&json!(columns).to_string()).into_owned()));
+        }
+        params.push(("format", format.extension().to_string()));
+        let request = self.request(Method::GET, "/minions/export").query(&params);
+        ResaltClient::text(request).await
+    }
+
+    pub async fn minion(&self, minion_id: &str) -> Result<Minion, ResaltClientError> {
+        let path = format!("/minions/{}", encode(minion_id));
+        ResaltClient::json(self.request(Method::GET, &path)).await
+    }
+
+    /// Ask the minion to send its grains, pillars and packages again.
+    pub async fn refresh_minion(&self, minion_id: &str) -> Result<(), ResaltClientError> {
+        let path = format!("/minions/{}/refresh", encode(minion_id));
+        ResaltClient::empty(self.request(Method::POST, &path)).await
+    }
+
+    pub async fn minion_availability(
+        &self,
+        minion_id: &str,
+        paginate: Paginate,
+    ) -> Result<Vec<MinionStatusTransition>, ResaltClientError> {
+        let path = format!("/minions/{}/availability", encode(minion_id));
+        let request = self
+            .request(Method::GET, &path)
+            .query(&paginate_params(paginate));
+        ResaltClient::json(request).await
+    }
+
+    pub async fn minion_history(
+        &self,
+        minion_id: &str,
+        kind: MinionSnapshotKind,
+        since: Option<ResaltTime>,
+        paginate: Paginate,
+    ) -> Result<Vec<MinionHistoryEntry>, ResaltClientError> {
+        let path = format!("/minions/{}/history/{}", encode(minion_id), kind);
+        let mut params = paginate_params(paginate);
+        params.extend(since.map(|since| ("since", since.to_string())));
+        let request = self.request(Method::GET, &path).query(&params);
+        ResaltClient::json(request).await
+    }
+
+    /// Value of the grain at `path` (e.g. `os` or `$.ip4_interfaces.eth0`) per minion.
+    pub async fn grains(
+        &self,
+        path: &str,
+        filter: &MinionsFilter,
+    ) -> Result<HashMap<String, Value>, ResaltClientError> {
+        let mut params = filter_params(filter)?;
+        params.push(("query", encode(path).into_owned()));
+        let request = self.request(Method::GET, "/grains").query(&params);
+        ResaltClient::json(request).await
+    }
+
+    pub async fn grains_export(
+        &self,
+        path: &str,
+        filter: &MinionsFilter,
+        format: ExportFormat,
+    ) -> Result<String, ResaltClientError> {
+        let mut params = filter_params(filter)?;
+        params.push(("query", encode(path).into_owned()));
+        params.push(("format", format.extension().to_string()));
+        let request = self.request(Method::GET, "/grains/export").query(&params);
+        ResaltClient::text(request).await
+    }
+
+    pub async fn packages(&self) -> Result<Vec<Package>, ResaltClientError> {
+        ResaltClient::json(self.request(Method::GET, "/packages")).await
+    }
+
+    /// A package, optionally only with the versions matching a constraint such as `<3.0.13`.
+    pub async fn package(
+        &self,
+        name: &str,
+        version: Option<&str>,
+    ) -> Result<Package, ResaltClientError> {
+        let path = format!("/packages/{}", encode(name));
+        let params: Vec<(&str, &str)> = version.map(|v| ("version", v)).into_iter().collect();
+        let request = self.request(Method::GET, &path).query(&params);
+        ResaltClient::json(request).await
+    }
+
+    /*
+    ===================
+    =     PRESETS     =
+    ===================
+    */
+
+    pub async fn presets(&self) -> Result<Vec<MinionPreset>, ResaltClientError> {
+        ResaltClient::json(self.request(Method::GET, "/presets")).await
+    }
+
+    pub async fn preset(&self, preset_id: &str) -> Result<MinionPreset, ResaltClientError> {
+        let path = format!("/presets/{}", encode(preset_id));
+        ResaltClient::json(self.request(Method::GET, &path)).await
+    }
+
+    pub async fn create_preset(
+        &self,
+        name: &str,
+        filters: &[FilterNode],
+    ) -> Result<MinionPreset, ResaltClientError> {
+        let request = self
+            .request(Method::POST, "/presets")
+            .json(&preset_body(name, filters)?);
+        ResaltClient::json(request).await
+    }
+
+    pub async fn update_preset(
+        &self,
+        preset_id: &str,
+        name: &str,
+        filters: &[FilterNode],
+    ) -> Result<MinionPreset, ResaltClientError> {
+        let path = format!("/presets/{}", encode(preset_id));
+        let request = self
+            .request(Method::PUT, &path)
+            .json(&preset_body(name, filters)?);
+        ResaltClient::json(request).await
+    }
+
+    /// Delete a preset, returning it if it existed.
+    pub async fn delete_preset(
+        &self,
+        preset_id: &str,
+    ) -> Result<Option<MinionPreset>, ResaltClientError> {
+        let path = format!("/presets/{}", encode(preset_id));
+        ResaltClient::json(self.request(Method::DELETE, &path)).await
+    }
+
+    /// Render presets as Salt nodegroups, all presets if `preset_ids` is empty.
+    pub async fn nodegroups(
+        &self,
+        preset_ids: &[&str],
+    ) -> Result<Vec<Nodegroup>, ResaltClientError> {
+        let mut params = vec![("format", "json".to_string())];
+        if !preset_ids.is_empty() {
+            params.push(("ids", preset_ids.join(",")));
+        }
+        let request = self
+            .request(Method::GET, "/presets/nodegroups")
+            .query(&params);
+        ResaltClient::json(request).await
+    }
+
+    /*
+    ===================
+    =      JOBS       =
+    ===================
+    */
+
+    pub async fn jobs(
+        &self,
+        sort: Option<JobSort>,
+        paginate: Paginate,
+    ) -> Result<Vec<Job>, ResaltClientError> {
+        let mut params = paginate_params(paginate);
+        params.extend(sort.map(|sort| ("sort", param_value(&sort))));
+        let request = self.request(Method::GET, "/jobs").query(&params);
+        ResaltClient::json(request).await
+    }
+
+    pub async fn job(&self, jid: &str) -> Result<JobGetResponse, ResaltClientError> {
+        let path = format!("/jobs/{}", encode(jid));
+        ResaltClient::json(self.request(Method::GET, &path)).await
+    }
+
+    /// Run a job, returning the result on each master it ran on, keyed by master name.
+    /// Fails with `ServerError(502)` if no master accepted the job.
+    pub async fn run_job(
+        &self,
+        job: &JobRunRequest,
+    ) -> Result<HashMap<String, JobRunResult>, ResaltClientError> {
+        let request = self
+            .request(Method::POST, "/jobs")
+            .query(&[("perMaster", "true")])
+            .json(job);
+        ResaltClient::json(request).await
+    }
+
+    /*
+    ===================
+    =      KEYS       =
+    ===================
+    */
+
+    pub async fn keys(&self) -> Result<Vec<SaltMinionKey>, ResaltClientError> {
+        ResaltClient::json(self.request(Method::GET, "/keys")).await
+    }
+
+    fn key_request(&self, method: Method, key: &SaltMinionKey, action: &str) -> RequestBuilder {
+        let path = format!("/keys/{}/{}/{}", key.state, encode(&key.id), action);
+        let params: Vec<(&str, &str)> = match key.master.is_empty() {
+            true => vec![],
+            false => vec![("master", &key.master)],
+        };
+        self.request(method, &path).query(&params)
+    }
+
+    pub async fn accept_key(&self, key: &SaltMinionKey) -> Result<(), ResaltClientError> {
+        ResaltClient::empty(self.key_request(Method::PUT, key, "accept")).await
+    }
+
+    pub async fn reject_key(&self, key: &SaltMinionKey) -> Result<(), ResaltClientError> {
+        ResaltClient::empty(self.key_request(Method::PUT, key, "reject")).await
+    }
+
+    pub async fn delete_key(&self, key: &SaltMinionKey) -> Result<(), ResaltClientError> {
+        ResaltClient::empty(self.key_request(Method::DELETE, key, "delete")).await
+    }
+
+    /*
+    ===================
+    =      USERS      =
+    ===================
+    */
+
+    pub async fn users(&self, paginate: Paginate) -> Result<Vec<PublicUser>, ResaltClientError> {
+        let request = self
+            .request(Method::GET, "/users")
+            .query(&paginate_params(paginate));
+        ResaltClient::json(request).await
+    }
+
+    pub async fn user(&self, user_id: &str) -> Result<PublicUser, ResaltClientError> {
+        let path = format!("/users/{}", encode(user_id));
+        ResaltClient::json(self.request(Method::GET, &path)).await
+    }
+
+    pub async fn create_user(
+        &self,
+        username: &str,
+        email: Option<&str>,
+    ) -> Result<PublicUser, ResaltClientError> {
+        let request = self.request(Method::POST, "/users").json(&json!({
+            "username": username,
+            "email": email,
+        }));
+        ResaltClient::json(request).await
+    }
+
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), ResaltClientError> {
+        let path = format!("/users/{}", encode(user_id));
+        ResaltClient::empty(self.request(Method::DELETE, &path)).await
+    }
+
+    pub async fn set_user_password(
+        &self,
+        user_id: &str,
+        password: &str,
+    ) -> Result<(), ResaltClientError> {
+        let path = format!("/users/{}/password", encode(user_id));
+        let request = self
+            .request(Method::POST, &path)
+            .json(&json!({ "password": password }));
+        ResaltClient::empty(request).await
+    }
+
+    /// Disable or enable a user, and set when they expire.
+    pub async fn set_user_access(
+        &self,
+        user_id: &str,
+        disabled: bool,
+        expires_at: Option<ResaltTime>,
+    ) -> Result<PublicUser, ResaltClientError> {
+        let path = format!("/users/{}/access", encode(user_id));
+        let request = self.request(Method::POST, &path).json(&json!({
+            "disabled": disabled,
+            "expiresAt": expires_at,
+        }));
+        ResaltClient::json(request).await
+    }
+
+    pub async fn set_user_preferences(
+        &self,
+        user_id: &str,
+        preferences: &Preferences,
+    ) -> Result<(), ResaltClientError> {
+        let path = format!("/users/{}/preferences", encode(user_id));
+        let request = self.request(Method::POST, &path).json(preferences);
+        ResaltClient::empty(request).await
+    }
+
+    pub async fn add_user_to_group(
+        &self,
+        user_id: &str,
+        group_id: &str,
+    ) -> Result<(), ResaltClientError> {
+        let path = format!(
+            "/users/{}/permissions/{}",
+            encode(user_id),
+            encode(group_id)
+        );
+        ResaltClient::empty(self.request(Method::POST, &path)).await
+    }
+
+    /// Add a user to a group for `duration` seconds, e.g. for an incident.
+    pub async fn grant_user_group(
+        &self,
+        user_id: &str,
+        group_id: &str,
+        duration: i64,
+        reason: &str,
+    ) -> Result<PermissionGroupGrant, ResaltClientError> {
+        let path = format!(
+            "/users/{}/permissions/{}/grant",
+            encode(user_id),
+            encode(group_id)
+        );
+        let request = self.request(Method::POST, &path).json(&json!({
+            "duration": duration,
+            "reason": reason,
+        }));
+        ResaltClient::json(request).await
+    }
+
+    pub async fn remove_user_from_group(
+        &self,
+        user_id: &str,
+        group_id: &str,
+    ) -> Result<(), ResaltClientError> {
+        let path = format!(
+            "/users/{}/permissions/{}",
+            encode(user_id),
+            encode(group_id)
+        );
+        ResaltClient::empty(self.request(Method::DELETE, &path)).await
+    }
+
+    /*
+    ===================
+    =     GROUPS      =
+    ===================
+    */
+
+    pub async fn groups(
+        &self,
+        paginate: Paginate,
+    ) -> Result<Vec<PublicPermissionGroup>, ResaltClientError> {
+        let request = self
+            .request(Method::GET, "/permissions")
+            .query(&paginate_params(paginate));
+        ResaltClient::json(request).await
+    }
+
+    /// Temporary memberships which have not expired, soonest expiring first.
+    pub async fn group_grants(&self) -> Result<Vec<PermissionGroupGrant>, ResaltClientError> {
+        ResaltClient::json(self.request(Method::GET, "/permissions/grants")).await
+    }
+
+    /// Changes to group memberships, newest first.
+    pub async fn audit_records(
+        &self,
+        paginate: Paginate,
+    ) -> Result<Vec<AuditRecord>, ResaltClientError> {
+        let request = self
+            .request(Method::GET, "/permissions/audit")
+            .query(&paginate_params(paginate));
+        ResaltClient::json(request).await
+    }
+
+    pub async fn group(&self, group_id: &str) -> Result<PublicPermissionGroup, ResaltClientError> {
+        let path = format!("/permissions/{}", encode(group_id));
+        ResaltClient::json(self.request(Method::GET, &path)).await
+    }
+
+    pub async fn create_group(
+        &self,
+        name: &str,
+    ) -> Result<PublicPermissionGroup, ResaltClientError> {
+        let request = self
+            .request(Method::POST, "/permissions")
+            .json(&json!({ "name": name }));
+        ResaltClient::json(request).await
+    }
+
+    /// Rename a group and replace its permissions, in the Salt eauth format.
+    pub async fn update_group(
+        &self,
+        group_id: &str,
+        name: &str,
+        perms: &Value,
+    ) -> Result<PublicPermissionGroup, ResaltClientError> {
+        let path = format!("/permissions/{}", encode(group_id));
+        let request = self.request(Method::PUT, &path).json(&json!({
+            "name": name,
+            "perms": perms.to_string(),
+        }));
+        ResaltClient::json(request).await
+    }
+
+    pub async fn delete_group(
+        &self,
+        group_id: &str,
+    ) -> Result<PublicPermissionGroup, ResaltClientError> {
+        let path = format!("/permissions/{}", encode(group_id));
+        ResaltClient::json(self.request(Method::DELETE, &path)).await
+    }
+
+    /// Include a group in another, so its members inherit the permissions of both.
+    pub async fn add_subgroup(
+        &self,
+        group_id: &str,
+        subgroup_id: &str,
+    ) -> Result<PublicPermissionGroup, ResaltClientError> {
+        let path = format!(
+            "/permissions/{}/subgroups/{}",
+            encode(group_id),
+            encode(subgroup_id)
+        );
+        ResaltClient::json(self.request(Method::POST, &path)).await
+    }
+
+    pub async fn remove_subgroup(
+        &self,
+        group_id: &str,
+        subgroup_id: &str,
+    ) -> Result<PublicPermissionGroup, ResaltClientError> {
+        let path = format!(
+            "/permissions/{}/subgroups/{}",
+            encode(group_id),
+            encode(subgroup_id)
+        );
+        ResaltClient::json(self.request(Method::DELETE, &path)).await
+    }
+
+    /*
+    ===================
+    =    SETTINGS     =
+    ===================
+    */
+
+    /// Backup of users, groups, minions and presets. Pillar secrets are redacted
+    /// unless `include_secrets` is set and the user may see them.
+    pub async fn export_settings(
+        &self,
+        include_secrets: bool,
+    ) -> Result<DataDump, ResaltClientError> {
+        let request = self
+            .request(Method::GET, "/settings/export")
+            .query(&[("includeSecrets", include_secrets)]);
+        ResaltClient::json(request).await
+    }
+
+    /// Import a backup. On a `dry_run` nothing is written, only summarized.
+    pub async fn import_settings(
+        &self,
+        dump: &DataDump,
+        dry_run: bool,
+    ) -> Result<DataDumpImportSummary, ResaltClientError> {
+        let request = self
+            .request(Method::POST, "/settings/import")
+            .query(&[("dryRun", dry_run)])
+            .json(dump);
+        ResaltClient::json(request).await
+    }
+}
+
+/// Serialize an enum such as `MinionSort` to its query parameter value.
+fn param_value<T: Serialize>(value: &T) -> String {
+    strip_quotes(serde_json::to_string(value).unwrap_or_default())
+}
+
+fn paginate_params(paginate: Paginate) -> Vec<(&'static str, String)> {
+    match paginate {
+        Some((limit, offset)) => vec![("limit", limit.to_string()), ("offset", offset.to_string())],
+        None => vec![],
+    }
+}
+
+/// The server URL-decodes the filter JSON itself, on top of the query string decoding.
+fn filter_params(filter: &MinionsFilter) -> Result<Vec<(&'static str, String)>, ResaltClientError> {
+    let mut params = Vec::new();
+    if !filter.filters.is_empty() {
+        let filters = serde_json::to_string(&filter.filters)
+            .map_err(|e| ResaltClientError::RequestError(e.to_string()))?;
+        params.push(("filter", encode(&filters).into_owned()));
+    }
+    if let Some(query) = &filter.query {
+        params.push(("q", query.clone()));
+    }
+    Ok(params)
+}
+
+fn preset_body(name: &str, filters: &[FilterNode]) -> Result<Value, ResaltClientError> {
+    let filter = serde_json::to_string(filters)
+        .map_err(|e| ResaltClientError::RequestError(e.to_string()))?;
+    Ok(json!({
+        "name": name,
+        "filter": filter,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_params() {
+        assert_eq!(param_value(&MinionSort::LastSeenDesc), "lastSeen.desc");
+        assert_eq!(
+            paginate_params(Some((10, 20))),
+            vec![("limit", "10".to_string()), ("offset", "20".to_string())]
+        );
+        assert!(paginate_params(None).is_empty());
+
+        let filter = MinionsFilter {
+            filters: serde_json::from_str(
+                r#"[{"fieldType": "object", "field": "id", "operand": "e", "value": "web 1"}]"#,
+            )
+            .unwrap(),
+            query: Some("os:Ubuntu".to_string()),
+        };
+        let params = filter_params(&filter).unwrap();
+        assert_eq!(params[0].0, "filter");
+        assert!(params[0].1.starts_with("%5B%7B%22fieldType%22"));
+        assert_eq!(params[1], ("q", "os:Ubuntu".to_string()));
+        assert!(filter_params(&MinionsFilter::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_request_url() {
+        let client = ResaltClient::new("https://resalt.local/").with_token("abc");
+        let request = client
+            .request(Method::GET, "/minions/web%201")
+            .build()
+            .unwrap();
+        assert_eq!(
+            request.url().as_str(),
+            "https://resalt.local/api/minions/web%201"
+        );
+        assert_eq!(request.headers()[COOKIE], "resalt-auth=abc");
+    }
+}
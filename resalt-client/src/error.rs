@@ -0,0 +1,87 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ResaltClientError {
+    /// 400, with the response body, e.g. where a text query failed to parse
+    BadRequest(String),
+    Unauthorized, // 401
+    Forbidden,    // 403
+    NotFound,     // 404
+    ServerError(u16),
+    UnexpectedStatus(u16),
+    RequestError(String),
+    ResponseParseError(String),
+}
+
+impl ResaltClientError {
+    /// Map a non-successful HTTP status to an error.
+    pub fn from_status(status: u16, body: String) -> Self {
+        match status {
+            400 => ResaltClientError::BadRequest(body),
+            401 => ResaltClientError::Unauthorized,
+            403 => ResaltClientError::Forbidden,
+            404 => ResaltClientError::NotFound,
+            500..=599 => ResaltClientError::ServerError(status),
+            _ => ResaltClientError::UnexpectedStatus(status),
+        }
+    }
+}
+
+impl fmt::Display for ResaltClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResaltClientError::BadRequest(body) if body.is_empty() => {
+                write!(f, "Bad request")
+            }
+            ResaltClientError::BadRequest(body) => write!(f, "Bad request: {}", body),
+            ResaltClientError::Unauthorized => write!(f, "Unauthorized by Resalt"),
+            ResaltClientError::Forbidden => write!(f, "Missing permission"),
+            ResaltClientError::NotFound => write!(f, "Not found"),
+            ResaltClientError::ServerError(status) => {
+                write!(f, "Resalt server error (HTTP {})", status)
+            }
+            ResaltClientError::UnexpectedStatus(status) => {
+                write!(f, "Unexpected response from Resalt (HTTP {})", status)
+            }
+            ResaltClientError::RequestError(e) => write!(f, "Resalt request error: {}", e),
+            ResaltClientError::ResponseParseError(e) => {
+                write!(f, "Failed parsing Resalt response: {}", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResaltClientError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status() {
+        assert!(matches!(
+            ResaltClientError::from_status(400, "bad filter".to_string()),
+            ResaltClientError::BadRequest(body) if body == "bad filter"
+        ));
+        assert!(matches!(
+            ResaltClientError::from_status(401, String::new()),
+            ResaltClientError::Unauthorized
+        ));
+        assert!(matches!(
+            ResaltClientError::from_status(403, String::new()),
+            ResaltClientError::Forbidden
+        ));
+        assert!(matches!(
+            ResaltClientError::from_status(404, String::new()),
+            ResaltClientError::NotFound
+        ));
+        assert!(matches!(
+            ResaltClientError::from_status(502, String::new()),
+            ResaltClientError::ServerError(502)
+        ));
+        assert!(matches!(
+            ResaltClientError::from_status(418, String::new()),
+            ResaltClientError::UnexpectedStatus(418)
+        ));
+    }
+}
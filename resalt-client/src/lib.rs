@@ -0,0 +1,7 @@
+mod client;
+mod error;
+mod model;
+
+pub use client::*;
+pub use error::*;
+pub use model::*;
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use resalt_models::{FilterNode, Job, JobReturn, SaltClientType, SaltTgtType};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct ApiConfig {
+    #[serde(rename = "authForwardEnabled")]
+    pub auth_forward_enabled: bool,
+    #[serde(rename = "currentVersion")]
+    pub current_version: String,
+    #[serde(rename = "latestVersion")]
+    pub latest_version: String,
+    #[serde(rename = "latestNews")]
+    pub latest_news: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct LoginResponse {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    pub token: String,
+    /// Unix timestamp at which the token expires.
+    pub expiry: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct JobGetResponse {
+    pub job: Job,
+    pub returns: Vec<JobReturn>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct JobRunRequest {
+    pub client: SaltClientType,
+    #[serde(rename = "tgtType")]
+    pub tgt_type: SaltTgtType,
+    pub tgt: String,
+    /// Target the minions currently matching this preset, instead of `tgt`.
+    #[serde(rename = "presetId", skip_serializing_if = "Option::is_none")]
+    pub preset_id: Option<String>,
+    pub fun: String,
+    pub arg: Vec<Value>,
+    pub kwarg: HashMap<String, String>,
+    #[serde(rename = "batchSize")]
+    pub batch_size: String,
+}
+
+/// Result of a job on one master: either the Salt API result, or why running it failed.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct JobRunResult {
+    pub job: Option<Value>,
+    pub error: Option<String>,
+}
+
+/// Which minions to list, search grains of, or export.
+///
+/// Both `filters` and the text `query` must match if both are set.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MinionsFilter {
+    pub filters: Vec<FilterNode>,
+    /// Text query, e.g. `os:Ubuntu and not pkg.nginx`.
+    pub query: Option<String>,
+}
+
+impl From<Vec<FilterNode>> for MinionsFilter {
+    fn from(filters: Vec<FilterNode>) -> Self {
+        MinionsFilter {
+            filters,
+            query: None,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use toml::Value;
+
+/// Env variable pointing at the TOML config file.
+pub(crate) const CONFIG_PATH_ENV: &str = "RESALT_CONFIG_PATH";
+/// Config file read when `RESALT_CONFIG_PATH` is not set, if it exists.
+pub(crate) const CONFIG_PATH_DEFAULT: &str = "/etc/resalt/resalt.toml";
+
+/// The config file, loaded once. `Err` holds a read or parse error.
+pub(crate) static CONFIG_FILE: Lazy<Result<Option<ConfigFile>, String>> =
+    Lazy::new(ConfigFile::load);
+
+/// A parsed config file, with nested tables flattened to dotted keys.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ConfigFile {
+    values: BTreeMap<String, Value>,
+}
+
+impl ConfigFile {
+    fn load() -> Result<Option<ConfigFile>, String> {
+        let path = match std::env::var(CONFIG_PATH_ENV)
+            .ok()
+            .map(|path| crate::strip_quotes(&path))
+            .filter(|path| !path.is_empty())
+        {
+            Some(path) => path,
+            None if std::path::Path::new(CONFIG_PATH_DEFAULT).exists() => {
+                CONFIG_PATH_DEFAULT.to_string()
+            }
+            None => return Ok(None),
+        };
+        let data = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Error reading config file \"{}\": {}", path, e))?;
+        ConfigFile::parse(&data)
+            .map(Some)
+            .map_err(|e| format!("Error parsing config file \"{}\": {}", path, e))
+    }
+
+    pub(crate) fn parse(data: &str) -> Result<ConfigFile, String> {
+        let table = toml::from_str::<toml::Table>(data).map_err(|e| e.message().to_string())?;
+        let mut values = BTreeMap::new();
+        flatten("", table, &mut values);
+        Ok(ConfigFile { values })
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(|key| key.as_str())
+    }
+}
+
+fn flatten(prefix: &str, table: toml::Table, values: &mut BTreeMap<String, Value>) {
+    for (key, value) in table {
+        let key = match prefix.is_empty() {
+            true => key,
+            false => format!("{}.{}", prefix, key),
+        };
+        match value {
+            Value::Table(table) => flatten(&key, table, values),
+            value => {
+                values.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Convert a config file value to the string form used by env variables.
+/// Arrays are only accepted for list settings, joined by `separator`.
+pub(crate) fn value_to_string(value: &Value, separator: Option<char>) -> Result<String, String> {
+    match (value, separator) {
+        (Value::String(s), _) => Ok(s.clone()),
+        (Value::Integer(i), _) => Ok(i.to_string()),
+        (Value::Boolean(b), _) => Ok(b.to_string()),
+        (Value::Array(items), Some(separator)) => items
+            .iter()
+            .map(|item| match item {
+                Value::String(s) => Ok(s.clone()),
+                _ => Err("expected an array of strings".to_string()),
+            })
+            .collect::<Result<Vec<String>, String>>()
+            .map(|items| items.join(&separator.to_string())),
+        (value, _) => Err(format!("unsupported value type {}", value.type_str())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_flatten() {
+        let file = ConfigFile::parse(
+            r#"
+            [database]
+            type = "redis"
+            port = 6380
+
+            [pillar]
+            redact_keys = ["*password*", "*token*"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            file.keys().collect::<Vec<&str>>(),
+            vec!["database.port", "database.type", "pillar.redact_keys"]
+        );
+        let port = file.get("database.port").unwrap();
+        assert_eq!(value_to_string(port, None).unwrap(), "6380");
+        let keys = file.get("pillar.redact_keys").unwrap();
+        assert_eq!(
+            value_to_string(keys, Some(',')).unwrap(),
+            "*password*,*token*"
+        );
+        assert!(value_to_string(keys, None).is_err());
+        assert!(ConfigFile::parse("[database\n").is_err());
+    }
+}
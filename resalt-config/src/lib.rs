@@ -1,10 +1,36 @@
+//! Resalt configuration.
+//!
+//! Every setting can be given in several ways, in order of precedence:
+//!
+//! 1. `RESALT_<KEY>_FILE`, a path to a file containing the value (e.g. Docker secrets).
+//! 2. `RESALT_<KEY>`, an environment variable.
+//! 3. A TOML config file, read from `RESALT_CONFIG_PATH`, or `/etc/resalt/resalt.toml`
+//!    if that exists. Keys are grouped in tables, e.g. `RESALT_DATABASE_HOST` is
+//!    `host` in `[database]`. List settings may be given as TOML arrays.
+//! 4. The built-in default.
+//!
+//! Several Salt masters can be configured by listing their names in `RESALT_SALT_API_MASTERS`,
+//! and overriding the `salt_api` settings per master, see [`SaltMaster`].
+//!
+//! Call [`validate_config`] at startup to report every invalid or unknown setting at once.
+
+mod file;
+mod master;
 mod util;
+use file::{ConfigFile, CONFIG_FILE};
+use master::{master_env_name, validate_master_name, SaltMasterField};
+pub use master::{SaltMaster, DEFAULT_SALT_MASTER};
 use once_cell::sync::Lazy;
+use std::{
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+};
 use util::generate_random_token;
 pub use util::strip_quotes;
 
 static SYSTEM_TOKEN_FALLBACK: Lazy<String> = Lazy::new(generate_random_token);
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum ResaltConfigKey {
     AuthForwardEnabled,
     AuthSessionLifespan,
@@ -14,14 +40,89 @@ enum ResaltConfigKey {
     DatabaseHost,
     DatabasePort,
     DatabaseDatabase,
+    DatabaseEncryptionKey,
+    DatabaseEncryptionOldKeys,
     MetricsEnabled,
+    MinionOfflineThreshold,
+    MinionProbeEnabled,
+    MinionProbeInterval,
+    MinionSnapshotRetention,
+    MinionStaleThreshold,
+    MinionStatusRetention,
+    PillarRedactKeys,
+    PillarRedactPaths,
     SaltApiUrl,
     SaltApiTlsSkipverify,
+    SaltApiTlsCa,
+    SaltApiTlsClientCert,
+    SaltApiTlsClientKey,
     SaltApiSystemServiceToken,
+    SaltApiMasters,
     HttpPort,
+    HttpBindIpv4,
+    HttpBindIpv6,
+    HttpTlsEnabled,
+    HttpTlsCert,
+    HttpTlsKey,
+    HttpRedirectPort,
+}
+
+/// The type a config value must parse as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ResaltConfigKind {
+    Bool,
+    U16,
+    U64,
+    String,
+    /// One of the listed values, case-insensitive
+    Choice(&'static [&'static str]),
+    /// A 256-bit key as 64 hex characters, or empty to disable
+    Key,
+    /// An IPv4 address, or empty to disable
+    Ipv4,
+    /// An IPv6 address, or empty to disable
+    Ipv6,
+    /// A list joined by the separator, which may be a TOML array in the config file
+    List(char),
 }
 
 impl ResaltConfigKey {
+    const ALL: [ResaltConfigKey; 33] = [
+        ResaltConfigKey::AuthForwardEnabled,
+        ResaltConfigKey::AuthSessionLifespan,
+        ResaltConfigKey::DatabaseType,
+        ResaltConfigKey::DatabaseUsername,
+        ResaltConfigKey::DatabasePassword,
+        ResaltConfigKey::DatabaseHost,
+        ResaltConfigKey::DatabasePort,
+        ResaltConfigKey::DatabaseDatabase,
+        ResaltConfigKey::DatabaseEncryptionKey,
+        ResaltConfigKey::DatabaseEncryptionOldKeys,
+        ResaltConfigKey::MetricsEnabled,
+        ResaltConfigKey::MinionOfflineThreshold,
+        ResaltConfigKey::MinionProbeEnabled,
+        ResaltConfigKey::MinionProbeInterval,
+        ResaltConfigKey::MinionSnapshotRetention,
+        ResaltConfigKey::MinionStaleThreshold,
+        ResaltConfigKey::MinionStatusRetention,
+        ResaltConfigKey::PillarRedactKeys,
+        ResaltConfigKey::PillarRedactPaths,
+        ResaltConfigKey::SaltApiUrl,
+        ResaltConfigKey::SaltApiTlsSkipverify,
+        ResaltConfigKey::SaltApiTlsCa,
+        ResaltConfigKey::SaltApiTlsClientCert,
+        ResaltConfigKey::SaltApiTlsClientKey,
+        ResaltConfigKey::SaltApiSystemServiceToken,
+        ResaltConfigKey::SaltApiMasters,
+        ResaltConfigKey::HttpPort,
+        ResaltConfigKey::HttpBindIpv4,
+        ResaltConfigKey::HttpBindIpv6,
+        ResaltConfigKey::HttpTlsEnabled,
+        ResaltConfigKey::HttpTlsCert,
+        ResaltConfigKey::HttpTlsKey,
+        ResaltConfigKey::HttpRedirectPort,
+    ];
+
     fn key(&self) -> &'static str {
         match self {
             ResaltConfigKey::AuthForwardEnabled => "RESALT_AUTH_FORWARD_ENABLED",
@@ -32,14 +133,121 @@ impl ResaltConfigKey {
             ResaltConfigKey::DatabaseHost => "RESALT_DATABASE_HOST",
             ResaltConfigKey::DatabasePort => "RESALT_DATABASE_PORT",
             ResaltConfigKey::DatabaseDatabase => "RESALT_DATABASE_DATABASE",
+            ResaltConfigKey::DatabaseEncryptionKey => "RESALT_DATABASE_ENCRYPTION_KEY",
+            ResaltConfigKey::DatabaseEncryptionOldKeys => "RESALT_DATABASE_ENCRYPTION_OLD_KEYS",
             ResaltConfigKey::MetricsEnabled => "RESALT_METRICS_ENABLED",
+            ResaltConfigKey::MinionOfflineThreshold => "RESALT_MINION_OFFLINE_THRESHOLD",
+            ResaltConfigKey::MinionProbeEnabled => "RESALT_MINION_PROBE_ENABLED",
+            ResaltConfigKey::MinionProbeInterval => "RESALT_MINION_PROBE_INTERVAL",
+            ResaltConfigKey::MinionSnapshotRetention => "RESALT_MINION_SNAPSHOT_RETENTION",
+            ResaltConfigKey::MinionStaleThreshold => "RESALT_MINION_STALE_THRESHOLD",
+            ResaltConfigKey::MinionStatusRetention => "RESALT_MINION_STATUS_RETENTION",
+            ResaltConfigKey::PillarRedactKeys => "RESALT_PILLAR_REDACT_KEYS",
+            ResaltConfigKey::PillarRedactPaths => "RESALT_PILLAR_REDACT_PATHS",
             ResaltConfigKey::SaltApiUrl => "RESALT_SALT_API_URL",
             ResaltConfigKey::SaltApiTlsSkipverify => "RESALT_SALT_API_TLS_SKIPVERIFY",
+            ResaltConfigKey::SaltApiTlsCa => "RESALT_SALT_API_TLS_CA",
+            ResaltConfigKey::SaltApiTlsClientCert => "RESALT_SALT_API_TLS_CLIENT_CERT",
+            ResaltConfigKey::SaltApiTlsClientKey => "RESALT_SALT_API_TLS_CLIENT_KEY",
             ResaltConfigKey::SaltApiSystemServiceToken => "RESALT_SALT_API_TOKEN",
+            ResaltConfigKey::SaltApiMasters => "RESALT_SALT_API_MASTERS",
             ResaltConfigKey::HttpPort => "RESALT_HTTP_PORT",
+            ResaltConfigKey::HttpBindIpv4 => "RESALT_HTTP_BIND_IPV4",
+            ResaltConfigKey::HttpBindIpv6 => "RESALT_HTTP_BIND_IPV6",
+            ResaltConfigKey::HttpTlsEnabled => "RESALT_HTTP_TLS_ENABLED",
+            ResaltConfigKey::HttpTlsCert => "RESALT_HTTP_TLS_CERT",
+            ResaltConfigKey::HttpTlsKey => "RESALT_HTTP_TLS_KEY",
+            ResaltConfigKey::HttpRedirectPort => "RESALT_HTTP_REDIRECT_PORT",
         }
     }
 
+    /// The dotted key in the TOML config file.
+    fn file_key(&self) -> &'static str {
+        match self {
+            ResaltConfigKey::AuthForwardEnabled => "auth.forward_enabled",
+            ResaltConfigKey::AuthSessionLifespan => "auth.session_lifespan",
+            ResaltConfigKey::DatabaseType => "database.type",
+            ResaltConfigKey::DatabaseUsername => "database.username",
+            ResaltConfigKey::DatabasePassword => "database.password",
+            ResaltConfigKey::DatabaseHost => "database.host",
+            ResaltConfigKey::DatabasePort => "database.port",
+            ResaltConfigKey::DatabaseDatabase => "database.database",
+            ResaltConfigKey::DatabaseEncryptionKey => "database.encryption_key",
+            ResaltConfigKey::DatabaseEncryptionOldKeys => "database.encryption_old_keys",
+            ResaltConfigKey::MetricsEnabled => "metrics.enabled",
+            ResaltConfigKey::MinionOfflineThreshold => "minion.offline_threshold",
+            ResaltConfigKey::MinionProbeEnabled => "minion.probe_enabled",
+            ResaltConfigKey::MinionProbeInterval => "minion.probe_interval",
+            ResaltConfigKey::MinionSnapshotRetention => "minion.snapshot_retention",
+            ResaltConfigKey::MinionStaleThreshold => "minion.stale_threshold",
+            ResaltConfigKey::MinionStatusRetention => "minion.status_retention",
+            ResaltConfigKey::PillarRedactKeys => "pillar.redact_keys",
+            ResaltConfigKey::PillarRedactPaths => "pillar.redact_paths",
+            ResaltConfigKey::SaltApiUrl => "salt_api.url",
+            ResaltConfigKey::SaltApiTlsSkipverify => "salt_api.tls_skipverify",
+            ResaltConfigKey::SaltApiTlsCa => "salt_api.tls_ca",
+            ResaltConfigKey::SaltApiTlsClientCert => "salt_api.tls_client_cert",
+            ResaltConfigKey::SaltApiTlsClientKey => "salt_api.tls_client_key",
+            ResaltConfigKey::SaltApiSystemServiceToken => "salt_api.token",
+            ResaltConfigKey::SaltApiMasters => "salt_api.masters",
+            ResaltConfigKey::HttpPort => "http.port",
+            ResaltConfigKey::HttpBindIpv4 => "http.bind_ipv4",
+            ResaltConfigKey::HttpBindIpv6 => "http.bind_ipv6",
+            ResaltConfigKey::HttpTlsEnabled => "http.tls_enabled",
+            ResaltConfigKey::HttpTlsCert => "http.tls_cert",
+            ResaltConfigKey::HttpTlsKey => "http.tls_key",
+            ResaltConfigKey::HttpRedirectPort => "http.redirect_port",
+        }
+    }
+
+    fn kind(&self) -> ResaltConfigKind {
+        match self {
+            ResaltConfigKey::AuthForwardEnabled
+            | ResaltConfigKey::MetricsEnabled
+            | ResaltConfigKey::MinionProbeEnabled
+            | ResaltConfigKey::SaltApiTlsSkipverify
+            | ResaltConfigKey::HttpTlsEnabled => ResaltConfigKind::Bool,
+            ResaltConfigKey::DatabasePort
+            | ResaltConfigKey::HttpPort
+            | ResaltConfigKey::HttpRedirectPort => ResaltConfigKind::U16,
+            ResaltConfigKey::AuthSessionLifespan
+            | ResaltConfigKey::MinionOfflineThreshold
+            | ResaltConfigKey::MinionProbeInterval
+            | ResaltConfigKey::MinionSnapshotRetention
+            | ResaltConfigKey::MinionStaleThreshold
+            | ResaltConfigKey::MinionStatusRetention => ResaltConfigKind::U64,
+            ResaltConfigKey::DatabaseType => ResaltConfigKind::Choice(&["files", "redis"]),
+            ResaltConfigKey::DatabaseEncryptionKey => ResaltConfigKind::Key,
+            ResaltConfigKey::DatabaseEncryptionOldKeys
+            | ResaltConfigKey::PillarRedactKeys
+            | ResaltConfigKey::SaltApiMasters => ResaltConfigKind::List(','),
+            ResaltConfigKey::PillarRedactPaths => ResaltConfigKind::List(';'),
+            ResaltConfigKey::DatabaseUsername
+            | ResaltConfigKey::DatabasePassword
+            | ResaltConfigKey::DatabaseHost
+            | ResaltConfigKey::DatabaseDatabase
+            | ResaltConfigKey::SaltApiUrl
+            | ResaltConfigKey::SaltApiSystemServiceToken
+            | ResaltConfigKey::SaltApiTlsCa
+            | ResaltConfigKey::SaltApiTlsClientCert
+            | ResaltConfigKey::SaltApiTlsClientKey
+            | ResaltConfigKey::HttpTlsCert
+            | ResaltConfigKey::HttpTlsKey => ResaltConfigKind::String,
+            ResaltConfigKey::HttpBindIpv4 => ResaltConfigKind::Ipv4,
+            ResaltConfigKey::HttpBindIpv6 => ResaltConfigKind::Ipv6,
+        }
+    }
+
+    fn is_secret(&self) -> bool {
+        matches!(
+            self,
+            ResaltConfigKey::DatabasePassword
+                | ResaltConfigKey::DatabaseEncryptionKey
+                | ResaltConfigKey::DatabaseEncryptionOldKeys
+                | ResaltConfigKey::SaltApiSystemServiceToken
+        )
+    }
+
     fn fallback(&self) -> &'static str {
         match self {
             ResaltConfigKey::AuthForwardEnabled => "false",
@@ -50,13 +258,211 @@ impl ResaltConfigKey {
             ResaltConfigKey::DatabaseHost => "docs/docker/filesdb",
             ResaltConfigKey::DatabasePort => "6379",
             ResaltConfigKey::DatabaseDatabase => "0",
+            ResaltConfigKey::DatabaseEncryptionKey => "",
+            ResaltConfigKey::DatabaseEncryptionOldKeys => "",
             ResaltConfigKey::MetricsEnabled => "true",
+            ResaltConfigKey::MinionOfflineThreshold => "3600",
+            ResaltConfigKey::MinionProbeEnabled => "false",
+            ResaltConfigKey::MinionProbeInterval => "300",
+            ResaltConfigKey::MinionSnapshotRetention => "100",
+            ResaltConfigKey::MinionStaleThreshold => "900",
+            ResaltConfigKey::MinionStatusRetention => "7776000",
+            ResaltConfigKey::PillarRedactKeys => "*password*,*secret*,*private_key*,*token*",
+            ResaltConfigKey::PillarRedactPaths => "",
             ResaltConfigKey::SaltApiUrl => "http://localhost:8080",
             ResaltConfigKey::SaltApiTlsSkipverify => "false",
+            ResaltConfigKey::SaltApiTlsCa => "",
+            ResaltConfigKey::SaltApiTlsClientCert => "",
+            ResaltConfigKey::SaltApiTlsClientKey => "",
             ResaltConfigKey::SaltApiSystemServiceToken => SYSTEM_TOKEN_FALLBACK.as_str(),
+            ResaltConfigKey::SaltApiMasters => "",
             ResaltConfigKey::HttpPort => "8000",
+            ResaltConfigKey::HttpBindIpv4 => "0.0.0.0",
+            ResaltConfigKey::HttpBindIpv6 => "",
+            ResaltConfigKey::HttpTlsEnabled => "false",
+            ResaltConfigKey::HttpTlsCert => "",
+            ResaltConfigKey::HttpTlsKey => "",
+            ResaltConfigKey::HttpRedirectPort => "0",
         }
     }
+
+    fn from_file_key(file_key: &str) -> Option<ResaltConfigKey> {
+        ResaltConfigKey::ALL
+            .into_iter()
+            .find(|rck| rck.file_key() == file_key)
+    }
+}
+
+impl ResaltConfigKind {
+    fn validate(&self, value: &str) -> Result<(), String> {
+        match self {
+            ResaltConfigKind::Bool => value
+                .parse::<bool>()
+                .map(|_| ())
+                .map_err(|_| format!("expected true or false, got \"{}\"", value)),
+            ResaltConfigKind::U16 => value
+                .parse::<u16>()
+                .map(|_| ())
+                .map_err(|_| format!("expected a number from 0 to 65535, got \"{}\"", value)),
+            ResaltConfigKind::U64 => value
+                .parse::<u64>()
+                .map(|_| ())
+                .map_err(|_| format!("expected a positive number, got \"{}\"", value)),
+            ResaltConfigKind::Choice(choices) => {
+                match choices.contains(&value.to_lowercase().as_str()) {
+                    true => Ok(()),
+                    false => Err(format!(
+                        "expected one of {}, got \"{}\"",
+                        choices.join(", "),
+                        value
+                    )),
+                }
+            }
+            ResaltConfigKind::Key if !value.is_empty() => {
+                match value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit()) {
+                    true => Ok(()),
+                    // The value is a secret, so it is not included
+                    false => Err(
+                        "expected 64 hex characters, e.g. from `openssl rand -hex 32`".to_string(),
+                    ),
+                }
+            }
+            ResaltConfigKind::Ipv4 if !value.is_empty() => value
+                .parse::<Ipv4Addr>()
+                .map(|_| ())
+                .map_err(|_| format!("expected an IPv4 address, got \"{}\"", value)),
+            ResaltConfigKind::Ipv6 if !value.is_empty() => value
+                .parse::<Ipv6Addr>()
+                .map(|_| ())
+                .map_err(|_| format!("expected an IPv6 address, got \"{}\"", value)),
+            ResaltConfigKind::Key
+            | ResaltConfigKind::Ipv4
+            | ResaltConfigKind::Ipv6
+            | ResaltConfigKind::String
+            | ResaltConfigKind::List(_) => Ok(()),
+        }
+    }
+}
+
+/// Where the effective value of a setting comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResaltConfigSource {
+    Default,
+    File,
+    Env,
+    EnvFile,
+}
+
+impl fmt::Display for ResaltConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResaltConfigSource::Default => write!(f, "default"),
+            ResaltConfigSource::File => write!(f, "config file"),
+            ResaltConfigSource::Env => write!(f, "env"),
+            ResaltConfigSource::EnvFile => write!(f, "env _FILE"),
+        }
+    }
+}
+
+/// Resolve the raw value of a setting from the env or the config file,
+/// following the documented precedence. `None` if it is set in neither.
+fn raw_value_at(
+    key: &str,
+    file_key: &str,
+    kind: ResaltConfigKind,
+    config_file: Option<&ConfigFile>,
+) -> Result<Option<(String, ResaltConfigSource)>, String> {
+    // Check if key_FILE env variable is set
+    let key_file = format!("{}_FILE", key);
+    if let Some(path) = std::env::var(&key_file).ok().filter(|p| !p.is_empty()) {
+        let path = strip_quotes(&path);
+        return match std::fs::read_to_string(&path) {
+            Ok(data) => Ok(Some((data.trim().to_string(), ResaltConfigSource::EnvFile))),
+            Err(e) => Err(format!("{}: error reading \"{}\": {}", key_file, path, e)),
+        };
+    }
+    // Then the key env variable
+    if let Some(value) = std::env::var(key)
+        .ok()
+        .map(|value| strip_quotes(&value))
+        .filter(|value| !value.is_empty())
+    {
+        return Ok(Some((value, ResaltConfigSource::Env)));
+    }
+    // Then the config file
+    if let Some(value) = config_file.and_then(|file| file.get(file_key)) {
+        let separator = match kind {
+            ResaltConfigKind::List(separator) => Some(separator),
+            _ => None,
+        };
+        return file::value_to_string(value, separator)
+            .map(|value| Some((value, ResaltConfigSource::File)))
+            .map_err(|e| format!("{} in config file: {}", file_key, e));
+    }
+    Ok(None)
+}
+
+/// Resolve the raw value of a setting, falling back to its default.
+fn raw_value(
+    rck: ResaltConfigKey,
+    config_file: Option<&ConfigFile>,
+) -> Result<(String, ResaltConfigSource), String> {
+    Ok(
+        raw_value_at(rck.key(), rck.file_key(), rck.kind(), config_file)?
+            .unwrap_or_else(|| (rck.fallback().to_string(), ResaltConfigSource::Default)),
+    )
+}
+
+/// Resolve the raw value of a per-master setting, falling back to the global setting.
+fn master_raw_value(
+    master: &str,
+    field: SaltMasterField,
+    config_file: Option<&ConfigFile>,
+) -> Result<(String, ResaltConfigSource), String> {
+    let rck = field.global();
+    match raw_value_at(
+        &field.key(master),
+        &field.file_key(master),
+        rck.kind(),
+        config_file,
+    )? {
+        Some(value) => Ok(value),
+        None => raw_value(rck, config_file),
+    }
+}
+
+/// The configured master names, or only the default master if none are listed.
+fn master_names(value: &str) -> Vec<String> {
+    match split_list(value, ',') {
+        names if names.is_empty() => vec![DEFAULT_SALT_MASTER.to_string()],
+        names => names,
+    }
+}
+
+fn loaded_config_file() -> Option<&'static ConfigFile> {
+    match &*CONFIG_FILE {
+        Ok(config_file) => config_file.as_ref(),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[must_use]
+fn parse_value<T: std::str::FromStr>(
+    key: &str,
+    kind: ResaltConfigKind,
+    value: Result<(String, ResaltConfigSource), String>,
+) -> T
+where
+    T::Err: std::fmt::Debug,
+{
+    let (value, source) = value.unwrap_or_else(|e| panic!("{}", e));
+    if let Err(e) = kind.validate(&value) {
+        panic!("{} (from {}): {}", key, source, e);
+    }
+    match value.parse() {
+        Ok(value) => value,
+        Err(e) => panic!("{} (from {}): {:?}", key, source, e),
+    }
 }
 
 #[must_use]
@@ -64,41 +470,216 @@ fn conf<T: std::str::FromStr>(rck: ResaltConfigKey) -> T
 where
     T::Err: std::fmt::Debug,
 {
-    // Check if key_FILE env variable is set
-    let key = rck.key();
-    let key_file = format!("{}_FILE", key);
-    let key_file = std::env::var(key_file).ok();
-    if let Some(key_file) = key_file {
-        if !key_file.is_empty() {
-            let data = match std::fs::read_to_string(strip_quotes(&key_file)) {
-                Ok(data) => data,
-                Err(e) => {
-                    panic!("Error reading {}: {}", &key_file, e);
-                }
+    parse_value(rck.key(), rck.kind(), raw_value(rck, loaded_config_file()))
+}
+
+#[must_use]
+fn master_conf<T: std::str::FromStr>(master: &str, field: SaltMasterField) -> T
+where
+    T::Err: std::fmt::Debug,
+{
+    parse_value(
+        &field.key(master),
+        field.global().kind(),
+        master_raw_value(master, field, loaded_config_file()),
+    )
+}
+
+/// The effective value of a setting, as reported by [`validate_config`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResaltConfigEntry {
+    pub key: String,
+    pub file_key: String,
+    /// The value, with secrets masked.
+    pub value: String,
+    pub source: ResaltConfigSource,
+}
+
+fn validate_with(config_file: Option<&ConfigFile>) -> (Vec<ResaltConfigEntry>, Vec<String>) {
+    let mut entries: Vec<ResaltConfigEntry> = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+
+    let masters = match raw_value(ResaltConfigKey::SaltApiMasters, config_file) {
+        Ok((value, _)) => master_names(&value),
+        // Reported below, with the other settings
+        Err(_) => Vec::new(),
+    };
+    for (i, master) in masters.iter().enumerate() {
+        if let Err(e) = validate_master_name(master) {
+            errors.push(format!("{}: {}", ResaltConfigKey::SaltApiMasters.key(), e));
+        } else if masters[..i].contains(master) {
+            errors.push(format!(
+                "{}: master \"{}\" is listed twice",
+                ResaltConfigKey::SaltApiMasters.key(),
+                master
+            ));
+        } else if let Some(other) = masters[..i]
+            .iter()
+            .find(|other| master_env_name(other) == master_env_name(master))
+        {
+            errors.push(format!(
+                "{}: masters \"{}\" and \"{}\" have the same env variables",
+                ResaltConfigKey::SaltApiMasters.key(),
+                other,
+                master
+            ));
+        }
+    }
+
+    if let Some(config_file) = config_file {
+        for file_key in config_file.keys() {
+            let known = match SaltMasterField::from_file_key(file_key) {
+                Some((master, _)) => masters.iter().any(|m| m == master),
+                None => ResaltConfigKey::from_file_key(file_key).is_some(),
             };
-            let data = data.trim();
-            return match data.parse() {
-                Ok(data) => data,
-                Err(e) => {
-                    panic!("Error parsing \"{}\": {:?}", data, e);
+            if !known {
+                errors.push(format!("{}: unknown key in config file", file_key));
+            }
+        }
+    }
+
+    for rck in ResaltConfigKey::ALL {
+        let (value, source) = match raw_value(rck, config_file) {
+            Ok(value) => value,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        if let Err(e) = rck.kind().validate(&value) {
+            errors.push(format!("{} (from {}): {}", rck.key(), source, e));
+        }
+        if rck == ResaltConfigKey::DatabaseEncryptionOldKeys {
+            for key in split_list(&value, ',') {
+                if let Err(e) = ResaltConfigKind::Key.validate(&key) {
+                    errors.push(format!("{} (from {}): {}", rck.key(), source, e));
                 }
-            };
+            }
         }
+        let value = match rck.is_secret() && !value.is_empty() {
+            true => "********".to_string(),
+            false => value,
+        };
+        entries.push(ResaltConfigEntry {
+            key: rck.key().to_string(),
+            file_key: rck.file_key().to_string(),
+            value,
+            source,
+        });
     }
-    // Fallback to key env variable
-    let fallback = rck.fallback();
-    std::env::var(key)
-        .ok()
-        .map(|value| strip_quotes(&value))
-        .filter(|value| !value.is_empty())
-        .and_then(|value| value.parse().ok())
-        .unwrap_or(fallback.parse().unwrap())
+
+    // Per-master settings, only listed when there are several masters
+    if masters.len() > 1 {
+        for master in &masters {
+            let mut client_cert_set = Vec::new();
+            for field in SaltMasterField::ALL {
+                let rck = field.global();
+                let key = field.key(master);
+                let (value, source) = match master_raw_value(master, field, config_file) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        errors.push(e);
+                        continue;
+                    }
+                };
+                if let Err(e) = rck.kind().validate(&value) {
+                    errors.push(format!("{} (from {}): {}", key, source, e));
+                }
+                if matches!(
+                    field,
+                    SaltMasterField::TlsClientCert | SaltMasterField::TlsClientKey
+                ) {
+                    client_cert_set.push(!value.is_empty());
+                }
+                let value = match rck.is_secret() && !value.is_empty() {
+                    true => "********".to_string(),
+                    false => value,
+                };
+                entries.push(ResaltConfigEntry {
+                    key,
+                    file_key: field.file_key(master),
+                    value,
+                    source,
+                });
+            }
+            if client_cert_set.len() == 2 && client_cert_set[0] != client_cert_set[1] {
+                errors.push(format!(
+                    "{} and {}: must be set together",
+                    SaltMasterField::TlsClientCert.key(master),
+                    SaltMasterField::TlsClientKey.key(master)
+                ));
+            }
+        }
+    }
+    // Settings which are only valid together
+    let value = |rck: ResaltConfigKey| {
+        entries
+            .iter()
+            .find(|e| e.key == rck.key())
+            .map(|e| e.value.as_str())
+            .unwrap_or_default()
+    };
+    if value(ResaltConfigKey::HttpTlsEnabled) == "true" {
+        for rck in [ResaltConfigKey::HttpTlsCert, ResaltConfigKey::HttpTlsKey] {
+            if value(rck).is_empty() {
+                errors.push(format!("{}: required when TLS is enabled", rck.key()));
+            }
+        }
+    } else if !matches!(value(ResaltConfigKey::HttpRedirectPort), "" | "0") {
+        errors.push(format!(
+            "{}: requires TLS to be enabled",
+            ResaltConfigKey::HttpRedirectPort.key()
+        ));
+    }
+    if value(ResaltConfigKey::SaltApiTlsClientCert).is_empty()
+        != value(ResaltConfigKey::SaltApiTlsClientKey).is_empty()
+    {
+        errors.push(format!(
+            "{} and {}: must be set together",
+            ResaltConfigKey::SaltApiTlsClientCert.key(),
+            ResaltConfigKey::SaltApiTlsClientKey.key()
+        ));
+    }
+    if value(ResaltConfigKey::HttpBindIpv4).is_empty()
+        && value(ResaltConfigKey::HttpBindIpv6).is_empty()
+    {
+        errors.push(format!(
+            "{} and {}: at least one bind address is required",
+            ResaltConfigKey::HttpBindIpv4.key(),
+            ResaltConfigKey::HttpBindIpv6.key()
+        ));
+    }
+
+    (entries, errors)
+}
+
+/// Check every setting from every source, and return the effective config with
+/// secrets masked, or every problem found.
+pub fn validate_config() -> Result<Vec<ResaltConfigEntry>, Vec<String>> {
+    let config_file = match &*CONFIG_FILE {
+        Ok(config_file) => config_file.as_ref(),
+        Err(e) => return Err(vec![e.clone()]),
+    };
+    let (entries, errors) = validate_with(config_file);
+    match errors.is_empty() {
+        true => Ok(entries),
+        false => Err(errors),
+    }
+}
+
+fn split_list(value: &str, separator: char) -> Vec<String> {
+    value
+        .split(separator)
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
 }
 
 #[allow(non_snake_case)]
 pub mod ResaltConfig {
-    use crate::ResaltConfigInternal;
+    use crate::{split_list, ResaltConfigInternal, SaltMaster};
     use once_cell::sync::Lazy;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     pub static AUTH_FORWARD_ENABLED: Lazy<bool> =
         Lazy::new(ResaltConfigInternal::auth_forward_enabled);
@@ -111,13 +692,66 @@ pub mod ResaltConfig {
     pub static DATABASE_HOST: Lazy<String> = Lazy::new(ResaltConfigInternal::database_host);
     pub static DATABASE_PORT: Lazy<u16> = Lazy::new(ResaltConfigInternal::database_port);
     pub static DATABASE_DATABASE: Lazy<String> = Lazy::new(ResaltConfigInternal::database_database);
+    /// Hex key encrypting sensitive fields in the database, empty if disabled.
+    pub static DATABASE_ENCRYPTION_KEY: Lazy<String> =
+        Lazy::new(ResaltConfigInternal::database_encryption_key);
+    /// Hex keys of earlier encryption keys, still accepted for decryption until rotated.
+    pub static DATABASE_ENCRYPTION_OLD_KEYS: Lazy<Vec<String>> =
+        Lazy::new(|| split_list(&ResaltConfigInternal::database_encryption_old_keys(), ','));
     pub static METRICS_ENABLED: Lazy<bool> = Lazy::new(ResaltConfigInternal::metrics_enabled);
+    pub static MINION_OFFLINE_THRESHOLD: Lazy<u64> =
+        Lazy::new(ResaltConfigInternal::minion_offline_threshold);
+    pub static MINION_PROBE_ENABLED: Lazy<bool> =
+        Lazy::new(ResaltConfigInternal::minion_probe_enabled);
+    pub static MINION_PROBE_INTERVAL: Lazy<u64> =
+        Lazy::new(ResaltConfigInternal::minion_probe_interval);
+    /// Snapshots kept per minion and kind of data, 0 to keep all of them.
+    pub static MINION_SNAPSHOT_RETENTION: Lazy<u64> =
+        Lazy::new(ResaltConfigInternal::minion_snapshot_retention);
+    pub static MINION_STALE_THRESHOLD: Lazy<u64> =
+        Lazy::new(ResaltConfigInternal::minion_stale_threshold);
+    /// Seconds to keep minion status transitions for, 0 to keep them forever.
+    pub static MINION_STATUS_RETENTION: Lazy<u64> =
+        Lazy::new(ResaltConfigInternal::minion_status_retention);
+    /// Comma-separated glob patterns of pillar keys to redact.
+    pub static PILLAR_REDACT_KEYS: Lazy<Vec<String>> =
+        Lazy::new(|| split_list(&ResaltConfigInternal::pillar_redact_keys(), ','));
+    /// Semicolon-separated JSONPath expressions of pillar values to redact.
+    pub static PILLAR_REDACT_PATHS: Lazy<Vec<String>> =
+        Lazy::new(|| split_list(&ResaltConfigInternal::pillar_redact_paths(), ';'));
     pub static SALT_API_URL: Lazy<String> = Lazy::new(ResaltConfigInternal::salt_api_url);
     pub static SALT_API_TLS_SKIPVERIFY: Lazy<bool> =
         Lazy::new(ResaltConfigInternal::salt_api_tls_skipverify);
+    /// Path to a PEM bundle of CA certificates trusted for the Salt API, in addition to the system ones.
+    pub static SALT_API_TLS_CA: Lazy<String> = Lazy::new(ResaltConfigInternal::salt_api_tls_ca);
+    /// Path to the PEM client certificate presented to the Salt API (mTLS).
+    pub static SALT_API_TLS_CLIENT_CERT: Lazy<String> =
+        Lazy::new(ResaltConfigInternal::salt_api_tls_client_cert);
+    /// Path to the PKCS#8 PEM key of the client certificate.
+    pub static SALT_API_TLS_CLIENT_KEY: Lazy<String> =
+        Lazy::new(ResaltConfigInternal::salt_api_tls_client_key);
     pub static SALT_API_SYSTEM_SERVICE_TOKEN: Lazy<String> =
         Lazy::new(ResaltConfigInternal::salt_api_system_service_token);
+    /// The Salt masters, in configured order. Always contains at least one master.
+    pub static SALT_MASTERS: Lazy<Vec<SaltMaster>> = Lazy::new(ResaltConfigInternal::salt_masters);
     pub static HTTP_PORT: Lazy<u16> = Lazy::new(ResaltConfigInternal::http_port);
+    /// IPv4 address to listen on, `None` if disabled.
+    pub static HTTP_BIND_IPV4: Lazy<Option<Ipv4Addr>> = Lazy::new(|| {
+        let addr = ResaltConfigInternal::http_bind_ipv4();
+        (!addr.is_empty()).then(|| addr.parse().unwrap())
+    });
+    /// IPv6 address to listen on, `None` if disabled.
+    pub static HTTP_BIND_IPV6: Lazy<Option<Ipv6Addr>> = Lazy::new(|| {
+        let addr = ResaltConfigInternal::http_bind_ipv6();
+        (!addr.is_empty()).then(|| addr.parse().unwrap())
+    });
+    pub static HTTP_TLS_ENABLED: Lazy<bool> = Lazy::new(ResaltConfigInternal::http_tls_enabled);
+    /// Path to the PEM certificate chain, reloaded on change or SIGHUP.
+    pub static HTTP_TLS_CERT: Lazy<String> = Lazy::new(ResaltConfigInternal::http_tls_cert);
+    /// Path to the PEM private key, reloaded on change or SIGHUP.
+    pub static HTTP_TLS_KEY: Lazy<String> = Lazy::new(ResaltConfigInternal::http_tls_key);
+    /// Port redirecting plain HTTP to HTTPS, 0 if disabled.
+    pub static HTTP_REDIRECT_PORT: Lazy<u16> = Lazy::new(ResaltConfigInternal::http_redirect_port);
 }
 
 pub struct ResaltConfigInternal {}
@@ -154,10 +788,50 @@ impl ResaltConfigInternal {
         conf::<String>(ResaltConfigKey::DatabaseDatabase)
     }
 
+    fn database_encryption_key() -> String {
+        conf::<String>(ResaltConfigKey::DatabaseEncryptionKey)
+    }
+
+    fn database_encryption_old_keys() -> String {
+        conf::<String>(ResaltConfigKey::DatabaseEncryptionOldKeys)
+    }
+
     fn metrics_enabled() -> bool {
         conf::<bool>(ResaltConfigKey::MetricsEnabled)
     }
 
+    fn minion_offline_threshold() -> u64 {
+        conf::<u64>(ResaltConfigKey::MinionOfflineThreshold)
+    }
+
+    fn minion_probe_enabled() -> bool {
+        conf::<bool>(ResaltConfigKey::MinionProbeEnabled)
+    }
+
+    fn minion_probe_interval() -> u64 {
+        conf::<u64>(ResaltConfigKey::MinionProbeInterval)
+    }
+
+    fn minion_snapshot_retention() -> u64 {
+        conf::<u64>(ResaltConfigKey::MinionSnapshotRetention)
+    }
+
+    fn minion_stale_threshold() -> u64 {
+        conf::<u64>(ResaltConfigKey::MinionStaleThreshold)
+    }
+
+    fn minion_status_retention() -> u64 {
+        conf::<u64>(ResaltConfigKey::MinionStatusRetention)
+    }
+
+    fn pillar_redact_keys() -> String {
+        conf::<String>(ResaltConfigKey::PillarRedactKeys)
+    }
+
+    fn pillar_redact_paths() -> String {
+        conf::<String>(ResaltConfigKey::PillarRedactPaths)
+    }
+
     fn salt_api_url() -> String {
         conf::<String>(ResaltConfigKey::SaltApiUrl)
     }
@@ -166,13 +840,69 @@ impl ResaltConfigInternal {
         conf::<bool>(ResaltConfigKey::SaltApiTlsSkipverify)
     }
 
+    fn salt_api_tls_ca() -> String {
+        conf::<String>(ResaltConfigKey::SaltApiTlsCa)
+    }
+
+    fn salt_api_tls_client_cert() -> String {
+        conf::<String>(ResaltConfigKey::SaltApiTlsClientCert)
+    }
+
+    fn salt_api_tls_client_key() -> String {
+        conf::<String>(ResaltConfigKey::SaltApiTlsClientKey)
+    }
+
     fn salt_api_system_service_token() -> String {
         conf::<String>(ResaltConfigKey::SaltApiSystemServiceToken)
     }
 
+    fn salt_masters() -> Vec<SaltMaster> {
+        master_names(&conf::<String>(ResaltConfigKey::SaltApiMasters))
+            .into_iter()
+            .map(|name| {
+                if let Err(e) = validate_master_name(&name) {
+                    panic!("{}: {}", ResaltConfigKey::SaltApiMasters.key(), e);
+                }
+                SaltMaster {
+                    url: master_conf(&name, SaltMasterField::Url),
+                    tls_skipverify: master_conf(&name, SaltMasterField::TlsSkipverify),
+                    tls_ca: master_conf(&name, SaltMasterField::TlsCa),
+                    tls_client_cert: master_conf(&name, SaltMasterField::TlsClientCert),
+                    tls_client_key: master_conf(&name, SaltMasterField::TlsClientKey),
+                    token: master_conf(&name, SaltMasterField::Token),
+                    name,
+                }
+            })
+            .collect()
+    }
+
     fn http_port() -> u16 {
         conf::<u16>(ResaltConfigKey::HttpPort)
     }
+
+    fn http_bind_ipv4() -> String {
+        conf::<String>(ResaltConfigKey::HttpBindIpv4)
+    }
+
+    fn http_bind_ipv6() -> String {
+        conf::<String>(ResaltConfigKey::HttpBindIpv6)
+    }
+
+    fn http_tls_enabled() -> bool {
+        conf::<bool>(ResaltConfigKey::HttpTlsEnabled)
+    }
+
+    fn http_tls_cert() -> String {
+        conf::<String>(ResaltConfigKey::HttpTlsCert)
+    }
+
+    fn http_tls_key() -> String {
+        conf::<String>(ResaltConfigKey::HttpTlsKey)
+    }
+
+    fn http_redirect_port() -> u16 {
+        conf::<u16>(ResaltConfigKey::HttpRedirectPort)
+    }
 }
 
 #[cfg(test)]
@@ -215,4 +945,112 @@ mod tests {
         std::env::remove_var("RESALT_DATABASE_DATABASE_FILE");
         assert_eq!(ResaltConfigInternal::database_database(), "0");
     }
+
+    #[test]
+    fn test_validate_file() {
+        let file = ConfigFile::parse(
+            r#"
+            [database]
+            type = "postgres"
+            port = "not a port"
+            password = "hunter2"
+            encryption_key = "abc"
+            encryption_old_keys = ["000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f", "nope"]
+
+            [minion]
+            offline_threshold = 60
+            unknown = true
+
+            [http]
+            tls_enabled = true
+            bind_ipv6 = "::"
+            "#,
+        )
+        .unwrap();
+        let (entries, errors) = validate_with(Some(&file));
+
+        let entry = |key: &str| entries.iter().find(|e| e.file_key == key).unwrap();
+        assert_eq!(entry("minion.offline_threshold").value, "60");
+        assert_eq!(
+            entry("minion.offline_threshold").source,
+            ResaltConfigSource::File
+        );
+        assert_eq!(entry("database.password").value, "********");
+        assert_eq!(entry("database.encryption_old_keys").value, "********");
+
+        // Every problem is reported, not just the first
+        for expected in [
+            "minion.unknown: unknown key in config file",
+            "RESALT_DATABASE_TYPE (from config file): expected one of files, redis, got \"postgres\"",
+            "RESALT_DATABASE_PORT (from config file): expected a number from 0 to 65535, got \"not a port\"",
+            "RESALT_HTTP_TLS_CERT: required when TLS is enabled",
+            "RESALT_DATABASE_ENCRYPTION_KEY (from config file): expected 64 hex characters, e.g. from `openssl rand -hex 32`",
+            "RESALT_DATABASE_ENCRYPTION_OLD_KEYS (from config file): expected 64 hex characters, e.g. from `openssl rand -hex 32`",
+        ] {
+            assert!(errors.iter().any(|e| e == expected), "{:?}", errors);
+        }
+    }
+
+    #[test]
+    fn test_validate_masters() {
+        let file = ConfigFile::parse(
+            r#"
+            [salt_api]
+            masters = ["dc1", "dc2"]
+            url = "https://salt:8000"
+            token = "secret"
+
+            [salt_masters.dc2]
+            url = "https://salt-dc2:8000"
+            tls_client_cert = "/etc/resalt/dc2.pem"
+
+            [salt_masters.dc3]
+            url = "https://salt-dc3:8000"
+            "#,
+        )
+        .unwrap();
+        let (entries, errors) = validate_with(Some(&file));
+
+        let entry = |key: &str| entries.iter().find(|e| e.file_key == key).unwrap();
+        assert_eq!(entry("salt_masters.dc1.url").value, "https://salt:8000");
+        assert_eq!(entry("salt_masters.dc2.url").value, "https://salt-dc2:8000");
+        assert_eq!(entry("salt_masters.dc2.token").value, "********");
+
+        for expected in [
+            "salt_masters.dc3.url: unknown key in config file",
+            "RESALT_SALT_MASTER_DC2_TLS_CLIENT_CERT and RESALT_SALT_MASTER_DC2_TLS_CLIENT_KEY: must be set together",
+        ] {
+            assert!(errors.iter().any(|e| e == expected), "{:?}", errors);
+        }
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+    }
+
+    #[test]
+    fn test_validate_master_env_collision() {
+        let file = ConfigFile::parse(
+            r#"
+            [salt_api]
+            masters = ["dc-1", "dc_1"]
+            url = "https://salt:8000"
+            "#,
+        )
+        .unwrap();
+        let (_, errors) = validate_with(Some(&file));
+        assert!(
+            errors
+                .iter()
+                .any(|e| e.ends_with("masters \"dc-1\" and \"dc_1\" have the same env variables")),
+            "{:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn test_master_names() {
+        assert_eq!(master_names(""), vec![DEFAULT_SALT_MASTER]);
+        assert_eq!(master_names("dc1, dc2"), vec!["dc1", "dc2"]);
+        assert!(validate_master_name("dc-1_a").is_ok());
+        assert!(validate_master_name("DC1").is_err());
+        assert!(validate_master_name("").is_err());
+    }
 }
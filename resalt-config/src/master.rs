@@ -0,0 +1,108 @@
+use crate::ResaltConfigKey;
+
+/// Name of the only master when `RESALT_SALT_API_MASTERS` is not set.
+pub const DEFAULT_SALT_MASTER: &str = "default";
+
+/// A Salt master, reached through its rest_cherrypy Salt API.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SaltMaster {
+    pub name: String,
+    pub url: String,
+    pub tls_skipverify: bool,
+    /// Path to a PEM bundle of CA certificates trusted in addition to the system ones.
+    pub tls_ca: String,
+    /// Path to the PEM client certificate presented to the Salt API (mTLS).
+    pub tls_client_cert: String,
+    /// Path to the PKCS#8 PEM key of the client certificate.
+    pub tls_client_key: String,
+    /// Token the Salt API uses to authenticate the Resalt system service against `/token`.
+    pub token: String,
+}
+
+/// Settings which can be set per master, falling back to the global `salt_api` setting.
+///
+/// Set through `RESALT_SALT_MASTER_<NAME>_<FIELD>` (e.g. `RESALT_SALT_MASTER_DC1_URL`),
+/// or `[salt_masters.<name>]` in the config file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SaltMasterField {
+    Url,
+    TlsSkipverify,
+    TlsCa,
+    TlsClientCert,
+    TlsClientKey,
+    Token,
+}
+
+impl SaltMasterField {
+    pub(crate) const ALL: [SaltMasterField; 6] = [
+        SaltMasterField::Url,
+        SaltMasterField::TlsSkipverify,
+        SaltMasterField::TlsCa,
+        SaltMasterField::TlsClientCert,
+        SaltMasterField::TlsClientKey,
+        SaltMasterField::Token,
+    ];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            SaltMasterField::Url => "url",
+            SaltMasterField::TlsSkipverify => "tls_skipverify",
+            SaltMasterField::TlsCa => "tls_ca",
+            SaltMasterField::TlsClientCert => "tls_client_cert",
+            SaltMasterField::TlsClientKey => "tls_client_key",
+            SaltMasterField::Token => "token",
+        }
+    }
+
+    pub(crate) fn global(&self) -> ResaltConfigKey {
+        match self {
+            SaltMasterField::Url => ResaltConfigKey::SaltApiUrl,
+            SaltMasterField::TlsSkipverify => ResaltConfigKey::SaltApiTlsSkipverify,
+            SaltMasterField::TlsCa => ResaltConfigKey::SaltApiTlsCa,
+            SaltMasterField::TlsClientCert => ResaltConfigKey::SaltApiTlsClientCert,
+            SaltMasterField::TlsClientKey => ResaltConfigKey::SaltApiTlsClientKey,
+            SaltMasterField::Token => ResaltConfigKey::SaltApiSystemServiceToken,
+        }
+    }
+
+    pub(crate) fn key(&self, master: &str) -> String {
+        format!(
+            "RESALT_SALT_MASTER_{}_{}",
+            master_env_name(master),
+            self.name().to_uppercase()
+        )
+    }
+
+    pub(crate) fn file_key(&self, master: &str) -> String {
+        format!("salt_masters.{}.{}", master, self.name())
+    }
+
+    /// Inverse of `file_key`, returning the master name and field.
+    pub(crate) fn from_file_key(file_key: &str) -> Option<(&str, SaltMasterField)> {
+        let (master, field) = file_key.strip_prefix("salt_masters.")?.split_once('.')?;
+        SaltMasterField::ALL
+            .into_iter()
+            .find(|f| f.name() == field)
+            .map(|f| (master, f))
+    }
+}
+
+/// The part of env variable names naming the master. Both `-` and `_` map to `_`.
+pub(crate) fn master_env_name(master: &str) -> String {
+    master.to_uppercase().replace('-', "_")
+}
+
+/// Master names are used in env variable names, so are restricted to `[a-z0-9_-]`.
+pub(crate) fn validate_master_name(name: &str) -> Result<(), String> {
+    match !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+    {
+        true => Ok(()),
+        false => Err(format!(
+            "invalid master name \"{}\", only a-z, 0-9, _ and - are allowed",
+            name
+        )),
+    }
+}
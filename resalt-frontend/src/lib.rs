@@ -29,7 +29,7 @@ pub fn frontend_get(path: String) -> (String, Vec<u8>) {
 }
 
 pub fn match_mime(filename: &str) -> &str {
-    let ext = filename.split('.').last().unwrap_or("").to_lowercase();
+    let ext = filename.split('.').next_back().unwrap_or("").to_lowercase();
     match ext.as_str() {
         // code
         "html" => "text/html; charset=utf-8",
@@ -1,6 +1,7 @@
 import Config from '$model/Config';
 import type Filter from '$model/Filter';
 import Job from '$model/Job';
+import JobRunResult from '$model/JobRunResult';
 import Key from '$model/Key';
 import Minion from '$model/Minion';
 import MinionPreset from '$model/MinionPreset';
@@ -239,8 +240,9 @@ export async function getJobs(
 	);
 }
 
-export async function runJob(command: RunCommand): Promise<unknown> {
-	return sendRequest('POST', '/jobs', {
+/// Run a job, returning the result on each master it ran on, keyed by master name.
+export async function runJob(command: RunCommand): Promise<Record<string, JobRunResult>> {
+	return sendRequest('POST', '/jobs?perMaster=true', {
 		client: command.client,
 		tgtType: command.targetType,
 		tgt: command.target,
@@ -248,7 +250,14 @@ export async function runJob(command: RunCommand): Promise<unknown> {
 		arg: command.arg,
 		kwarg: Object.fromEntries(command.kwarg), // Map<>'s are invisible to JSON.stringify
 		batchSize: command.batchSize,
-	});
+	}).then((data: unknown) =>
+		Object.fromEntries(
+			Object.entries(data as Record<string, unknown>).map(([master, result]) => [
+				master,
+				JobRunResult.fromObject(result),
+			]),
+		),
+	);
 }
 
 export async function getJobById(jobId: string): Promise<Job> {
@@ -19,6 +19,7 @@ export const P_RUN_TEMPLATE_GLOBAL = 'run.template.global'; // Can create global
 export const P_MINION_LIST = 'minion.list';
 export const P_MINION_CONFORMITY = 'minion.conformity';
 export const P_MINION_PILLARS = 'minion.pillars';
+export const P_MINION_PILLARS_SECRETS = 'minion.pillars.secrets';
 export const P_MINION_PACKAGES = 'minion.packages';
 export const P_MINION_REFRESH = 'minion.refresh';
 export const P_MINION_PRESETS_LIST = 'minion.presets.list';
@@ -135,6 +136,13 @@ export const resaltWebPermissions: {
 		title: '[Minion] See Pillars',
 		description: 'Allow user to see the pillars of minions.',
 	},
+	{
+		permission: P_MINION_PILLARS_SECRETS,
+		title: '[Minion] See Pillar Secrets',
+		description:
+			'Allow user to see pillar values which are otherwise redacted, such as passwords and keys.',
+		warning: true,
+	},
 	{
 		permission: P_MINION_PACKAGES,
 		title: '[Minion] See Packages',
@@ -7,4 +7,6 @@ export enum FilterFieldType {
 	GRAIN = 'grain',
 	// eslint-disable-next-line no-unused-vars
 	PACKAGE = 'package',
+	// eslint-disable-next-line no-unused-vars
+	PILLAR = 'pillar',
 }
@@ -15,4 +15,20 @@ export enum FilterOperand {
 	GREATER_THAN_OR_EQUAL = 'gte',
 	// eslint-disable-next-line no-unused-vars
 	LESS_THAN_OR_EQUAL = 'lte',
+	// eslint-disable-next-line no-unused-vars
+	GREATER_THAN = 'gt',
+	// eslint-disable-next-line no-unused-vars
+	LESS_THAN = 'lt',
+	// eslint-disable-next-line no-unused-vars
+	REGEX = 're',
+	// eslint-disable-next-line no-unused-vars
+	IN = 'in',
+	// eslint-disable-next-line no-unused-vars
+	EXISTS = 'ex',
+	// eslint-disable-next-line no-unused-vars
+	NOT_EXISTS = 'nex',
+	// eslint-disable-next-line no-unused-vars
+	IS_EMPTY = 'empty',
+	// eslint-disable-next-line no-unused-vars
+	NOT_EMPTY = 'nempty',
 }
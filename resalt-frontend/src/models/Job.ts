@@ -1,7 +1,7 @@
 export default class Job {
 	static fromObject(data: unknown): Job {
-		const { id, timestamp, jid, user, eventId } = data as Job;
-		return new Job(id, timestamp, jid, user, eventId);
+		const { id, timestamp, jid, user, eventId, presetId } = data as Job;
+		return new Job(id, timestamp, jid, user, eventId, presetId ?? null);
 	}
 
 	id: string;
@@ -14,11 +14,21 @@ export default class Job {
 
 	eventId: string[];
 
-	constructor(id: string, timestamp: string, jid: string, user: string, eventId: string[]) {
+	presetId: string | null;
+
+	constructor(
+		id: string,
+		timestamp: string,
+		jid: string,
+		user: string,
+		eventId: string[],
+		presetId: string | null,
+	) {
 		this.id = id;
 		this.timestamp = timestamp;
 		this.jid = jid;
 		this.user = user;
 		this.eventId = eventId;
+		this.presetId = presetId;
 	}
 }
@@ -1,3 +1,4 @@
+import type JobRunResult from './JobRunResult';
 import type RunCommand from './RunCommand';
 
 export default class RunResult {
@@ -5,9 +6,10 @@ export default class RunResult {
 
 	num: number;
 
-	data: unknown;
+	/// Result on each master, keyed by master name
+	data: Record<string, JobRunResult>;
 
-	constructor(command: RunCommand, num: number, data: unknown) {
+	constructor(command: RunCommand, num: number, data: Record<string, JobRunResult>) {
 		this.command = command;
 		this.num = num;
 		this.data = data;
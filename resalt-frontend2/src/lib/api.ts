@@ -1,6 +1,7 @@
 import Config from '../models/Config';
 import type Filter from '../models/Filter';
 import Job from '../models/Job';
+import JobRunResult from '../models/JobRunResult';
 import Key from '../models/Key';
 import Minion from '../models/Minion';
 import MinionPreset from '../models/MinionPreset';
@@ -313,10 +314,14 @@ export async function getJobs(
 	);
 }
 
-export async function runJob(command: RunCommand, abort: AbortSignal): Promise<unknown> {
+/// Run a job, returning the result on each master it ran on, keyed by master name.
+export async function runJob(
+	command: RunCommand,
+	abort: AbortSignal,
+): Promise<Record<string, JobRunResult>> {
 	return sendRequest(
 		'POST',
-		'/jobs',
+		'/jobs?perMaster=true',
 		{
 			client: command.client,
 			tgtType: command.targetType,
@@ -327,6 +332,13 @@ export async function runJob(command: RunCommand, abort: AbortSignal): Promise<u
 			batchSize: command.batchSize,
 		},
 		abort,
+	).then((data: unknown) =>
+		Object.fromEntries(
+			Object.entries(data as Record<string, unknown>).map(([master, result]) => [
+				master,
+				JobRunResult.fromObject(result),
+			]),
+		),
 	);
 }
 
@@ -0,0 +1,16 @@
+/// Result of a job on one master: either the Salt API result, or why running it failed.
+export default class JobRunResult {
+	static fromObject(data: unknown): JobRunResult {
+		const { job, error } = data as JobRunResult;
+		return new JobRunResult(job, error);
+	}
+
+	job: unknown | undefined;
+
+	error: string | undefined;
+
+	constructor(job: unknown | undefined, error: string | undefined) {
+		this.job = job;
+		this.error = error;
+	}
+}
@@ -107,9 +107,15 @@ export default function MinionTerminalRoute(props: { toastController: ToastContr
 			),
 			abort.signal,
 		)
-			.then((result) => {
-				// Fetch result[minionId] and display it
-				let resultString = (result as Record<string, unknown>)[minionId] as string;
+			.then((results) => {
+				// Fetch the result of the minion from the master it is connected to
+				const errors = Object.values(results).flatMap((r) => (r.error ? [r.error] : []));
+				const jobs = Object.values(results).map((r) => r.job as Record<string, unknown>);
+				const job = jobs.find((j) => j && minionId in j);
+				if (!job && errors.length > 0) {
+					throw new Error(errors.join(', '));
+				}
+				let resultString = job?.[minionId] as string;
 				if (typeof resultString === 'boolean') {
 					resultString = resultString ? 'True' : 'False';
 				}
@@ -0,0 +1,300 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::{
+    strip_quotes, AuthToken, Event, Job, JobReturn, Minion, MinionPreset, PermissionGroup,
+    Preferences, User,
+};
+
+/// Version of the [`DataDump`] format written by this release.
+///
+/// Older dumps are upgraded on import, see [`DataDump::from_value`].
+pub const DATA_DUMP_VERSION: u32 = 2;
+
+/// The parts of Resalt which can be included in a [`DataDump`].
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, ToSchema,
+)]
+pub enum DataDumpSection {
+    #[serde(rename = "users")]
+    Users,
+    #[serde(rename = "groups")]
+    Groups,
+    #[serde(rename = "memberships")]
+    Memberships,
+    #[serde(rename = "minions")]
+    Minions,
+    #[serde(rename = "minionPresets")]
+    MinionPresets,
+    #[serde(rename = "preferences")]
+    Preferences,
+    /// Jobs including their returns.
+    #[serde(rename = "jobs")]
+    Jobs,
+    #[serde(rename = "events")]
+    Events,
+    #[serde(rename = "authTokens")]
+    AuthTokens,
+}
+
+impl DataDumpSection {
+    pub const ALL: [DataDumpSection; 9] = [
+        DataDumpSection::Users,
+        DataDumpSection::Groups,
+        DataDumpSection::Memberships,
+        DataDumpSection::Minions,
+        DataDumpSection::MinionPresets,
+        DataDumpSection::Preferences,
+        DataDumpSection::Jobs,
+        DataDumpSection::Events,
+        DataDumpSection::AuthTokens,
+    ];
+
+    /// The sections exported in the settings, and the only ones of a version 1 dump.
+    pub const SETTINGS: [DataDumpSection; 5] = [
+        DataDumpSection::Users,
+        DataDumpSection::Groups,
+        DataDumpSection::Memberships,
+        DataDumpSection::Minions,
+        DataDumpSection::MinionPresets,
+    ];
+}
+
+impl fmt::Display for DataDumpSection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            strip_quotes(serde_json::to_string(self).unwrap_or_default())
+        )
+    }
+}
+
+impl FromStr for DataDumpSection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DataDumpSection::ALL
+            .into_iter()
+            .find(|section| section.to_string() == s)
+            .ok_or_else(|| format!("Invalid backup section: {}", s))
+    }
+}
+
+/// Backup of Resalt, as exported and imported in the settings and by `resalt-cli backup`.
+///
+/// Sections which were not selected on export are left out, and are not touched on import.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct DataDump {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub users: Option<Vec<User>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<PermissionGroup>>,
+    /// User IDs by group ID.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memberships: Option<HashMap<String, Vec<String>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minions: Option<Vec<Minion>>,
+    #[serde(
+        rename = "minionPresets",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub minion_presets: Option<Vec<MinionPreset>>,
+    /// Preferences by user ID.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferences: Option<HashMap<String, Preferences>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<Vec<Job>>,
+    #[serde(
+        rename = "jobReturns",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub job_returns: Option<Vec<JobReturn>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub events: Option<Vec<Event>>,
+    #[serde(
+        rename = "authTokens",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub auth_tokens: Option<Vec<AuthToken>>,
+}
+
+impl DataDump {
+    pub fn new() -> Self {
+        DataDump {
+            version: DATA_DUMP_VERSION,
+            ..Default::default()
+        }
+    }
+
+    /// The sections included in this dump.
+    pub fn sections(&self) -> Vec<DataDumpSection> {
+        DataDumpSection::ALL
+            .into_iter()
+            .filter(|section| match section {
+                DataDumpSection::Users => self.users.is_some(),
+                DataDumpSection::Groups => self.groups.is_some(),
+                DataDumpSection::Memberships => self.memberships.is_some(),
+                DataDumpSection::Minions => self.minions.is_some(),
+                DataDumpSection::MinionPresets => self.minion_presets.is_some(),
+                DataDumpSection::Preferences => self.preferences.is_some(),
+                DataDumpSection::Jobs => self.jobs.is_some(),
+                DataDumpSection::Events => self.events.is_some(),
+                DataDumpSection::AuthTokens => self.auth_tokens.is_some(),
+            })
+            .collect()
+    }
+
+    /// Parse a dump of any version, upgrading it to [`DATA_DUMP_VERSION`].
+    pub fn from_value(mut value: Value) -> Result<DataDump, String> {
+        let object = match value.as_object_mut() {
+            Some(object) => object,
+            None => return Err("Backup is not a JSON object".to_string()),
+        };
+        // Dumps from before versioning have no version field
+        let mut version = match object.get("version") {
+            None => 1,
+            Some(version) => match version.as_u64() {
+                Some(version) if version >= 1 => version as u32,
+                _ => return Err(format!("Invalid backup version: {}", version)),
+            },
+        };
+        if version > DATA_DUMP_VERSION {
+            return Err(format!(
+                "Backup version {} is newer than the supported version {}",
+                version, DATA_DUMP_VERSION
+            ));
+        }
+        while version < DATA_DUMP_VERSION {
+            match version {
+                1 => {
+                    // Version 1 always contained all settings sections, and nothing else
+                    for section in DataDumpSection::SETTINGS {
+                        let empty = match section {
+                            DataDumpSection::Memberships => Value::Object(Default::default()),
+                            _ => Value::Array(Vec::new()),
+                        };
+                        object.entry(section.to_string()).or_insert(empty);
+                    }
+                }
+                _ => unreachable!(),
+            }
+            version += 1;
+        }
+        object.insert("version".to_string(), Value::from(DATA_DUMP_VERSION));
+        serde_json::from_value(value).map_err(|e| format!("Invalid backup: {}", e))
+    }
+
+    /// Parse a dump as written by [`DataDump::to_bytes`], compressed or not.
+    pub fn from_bytes(bytes: &[u8]) -> Result<DataDump, String> {
+        let value: Value = match bytes.starts_with(&GZIP_MAGIC) {
+            true => {
+                let mut json = Vec::new();
+                GzDecoder::new(bytes)
+                    .read_to_end(&mut json)
+                    .map_err(|e| format!("Failed to decompress backup: {}", e))?;
+                serde_json::from_slice(&json)
+            }
+            false => serde_json::from_slice(bytes),
+        }
+        .map_err(|e| format!("Backup is not valid JSON: {}", e))?;
+        DataDump::from_value(value)
+    }
+
+    pub fn to_bytes(&self, gzip: bool) -> Result<Vec<u8>, String> {
+        let json = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        if !gzip {
+            return Ok(json);
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&json)
+            .and_then(|_| encoder.finish())
+            .map_err(|e| format!("Failed to compress backup: {}", e))
+    }
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// What an import did, or would do on a dry-run, to the objects of a section.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct DataDumpSectionSummary {
+    pub created: usize,
+    pub updated: usize,
+    /// Already identical, or conflicting with an existing object.
+    pub skipped: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct DataDumpImportSummary {
+    #[serde(rename = "dryRun")]
+    pub dry_run: bool,
+    pub sections: BTreeMap<DataDumpSection, DataDumpSectionSummary>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_upgrade_v1() {
+        let dump = DataDump::from_value(json!({
+            "users": [],
+            "groups": [],
+            "memberships": {"perm_1": ["usr_1"]},
+            "minionPresets": [{"id": "mnp_1", "name": "web", "filter": "[]"}],
+        }))
+        .unwrap();
+        assert_eq!(dump.version, DATA_DUMP_VERSION);
+        assert_eq!(dump.sections(), DataDumpSection::SETTINGS.to_vec());
+        assert_eq!(dump.minions, Some(Vec::new()));
+        assert_eq!(dump.minion_presets.unwrap()[0].name, "web");
+        assert_eq!(dump.jobs, None);
+
+        assert!(DataDump::from_value(json!({"version": DATA_DUMP_VERSION + 1})).is_err());
+        assert!(DataDump::from_value(json!({"version": 0})).is_err());
+        assert!(DataDump::from_value(json!([])).is_err());
+    }
+
+    #[test]
+    fn test_to_from_bytes() {
+        let mut dump = DataDump::new();
+        dump.minion_presets = Some(vec![MinionPreset {
+            id: "mnp_1".to_string(),
+            name: "web".to_string(),
+            filter: "[]".to_string(),
+        }]);
+        for gzip in [false, true] {
+            let bytes = dump.to_bytes(gzip).unwrap();
+            assert_eq!(bytes.starts_with(&GZIP_MAGIC), gzip);
+            assert_eq!(DataDump::from_bytes(&bytes).unwrap(), dump);
+        }
+        assert_eq!(dump.sections(), vec![DataDumpSection::MinionPresets]);
+    }
+
+    #[test]
+    fn test_section_from_str() {
+        for section in DataDumpSection::ALL {
+            assert_eq!(section.to_string().parse::<DataDumpSection>(), Ok(section));
+        }
+        assert_eq!(
+            "minionPresets".parse::<DataDumpSection>(),
+            Ok(DataDumpSection::MinionPresets)
+        );
+        assert!("nope".parse::<DataDumpSection>().is_err());
+    }
+}
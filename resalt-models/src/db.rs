@@ -1,6 +1,10 @@
-use crate::{de_string_as_option_i32, ResaltTime, SaltToken};
+use crate::{
+    de_string_as_option_i32, de_string_or_bool, de_string_or_vec, FilterNode, MinionSnapshotKind,
+    MinionStatus, ResaltTime, SaltToken,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use utoipa::ToSchema;
 
 /*
 =========================
@@ -8,7 +12,7 @@ use serde_json::{json, Value};
 =========================
 */
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct AuthToken {
     pub id: String,
     #[serde(rename = "userId")]
@@ -19,26 +23,31 @@ pub struct AuthToken {
 }
 
 impl AuthToken {
-    pub fn salt_token(&self) -> Option<SaltToken> {
-        match &self.salt_token_str {
-            Some(salt_token) => match serde_json::from_str(salt_token) {
-                Ok(salt_token) => Some(salt_token),
-                Err(_) => None,
-            },
-            None => None,
-        }
+    /// The Salt sessions of this token, one per master.
+    pub fn salt_tokens(&self) -> Vec<SaltToken> {
+        let salt_token = match &self.salt_token_str {
+            Some(salt_token) if !salt_token.is_empty() => salt_token,
+            _ => return Vec::new(),
+        };
+        // Older versions stored a single token, instead of a list
+        serde_json::from_str(salt_token)
+            .or_else(|_| serde_json::from_str(salt_token).map(|token| vec![token]))
+            .unwrap_or_default()
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct Event {
     pub id: String,
     pub timestamp: ResaltTime,
     pub tag: String,
     pub data: String,
+    /// The master the event was received from.
+    #[serde(default)]
+    pub master: Option<String>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct Job {
     pub id: String,
     pub timestamp: ResaltTime,
@@ -46,9 +55,15 @@ pub struct Job {
     pub user: Option<String>,
     #[serde(rename = "eventId")]
     pub event_id: Option<String>,
+    /// The minion preset the job was targeted at, if any.
+    #[serde(rename = "presetId", default)]
+    pub preset_id: Option<String>,
+    /// The master the job was published on.
+    #[serde(default)]
+    pub master: Option<String>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct JobReturn {
     pub id: String,
     pub timestamp: ResaltTime,
@@ -61,7 +76,7 @@ pub struct JobReturn {
     pub minion_id: String,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct Minion {
     pub id: String,
     #[serde(rename = "lastSeen")]
@@ -98,6 +113,17 @@ pub struct Minion {
     pub last_updated_conformity: Option<ResaltTime>,
     #[serde(rename = "osType")]
     pub os_type: Option<String>,
+    #[serde(default)]
+    pub status: MinionStatus,
+    /// The status last recorded as a transition, which `status` is compared against.
+    #[serde(rename = "recordedStatus", default)]
+    pub recorded_status: Option<MinionStatus>,
+    /// When the minion entered its recorded status.
+    #[serde(rename = "statusSince", default)]
+    pub status_since: Option<ResaltTime>,
+    /// The master the minion was last seen on.
+    #[serde(default)]
+    pub master: Option<String>,
 }
 
 impl Minion {
@@ -117,11 +143,35 @@ impl Minion {
             conformity_error: None,
             last_updated_conformity: None,
             os_type: None,
+            status: MinionStatus::default(),
+            recorded_status: None,
+            status_since: None,
+            master: None,
         }
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct MinionStatusTransition {
+    pub id: String,
+    #[serde(rename = "minionId")]
+    pub minion_id: String,
+    pub timestamp: ResaltTime,
+    pub from: MinionStatus,
+    pub to: MinionStatus,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MinionSnapshot {
+    pub id: String,
+    #[serde(rename = "minionId")]
+    pub minion_id: String,
+    pub kind: MinionSnapshotKind,
+    pub timestamp: ResaltTime,
+    pub data: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct Preferences {
     pub theme: String,
 }
@@ -139,7 +189,7 @@ impl Default for Preferences {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct User {
     pub id: String,
     pub username: String,
@@ -148,6 +198,12 @@ pub struct User {
     #[serde(rename = "lastLogin")]
     pub last_login: Option<ResaltTime>,
     pub email: Option<String>,
+    /// Disabled users can not log in, and their tokens are not accepted.
+    #[serde(default, deserialize_with = "de_string_or_bool")]
+    pub disabled: bool,
+    /// After this time the user is treated as disabled.
+    #[serde(rename = "expiresAt", default)]
+    pub expires_at: Option<ResaltTime>,
 }
 
 impl Default for User {
@@ -159,80 +215,134 @@ impl Default for User {
             perms: "".to_string(),
             last_login: None,
             email: None,
+            disabled: false,
+            expires_at: None,
         }
     }
 }
 
 impl User {
+    /// Whether the user may log in and use their tokens, i.e. is neither disabled nor expired.
+    pub fn is_active(&self) -> bool {
+        !self.disabled
+            && self
+                .expires_at
+                .map_or(true, |expires_at| expires_at > ResaltTime::now())
+    }
+
+    /// The user as exposed by the API, without the password.
     pub fn public(
         &self,
         permission_groups: Vec<PermissionGroup>,
         preferences: Preferences,
-    ) -> Value {
-        let mut result: Value = serde_json::value::to_value(self).unwrap();
-        // Remove password
-        result.as_object_mut().unwrap().remove("password");
-
-        // Add groups
-        let permission_groups_json = permission_groups
-            .iter()
-            .map(|g| {
-                json!({
-                    "id": g.id,
-                    "name": g.name,
+    ) -> PublicUser {
+        PublicUser {
+            id: self.id.clone(),
+            username: self.username.clone(),
+            perms: parse_perms(&self.perms),
+            last_login: self.last_login,
+            email: self.email.clone(),
+            disabled: self.disabled,
+            expires_at: self.expires_at,
+            permission_groups: permission_groups
+                .into_iter()
+                .map(|g| PublicUserGroup {
+                    id: g.id,
+                    name: g.name,
                 })
-            })
-            .collect::<Vec<Value>>();
-        result.as_object_mut().unwrap().insert(
-            "permissionGroups".to_owned(),
-            serde_json::Value::Array(permission_groups_json),
-        );
+                .collect(),
+            preferences,
+        }
+    }
+}
 
-        // Add preferences
-        result.as_object_mut().unwrap().insert(
-            "preferences".to_owned(),
-            serde_json::to_value(&preferences).unwrap(),
-        );
+/// Permissions are stored as a JSON encoded array, which is exposed as an actual array.
+fn parse_perms(perms: &str) -> Value {
+    serde_json::from_str(perms).unwrap_or_else(|_| json!(Vec::<String>::new()))
+}
 
-        // Convert "perms" to array
-        let perms: Value = match serde_json::from_str(&self.perms) {
-            Ok(perms) => perms,
-            Err(_) => json!(Vec::<String>::new()),
-        };
-        result
-            .as_object_mut()
-            .unwrap()
-            .insert("perms".to_owned(), perms);
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PublicUser {
+    pub id: String,
+    pub username: String,
+    pub perms: Value,
+    #[serde(rename = "lastLogin")]
+    pub last_login: Option<ResaltTime>,
+    pub email: Option<String>,
+    pub disabled: bool,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<ResaltTime>,
+    #[serde(rename = "permissionGroups")]
+    pub permission_groups: Vec<PublicUserGroup>,
+    pub preferences: Preferences,
+}
 
-        result
-    }
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PublicUserGroup {
+    pub id: String,
+    pub name: String,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct PermissionGroup {
     pub id: String,
     pub name: String,
     pub perms: String,
+    /// IDs of the groups included in this group. Their members are members of this
+    /// group too, and inherit its permissions.
+    #[serde(default, deserialize_with = "de_string_or_vec")]
+    pub subgroups: Vec<String>,
 }
 
 impl PermissionGroup {
-    pub fn public(&self, users: Vec<User>) -> serde_json::Value {
-        let perms: Value = match serde_json::from_str(&self.perms) {
-            Ok(perms) => perms,
-            Err(_) => json!(Vec::<String>::new()),
-        };
-        serde_json::json!({
-            "id": self.id,
-            "name": self.name,
-            "perms": perms,
-            "users": users.iter().map(|u| json!({
-                "id": u.id,
-                "username": u.username,
-            })).collect::<Vec<Value>>(),
-        })
+    /// The permissions of this group alone, without inherited ones.
+    pub fn perms_list(&self) -> Result<Vec<Value>, String> {
+        match serde_json::from_str::<Value>(&self.perms) {
+            Ok(Value::Array(perms)) => Ok(perms),
+            Ok(_) => Ok(Vec::new()),
+            Err(e) => Err(format!("Invalid permissions in group {}: {}", self.id, e)),
+        }
+    }
+
+    /// The group as exposed by the API, with its members and its permissions including
+    /// inherited ones.
+    pub fn public(&self, users: Vec<User>, resolved_perms: Vec<Value>) -> PublicPermissionGroup {
+        PublicPermissionGroup {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            perms: parse_perms(&self.perms),
+            subgroups: self.subgroups.clone(),
+            resolved_perms: Value::Array(resolved_perms),
+            users: users
+                .into_iter()
+                .map(|u| PublicPermissionGroupUser {
+                    id: u.id,
+                    username: u.username,
+                })
+                .collect(),
+        }
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PublicPermissionGroup {
+    pub id: String,
+    pub name: String,
+    pub perms: Value,
+    pub subgroups: Vec<String>,
+    /// The permissions members get from this group, including those inherited from
+    /// the groups which include it.
+    #[serde(rename = "resolvedPerms")]
+    pub resolved_perms: Value,
+    pub users: Vec<PublicPermissionGroupUser>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PublicPermissionGroupUser {
+    pub id: String,
+    pub username: String,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PermissionGroupUser {
     pub id: String,
@@ -240,13 +350,63 @@ pub struct PermissionGroupUser {
     pub user_id: String,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// A group membership which is revoked when it expires, e.g. access for an incident.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PermissionGroupGrant {
+    pub id: String,
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    #[serde(rename = "groupId")]
+    pub group_id: String,
+    pub reason: String,
+    /// The user who granted the membership.
+    #[serde(rename = "grantedBy")]
+    pub granted_by: String,
+    #[serde(rename = "grantedAt")]
+    pub granted_at: ResaltTime,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: ResaltTime,
+}
+
+impl PermissionGroupGrant {
+    pub fn is_expired(&self, now: ResaltTime) -> bool {
+        self.expires_at <= now
+    }
+}
+
+/// A record of a change to who has access to what.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct AuditRecord {
+    pub id: String,
+    pub timestamp: ResaltTime,
+    /// What happened, like "grant.expired".
+    pub action: String,
+    /// The user who made the change, or none if Resalt did.
+    #[serde(rename = "actorId")]
+    pub actor_id: Option<String>,
+    /// The user whose access changed.
+    #[serde(rename = "userId")]
+    pub user_id: Option<String>,
+    #[serde(rename = "groupId")]
+    pub group_id: Option<String>,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct MinionPreset {
     pub id: String,
     pub name: String,
     pub filter: String,
 }
 
+impl MinionPreset {
+    /// Parse the saved filter, which is stored as a JSON list of filter nodes.
+    pub fn filters(&self) -> Result<Vec<FilterNode>, String> {
+        serde_json::from_str(&self.filter)
+            .map_err(|e| format!("Invalid filter in preset {}: {}", self.id, e))
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct StorageStatus {
     pub auth_tokens_total: i64,
@@ -0,0 +1,273 @@
+use std::str::FromStr;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use utoipa::ToSchema;
+
+use crate::Minion;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ToSchema)]
+pub enum ExportFormat {
+    #[default]
+    #[serde(rename = "csv")]
+    Csv,
+    #[serde(rename = "ndjson")]
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+
+    /// Header line, only present for CSV.
+    pub fn header(&self, columns: &[String]) -> Option<String> {
+        match self {
+            ExportFormat::Csv => Some(csv_line(columns.iter().map(|c| c.as_str()))),
+            ExportFormat::Ndjson => None,
+        }
+    }
+
+    /// Encode one row of values, in the same order as `columns`.
+    pub fn row(&self, columns: &[String], values: Vec<Value>) -> String {
+        match self {
+            ExportFormat::Csv => {
+                let values: Vec<String> = values.iter().map(export_value_str).collect();
+                csv_line(values.iter().map(|v| v.as_str()))
+            }
+            ExportFormat::Ndjson => {
+                let mut map = Map::new();
+                for (column, value) in columns.iter().zip(values) {
+                    map.insert(column.clone(), value);
+                }
+                format!("{}\n", Value::Object(map))
+            }
+        }
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    // Spreadsheets run cells starting like a formula, so prefix them to be read as text
+    let field = match field.starts_with(['=', '+', '-', '@', '\t', '\r'])
+        && field.parse::<f64>().is_err()
+    {
+        true => format!("'{}", field),
+        false => field.to_string(),
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+fn csv_line<'a>(fields: impl Iterator<Item = &'a str>) -> String {
+    let fields: Vec<String> = fields.map(csv_field).collect();
+    format!("{}\r\n", fields.join(","))
+}
+
+/// Flatten a value for a CSV cell. Strings are unquoted, lists joined with ", ".
+pub fn export_value_str(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(a) => a
+            .iter()
+            .map(export_value_str)
+            .collect::<Vec<String>>()
+            .join(", "),
+        _ => value.to_string(),
+    }
+}
+
+/// A column in a minion export.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExportColumn {
+    /// A field on the minion, named as in object filters (e.g. `os_type`).
+    Field(String),
+    /// A JSONPath into the minion grains, e.g. `$.osrelease`.
+    Grain(String),
+    /// The installed version of a package, e.g. `pkg:openssl`.
+    Package(String),
+}
+
+const EXPORT_FIELDS: &[&str] = &[
+    "id",
+    "os_type",
+    "status",
+    "last_seen",
+    "last_updated_grains",
+    "last_updated_pillars",
+    "last_updated_pkgs",
+    "last_updated_conformity",
+    "conformity_success",
+    "conformity_incorrect",
+    "conformity_error",
+];
+
+impl ExportColumn {
+    pub fn default_columns() -> Vec<ExportColumn> {
+        ["id", "os_type", "status", "last_seen"]
+            .iter()
+            .map(|field| ExportColumn::Field(field.to_string()))
+            .collect()
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            ExportColumn::Field(field) => field.clone(),
+            ExportColumn::Grain(path) => path.clone(),
+            ExportColumn::Package(name) => format!("pkg:{}", name),
+        }
+    }
+
+    /// Extract the column value from a minion, with `grains` being its parsed grains.
+    /// Fields stripped from the minion (e.g. due to missing permissions) export as null.
+    pub fn minion_value(&self, minion: &Minion, grains: &Value) -> Value {
+        let opt_str = |s: Option<String>| s.map(Value::String).unwrap_or(Value::Null);
+        let opt_i32 = |i: Option<i32>| i.map(Value::from).unwrap_or(Value::Null);
+        match self {
+            ExportColumn::Field(field) => match field.as_str() {
+                "id" => Value::String(minion.id.clone()),
+                "os_type" => opt_str(minion.os_type.clone()),
+                "status" => Value::String(minion.status.to_string()),
+                "last_seen" => Value::String(minion.last_seen.to_string()),
+                "last_updated_grains" => opt_str(minion.last_updated_grains.map(|t| t.to_string())),
+                "last_updated_pillars" => {
+                    opt_str(minion.last_updated_pillars.map(|t| t.to_string()))
+                }
+                "last_updated_pkgs" => opt_str(minion.last_updated_pkgs.map(|t| t.to_string())),
+                "last_updated_conformity" => {
+                    opt_str(minion.last_updated_conformity.map(|t| t.to_string()))
+                }
+                "conformity_success" => opt_i32(minion.conformity_success),
+                "conformity_incorrect" => opt_i32(minion.conformity_incorrect),
+                "conformity_error" => opt_i32(minion.conformity_error),
+                _ => Value::Null,
+            },
+            ExportColumn::Grain(path) => match jsonpath_lib::select(grains, path) {
+                Ok(selected) => match selected.len() {
+                    0 => Value::Null,
+                    1 => selected[0].clone(),
+                    _ => Value::Array(selected.into_iter().cloned().collect()),
+                },
+                Err(_) => Value::Null,
+            },
+            ExportColumn::Package(name) => {
+                let pkgs = minion.pkgs.as_deref().unwrap_or("{}");
+                match serde_json::from_str::<Value>(pkgs) {
+                    Ok(pkgs) => pkgs.get(name).cloned().unwrap_or(Value::Null),
+                    Err(_) => Value::Null,
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for ExportColumn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('$') {
+            // Validate the JSONPath up front, rather than exporting empty columns
+            jsonpath_lib::select(&Value::Null, s)
+                .map_err(|e| format!("Invalid JSONPath \"{}\": {:?}", s, e))?;
+            Ok(ExportColumn::Grain(s.to_string()))
+        } else if let Some(name) = s.strip_prefix("pkg:") {
+            Ok(ExportColumn::Package(name.to_string()))
+        } else if EXPORT_FIELDS.contains(&s) {
+            Ok(ExportColumn::Field(s.to_string()))
+        } else {
+            Err(format!("Unknown export column: {}", s))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_csv_escaping() {
+        let columns = vec!["id".to_string(), "note".to_string()];
+        assert_eq!(
+            ExportFormat::Csv.row(&columns, vec![json!("m1"), json!("a, \"b\"")]),
+            "m1,\"a, \"\"b\"\"\"\r\n"
+        );
+        assert_eq!(
+            ExportFormat::Csv.header(&columns),
+            Some("id,note\r\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_csv_formula_injection() {
+        let columns = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        assert_eq!(
+            ExportFormat::Csv.row(
+                &columns,
+                vec![json!("=1+2"), json!("@SUM(A1)"), json!("-2+3|cmd")]
+            ),
+            "'=1+2,'@SUM(A1),'-2+3|cmd\r\n"
+        );
+        assert_eq!(
+            ExportFormat::Csv.row(&columns, vec![json!("\tx"), json!("\rx"), json!("a=b")]),
+            "'\tx,\"'\rx\",a=b\r\n"
+        );
+        // Numbers are left alone
+        assert_eq!(
+            ExportFormat::Csv.row(&columns, vec![json!(-5), json!("-1.5"), json!("+3")]),
+            "-5,-1.5,+3\r\n"
+        );
+        assert_eq!(
+            ExportFormat::Csv.header(&["=cmd".to_string()]),
+            Some("'=cmd\r\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_ndjson_row() {
+        let columns = vec!["id".to_string(), "$.ipv4".to_string()];
+        let row = ExportFormat::Ndjson.row(&columns, vec![json!("m1"), json!(["10.0.0.1"])]);
+        assert!(row.ends_with('\n'));
+        assert_eq!(
+            serde_json::from_str::<Value>(&row).unwrap(),
+            json!({"id": "m1", "$.ipv4": ["10.0.0.1"]})
+        );
+        assert_eq!(ExportFormat::Ndjson.header(&columns), None);
+    }
+
+    #[test]
+    fn test_export_columns() {
+        let mut minion = Minion::default_with_id("m1");
+        minion.pkgs = Some(r#"{"openssl":"3.0.2"}"#.to_string());
+        minion.conformity_success = Some(3);
+        let grains = json!({"osrelease": "22.04", "ipv4": ["10.0.0.1", "127.0.0.1"]});
+
+        let value = |column: &str| {
+            column
+                .parse::<ExportColumn>()
+                .unwrap()
+                .minion_value(&minion, &grains)
+        };
+        assert_eq!(value("id"), json!("m1"));
+        assert_eq!(value("$.osrelease"), json!("22.04"));
+        assert_eq!(value("$.ipv4[*]"), json!(["10.0.0.1", "127.0.0.1"]));
+        assert_eq!(value("pkg:openssl"), json!("3.0.2"));
+        assert_eq!(value("pkg:missing"), Value::Null);
+        assert_eq!(value("conformity_success"), json!(3));
+        assert_eq!(value("conformity_error"), Value::Null);
+        assert!("unknown".parse::<ExportColumn>().is_err());
+    }
+}
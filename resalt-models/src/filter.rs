@@ -1,5 +1,9 @@
+use std::{collections::HashMap, fmt};
+
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use utoipa::IntoParams;
 use version_compare::Cmp;
 
 use crate::{empty_i64_as_none, strip_quotes, Minion, ResaltTime};
@@ -7,7 +11,8 @@ use crate::{empty_i64_as_none, strip_quotes, Minion, ResaltTime};
 /// Pagination
 pub type Paginate = Option<(i64, i64)>;
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct PaginateQuery {
     #[serde(default, deserialize_with = "empty_i64_as_none")]
     pub limit: Option<i64>,
@@ -34,6 +39,48 @@ pub struct Filter {
     pub value: String,
 }
 
+/// A node in a filter tree. A list of nodes at the top level is ANDed together,
+/// like an `all` group, and a `not` node inverts the node it wraps.
+///
+/// ```rust
+/// use resalt_models::FilterNode;
+///
+/// let filters: Vec<FilterNode> = serde_json::from_str(r#"[
+///     {"fieldType": "object", "field": "status", "operand": "e", "value": "online"},
+///     {"any": [
+///         {"fieldType": "grain", "field": "$.os", "operand": "e", "value": "Ubuntu"},
+///         {"fieldType": "grain", "field": "$.os", "operand": "e", "value": "Debian"}
+///     ]}
+/// ]"#).unwrap();
+/// assert_eq!(filters.len(), 2);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FilterNode {
+    All { all: Vec<FilterNode> },
+    Any { any: Vec<FilterNode> },
+    Not { not: Box<FilterNode> },
+    Filter(Filter),
+}
+
+impl FilterNode {
+    /// Whether any filter in this node, at any depth, is on the given field type.
+    pub fn uses_field_type(&self, field_type: &FilterFieldType) -> bool {
+        match self {
+            FilterNode::All { all } => all.iter().any(|n| n.uses_field_type(field_type)),
+            FilterNode::Any { any } => any.iter().any(|n| n.uses_field_type(field_type)),
+            FilterNode::Not { not } => not.uses_field_type(field_type),
+            FilterNode::Filter(filter) => &filter.field_type == field_type,
+        }
+    }
+}
+
+impl From<Filter> for FilterNode {
+    fn from(filter: Filter) -> Self {
+        FilterNode::Filter(filter)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum FilterFieldType {
     #[serde(rename = "")]
@@ -44,6 +91,8 @@ pub enum FilterFieldType {
     Grain,
     #[serde(rename = "package")]
     Package,
+    #[serde(rename = "pillar")]
+    Pillar,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -64,6 +113,22 @@ pub enum FilterOperand {
     GreaterThanOrEqual,
     #[serde(rename = "lte")]
     LessThanOrEqual,
+    #[serde(rename = "gt")]
+    GreaterThan,
+    #[serde(rename = "lt")]
+    LessThan,
+    #[serde(rename = "re")]
+    Regex,
+    #[serde(rename = "in")]
+    In,
+    #[serde(rename = "ex")]
+    Exists,
+    #[serde(rename = "nex")]
+    NotExists,
+    #[serde(rename = "empty")]
+    IsEmpty,
+    #[serde(rename = "nempty")]
+    NotEmpty,
 }
 
 impl FilterOperand {
@@ -77,8 +142,153 @@ impl FilterOperand {
             FilterOperand::EndsWith => a.ends_with(b),
             FilterOperand::GreaterThanOrEqual => a >= b,
             FilterOperand::LessThanOrEqual => a <= b,
+            FilterOperand::GreaterThan => a > b,
+            FilterOperand::LessThan => a < b,
+            FilterOperand::In => filter_in_logic(a, b),
+            FilterOperand::Exists | FilterOperand::NotEmpty => !a.is_empty(),
+            FilterOperand::NotExists | FilterOperand::IsEmpty => a.is_empty(),
+            // Regexes are pre-compiled and evaluated in `filter_generic_logic`
+            FilterOperand::Regex => false,
+        }
+    }
+
+    /// Operands which behave the same regardless of field type.
+    fn is_generic(&self) -> bool {
+        matches!(
+            self,
+            FilterOperand::Regex
+                | FilterOperand::In
+                | FilterOperand::Exists
+                | FilterOperand::NotExists
+                | FilterOperand::IsEmpty
+                | FilterOperand::NotEmpty
+        )
+    }
+}
+
+/// Why a filter can not be evaluated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FilterError {
+    UnknownField(String),
+    InvalidJsonPath(String),
+    InvalidRegex(String),
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::UnknownField(field) => write!(f, "Filtering on unknown field: {}", field),
+            FilterError::InvalidJsonPath(path) => {
+                write!(f, "Filtering with invalid JSONPath: {}", path)
+            }
+            FilterError::InvalidRegex(regex) => {
+                write!(f, "Filtering with invalid regex: {}", regex)
+            }
+        }
+    }
+}
+
+pub(crate) const FILTER_OBJECT_FIELDS: &[&str] = &[
+    "id",
+    "os_type",
+    "status",
+    "last_seen",
+    "conformity_success",
+    "conformity_incorrect",
+    "conformity_error",
+];
+
+/// Regexes compiled once per filter evaluation, keyed by pattern.
+type FilterRegexes = HashMap<String, Regex>;
+
+fn compile_filters(filters: &[FilterNode], regexes: &mut FilterRegexes) -> Result<(), FilterError> {
+    for node in filters {
+        let filter = match node {
+            FilterNode::All { all } => {
+                compile_filters(all, regexes)?;
+                continue;
+            }
+            FilterNode::Any { any } => {
+                compile_filters(any, regexes)?;
+                continue;
+            }
+            FilterNode::Not { not } => {
+                compile_filters(std::slice::from_ref(not), regexes)?;
+                continue;
+            }
+            FilterNode::Filter(filter) => filter,
+        };
+        match filter.field_type {
+            FilterFieldType::None | FilterFieldType::Package => {}
+            FilterFieldType::Object => {
+                if !FILTER_OBJECT_FIELDS.contains(&filter.field.as_str()) {
+                    return Err(FilterError::UnknownField(filter.field.clone()));
+                }
+            }
+            FilterFieldType::Grain | FilterFieldType::Pillar => {
+                if jsonpath_lib::select(&Value::Null, &filter.field).is_err() {
+                    return Err(FilterError::InvalidJsonPath(filter.field.clone()));
+                }
+            }
+        }
+        if filter.operand == FilterOperand::Regex && !regexes.contains_key(&filter.value) {
+            let regex = Regex::new(&filter.value)
+                .map_err(|_| FilterError::InvalidRegex(filter.value.clone()))?;
+            regexes.insert(filter.value.clone(), regex);
+        }
+    }
+    Ok(())
+}
+
+/// Check that all fields, JSONPaths and regexes in the filters are valid.
+pub fn validate_filters(filters: &[FilterNode]) -> Result<(), FilterError> {
+    compile_filters(filters, &mut HashMap::new())
+}
+
+/// Split the value of an `in` filter into its items. Items are separated by `,`,
+/// and `\` escapes a `,` or `\` which is part of an item.
+pub fn split_in_list(list: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut chars = list.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => item.push(chars.next().unwrap_or('\\')),
+            ',' => items.push(std::mem::take(&mut item).trim().to_string()),
+            c => item.push(c),
         }
     }
+    items.push(item.trim().to_string());
+    items
+}
+
+/// Join items into the value of an `in` filter, escaping `,` and `\`.
+pub fn join_in_list(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| item.replace('\\', "\\\\").replace(',', "\\,"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn filter_in_logic(a: &str, list: &str) -> bool {
+    split_in_list(list).iter().any(|item| item == a)
+}
+
+/// Evaluate operands which only depend on the (possibly missing) string value of the field.
+fn filter_generic_logic(value: Option<&str>, filter: &Filter, regexes: &FilterRegexes) -> bool {
+    match filter.operand {
+        FilterOperand::Exists => value.is_some(),
+        FilterOperand::NotExists => value.is_none(),
+        FilterOperand::IsEmpty => value.map_or(true, |v| v.is_empty()),
+        FilterOperand::NotEmpty => value.is_some_and(|v| !v.is_empty()),
+        FilterOperand::In => value.is_some_and(|v| filter_in_logic(v, &filter.value)),
+        FilterOperand::Regex => match (value, regexes.get(&filter.value)) {
+            (Some(value), Some(regex)) => regex.is_match(value),
+            _ => false,
+        },
+        _ => false,
+    }
 }
 
 fn value_to_simple_str(value: &Value) -> String {
@@ -103,10 +313,9 @@ fn filter_i32_logic(minion_value: i32, filter_value: &str, operand: &FilterOpera
             FilterOperand::NotEquals => minion_value != filter_value,
             FilterOperand::GreaterThanOrEqual => minion_value >= filter_value,
             FilterOperand::LessThanOrEqual => minion_value <= filter_value,
-            FilterOperand::Contains
-            | FilterOperand::NotContains
-            | FilterOperand::StartsWith
-            | FilterOperand::EndsWith => false,
+            FilterOperand::GreaterThan => minion_value > filter_value,
+            FilterOperand::LessThan => minion_value < filter_value,
+            _ => false,
         },
         Err(_) => false,
     }
@@ -128,244 +337,367 @@ fn filter_timestamp_logic(
         FilterOperand::NotEquals => minion_timestamp != filter_timestamp,
         FilterOperand::GreaterThanOrEqual => minion_timestamp >= filter_timestamp,
         FilterOperand::LessThanOrEqual => minion_timestamp <= filter_timestamp,
+        FilterOperand::GreaterThan => minion_timestamp > filter_timestamp,
+        FilterOperand::LessThan => minion_timestamp < filter_timestamp,
+        _ => false,
     }
 }
 
-fn filter_minion(minion: &Minion, filters: &[Filter]) -> bool {
-    for filter in filters {
-        let operand = filter.operand.clone();
-        match filter.field_type {
-            FilterFieldType::None => {}
-            FilterFieldType::Object => match filter.field.as_str() {
-                "id" => {
-                    if !operand.filter_str_logic(&minion.id, &filter.value) {
-                        return false;
-                    }
-                }
-                "os_type" => {
-                    let value = minion.os_type.as_deref().unwrap_or("");
-                    if !operand.filter_str_logic(value, &filter.value) {
-                        return false;
-                    }
-                }
-                "last_seen" => {
-                    if !filter_timestamp_logic(
-                        minion.last_seen,
-                        ResaltTime::parse_from_rfc3339(&filter.value).unwrap_or_default(),
-                        &filter.operand,
-                    ) {
-                        return false;
-                    }
-                }
-                "conformity_success" => {
-                    let value: i32 = match minion.conformity_success {
-                        Some(value) => value,
-                        None => return false,
-                    };
-                    if !filter_i32_logic(value, &filter.value, &filter.operand) {
-                        return false;
-                    }
-                }
-                "conformity_incorrect" => {
-                    let value: i32 = match minion.conformity_incorrect {
-                        Some(value) => value,
-                        None => return false,
-                    };
-                    if !filter_i32_logic(value, &filter.value, &filter.operand) {
-                        return false;
-                    }
-                }
-                "conformity_error" => {
-                    let value: i32 = match minion.conformity_error {
-                        Some(value) => value,
-                        None => return false,
-                    };
-                    if !filter_i32_logic(value, &filter.value, &filter.operand) {
-                        return false;
-                    }
-                }
-                _ => panic!("Filtering on unknown field: {}", filter.field),
-            },
-            FilterFieldType::Grain => {
-                let grains = minion.grains.clone().unwrap_or_default();
-                let grains: Value = serde_json::from_str(&grains).unwrap_or_default();
-                let json_path = filter.field.clone();
-                let selected = match jsonpath_lib::select(&grains, &json_path) {
-                    Ok(selected) => selected,
-                    Err(_) => panic!("Filtering on grain with invalid JSONPath: {}", json_path),
-                };
-
-                // Convert the selected JSON value to a string. "selected" is always a JSON array.
-                // If it is empty, return an empty string.
-                // If it contains just one object, return that, without quotes.
-                // If it contains multiple objects, join them with ", " and without each string having quotes.
-                let selected_str = match selected.len() {
-                    0 => {
-                        if filter.operand == FilterOperand::NotContains && filter.value.is_empty() {
-                            return false;
-                        }
-                        String::new()
-                    }
-                    1 => value_to_simple_str(selected[0]),
-                    _ => selected
-                        .iter()
-                        .map(|s| value_to_simple_str(s))
-                        .collect::<Vec<String>>()
-                        .join(", "),
-                };
-
-                // log::debug!("Selected stringified: {}", selected_str);
-
-                match filter.operand {
-                    FilterOperand::Contains => {
-                        if !selected_str.contains(&filter.value) {
-                            return false;
-                        }
-                    }
-                    FilterOperand::NotContains => {
-                        if selected_str.contains(&filter.value) {
-                            return false;
-                        }
-                    }
-                    FilterOperand::Equals => {
-                        if selected_str != filter.value {
-                            return false;
-                        }
-                    }
-                    FilterOperand::NotEquals => {
-                        if selected_str == filter.value {
-                            return false;
-                        }
-                    }
-                    FilterOperand::StartsWith => {
-                        if !selected_str.starts_with(&filter.value) {
-                            return false;
-                        }
-                    }
-                    FilterOperand::EndsWith => {
-                        if !selected_str.ends_with(&filter.value) {
-                            return false;
-                        }
-                    }
-                    FilterOperand::GreaterThanOrEqual => {
-                        let selected_float = match selected_str.parse::<f64>() {
-                            Ok(selected_float) => selected_float,
-                            Err(_) => {
-                                return false;
-                            }
-                        };
-                        let filter_float = match filter.value.parse::<f64>() {
-                            Ok(filter_float) => filter_float,
-                            Err(_) => {
-                                return false;
-                            }
-                        };
-                        if selected_float < filter_float {
-                            return false;
-                        }
-                    }
-                    FilterOperand::LessThanOrEqual => {
-                        let selected_float = match selected_str.parse::<f64>() {
-                            Ok(selected_float) => selected_float,
-                            Err(_) => {
-                                return false;
-                            }
-                        };
-                        let filter_float = match filter.value.parse::<f64>() {
-                            Ok(filter_float) => filter_float,
-                            Err(_) => {
-                                return false;
-                            }
-                        };
-                        if selected_float > filter_float {
-                            return false;
-                        }
-                    }
-                };
+/// The string value of an object field, `None` if the minion does not have it set.
+fn object_field_str(minion: &Minion, field: &str) -> Option<String> {
+    match field {
+        "id" => Some(minion.id.clone()),
+        "os_type" => minion.os_type.clone(),
+        "status" => Some(minion.status.to_string()),
+        "last_seen" => Some(minion.last_seen.to_string()),
+        "conformity_success" => minion.conformity_success.map(|v| v.to_string()),
+        "conformity_incorrect" => minion.conformity_incorrect.map(|v| v.to_string()),
+        "conformity_error" => minion.conformity_error.map(|v| v.to_string()),
+        _ => None,
+    }
+}
+
+fn filter_object(minion: &Minion, filter: &Filter) -> bool {
+    let operand = filter.operand.clone();
+    match filter.field.as_str() {
+        "id" => operand.filter_str_logic(&minion.id, &filter.value),
+        "os_type" => {
+            let value = minion.os_type.as_deref().unwrap_or("");
+            operand.filter_str_logic(value, &filter.value)
+        }
+        "status" => operand.filter_str_logic(&minion.status.to_string(), &filter.value),
+        "last_seen" => filter_timestamp_logic(
+            minion.last_seen,
+            ResaltTime::parse_from_rfc3339(&filter.value).unwrap_or_default(),
+            &filter.operand,
+        ),
+        "conformity_success" => match minion.conformity_success {
+            Some(value) => filter_i32_logic(value, &filter.value, &filter.operand),
+            None => false,
+        },
+        "conformity_incorrect" => match minion.conformity_incorrect {
+            Some(value) => filter_i32_logic(value, &filter.value, &filter.operand),
+            None => false,
+        },
+        "conformity_error" => match minion.conformity_error {
+            Some(value) => filter_i32_logic(value, &filter.value, &filter.operand),
+            None => false,
+        },
+        // Rejected by `validate_filters`
+        _ => false,
+    }
+}
+
+/// Filter on a JSONPath selection in a JSON blob, such as grains or pillars.
+fn filter_json_path(data: Option<&str>, filter: &Filter, regexes: &FilterRegexes) -> bool {
+    let data: Value = serde_json::from_str(data.unwrap_or_default()).unwrap_or_default();
+    let json_path = filter.field.clone();
+    let selected = match jsonpath_lib::select(&data, &json_path) {
+        Ok(selected) => selected,
+        // Rejected by `validate_filters`
+        Err(_) => return false,
+    };
+
+    // Convert the selected JSON value to a string. "selected" is always a JSON array.
+    // If it is empty, return an empty string.
+    // If it contains just one object, return that, without quotes.
+    // If it contains multiple objects, join them with ", " and without each string having quotes.
+    let selected_str = match selected.len() {
+        0 => {
+            if filter.operand == FilterOperand::NotContains && filter.value.is_empty() {
+                return false;
+            }
+            if filter.operand.is_generic() {
+                return filter_generic_logic(None, filter, regexes);
+            }
+            String::new()
+        }
+        1 => value_to_simple_str(selected[0]),
+        _ => selected
+            .iter()
+            .map(|s| value_to_simple_str(s))
+            .collect::<Vec<String>>()
+            .join(", "),
+    };
+
+    // log::debug!("Selected stringified: {}", selected_str);
+
+    match filter.operand {
+        FilterOperand::Contains => selected_str.contains(&filter.value),
+        FilterOperand::NotContains => !selected_str.contains(&filter.value),
+        FilterOperand::Equals => selected_str == filter.value,
+        FilterOperand::NotEquals => selected_str != filter.value,
+        FilterOperand::StartsWith => selected_str.starts_with(&filter.value),
+        FilterOperand::EndsWith => selected_str.ends_with(&filter.value),
+        FilterOperand::GreaterThanOrEqual
+        | FilterOperand::LessThanOrEqual
+        | FilterOperand::GreaterThan
+        | FilterOperand::LessThan => {
+            let selected_float = match selected_str.parse::<f64>() {
+                Ok(selected_float) => selected_float,
+                Err(_) => return false,
+            };
+            let filter_float = match filter.value.parse::<f64>() {
+                Ok(filter_float) => filter_float,
+                Err(_) => return false,
+            };
+            match filter.operand {
+                FilterOperand::GreaterThanOrEqual => selected_float >= filter_float,
+                FilterOperand::LessThanOrEqual => selected_float <= filter_float,
+                FilterOperand::GreaterThan => selected_float > filter_float,
+                _ => selected_float < filter_float,
+            }
+        }
+        _ => {
+            // A single selected null, empty list or empty object counts as empty
+            let selected_str = match selected.len() {
+                1 => match selected[0] {
+                    Value::Null => String::new(),
+                    Value::Array(a) if a.is_empty() => String::new(),
+                    Value::Object(o) if o.is_empty() => String::new(),
+                    _ => selected_str,
+                },
+                _ => selected_str,
+            };
+            filter_generic_logic(Some(&selected_str), filter, regexes)
+        }
+    }
+}
+
+fn filter_package(minion: &Minion, filter: &Filter, regexes: &FilterRegexes) -> bool {
+    let packages = minion.pkgs.clone().unwrap_or_default();
+    let packages: Value = serde_json::from_str(&packages).unwrap_or_default();
+    let version = match &packages[&filter.field] {
+        Value::String(s) => Some(s),
+        _ => None,
+    };
+
+    match filter.operand {
+        FilterOperand::Contains => {
+            if filter.value.is_empty() {
+                version.is_some()
+            } else {
+                version.is_some_and(|version| version.contains(&filter.value))
+            }
+        }
+        FilterOperand::NotContains => {
+            if filter.value.is_empty() {
+                version.is_none()
+            } else {
+                !version.is_some_and(|version| version.contains(&filter.value))
             }
-            FilterFieldType::Package => {
-                let packages = minion.pkgs.clone().unwrap_or_default();
-                let packages: Value = serde_json::from_str(&packages).unwrap_or_default();
-                let version = match &packages[&filter.field] {
-                    Value::String(s) => Some(s),
-                    _ => None,
-                };
-
-                match filter.operand {
-                    FilterOperand::Contains => {
-                        if filter.value.is_empty() {
-                            if version.is_none() {
-                                return false;
-                            }
-                        } else if version.is_none() || !version.unwrap().contains(&filter.value) {
-                            return false;
-                        }
-                    }
-                    FilterOperand::NotContains => {
-                        if filter.value.is_empty() {
-                            if version.is_some() {
-                                return false;
-                            }
-                        } else if version.is_some() && version.unwrap().contains(&filter.value) {
-                            return false;
-                        }
-                    }
-                    FilterOperand::Equals => {
-                        if version.is_none() || version.unwrap() != &filter.value {
-                            return false;
-                        }
-                    }
-                    FilterOperand::NotEquals => {
-                        if version.is_none() || version.unwrap() == &filter.value {
-                            return false;
-                        }
-                    }
-                    FilterOperand::StartsWith => {
-                        if version.is_none() || !version.unwrap().starts_with(&filter.value) {
-                            return false;
-                        }
-                    }
-                    FilterOperand::EndsWith => {
-                        if version.is_none() || !version.unwrap().ends_with(&filter.value) {
-                            return false;
-                        }
-                    }
-                    FilterOperand::GreaterThanOrEqual => {
-                        if version.is_none() {
-                            return false;
-                        }
-                        match version_compare::compare_to(version.unwrap(), &filter.value, Cmp::Ge)
-                        {
-                            Ok(result) => {
-                                if !result {
-                                    return false;
-                                }
-                            }
-                            Err(_) => return false,
-                        }
-                    }
-                    FilterOperand::LessThanOrEqual => {
-                        if version.is_none() {
-                            return false;
-                        }
-                        match version_compare::compare_to(version.unwrap(), &filter.value, Cmp::Le)
-                        {
-                            Ok(result) => {
-                                if !result {
-                                    return false;
-                                }
-                            }
-                            Err(_) => return false,
-                        }
-                    }
-                };
+        }
+        FilterOperand::Equals => version.is_some_and(|version| version == &filter.value),
+        FilterOperand::NotEquals => version.is_some_and(|version| version != &filter.value),
+        FilterOperand::StartsWith => {
+            version.is_some_and(|version| version.starts_with(&filter.value))
+        }
+        FilterOperand::EndsWith => version.is_some_and(|version| version.ends_with(&filter.value)),
+        FilterOperand::GreaterThanOrEqual => version.is_some_and(|version| {
+            version_compare::compare_to(version, &filter.value, Cmp::Ge).unwrap_or(false)
+        }),
+        FilterOperand::LessThanOrEqual => version.is_some_and(|version| {
+            version_compare::compare_to(version, &filter.value, Cmp::Le).unwrap_or(false)
+        }),
+        FilterOperand::GreaterThan => version.is_some_and(|version| {
+            version_compare::compare_to(version, &filter.value, Cmp::Gt).unwrap_or(false)
+        }),
+        FilterOperand::LessThan => version.is_some_and(|version| {
+            version_compare::compare_to(version, &filter.value, Cmp::Lt).unwrap_or(false)
+        }),
+        _ => filter_generic_logic(version.map(|v| v.as_str()), filter, regexes),
+    }
+}
+
+fn filter_leaf(minion: &Minion, filter: &Filter, regexes: &FilterRegexes) -> bool {
+    match filter.field_type {
+        FilterFieldType::None => true,
+        FilterFieldType::Object => {
+            if filter.operand.is_generic() {
+                let value = object_field_str(minion, &filter.field);
+                filter_generic_logic(value.as_deref(), filter, regexes)
+            } else {
+                filter_object(minion, filter)
             }
         }
+        FilterFieldType::Grain => filter_json_path(minion.grains.as_deref(), filter, regexes),
+        FilterFieldType::Pillar => filter_json_path(minion.pillars.as_deref(), filter, regexes),
+        FilterFieldType::Package => filter_package(minion, filter, regexes),
+    }
+}
+
+fn filter_node(minion: &Minion, node: &FilterNode, regexes: &FilterRegexes) -> bool {
+    match node {
+        FilterNode::All { all } => all.iter().all(|n| filter_node(minion, n, regexes)),
+        // An empty any-group matches nothing, just like an empty OR
+        FilterNode::Any { any } => any.iter().any(|n| filter_node(minion, n, regexes)),
+        FilterNode::Not { not } => !filter_node(minion, not, regexes),
+        FilterNode::Filter(filter) => filter_leaf(minion, filter, regexes),
     }
-    true
 }
 
-pub fn filter_minions(minions: &mut Vec<Minion>, filters: &[Filter]) {
-    // Filter each minion on filter_minion
-    minions.retain(|minion| filter_minion(minion, filters));
+pub fn filter_minions(
+    minions: &mut Vec<Minion>,
+    filters: &[FilterNode],
+) -> Result<(), FilterError> {
+    let mut regexes: FilterRegexes = HashMap::new();
+    compile_filters(filters, &mut regexes)?;
+
+    // Filter each minion on all top-level filters
+    minions.retain(|minion| {
+        filters
+            .iter()
+            .all(|node| filter_node(minion, node, &regexes))
+    });
+    Ok(())
+}
+
+/// A single filter, for tests building filter trees.
+#[cfg(test)]
+pub(crate) fn leaf(
+    field_type: FilterFieldType,
+    field: &str,
+    operand: FilterOperand,
+    value: &str,
+) -> FilterNode {
+    FilterNode::Filter(Filter {
+        field_type,
+        field: field.to_string(),
+        operand,
+        value: value.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minions() -> Vec<Minion> {
+        let mut web = Minion::default_with_id("web01");
+        web.os_type = Some("Ubuntu 22.04".to_string());
+        web.grains = Some(r#"{"os":"Ubuntu","roles":["web"],"empty":""}"#.to_string());
+        web.pkgs = Some(r#"{"nginx":"1.24.0"}"#.to_string());
+        let mut db = Minion::default_with_id("db01");
+        db.os_type = Some("Debian 12".to_string());
+        db.grains = Some(r#"{"os":"Debian","roles":["db"]}"#.to_string());
+        db.pkgs = Some(r#"{"postgresql":"15.4"}"#.to_string());
+        db.pillars = Some(r#"{"postgres":{"version":15}}"#.to_string());
+        vec![web, db]
+    }
+
+    fn ids(filters: &[FilterNode]) -> Vec<String> {
+        let mut minions = minions();
+        filter_minions(&mut minions, filters).unwrap();
+        minions.into_iter().map(|m| m.id).collect()
+    }
+
+    #[test]
+    fn test_filter_groups() {
+        let any = FilterNode::Any {
+            any: vec![
+                leaf(
+                    FilterFieldType::Object,
+                    "id",
+                    FilterOperand::Equals,
+                    "web01",
+                ),
+                leaf(FilterFieldType::Object, "id", FilterOperand::Equals, "db01"),
+            ],
+        };
+        assert_eq!(ids(std::slice::from_ref(&any)), vec!["web01", "db01"]);
+
+        let all = FilterNode::All {
+            all: vec![
+                any,
+                leaf(
+                    FilterFieldType::Grain,
+                    "$.os",
+                    FilterOperand::Equals,
+                    "Debian",
+                ),
+            ],
+        };
+        assert_eq!(ids(&[all]), vec!["db01"]);
+        assert!(ids(&[FilterNode::Any { any: vec![] }]).is_empty());
+    }
+
+    #[test]
+    fn test_filter_new_operands() {
+        use FilterFieldType::*;
+        use FilterOperand::*;
+        assert_eq!(
+            ids(&[leaf(Object, "id", Regex, "^web\\d+$")]),
+            vec!["web01"]
+        );
+        assert_eq!(ids(&[leaf(Object, "id", In, "db01, other")]), vec!["db01"]);
+        assert!(ids(&[leaf(Object, "id", In, "db\\,01, other")]).is_empty());
+        let items = vec!["a,b".to_string(), "c\\".to_string(), "d".to_string()];
+        assert_eq!(split_in_list(&join_in_list(&items)), items);
+        assert_eq!(ids(&[leaf(Package, "nginx", Exists, "")]), vec!["web01"]);
+        assert_eq!(ids(&[leaf(Package, "nginx", NotExists, "")]), vec!["db01"]);
+        assert_eq!(
+            ids(&[leaf(Grain, "$.empty", IsEmpty, "")]),
+            vec!["web01", "db01"]
+        );
+        assert_eq!(ids(&[leaf(Grain, "$.empty", Exists, "")]), vec!["web01"]);
+        assert_eq!(ids(&[leaf(Grain, "$.os", Regex, "^Deb")]), vec!["db01"]);
+        assert_eq!(
+            ids(&[leaf(Object, "os_type", NotEmpty, "")]),
+            vec!["web01", "db01"]
+        );
+    }
+
+    #[test]
+    fn test_filter_pillar() {
+        let filter = leaf(
+            FilterFieldType::Pillar,
+            "$.postgres.version",
+            FilterOperand::GreaterThanOrEqual,
+            "14",
+        );
+        assert!(filter.uses_field_type(&FilterFieldType::Pillar));
+        assert_eq!(ids(&[filter]), vec!["db01"]);
+        let nested = FilterNode::All {
+            all: vec![leaf(
+                FilterFieldType::Pillar,
+                "$.postgres",
+                FilterOperand::NotExists,
+                "",
+            )],
+        };
+        assert!(nested.uses_field_type(&FilterFieldType::Pillar));
+        assert_eq!(ids(&[nested]), vec!["web01"]);
+    }
+
+    #[test]
+    fn test_filter_errors() {
+        use FilterFieldType::*;
+        use FilterOperand::*;
+        let mut minions = minions();
+        assert_eq!(
+            filter_minions(&mut minions, &[leaf(Object, "nope", Equals, "")]),
+            Err(FilterError::UnknownField("nope".to_string()))
+        );
+        assert_eq!(
+            filter_minions(&mut minions, &[leaf(Grain, "$[", Equals, "")]),
+            Err(FilterError::InvalidJsonPath("$[".to_string()))
+        );
+        let nested = FilterNode::Any {
+            any: vec![leaf(Object, "id", Regex, "(")],
+        };
+        assert_eq!(
+            validate_filters(&[nested]),
+            Err(FilterError::InvalidRegex("(".to_string()))
+        );
+        assert_eq!(minions.len(), 2);
+    }
+
+    #[test]
+    fn test_filter_legacy_json() {
+        let filters: Vec<FilterNode> = serde_json::from_str(
+            r#"[{"fieldType":"object","field":"id","operand":"sw","value":"web"}]"#,
+        )
+        .unwrap();
+        assert_eq!(ids(&filters), vec!["web01"]);
+    }
 }
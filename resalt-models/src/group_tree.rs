@@ -0,0 +1,175 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use serde_json::Value;
+
+use crate::PermissionGroup;
+
+/// All permission groups, to resolve the permissions inherited through nested groups.
+///
+/// A group including a subgroup makes the members of the subgroup members of the group
+/// too, so they inherit its permissions.
+#[derive(Clone, Debug, Default)]
+pub struct PermissionGroupTree {
+    groups: HashMap<String, PermissionGroup>,
+}
+
+impl PermissionGroupTree {
+    pub fn new(groups: Vec<PermissionGroup>) -> Self {
+        Self {
+            groups: groups
+                .into_iter()
+                .map(|group| (group.id.clone(), group))
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&PermissionGroup> {
+        self.groups.get(id)
+    }
+
+    /// Add or replace a group, e.g. to check a change before saving it.
+    pub fn insert(&mut self, group: PermissionGroup) {
+        self.groups.insert(group.id.clone(), group);
+    }
+
+    /// Groups which directly include `id`, sorted so inherited permissions keep their order.
+    fn parents(&self, id: &str) -> Vec<&str> {
+        let mut parents: Vec<&str> = self
+            .groups
+            .values()
+            .filter(|group| group.subgroups.iter().any(|subgroup| subgroup == id))
+            .map(|group| group.id.as_str())
+            .collect();
+        parents.sort();
+        parents
+    }
+
+    /// Breadth-first walk from `ids`, visiting each existing group once.
+    fn walk<'a>(&'a self, ids: &[&'a str], next: impl Fn(&'a str) -> Vec<&'a str>) -> Vec<String> {
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut queue: VecDeque<&str> = ids.iter().copied().collect();
+        let mut result = Vec::new();
+        while let Some(id) = queue.pop_front() {
+            if !self.groups.contains_key(id) || !seen.insert(id) {
+                continue;
+            }
+            result.push(id.to_string());
+            queue.extend(next(id));
+        }
+        result
+    }
+
+    /// The group and every group including it, directly or through other groups,
+    /// nearest first.
+    pub fn ancestors(&self, id: &str) -> Vec<String> {
+        self.walk(&[id], |id| self.parents(id))
+    }
+
+    /// The group and every group it includes, directly or through other groups.
+    pub fn descendants(&self, id: &str) -> Vec<String> {
+        self.walk(&[id], |id| match self.groups.get(id) {
+            Some(group) => group.subgroups.iter().map(String::as_str).collect(),
+            None => Vec::new(),
+        })
+    }
+
+    /// Whether the group ends up including itself, through any of its subgroups.
+    pub fn has_cycle(&self, id: &str) -> bool {
+        let subgroups = match self.groups.get(id) {
+            Some(group) => &group.subgroups,
+            None => return false,
+        };
+        subgroups.iter().any(|subgroup| {
+            subgroup == id || self.descendants(subgroup).iter().any(|other| other == id)
+        })
+    }
+
+    /// The permissions of a member of all `group_ids`, from those groups and every group
+    /// including them. Each group is only counted once.
+    pub fn effective_perms(&self, group_ids: &[String]) -> Result<Vec<Value>, String> {
+        let ids: Vec<&str> = group_ids.iter().map(String::as_str).collect();
+        let mut perms = Vec::new();
+        for id in self.walk(&ids, |id| self.parents(id)) {
+            perms.extend(self.groups[&id].perms_list()?);
+        }
+        Ok(perms)
+    }
+
+    /// The permissions members get from a group, including the inherited ones.
+    pub fn resolved_perms(&self, id: &str) -> Result<Vec<Value>, String> {
+        self.effective_perms(&[id.to_string()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn group(id: &str, perms: Value, subgroups: &[&str]) -> PermissionGroup {
+        PermissionGroup {
+            id: id.to_string(),
+            name: id.to_string(),
+            perms: perms.to_string(),
+            subgroups: subgroups.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn tree() -> PermissionGroupTree {
+        // company > engineering > {backend, frontend}
+        PermissionGroupTree::new(vec![
+            group("company", json!(["test.ping"]), &["engineering"]),
+            group("engineering", json!(["@runner"]), &["backend", "frontend"]),
+            group("backend", json!(["state.apply"]), &[]),
+            group("frontend", json!([]), &["missing"]),
+        ])
+    }
+
+    #[test]
+    fn test_ancestors_descendants() {
+        let tree = tree();
+        assert_eq!(
+            tree.ancestors("backend"),
+            vec!["backend", "engineering", "company"]
+        );
+        assert_eq!(tree.ancestors("company"), vec!["company"]);
+        assert_eq!(
+            tree.descendants("company"),
+            vec!["company", "engineering", "backend", "frontend"]
+        );
+        assert!(tree.ancestors("missing").is_empty());
+    }
+
+    #[test]
+    fn test_resolved_perms() {
+        let tree = tree();
+        assert_eq!(
+            tree.resolved_perms("backend").unwrap(),
+            vec![json!("state.apply"), json!("@runner"), json!("test.ping")]
+        );
+        // Shared ancestors are only counted once
+        assert_eq!(
+            tree.effective_perms(&["backend".to_string(), "frontend".to_string()])
+                .unwrap(),
+            vec![json!("state.apply"), json!("@runner"), json!("test.ping")]
+        );
+        assert_eq!(
+            tree.resolved_perms("company").unwrap(),
+            vec![json!("test.ping")]
+        );
+    }
+
+    #[test]
+    fn test_has_cycle() {
+        let mut tree = tree();
+        assert!(!tree.has_cycle("company"));
+        tree.insert(group("backend", json!([]), &["company"]));
+        assert!(tree.has_cycle("backend"));
+        assert!(tree.has_cycle("company"));
+        tree.insert(group("backend", json!([]), &["backend"]));
+        assert!(tree.has_cycle("backend"));
+        assert!(!tree.has_cycle("company"));
+        // Walks still end with a cycle
+        assert_eq!(tree.ancestors("backend").len(), 3);
+    }
+}
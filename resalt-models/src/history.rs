@@ -0,0 +1,179 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::{strip_quotes, MinionSnapshot, ResaltTime};
+
+/// The minion data blobs which are versioned on every update.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub enum MinionSnapshotKind {
+    #[serde(rename = "grains")]
+    Grains,
+    #[serde(rename = "pillars")]
+    Pillars,
+    #[serde(rename = "pkgs")]
+    Pkgs,
+}
+
+impl fmt::Display for MinionSnapshotKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            strip_quotes(serde_json::to_string(self).unwrap_or_default())
+        )
+    }
+}
+
+impl FromStr for MinionSnapshotKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grains" => Ok(MinionSnapshotKind::Grains),
+            "pillars" => Ok(MinionSnapshotKind::Pillars),
+            "pkgs" => Ok(MinionSnapshotKind::Pkgs),
+            _ => Err(format!("Invalid snapshot kind: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct MinionSnapshotChange {
+    pub from: Value,
+    pub to: Value,
+}
+
+/// Structured difference between two snapshots, keyed on the top-level keys of the blob.
+///
+/// For packages this is the package name (value being the version), and for grains
+/// and pillars it is the grain or pillar key.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct MinionSnapshotDiff {
+    pub added: BTreeMap<String, Value>,
+    pub removed: BTreeMap<String, Value>,
+    pub changed: BTreeMap<String, MinionSnapshotChange>,
+}
+
+impl MinionSnapshotDiff {
+    /// Diff two JSON object blobs. A missing or unparsable `old` is treated as empty,
+    /// so the first snapshot of a minion shows every key as added.
+    pub fn compute(old: Option<&str>, new: &str) -> MinionSnapshotDiff {
+        let old = parse_object(old.unwrap_or("{}"));
+        let new = parse_object(new);
+
+        let mut diff = MinionSnapshotDiff::default();
+        for (key, value) in old.iter() {
+            match new.get(key) {
+                Some(new_value) if new_value != value => {
+                    diff.changed.insert(
+                        key.clone(),
+                        MinionSnapshotChange {
+                            from: value.clone(),
+                            to: new_value.clone(),
+                        },
+                    );
+                }
+                Some(_) => {}
+                None => {
+                    diff.removed.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        for (key, value) in new.iter() {
+            if !old.contains_key(key) {
+                diff.added.insert(key.clone(), value.clone());
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn parse_object(data: &str) -> serde_json::Map<String, Value> {
+    match serde_json::from_str::<Value>(data) {
+        Ok(Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    }
+}
+
+/// One version of a minion data blob, together with what changed since the version before it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct MinionHistoryEntry {
+    pub id: String,
+    #[serde(rename = "minionId")]
+    pub minion_id: String,
+    pub kind: MinionSnapshotKind,
+    pub timestamp: ResaltTime,
+    pub diff: MinionSnapshotDiff,
+}
+
+/// Build history entries from snapshots sorted oldest first. Returned newest first.
+pub fn minion_history(snapshots: &[MinionSnapshot]) -> Vec<MinionHistoryEntry> {
+    let mut entries: Vec<MinionHistoryEntry> = Vec::with_capacity(snapshots.len());
+    let mut previous: Option<&str> = None;
+    for snapshot in snapshots {
+        entries.push(MinionHistoryEntry {
+            id: snapshot.id.clone(),
+            minion_id: snapshot.minion_id.clone(),
+            kind: snapshot.kind,
+            timestamp: snapshot.timestamp,
+            diff: MinionSnapshotDiff::compute(previous, &snapshot.data),
+        });
+        previous = Some(&snapshot.data);
+    }
+    entries.reverse();
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_pkgs() {
+        let old = r#"{"bash":"5.1","curl":"7.81","vim":"8.2"}"#;
+        let new = r#"{"bash":"5.1","curl":"7.88","htop":"3.2"}"#;
+        let diff = MinionSnapshotDiff::compute(Some(old), new);
+        assert_eq!(diff.added.get("htop"), Some(&json!("3.2")));
+        assert_eq!(diff.removed.get("vim"), Some(&json!("8.2")));
+        assert_eq!(
+            diff.changed.get("curl"),
+            Some(&MinionSnapshotChange {
+                from: json!("7.81"),
+                to: json!("7.88"),
+            })
+        );
+        assert!(!diff.changed.contains_key("bash"));
+    }
+
+    #[test]
+    fn test_diff_first_snapshot() {
+        let diff = MinionSnapshotDiff::compute(None, r#"{"os":"Ubuntu"}"#);
+        assert_eq!(diff.added.len(), 1);
+        assert!(diff.removed.is_empty());
+        assert!(diff.changed.is_empty());
+        assert!(MinionSnapshotDiff::compute(Some("{}"), "{}").is_empty());
+    }
+
+    #[test]
+    fn test_minion_history_newest_first() {
+        let snapshot = |id: &str, data: &str| MinionSnapshot {
+            id: id.to_string(),
+            minion_id: "m1".to_string(),
+            kind: MinionSnapshotKind::Grains,
+            timestamp: ResaltTime::default(),
+            data: data.to_string(),
+        };
+        let history = minion_history(&[snapshot("s1", r#"{"a":1}"#), snapshot("s2", r#"{"a":2}"#)]);
+        assert_eq!(history[0].id, "s2");
+        assert!(history[0].diff.changed.contains_key("a"));
+        assert!(history[1].diff.added.contains_key("a"));
+    }
+}
@@ -1,5 +1,13 @@
+pub mod backup;
 pub mod db;
+pub mod export;
 pub mod filter;
+pub mod group_tree;
+pub mod history;
+pub mod nodegroup;
+pub mod package;
+pub mod query;
+pub mod redact;
 pub mod salt;
 pub mod sort;
 pub mod status;
@@ -8,8 +16,16 @@ pub mod time;
 
 use std::{fmt, str::FromStr};
 
+pub use backup::*;
 pub use db::*;
+pub use export::*;
 pub use filter::*;
+pub use group_tree::*;
+pub use history::*;
+pub use nodegroup::*;
+pub use package::*;
+pub use query::*;
+pub use redact::*;
 pub use salt::*;
 pub use sort::*;
 pub use status::*;
@@ -93,10 +109,53 @@ where
     }
 }
 
+/// Booleans are stored as strings, but are real booleans everywhere else.
+pub fn de_string_or_bool<'de, D>(de: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrBool {
+        Bool(bool),
+        String(String),
+    }
+    match StringOrBool::deserialize(de)? {
+        StringOrBool::Bool(value) => Ok(value),
+        StringOrBool::String(value) => value.parse::<bool>().map_err(serde::de::Error::custom),
+    }
+}
+
+/// Lists are stored as JSON encoded strings, but are real lists everywhere else.
+pub fn de_string_or_vec<'de, D>(de: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrVec {
+        Vec(Vec<String>),
+        String(String),
+    }
+    match StringOrVec::deserialize(de)? {
+        StringOrVec::Vec(value) => Ok(value),
+        StringOrVec::String(value) if value.is_empty() => Ok(Vec::new()),
+        StringOrVec::String(value) => {
+            serde_json::from_str(&value).map_err(serde::de::Error::custom)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[derive(Deserialize)]
+    struct Flag {
+        #[serde(default, deserialize_with = "de_string_or_bool")]
+        flag: bool,
+    }
+
     #[test]
     fn test_strip_quotes() {
         assert_eq!(strip_quotes("\"test\""), "test");
@@ -104,4 +163,29 @@ mod tests {
         assert_eq!(strip_quotes("test"), "test");
         assert_eq!(strip_quotes("te\"st"), "te\"st");
     }
+
+    #[test]
+    fn test_de_string_or_bool() {
+        let flag = |json: &str| serde_json::from_str::<Flag>(json).map(|f| f.flag);
+        assert!(flag(r#"{"flag": true}"#).unwrap());
+        assert!(flag(r#"{"flag": "true"}"#).unwrap());
+        assert!(!flag(r#"{"flag": "false"}"#).unwrap());
+        assert!(!flag("{}").unwrap());
+        assert!(flag(r#"{"flag": "yes"}"#).is_err());
+    }
+
+    #[test]
+    fn test_de_string_or_vec() {
+        let group = |json: &str| serde_json::from_str::<PermissionGroup>(json).map(|g| g.subgroups);
+        let base = r#""id": "perm_1", "name": "a", "perms": "[]""#;
+        assert_eq!(
+            group(&format!(r#"{{{}, "subgroups": ["perm_2"]}}"#, base)).unwrap(),
+            vec!["perm_2"]
+        );
+        assert_eq!(
+            group(&format!(r#"{{{}, "subgroups": "[\"perm_2\"]"}}"#, base)).unwrap(),
+            vec!["perm_2"]
+        );
+        assert!(group(&format!("{{{}}}", base)).unwrap().is_empty());
+    }
 }
@@ -0,0 +1,542 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    redact::glob_match, split_in_list, Filter, FilterFieldType, FilterNode, FilterOperand, Minion,
+};
+
+/// A Salt compound matcher, limited to the matchers which presets can be translated to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompoundMatcher {
+    All(Vec<CompoundMatcher>),
+    Any(Vec<CompoundMatcher>),
+    Not(Box<CompoundMatcher>),
+    /// Minion ID glob, e.g. `web*`
+    Glob(String),
+    /// `L@` list of minion IDs
+    List(Vec<String>),
+    /// `E@` minion ID regex
+    Pcre(String),
+    /// `G@` grain glob, with the path split on `:`
+    Grain(Vec<String>, String),
+    /// `P@` grain regex
+    GrainPcre(Vec<String>, String),
+    /// `I@` pillar glob
+    Pillar(Vec<String>, String),
+    /// `J@` pillar regex
+    PillarPcre(Vec<String>, String),
+}
+
+impl fmt::Display for CompoundMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let group = |f: &mut fmt::Formatter<'_>, matchers: &[CompoundMatcher], join: &str| {
+            let parts: Vec<String> = matchers.iter().map(|m| m.to_string()).collect();
+            match parts.len() {
+                1 => write!(f, "{}", parts[0]),
+                _ => write!(f, "( {} )", parts.join(join)),
+            }
+        };
+        match self {
+            CompoundMatcher::All(all) => group(f, all, " and "),
+            CompoundMatcher::Any(any) => group(f, any, " or "),
+            CompoundMatcher::Not(not) => write!(f, "not {}", not),
+            CompoundMatcher::Glob(glob) => write!(f, "{}", glob),
+            CompoundMatcher::List(ids) => write!(f, "L@{}", ids.join(",")),
+            CompoundMatcher::Pcre(regex) => write!(f, "E@{}", regex),
+            CompoundMatcher::Grain(path, glob) => write!(f, "G@{}:{}", path.join(":"), glob),
+            CompoundMatcher::GrainPcre(path, regex) => {
+                write!(f, "P@{}:{}", path.join(":"), regex)
+            }
+            CompoundMatcher::Pillar(path, glob) => write!(f, "I@{}:{}", path.join(":"), glob),
+            CompoundMatcher::PillarPcre(path, regex) => {
+                write!(f, "J@{}:{}", path.join(":"), regex)
+            }
+        }
+    }
+}
+
+impl CompoundMatcher {
+    /// Translate filters into an equivalent compound matcher, if all of them can be expressed.
+    ///
+    /// Only minion ID filters and string comparisons on simple grain and pillar paths
+    /// (`$.a.b`) are supported. Numeric comparisons, packages and other minion fields
+    /// have no compound equivalent.
+    pub fn from_filters(filters: &[FilterNode]) -> Option<CompoundMatcher> {
+        if filters.is_empty() {
+            return Some(CompoundMatcher::Glob("*".to_string()));
+        }
+        Some(CompoundMatcher::All(
+            filters
+                .iter()
+                .map(CompoundMatcher::from_node)
+                .collect::<Option<Vec<_>>>()?,
+        ))
+    }
+
+    fn from_node(node: &FilterNode) -> Option<CompoundMatcher> {
+        match node {
+            FilterNode::All { all } if !all.is_empty() => Some(CompoundMatcher::All(
+                all.iter()
+                    .map(CompoundMatcher::from_node)
+                    .collect::<Option<Vec<_>>>()?,
+            )),
+            // An empty any-group matches nothing, which a compound matcher can not express
+            FilterNode::Any { any } if !any.is_empty() => Some(CompoundMatcher::Any(
+                any.iter()
+                    .map(CompoundMatcher::from_node)
+                    .collect::<Option<Vec<_>>>()?,
+            )),
+            FilterNode::Not { not } => Some(CompoundMatcher::Not(Box::new(
+                CompoundMatcher::from_node(not)?,
+            ))),
+            FilterNode::Filter(filter) => CompoundMatcher::from_filter(filter),
+            _ => None,
+        }
+    }
+
+    fn from_filter(filter: &Filter) -> Option<CompoundMatcher> {
+        if filter.field_type == FilterFieldType::Object
+            && filter.field == "id"
+            && filter.operand == FilterOperand::In
+        {
+            let ids: Vec<String> = split_in_list(&filter.value)
+                .into_iter()
+                .filter(|id| !id.is_empty())
+                .collect();
+            let valid = !ids.is_empty()
+                && ids
+                    .iter()
+                    .all(|id| !id.contains(|c: char| c.is_whitespace() || c == ','));
+            return valid.then_some(CompoundMatcher::List(ids));
+        }
+
+        let value = filter.value.as_str();
+        if value.is_empty() || value.contains(|c: char| c.is_whitespace() || "():".contains(c)) {
+            return None;
+        }
+        let literal = !value.contains(['*', '?', '[', ']']);
+        let glob = match filter.operand {
+            FilterOperand::Equals | FilterOperand::NotEquals if literal => Some(value.to_string()),
+            FilterOperand::Contains | FilterOperand::NotContains if literal => {
+                Some(format!("*{}*", value))
+            }
+            FilterOperand::StartsWith if literal => Some(format!("{}*", value)),
+            FilterOperand::EndsWith if literal => Some(format!("*{}", value)),
+            _ => None,
+        };
+        // Resalt regexes search anywhere in the value, while Salt matches from the start
+        let regex = match filter.operand {
+            FilterOperand::Regex => Some(format!(".*{}", value)),
+            _ => None,
+        };
+        let negate = matches!(
+            filter.operand,
+            FilterOperand::NotEquals | FilterOperand::NotContains
+        );
+
+        let matcher = match filter.field_type {
+            FilterFieldType::Object if filter.field == "id" => match filter.operand {
+                FilterOperand::Equals | FilterOperand::NotEquals if literal => {
+                    CompoundMatcher::List(vec![value.to_string()])
+                }
+                _ => match (glob, regex) {
+                    (Some(glob), _) => CompoundMatcher::Glob(glob),
+                    (_, Some(regex)) => CompoundMatcher::Pcre(regex),
+                    _ => return None,
+                },
+            },
+            FilterFieldType::Grain | FilterFieldType::Pillar => {
+                let path = json_path_keys(&filter.field)?;
+                let grain = filter.field_type == FilterFieldType::Grain;
+                match (glob, regex, grain) {
+                    (Some(glob), _, true) => CompoundMatcher::Grain(path, glob),
+                    (Some(glob), _, false) => CompoundMatcher::Pillar(path, glob),
+                    (_, Some(regex), true) => CompoundMatcher::GrainPcre(path, regex),
+                    (_, Some(regex), false) => CompoundMatcher::PillarPcre(path, regex),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        Some(match negate {
+            true => CompoundMatcher::Not(Box::new(matcher)),
+            false => matcher,
+        })
+    }
+
+    /// Evaluate the matcher against a minion the way the Salt master would.
+    pub fn matches(&self, minion: &Minion) -> bool {
+        let parse = |data: &Option<String>| -> Value {
+            serde_json::from_str(data.as_deref().unwrap_or("{}")).unwrap_or_default()
+        };
+        match self {
+            CompoundMatcher::All(all) => all.iter().all(|m| m.matches(minion)),
+            CompoundMatcher::Any(any) => any.iter().any(|m| m.matches(minion)),
+            CompoundMatcher::Not(not) => !not.matches(minion),
+            CompoundMatcher::Glob(glob) => glob_match(glob, &minion.id),
+            CompoundMatcher::List(ids) => ids.contains(&minion.id),
+            CompoundMatcher::Pcre(regex) => pcre_match(regex, &minion.id),
+            CompoundMatcher::Grain(path, glob) => {
+                subdict_match(&parse(&minion.grains), path, &|v| glob_match(glob, v))
+            }
+            CompoundMatcher::GrainPcre(path, regex) => {
+                subdict_match(&parse(&minion.grains), path, &|v| pcre_match(regex, v))
+            }
+            CompoundMatcher::Pillar(path, glob) => {
+                subdict_match(&parse(&minion.pillars), path, &|v| glob_match(glob, v))
+            }
+            CompoundMatcher::PillarPcre(path, regex) => {
+                subdict_match(&parse(&minion.pillars), path, &|v| pcre_match(regex, v))
+            }
+        }
+    }
+}
+
+/// Split a simple JSONPath like `$.a.b` into its keys.
+fn json_path_keys(path: &str) -> Option<Vec<String>> {
+    let keys: Vec<String> = path
+        .strip_prefix("$.")?
+        .split('.')
+        .map(|key| key.to_string())
+        .collect();
+    let valid = keys.iter().all(|key| {
+        !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    });
+    valid.then_some(keys)
+}
+
+/// Python `re.match`, which is anchored at the start of the value.
+pub(crate) fn pcre_match(regex: &str, value: &str) -> bool {
+    Regex::new(&format!("^(?:{})", regex)).is_ok_and(|regex| regex.is_match(value))
+}
+
+/// Salt's `subdict_match`: follow the path, and match the value or any item of a list.
+fn subdict_match(data: &Value, path: &[String], matcher: &dyn Fn(&str) -> bool) -> bool {
+    let value = path.iter().try_fold(data, |data, key| data.get(key));
+    let value_str = |value: &Value| match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    };
+    match value {
+        Some(Value::Array(items)) => items.iter().any(|item| matcher(&value_str(item))),
+        Some(Value::Object(_)) | None => false,
+        Some(value) => matcher(&value_str(value)),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodegroupKind {
+    #[serde(rename = "compound")]
+    Compound,
+    #[serde(rename = "list")]
+    List,
+}
+
+/// A minion preset rendered as a Salt nodegroup.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Nodegroup {
+    pub name: String,
+    #[serde(rename = "presetId")]
+    pub preset_id: String,
+    pub kind: NodegroupKind,
+    pub matcher: String,
+    /// Minions currently matching the preset.
+    pub minions: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Nodegroup name for a preset name, e.g. `Prod Web` becomes `prod_web`.
+pub fn nodegroup_name(preset_name: &str) -> String {
+    preset_name
+        .trim()
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+            true => c.to_ascii_lowercase(),
+            false => '_',
+        })
+        .collect()
+}
+
+impl Nodegroup {
+    /// Render a preset as a nodegroup. `matched` are the minions matching the preset filters,
+    /// and `minions` all known minions, used to check that a translated compound matcher
+    /// selects exactly the same minions as the preset. If it does not, or the filters can
+    /// not be translated, the nodegroup falls back to an `L@` list of the current matches.
+    pub fn build(
+        preset_id: &str,
+        preset_name: &str,
+        filters: &[FilterNode],
+        minions: &[Minion],
+        matched: &[Minion],
+    ) -> Nodegroup {
+        let mut ids: Vec<String> = matched.iter().map(|m| m.id.clone()).collect();
+        ids.sort();
+        let mut warnings: Vec<String> = Vec::new();
+
+        match CompoundMatcher::from_filters(filters) {
+            Some(compound) => {
+                let mut compound_ids: Vec<String> = minions
+                    .iter()
+                    .filter(|m| compound.matches(m))
+                    .map(|m| m.id.clone())
+                    .collect();
+                compound_ids.sort();
+                if compound_ids == ids {
+                    return Nodegroup {
+                        name: nodegroup_name(preset_name),
+                        preset_id: preset_id.to_string(),
+                        kind: NodegroupKind::Compound,
+                        matcher: compound.to_string(),
+                        minions: ids,
+                        warnings,
+                    };
+                }
+                let extra = compound_ids.iter().filter(|id| !ids.contains(id)).count();
+                let missing = ids.iter().filter(|id| !compound_ids.contains(id)).count();
+                warnings.push(format!(
+                    "Compound matcher \"{}\" would match {} extra and miss {} minions, using a static list",
+                    compound, extra, missing
+                ));
+            }
+            None => warnings.push(
+                "Filters can not be expressed as a compound matcher, using a static list"
+                    .to_string(),
+            ),
+        }
+        if ids.is_empty() {
+            warnings.push("Preset does not match any minions".to_string());
+        }
+
+        Nodegroup {
+            name: nodegroup_name(preset_name),
+            preset_id: preset_id.to_string(),
+            kind: NodegroupKind::List,
+            matcher: CompoundMatcher::List(ids.clone()).to_string(),
+            minions: ids,
+            warnings,
+        }
+    }
+}
+
+/// Make nodegroup names unique, as different preset names may map to the same nodegroup
+/// name, e.g. `Prod Web` and `prod web`. Later duplicates get a numeric suffix and a
+/// warning, so one nodegroup does not silently replace the other in the master config.
+pub fn dedupe_nodegroup_names(nodegroups: &mut [Nodegroup]) {
+    let mut taken: HashMap<String, String> = HashMap::new();
+    for nodegroup in nodegroups.iter() {
+        taken
+            .entry(nodegroup.name.clone())
+            .or_insert_with(|| nodegroup.preset_id.clone());
+    }
+    let mut seen: HashSet<String> = HashSet::new();
+    for nodegroup in nodegroups.iter_mut() {
+        if seen.insert(nodegroup.name.clone()) {
+            continue;
+        }
+        let mut n = 2;
+        let name = loop {
+            let name = format!("{}_{}", nodegroup.name, n);
+            if !taken.contains_key(&name) {
+                break name;
+            }
+            n += 1;
+        };
+        nodegroup.warnings.push(format!(
+            "Name \"{}\" is already used by preset {}, renamed to \"{}\"",
+            nodegroup.name, taken[&nodegroup.name], name
+        ));
+        taken.insert(name.clone(), nodegroup.preset_id.clone());
+        seen.insert(name.clone());
+        nodegroup.name = name;
+    }
+}
+
+fn yaml_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// Render nodegroups as a `nodegroups:` master config document. Warnings are kept as
+/// comments, and nodegroups matching no minions are commented out, as an empty `L@`
+/// list is not valid.
+pub fn render_nodegroups_yaml(nodegroups: &[Nodegroup]) -> String {
+    if nodegroups.is_empty() {
+        return String::from("nodegroups: {}\n");
+    }
+    let mut yaml = String::from("nodegroups:\n");
+    for nodegroup in nodegroups {
+        yaml.push_str(&format!("  # Preset {}\n", nodegroup.preset_id));
+        for warning in &nodegroup.warnings {
+            yaml.push_str(&format!("  # {}\n", warning));
+        }
+        let comment = match nodegroup.minions.is_empty() {
+            true => "# ",
+            false => "",
+        };
+        yaml.push_str(&format!(
+            "  {}{}: {}\n",
+            comment,
+            yaml_quote(&nodegroup.name),
+            yaml_quote(&nodegroup.matcher)
+        ));
+    }
+    yaml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::leaf;
+
+    fn minion(id: &str, grains: &str) -> Minion {
+        let mut minion = Minion::default_with_id(id);
+        minion.grains = Some(grains.to_string());
+        minion
+    }
+
+    #[test]
+    fn test_compound_from_filters() {
+        let filters = vec![
+            leaf(
+                FilterFieldType::Grain,
+                "$.os",
+                FilterOperand::Equals,
+                "Ubuntu",
+            ),
+            FilterNode::Any {
+                any: vec![
+                    leaf(
+                        FilterFieldType::Object,
+                        "id",
+                        FilterOperand::StartsWith,
+                        "web",
+                    ),
+                    leaf(
+                        FilterFieldType::Pillar,
+                        "$.role",
+                        FilterOperand::Regex,
+                        "^db",
+                    ),
+                ],
+            },
+            leaf(
+                FilterFieldType::Object,
+                "id",
+                FilterOperand::NotEquals,
+                "web03",
+            ),
+            leaf(
+                FilterFieldType::Object,
+                "id",
+                FilterOperand::In,
+                "web01, web02",
+            ),
+        ];
+        assert_eq!(
+            CompoundMatcher::from_filters(&filters).unwrap().to_string(),
+            "( G@os:Ubuntu and ( web* or J@role:.*^db ) and not L@web03 and L@web01,web02 )"
+        );
+        let package = leaf(FilterFieldType::Package, "nginx", FilterOperand::Exists, "");
+        assert_eq!(CompoundMatcher::from_filters(&[package]), None);
+        let spaces = leaf(FilterFieldType::Grain, "$.os", FilterOperand::Equals, "a b");
+        assert_eq!(CompoundMatcher::from_filters(&[spaces]), None);
+    }
+
+    #[test]
+    fn test_nodegroup_validates_compound() {
+        let web = minion("web01", r#"{"os":"Ubuntu","roles":["web","cache"]}"#);
+        let db = minion("db01", r#"{"os":"Debian","roles":["db"]}"#);
+        let minions = vec![web.clone(), db.clone()];
+
+        let os = vec![leaf(
+            FilterFieldType::Grain,
+            "$.os",
+            FilterOperand::Equals,
+            "Ubuntu",
+        )];
+        let nodegroup = Nodegroup::build(
+            "p1",
+            "Ubuntu Hosts",
+            &os,
+            &minions,
+            std::slice::from_ref(&web),
+        );
+        assert_eq!(nodegroup.name, "ubuntu_hosts");
+        assert_eq!(nodegroup.kind, NodegroupKind::Compound);
+        assert_eq!(nodegroup.matcher, "G@os:Ubuntu");
+
+        // Salt matches any item of a list grain, Resalt compares the joined list
+        let roles = vec![leaf(
+            FilterFieldType::Grain,
+            "$.roles",
+            FilterOperand::Equals,
+            "web",
+        )];
+        let nodegroup = Nodegroup::build("p2", "web", &roles, &minions, &[]);
+        assert_eq!(nodegroup.kind, NodegroupKind::List);
+        assert_eq!(nodegroup.warnings.len(), 2);
+
+        let ids = vec![leaf(
+            FilterFieldType::Object,
+            "conformity_error",
+            FilterOperand::GreaterThan,
+            "0",
+        )];
+        let nodegroup = Nodegroup::build("p3", "failing", &ids, &minions, &[db, web]);
+        assert_eq!(nodegroup.matcher, "L@db01,web01");
+    }
+
+    #[test]
+    fn test_render_nodegroups_yaml() {
+        let nodegroup = |name: &str, matcher: &str, minions: Vec<String>| Nodegroup {
+            name: name.to_string(),
+            preset_id: "p1".to_string(),
+            kind: NodegroupKind::List,
+            matcher: matcher.to_string(),
+            minions,
+            warnings: vec!["Some warning".to_string()],
+        };
+        let yaml = render_nodegroups_yaml(&[
+            nodegroup("web", "L@web01", vec!["web01".to_string()]),
+            nodegroup("it's", "L@", vec![]),
+        ]);
+        assert_eq!(
+            yaml,
+            "nodegroups:\n  # Preset p1\n  # Some warning\n  'web': 'L@web01'\n  # Preset p1\n  # Some warning\n  # 'it''s': 'L@'\n"
+        );
+        assert_eq!(render_nodegroups_yaml(&[]), "nodegroups: {}\n");
+    }
+
+    #[test]
+    fn test_dedupe_nodegroup_names() {
+        let nodegroup = |preset_id: &str, preset_name: &str| Nodegroup {
+            name: nodegroup_name(preset_name),
+            preset_id: preset_id.to_string(),
+            kind: NodegroupKind::List,
+            matcher: "L@web01".to_string(),
+            minions: vec!["web01".to_string()],
+            warnings: Vec::new(),
+        };
+        let mut nodegroups = vec![
+            nodegroup("p1", "Prod Web"),
+            nodegroup("p2", "prod web"),
+            nodegroup("p3", "prod_web_2"),
+            nodegroup("p4", "db"),
+        ];
+        dedupe_nodegroup_names(&mut nodegroups);
+        let names: Vec<&str> = nodegroups.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, vec!["prod_web", "prod_web_3", "prod_web_2", "db"]);
+        assert!(nodegroups[0].warnings.is_empty());
+        assert_eq!(
+            nodegroups[1].warnings,
+            vec!["Name \"prod_web\" is already used by preset p1, renamed to \"prod_web_3\""]
+        );
+        assert!(nodegroups[2].warnings.is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+use std::{cmp::Ordering, collections::BTreeMap, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+use version_compare::Cmp;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PackageVersion {
+    pub version: String,
+    pub minions: Vec<String>,
+}
+
+/// A package across the whole fleet, with each distinct installed version
+/// and the minions which have it installed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Package {
+    pub name: String,
+    pub versions: Vec<PackageVersion>,
+}
+
+impl Package {
+    /// Only keep the versions which satisfy the constraint.
+    pub fn retain_versions(&mut self, constraint: &PackageVersionConstraint) {
+        self.versions.retain(|v| constraint.matches(&v.version));
+    }
+}
+
+/// Compare two package versions, falling back to plain string comparison
+/// for versions which `version_compare` can not parse.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    match version_compare::compare(a, b)
+        .ok()
+        .and_then(|cmp| cmp.ord())
+    {
+        Some(ordering) => ordering,
+        None => a.cmp(b),
+    }
+}
+
+/// Split a `pkg.list_pkgs` version value into its installed versions.
+///
+/// Salt reports multiple installed versions (e.g. kernels) either as a
+/// comma-separated string, or as a list when `versions_as_list=True`.
+pub fn split_package_versions(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(s) => s
+            .split(',')
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .collect(),
+        Value::Array(a) => a.iter().flat_map(split_package_versions).collect(),
+        Value::Number(n) => vec![n.to_string()],
+        _ => Vec::new(),
+    }
+}
+
+/// Group `(package, minion, version)` rows into packages, with versions sorted
+/// ascending using version-aware comparison.
+pub fn group_packages(rows: Vec<(String, String, String)>) -> Vec<Package> {
+    let mut packages: BTreeMap<String, BTreeMap<String, Vec<String>>> = BTreeMap::new();
+    for (name, minion_id, version) in rows {
+        packages
+            .entry(name)
+            .or_default()
+            .entry(version)
+            .or_default()
+            .push(minion_id);
+    }
+
+    packages
+        .into_iter()
+        .map(|(name, versions)| {
+            let mut versions: Vec<PackageVersion> = versions
+                .into_iter()
+                .map(|(version, mut minions)| {
+                    minions.sort();
+                    minions.dedup();
+                    PackageVersion { version, minions }
+                })
+                .collect();
+            versions.sort_by(|a, b| compare_versions(&a.version, &b.version));
+            Package { name, versions }
+        })
+        .collect()
+}
+
+/// A version constraint such as `<3.0.13`, `>=1.2` or `=2.0`.
+/// A bare version without operator means equality.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackageVersionConstraint {
+    cmp: Cmp,
+    version: String,
+}
+
+impl PackageVersionConstraint {
+    pub fn matches(&self, version: &str) -> bool {
+        let ordering = compare_versions(version, &self.version);
+        match self.cmp {
+            Cmp::Eq => ordering == Ordering::Equal,
+            Cmp::Ne => ordering != Ordering::Equal,
+            Cmp::Lt => ordering == Ordering::Less,
+            Cmp::Le => ordering != Ordering::Greater,
+            Cmp::Ge => ordering != Ordering::Less,
+            Cmp::Gt => ordering == Ordering::Greater,
+        }
+    }
+}
+
+impl FromStr for PackageVersionConstraint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !matches!(c, '<' | '>' | '=' | '!'))
+            .unwrap_or(s.len());
+        let (sign, version) = s.split_at(split);
+        let version = version.trim();
+        if version.is_empty() {
+            return Err(format!("Missing version in constraint: {}", s));
+        }
+        let cmp = match sign {
+            "" => Cmp::Eq,
+            sign => Cmp::from_sign(sign)
+                .map_err(|_| format!("Invalid operator in constraint: {}", s))?,
+        };
+        Ok(PackageVersionConstraint {
+            cmp,
+            version: version.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_split_package_versions() {
+        assert_eq!(split_package_versions(&json!("1.0")), vec!["1.0"]);
+        assert_eq!(
+            split_package_versions(&json!("5.15.0-91,5.15.0-94")),
+            vec!["5.15.0-91", "5.15.0-94"]
+        );
+        assert_eq!(
+            split_package_versions(&json!(["1.0", "2.0"])),
+            vec!["1.0", "2.0"]
+        );
+    }
+
+    #[test]
+    fn test_group_packages_sorts_versions() {
+        let row = |n: &str, m: &str, v: &str| (n.to_string(), m.to_string(), v.to_string());
+        let packages = group_packages(vec![
+            row("openssl", "m2", "3.0.10"),
+            row("openssl", "m1", "3.0.9"),
+            row("openssl", "m3", "3.0.10"),
+            row("curl", "m1", "7.88"),
+        ]);
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].name, "curl");
+        let openssl = &packages[1];
+        assert_eq!(openssl.versions[0].version, "3.0.9");
+        assert_eq!(openssl.versions[1].version, "3.0.10");
+        assert_eq!(openssl.versions[1].minions, vec!["m2", "m3"]);
+    }
+
+    #[test]
+    fn test_version_constraint() {
+        let c: PackageVersionConstraint = "<3.0.13".parse().unwrap();
+        assert!(c.matches("3.0.9"));
+        assert!(!c.matches("3.0.13"));
+        assert!(!c.matches("3.1.0"));
+        let c: PackageVersionConstraint = ">= 1.2".parse().unwrap();
+        assert!(c.matches("1.2"));
+        assert!(!c.matches("1.1"));
+        let c: PackageVersionConstraint = "2.0".parse().unwrap();
+        assert!(c.matches("2.0"));
+        assert!("<".parse::<PackageVersionConstraint>().is_err());
+        assert!("=<>1.0".parse::<PackageVersionConstraint>().is_err());
+    }
+}
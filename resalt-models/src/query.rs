@@ -0,0 +1,481 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{
+    filter::FILTER_OBJECT_FIELDS, join_in_list, Filter, FilterFieldType, FilterNode, FilterOperand,
+};
+
+/// Why a text query could not be parsed. `position` is the character offset
+/// (starting at 0) in the query where the problem was found.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct QueryParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for QueryParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+/// Parse a text query into filters, e.g.
+/// `os:Ubuntu and grain.osrelease>=22.04 and not pkg.nginx and conformity_error>0`.
+///
+/// - Fields are minion fields (`id`, `os_type`, `status`, ...), `grain.<path>`,
+///   `pillar.<path>` or `pkg.<name>`. Any other bare name is a top-level grain,
+///   so `os` is the same as `grain.os`. Paths starting with `$` are used as-is.
+/// - Operators are `:` or `=`, `!=`, `>=`, `<=`, `>`, `<`, `~` (regex), `!~`,
+///   `contains`, `startswith`, `endswith` and `in (a, b)`. A field without
+///   an operator checks that it exists.
+/// - Values are bare words or quoted with `"` or `'`.
+/// - Terms are combined with `and`, `or`, `not` and parentheses, with `and`
+///   binding tighter than `or`.
+///
+/// ```rust
+/// use resalt_models::parse_query;
+///
+/// let filters = parse_query("os:Ubuntu and not pkg.nginx").unwrap();
+/// assert_eq!(filters.len(), 2);
+/// ```
+pub fn parse_query(query: &str) -> Result<Vec<FilterNode>, QueryParseError> {
+    if query.chars().count() > MAX_QUERY_LENGTH {
+        return Err(QueryParseError {
+            position: MAX_QUERY_LENGTH,
+            message: format!("Query is longer than {} characters", MAX_QUERY_LENGTH),
+        });
+    }
+    let mut parser = QueryParser {
+        chars: query.chars().collect(),
+        pos: 0,
+        depth: 0,
+    };
+    parser.skip_whitespace();
+    if parser.at_end() {
+        return Ok(Vec::new());
+    }
+    let node = parser.parse_or()?;
+    parser.skip_whitespace();
+    if !parser.at_end() {
+        return Err(parser.error("Expected \"and\", \"or\" or end of query"));
+    }
+    Ok(match node {
+        FilterNode::All { all } => all,
+        node => vec![node],
+    })
+}
+
+/// Longest query accepted, in characters.
+const MAX_QUERY_LENGTH: usize = 4096;
+
+/// How deep `not` and parentheses may be nested, to bound the parser's recursion.
+const MAX_QUERY_DEPTH: usize = 64;
+
+const QUERY_OPERATORS: &[(&str, FilterOperand)] = &[
+    (">=", FilterOperand::GreaterThanOrEqual),
+    ("<=", FilterOperand::LessThanOrEqual),
+    ("!=", FilterOperand::NotEquals),
+    ("!~", FilterOperand::Regex),
+    (":", FilterOperand::Equals),
+    ("=", FilterOperand::Equals),
+    (">", FilterOperand::GreaterThan),
+    ("<", FilterOperand::LessThan),
+    ("~", FilterOperand::Regex),
+];
+
+const QUERY_WORD_OPERATORS: &[(&str, FilterOperand)] = &[
+    ("contains", FilterOperand::Contains),
+    ("startswith", FilterOperand::StartsWith),
+    ("endswith", FilterOperand::EndsWith),
+    ("in", FilterOperand::In),
+];
+
+struct QueryParser {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+}
+
+impl QueryParser {
+    fn error(&self, message: &str) -> QueryParseError {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at(&self, position: usize, message: &str) -> QueryParseError {
+        QueryParseError {
+            position,
+            message: message.to_string(),
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        let mut pos = self.pos;
+        for c in s.chars() {
+            match self.chars.get(pos) {
+                Some(other) if other.eq_ignore_ascii_case(&c) => pos += 1,
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Consume a case-insensitive keyword, if it is not just the start of a longer word.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        self.skip_whitespace();
+        if !self.starts_with(keyword) {
+            return false;
+        }
+        let end = self.pos + keyword.chars().count();
+        if self.chars.get(end).is_some_and(|c| is_word_char(*c)) {
+            return false;
+        }
+        self.pos = end;
+        true
+    }
+
+    fn parse_or(&mut self) -> Result<FilterNode, QueryParseError> {
+        let mut nodes = vec![self.parse_and()?];
+        while self.keyword("or") {
+            nodes.push(self.parse_and()?);
+        }
+        Ok(match nodes.len() {
+            1 => nodes.remove(0),
+            _ => FilterNode::Any { any: nodes },
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<FilterNode, QueryParseError> {
+        let mut nodes = vec![self.parse_unary()?];
+        while self.keyword("and") {
+            nodes.push(self.parse_unary()?);
+        }
+        Ok(match nodes.len() {
+            1 => nodes.remove(0),
+            _ => FilterNode::All { all: nodes },
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<FilterNode, QueryParseError> {
+        self.skip_whitespace();
+        if self.depth >= MAX_QUERY_DEPTH {
+            return Err(self.error("Query is nested too deeply"));
+        }
+        self.depth += 1;
+        let node = self.parse_nested();
+        self.depth -= 1;
+        node
+    }
+
+    fn parse_nested(&mut self) -> Result<FilterNode, QueryParseError> {
+        if self.keyword("not") {
+            let node = self.parse_unary()?;
+            return Ok(FilterNode::Not {
+                not: Box::new(node),
+            });
+        }
+        self.skip_whitespace();
+        if self.peek() == Some('(') {
+            let open = self.pos;
+            self.pos += 1;
+            let node = self.parse_or()?;
+            self.skip_whitespace();
+            if self.peek() != Some(')') {
+                return Err(self.error_at(open, "Unclosed parenthesis"));
+            }
+            self.pos += 1;
+            return Ok(node);
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<FilterNode, QueryParseError> {
+        let start = self.pos;
+        while self.peek().is_some_and(is_field_char) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(self.error("Expected field"));
+        }
+        let field: String = self.chars[start..self.pos].iter().collect();
+        let (field_type, field) = query_field(&field);
+        if field.is_empty() || field == "$." {
+            return Err(self.error_at(start, "Expected field name after prefix"));
+        }
+        let leaf = |operand: FilterOperand, value: String| {
+            FilterNode::Filter(Filter {
+                field_type: field_type.clone(),
+                field: field.clone(),
+                operand,
+                value,
+            })
+        };
+
+        self.skip_whitespace();
+        let symbol = QUERY_OPERATORS
+            .iter()
+            .find(|(symbol, _)| self.starts_with(symbol));
+        if let Some((symbol, operand)) = symbol {
+            self.pos += symbol.len();
+            let value = self.parse_value()?;
+            let node = leaf(operand.clone(), value);
+            return Ok(match *symbol {
+                "!~" => FilterNode::Not {
+                    not: Box::new(node),
+                },
+                _ => node,
+            });
+        }
+        for (word, operand) in QUERY_WORD_OPERATORS {
+            if !self.keyword(word) {
+                continue;
+            }
+            let value = match operand {
+                FilterOperand::In => self.parse_list()?,
+                _ => self.parse_value()?,
+            };
+            return Ok(leaf(operand.clone(), value));
+        }
+
+        // A field on its own checks that it exists
+        Ok(leaf(FilterOperand::Exists, String::new()))
+    }
+
+    fn parse_value(&mut self) -> Result<String, QueryParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('"') | Some('\'') => self.parse_quoted(),
+            _ => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| !c.is_whitespace() && c != '(' && c != ')')
+                {
+                    self.pos += 1;
+                }
+                if self.pos == start {
+                    return Err(self.error("Expected value"));
+                }
+                Ok(self.chars[start..self.pos].iter().collect())
+            }
+        }
+    }
+
+    fn parse_quoted(&mut self) -> Result<String, QueryParseError> {
+        let open = self.pos;
+        let quote = self.chars[open];
+        self.pos += 1;
+        let mut value = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '\\' => match self.peek() {
+                    Some(escaped) => {
+                        value.push(escaped);
+                        self.pos += 1;
+                    }
+                    None => break,
+                },
+                c if c == quote => return Ok(value),
+                c => value.push(c),
+            }
+        }
+        Err(self.error_at(open, "Unterminated string"))
+    }
+
+    /// Parse `(a, b, "c d")` into the comma-separated value used by the `in` operand.
+    /// Commas inside quoted values are escaped, see [`join_in_list`].
+    fn parse_list(&mut self) -> Result<String, QueryParseError> {
+        self.skip_whitespace();
+        if self.peek() != Some('(') {
+            return Err(self.error("Expected \"(\" after \"in\""));
+        }
+        let open = self.pos;
+        self.pos += 1;
+        let mut values: Vec<String> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let value = match self.peek() {
+                Some('"') | Some('\'') => self.parse_quoted()?,
+                _ => {
+                    let start = self.pos;
+                    while self
+                        .peek()
+                        .is_some_and(|c| !c.is_whitespace() && c != ',' && c != ')')
+                    {
+                        self.pos += 1;
+                    }
+                    if self.pos == start {
+                        return Err(self.error("Expected value"));
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            values.push(value);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(')') => {
+                    self.pos += 1;
+                    return Ok(join_in_list(&values));
+                }
+                Some(_) => return Err(self.error("Expected \",\" or \")\"")),
+                None => return Err(self.error_at(open, "Unclosed parenthesis")),
+            }
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn is_field_char(c: char) -> bool {
+    is_word_char(c) || matches!(c, '.' | '-' | '$' | '[' | ']' | '*' | '@')
+}
+
+/// Map a query field to its filter field type and filter field.
+fn query_field(field: &str) -> (FilterFieldType, String) {
+    let json_path = |path: &str| match path.starts_with('$') {
+        true => path.to_string(),
+        false => format!("$.{}", path),
+    };
+    if let Some(path) = field.strip_prefix("grain.") {
+        (FilterFieldType::Grain, json_path(path))
+    } else if let Some(path) = field.strip_prefix("pillar.") {
+        (FilterFieldType::Pillar, json_path(path))
+    } else if let Some(name) = field.strip_prefix("pkg.") {
+        (FilterFieldType::Package, name.to_string())
+    } else if FILTER_OBJECT_FIELDS.contains(&field) {
+        (FilterFieldType::Object, field.to_string())
+    } else {
+        (FilterFieldType::Grain, json_path(field))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::leaf;
+
+    #[test]
+    fn test_parse_query_example() {
+        let filters = parse_query(
+            "os:Ubuntu and grain.osrelease>=22.04 and not pkg.nginx and conformity_error>0",
+        )
+        .unwrap();
+        assert_eq!(
+            filters,
+            vec![
+                leaf(
+                    FilterFieldType::Grain,
+                    "$.os",
+                    FilterOperand::Equals,
+                    "Ubuntu"
+                ),
+                leaf(
+                    FilterFieldType::Grain,
+                    "$.osrelease",
+                    FilterOperand::GreaterThanOrEqual,
+                    "22.04"
+                ),
+                FilterNode::Not {
+                    not: Box::new(leaf(
+                        FilterFieldType::Package,
+                        "nginx",
+                        FilterOperand::Exists,
+                        ""
+                    )),
+                },
+                leaf(
+                    FilterFieldType::Object,
+                    "conformity_error",
+                    FilterOperand::GreaterThan,
+                    "0"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_query_groups_and_values() {
+        let filters =
+            parse_query(r#"(id ~ "^web" OR pillar.role in (db, 'cache')) and status != offline"#)
+                .unwrap();
+        assert_eq!(filters.len(), 2);
+        assert_eq!(
+            filters[0],
+            FilterNode::Any {
+                any: vec![
+                    leaf(FilterFieldType::Object, "id", FilterOperand::Regex, "^web"),
+                    leaf(
+                        FilterFieldType::Pillar,
+                        "$.role",
+                        FilterOperand::In,
+                        "db,cache"
+                    ),
+                ],
+            }
+        );
+        assert_eq!(
+            parse_query(r#"id in ("a,b", c)"#).unwrap(),
+            vec![leaf(
+                FilterFieldType::Object,
+                "id",
+                FilterOperand::In,
+                "a\\,b,c"
+            )]
+        );
+        assert_eq!(
+            parse_query("last_seen>=2024-01-01T00:00:00Z").unwrap(),
+            vec![leaf(
+                FilterFieldType::Object,
+                "last_seen",
+                FilterOperand::GreaterThanOrEqual,
+                "2024-01-01T00:00:00Z"
+            )]
+        );
+        assert_eq!(parse_query("  ").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_parse_query_errors() {
+        let position = |query: &str| parse_query(query).unwrap_err().position;
+        assert_eq!(position("os:"), 3);
+        assert_eq!(position("(os:Ubuntu"), 0);
+        assert_eq!(position("os:Ubuntu and"), 13);
+        assert_eq!(position("os:\"Ubuntu"), 3);
+        assert_eq!(position("os:Ubuntu Debian"), 10);
+        assert_eq!(position("grain.:x"), 0);
+    }
+
+    #[test]
+    fn test_parse_query_limits() {
+        let nested = format!("{}os:Ubuntu{}", "(".repeat(1000), ")".repeat(1000));
+        let err = parse_query(&nested).unwrap_err();
+        assert_eq!(err.message, "Query is nested too deeply");
+        assert_eq!(err.position, 64);
+        let err = parse_query(&"not ".repeat(1000)).unwrap_err();
+        assert_eq!(err.message, "Query is nested too deeply");
+        assert_eq!(err.position, 256);
+        let err = parse_query(&"(".repeat(MAX_QUERY_LENGTH + 1)).unwrap_err();
+        assert_eq!(err.position, MAX_QUERY_LENGTH);
+
+        let nested = format!("{}os:Ubuntu{}", "(".repeat(63), ")".repeat(63));
+        assert_eq!(parse_query(&nested).unwrap().len(), 1);
+    }
+}
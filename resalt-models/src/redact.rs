@@ -0,0 +1,187 @@
+use serde_json::Value;
+
+use crate::Minion;
+
+/// Placeholder replacing redacted values.
+pub const REDACTED: &str = "<REDACTED>";
+
+/// Rules for hiding secrets in pillar data.
+///
+/// `keys` are case-insensitive glob patterns (e.g. `*password*`) matched against
+/// object keys at any depth, and `paths` are JSONPath expressions (e.g. `$.aws.secret`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PillarRedaction {
+    keys: Vec<String>,
+    paths: Vec<String>,
+}
+
+impl PillarRedaction {
+    pub fn new(keys: Vec<String>, paths: Vec<String>) -> Self {
+        Self {
+            keys: keys.into_iter().map(|k| k.to_lowercase()).collect(),
+            paths,
+        }
+    }
+
+    fn key_matches(&self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        self.keys.iter().any(|pattern| glob_match(pattern, &key))
+    }
+
+    fn redact_keys(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if self.key_matches(key) {
+                        *value = Value::String(REDACTED.to_string());
+                    } else {
+                        self.redact_keys(value);
+                    }
+                }
+            }
+            Value::Array(array) => {
+                for value in array.iter_mut() {
+                    self.redact_keys(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn redact_value(&self, mut value: Value) -> Value {
+        self.redact_keys(&mut value);
+        for path in &self.paths {
+            let fallback = value.clone();
+            value = jsonpath_lib::replace_with(value, path, &mut |_| {
+                Some(Value::String(REDACTED.to_string()))
+            })
+            .unwrap_or(fallback);
+        }
+        value
+    }
+
+    /// Redact a JSON string. Unparsable input is redacted entirely rather than leaked.
+    pub fn redact_str(&self, data: &str) -> String {
+        match serde_json::from_str::<Value>(data) {
+            Ok(value) => self.redact_value(value).to_string(),
+            Err(_) => Value::String(REDACTED.to_string()).to_string(),
+        }
+    }
+
+    pub fn redact_minion(&self, minion: &mut Minion) {
+        if let Some(pillars) = &minion.pillars {
+            minion.pillars = Some(self.redact_str(pillars));
+        }
+    }
+}
+
+/// Put back the values of `stored` wherever `value` has the redaction placeholder,
+/// e.g. to import a redacted backup without overwriting the real secrets.
+///
+/// Returns whether every placeholder could be restored.
+pub fn restore_redacted(value: &mut Value, stored: Option<&Value>) -> bool {
+    match value {
+        Value::String(s) if s == REDACTED => match stored {
+            Some(stored) => {
+                *value = stored.clone();
+                true
+            }
+            None => false,
+        },
+        Value::Object(map) => map.iter_mut().fold(true, |restored, (key, value)| {
+            restore_redacted(value, stored.and_then(|s| s.get(key.as_str()))) && restored
+        }),
+        Value::Array(array) => array
+            .iter_mut()
+            .enumerate()
+            .fold(true, |restored, (i, value)| {
+                restore_redacted(value, stored.and_then(|s| s.get(i))) && restored
+            }),
+        _ => true,
+    }
+}
+
+/// Case-sensitive glob matching supporting `*` and `?`.
+pub(crate) fn glob_match(pattern: &str, s: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    let (mut p, mut i) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while i < s.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == s[i]) {
+            p += 1;
+            i += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, i));
+            p += 1;
+        } else if let Some((bp, bi)) = backtrack {
+            p = bp + 1;
+            i = bi + 1;
+            backtrack = Some((bp, bi + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_restore_redacted() {
+        let stored = json!({"db": {"password": "secret", "host": "db1"}, "keys": ["a", "b"]});
+        let mut value =
+            json!({"db": {"password": REDACTED, "host": "db2"}, "keys": [REDACTED, "c"]});
+        assert!(restore_redacted(&mut value, Some(&stored)));
+        assert_eq!(
+            value,
+            json!({"db": {"password": "secret", "host": "db2"}, "keys": ["a", "c"]})
+        );
+
+        let mut value = json!({"db": {"password": REDACTED}, "aws": {"key": REDACTED}});
+        assert!(!restore_redacted(&mut value, Some(&stored)));
+        assert!(!restore_redacted(&mut json!(REDACTED), None));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*password*", "db_password"));
+        assert!(glob_match("*password*", "password"));
+        assert!(glob_match("api_?ey", "api_key"));
+        assert!(!glob_match("*secret*", "public"));
+        assert!(!glob_match("token", "tokens"));
+    }
+
+    #[test]
+    fn test_redact_keys_and_paths() {
+        let redaction = PillarRedaction::new(
+            vec!["*PASSWORD*".to_string()],
+            vec!["$.aws.key".to_string()],
+        );
+        let value = redaction.redact_value(json!({
+            "db": {"Password": "hunter2", "host": "db1"},
+            "users": [{"name": "a", "password": "b"}],
+            "aws": {"key": "AKIA", "region": "eu"},
+        }));
+        assert_eq!(
+            value,
+            json!({
+                "db": {"Password": REDACTED, "host": "db1"},
+                "users": [{"name": "a", "password": REDACTED}],
+                "aws": {"key": REDACTED, "region": "eu"},
+            })
+        );
+    }
+
+    #[test]
+    fn test_redact_str_unparsable() {
+        let redaction = PillarRedaction::default();
+        assert_eq!(
+            redaction.redact_str("not json"),
+            format!("\"{}\"", REDACTED)
+        );
+    }
+}
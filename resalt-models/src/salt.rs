@@ -1,9 +1,10 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
 use serde::{ser::SerializeMap, Deserialize, Serialize};
 use serde_json::Value;
+use utoipa::ToSchema;
 
-use crate::{strip_quotes, ResaltTime};
+use crate::{nodegroup::pcre_match, redact::glob_match, strip_quotes, Minion, ResaltTime};
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct SaltToken {
@@ -13,6 +14,9 @@ pub struct SaltToken {
     pub user: String,
     pub eauth: String,
     pub perms: serde_json::Value,
+    /// The master which issued the token. Not part of the Salt response, set after login.
+    #[serde(default)]
+    pub master: String,
 }
 
 impl SaltToken {
@@ -36,17 +40,26 @@ pub struct AuthStatus {
     pub user_id: String,
     pub perms: String,
     pub auth_token: String,
-    pub salt_token: Option<SaltToken>,
+    /// One Salt session per master the user is logged in to.
+    pub salt_tokens: Vec<SaltToken>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+impl AuthStatus {
+    pub fn salt_token(&self, master: &str) -> Option<&SaltToken> {
+        self.salt_tokens.iter().find(|token| token.master == master)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct SaltMinionKey {
     pub id: String,
     pub state: SaltKeyState,
     pub finger: String,
+    #[serde(default)]
+    pub master: String,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
 pub enum SaltKeyState {
     #[default]
     #[serde(rename = "minions")]
@@ -59,13 +72,13 @@ pub enum SaltKeyState {
     Denied,
 }
 
-impl ToString for SaltKeyState {
-    fn to_string(&self) -> String {
-        strip_quotes(serde_json::to_string(self).unwrap())
+impl fmt::Display for SaltKeyState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", strip_quotes(serde_json::to_string(self).unwrap()))
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
 pub enum SaltClientType {
     #[default]
     #[serde(rename = "local")]
@@ -84,13 +97,13 @@ pub enum SaltClientType {
     WheelAsync,
 }
 
-impl ToString for SaltClientType {
-    fn to_string(&self) -> String {
-        strip_quotes(serde_json::to_string(self).unwrap())
+impl fmt::Display for SaltClientType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", strip_quotes(serde_json::to_string(self).unwrap()))
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
 pub enum SaltTgtType {
     #[default]
     #[serde(rename = "glob")]
@@ -117,9 +130,9 @@ pub enum SaltTgtType {
     IPCIDR,
 }
 
-impl ToString for SaltTgtType {
-    fn to_string(&self) -> String {
-        strip_quotes(serde_json::to_string(self).unwrap())
+impl fmt::Display for SaltTgtType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", strip_quotes(serde_json::to_string(self).unwrap()))
     }
 }
 
@@ -169,6 +182,66 @@ pub enum SaltRunJob {
     },
 }
 
+impl SaltRunJob {
+    /// The masters a job has to be sent to, out of `masters`.
+    ///
+    /// List, glob and PCRE targets are routed to the masters of the minions they match.
+    /// Other target types, runner and wheel jobs, and targets matching a minion with an
+    /// unknown master (or no minion at all) are sent to every master.
+    pub fn target_masters(&self, minions: &[Minion], masters: &[String]) -> Vec<String> {
+        let (tgt, tgt_type) = match self {
+            SaltRunJob::Local { tgt, tgt_type, .. }
+            | SaltRunJob::LocalAsync { tgt, tgt_type, .. }
+            | SaltRunJob::LocalBatch { tgt, tgt_type, .. } => (tgt, tgt_type.unwrap_or_default()),
+            _ => return masters.to_vec(),
+        };
+        let matched: Vec<Option<&String>> = match tgt_type {
+            SaltTgtType::List => {
+                let mut matched = Vec::new();
+                for id in tgt
+                    .split(',')
+                    .map(|id| id.trim())
+                    .filter(|id| !id.is_empty())
+                {
+                    match minions.iter().find(|minion| minion.id == id) {
+                        Some(minion) => matched.push(minion.master.as_ref()),
+                        None => return masters.to_vec(),
+                    }
+                }
+                matched
+            }
+            SaltTgtType::Glob => minions
+                .iter()
+                .filter(|minion| glob_match(tgt, &minion.id))
+                .map(|minion| minion.master.as_ref())
+                .collect(),
+            SaltTgtType::PCRE => minions
+                .iter()
+                .filter(|minion| pcre_match(tgt, &minion.id))
+                .map(|minion| minion.master.as_ref())
+                .collect(),
+            _ => return masters.to_vec(),
+        };
+        let mut targets: Vec<&String> = Vec::new();
+        for master in matched {
+            match master {
+                Some(master) if masters.contains(master) => targets.push(master),
+                // Never seen on a master, or on one which is no longer configured
+                _ => return masters.to_vec(),
+            }
+        }
+        match targets.is_empty() {
+            true => masters.to_vec(),
+            // Keep the configured order
+            false => masters
+                .iter()
+                .filter(|master| targets.contains(master))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
 impl Serialize for SaltRunJob {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -251,7 +324,7 @@ impl Serialize for SaltRunJob {
 
 #[cfg(test)]
 mod tests {
-    use crate::{SaltRunJob, SaltTgtType};
+    use crate::{Minion, SaltRunJob, SaltTgtType};
     use serde_json::json;
     use std::collections::HashMap;
 
@@ -376,4 +449,62 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn test_target_masters() {
+        let masters = vec!["dc1".to_string(), "dc2".to_string()];
+        let minion = |id: &str, master: Option<&str>| Minion {
+            master: master.map(|m| m.to_string()),
+            ..Minion::default_with_id(id)
+        };
+        let minions = vec![
+            minion("web1", Some("dc1")),
+            minion("web2", Some("dc2")),
+            minion("db1", Some("dc2")),
+            minion("new1", None),
+        ];
+        let job = |tgt: &str, tgt_type: SaltTgtType| SaltRunJob::LocalAsync {
+            tgt: tgt.to_string(),
+            fun: "test.ping".to_string(),
+            arg: None,
+            tgt_type: Some(tgt_type),
+            kwarg: None,
+        };
+
+        assert_eq!(
+            job("web1", SaltTgtType::Glob).target_masters(&minions, &masters),
+            vec!["dc1"]
+        );
+        assert_eq!(
+            job("web2,db1", SaltTgtType::List).target_masters(&minions, &masters),
+            vec!["dc2"]
+        );
+        assert_eq!(
+            job("web*", SaltTgtType::Glob).target_masters(&minions, &masters),
+            masters
+        );
+        assert_eq!(
+            job("db.*", SaltTgtType::PCRE).target_masters(&minions, &masters),
+            vec!["dc2"]
+        );
+        // Unknown master, unknown minion, or untracked target types go everywhere
+        assert_eq!(
+            job("new1", SaltTgtType::Glob).target_masters(&minions, &masters),
+            masters
+        );
+        assert_eq!(
+            job("web1,other", SaltTgtType::List).target_masters(&minions, &masters),
+            masters
+        );
+        assert_eq!(
+            job("G@os:Debian", SaltTgtType::Compound).target_masters(&minions, &masters),
+            masters
+        );
+        let runner = SaltRunJob::Runner {
+            fun: "manage.status".to_string(),
+            arg: None,
+            kwarg: None,
+        };
+        assert_eq!(runner.target_masters(&minions, &masters), masters);
+    }
 }
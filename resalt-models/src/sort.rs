@@ -1,8 +1,9 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::{Job, Minion};
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default, ToSchema)]
 pub enum MinionSort {
     #[default]
     #[serde(rename = "id.asc")]
@@ -29,6 +30,10 @@ pub enum MinionSort {
     OsTypeAsc,
     #[serde(rename = "osType.desc")]
     OsTypeDesc,
+    #[serde(rename = "status.asc")]
+    StatusAsc,
+    #[serde(rename = "status.desc")]
+    StatusDesc,
 }
 
 pub fn sort_minions(minions: &mut [Minion], sort: &MinionSort) {
@@ -71,10 +76,12 @@ pub fn sort_minions(minions: &mut [Minion], sort: &MinionSort) {
             .as_ref()
             .unwrap_or(&String::from(""))
             .cmp(a.os_type.as_ref().unwrap_or(&String::from(""))),
+        MinionSort::StatusAsc => a.status.cmp(&b.status),
+        MinionSort::StatusDesc => b.status.cmp(&a.status),
     })
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Default, ToSchema)]
 pub enum JobSort {
     #[default]
     #[serde(rename = "id.asc")]
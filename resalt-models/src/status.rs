@@ -1,8 +1,17 @@
+use std::fmt;
+
+use chrono::Duration;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{strip_quotes, ResaltTime};
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 pub struct SystemStatus {
+    /// Whether the event listener of every master is connected.
     pub salt: bool,
+    #[serde(rename = "saltMasters")]
+    pub salt_masters: Vec<SaltMasterStatus>,
     pub db: bool,
     #[serde(rename = "dbAuthTokensTotal")]
     pub db_auth_tokens_total: Option<i64>,
@@ -23,3 +32,145 @@ pub struct SystemStatus {
     #[serde(rename = "dbUsersTotal")]
     pub db_users_total: Option<i64>,
 }
+
+/// Connection status of the event listener of a Salt master.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct SaltMasterStatus {
+    pub name: String,
+    pub connected: bool,
+    /// Why the listener is not connected, e.g. an invalid CA bundle.
+    pub error: Option<String>,
+    /// When the last event was received from the master.
+    #[serde(rename = "lastEvent")]
+    pub last_event: Option<ResaltTime>,
+    /// How many times the listener lost its connection.
+    pub disconnects: u64,
+    /// Total time spent disconnected, in seconds, over reconnected outages.
+    #[serde(rename = "disconnectedSeconds")]
+    pub disconnected_seconds: u64,
+}
+
+/// Availability of a minion, derived from how long ago it was last seen.
+///
+/// The variants are ordered from most to least available, which is also the order used when sorting.
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Default,
+    Serialize,
+    Deserialize,
+    ToSchema,
+)]
+pub enum MinionStatus {
+    #[serde(rename = "online")]
+    Online,
+    #[serde(rename = "stale")]
+    Stale,
+    #[serde(rename = "offline")]
+    Offline,
+    #[default]
+    #[serde(rename = "never_seen")]
+    NeverSeen,
+}
+
+impl MinionStatus {
+    /// Derive the status of a minion last seen at `last_seen`, as of `now`.
+    ///
+    /// `stale_after` and `offline_after` are thresholds in seconds. A minion which has never
+    /// been seen has the default (epoch) timestamp as `last_seen`.
+    pub fn from_last_seen(
+        last_seen: ResaltTime,
+        now: ResaltTime,
+        stale_after: u64,
+        offline_after: u64,
+    ) -> MinionStatus {
+        if last_seen.timestamp() <= 0 {
+            return MinionStatus::NeverSeen;
+        }
+        let elapsed = (now - last_seen).num_seconds();
+        if elapsed >= offline_after as i64 {
+            MinionStatus::Offline
+        } else if elapsed >= stale_after as i64 {
+            MinionStatus::Stale
+        } else {
+            MinionStatus::Online
+        }
+    }
+
+    /// The point in time a minion last seen at `last_seen` entered this status.
+    pub fn entered_at(
+        &self,
+        last_seen: ResaltTime,
+        stale_after: u64,
+        offline_after: u64,
+    ) -> ResaltTime {
+        match self {
+            MinionStatus::Online | MinionStatus::NeverSeen => last_seen,
+            MinionStatus::Stale => last_seen + Duration::seconds(stale_after as i64),
+            MinionStatus::Offline => last_seen + Duration::seconds(offline_after as i64),
+        }
+    }
+}
+
+impl fmt::Display for MinionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", strip_quotes(serde_json::to_string(self).unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minion_status_from_last_seen() {
+        let now = ResaltTime::parse_from_rfc3339("2020-01-01T12:00:00.000000Z").unwrap();
+        let status = |s: &str| {
+            MinionStatus::from_last_seen(ResaltTime::parse_from_rfc3339(s).unwrap(), now, 600, 3600)
+        };
+        assert_eq!(status("2020-01-01T11:59:00.000000Z"), MinionStatus::Online);
+        assert_eq!(status("2020-01-01T11:50:00.000000Z"), MinionStatus::Stale);
+        assert_eq!(status("2020-01-01T11:00:00.000000Z"), MinionStatus::Offline);
+        assert_eq!(
+            MinionStatus::from_last_seen(ResaltTime::default(), now, 600, 3600),
+            MinionStatus::NeverSeen
+        );
+    }
+
+    #[test]
+    fn test_minion_status_entered_at() {
+        let last_seen = ResaltTime::parse_from_rfc3339("2020-01-01T11:00:00.000000Z").unwrap();
+        assert_eq!(
+            MinionStatus::Online.entered_at(last_seen, 600, 3600),
+            last_seen
+        );
+        assert_eq!(
+            MinionStatus::Stale
+                .entered_at(last_seen, 600, 3600)
+                .to_string(),
+            "2020-01-01T11:10:00.000000Z"
+        );
+        assert_eq!(
+            MinionStatus::Offline
+                .entered_at(last_seen, 600, 3600)
+                .to_string(),
+            "2020-01-01T12:00:00.000000Z"
+        );
+    }
+
+    #[test]
+    fn test_minion_status_serde() {
+        assert_eq!(MinionStatus::NeverSeen.to_string(), "never_seen");
+        assert_eq!(
+            serde_json::from_str::<MinionStatus>("\"stale\"").unwrap(),
+            MinionStatus::Stale
+        );
+        assert!(MinionStatus::Online < MinionStatus::Offline);
+    }
+}
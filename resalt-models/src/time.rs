@@ -10,6 +10,10 @@ use std::{
     fmt::Formatter,
     ops::{Add, Sub},
 };
+use utoipa::{
+    openapi::{KnownFormat, ObjectBuilder, RefOr, Schema, SchemaFormat, Type},
+    PartialSchema, ToSchema,
+};
 
 #[derive(Clone, Copy, Eq, Default)]
 pub struct ResaltTime {
@@ -43,6 +47,11 @@ impl ResaltTime {
         }
     }
 
+    /// Parse the time a Salt job was published from its jid, e.g. `20200101120000123456`.
+    pub fn parse_from_jid(jid: &str) -> Result<ResaltTime, ParseError> {
+        NaiveDateTime::parse_from_str(jid, "%Y%m%d%H%M%S%6f").map(ResaltTime::from)
+    }
+
     #[inline]
     #[must_use]
     pub fn now() -> ResaltTime {
@@ -158,6 +167,18 @@ impl<'de> Deserialize<'de> for ResaltTime {
     }
 }
 
+// Serialized as an RFC 3339 string, see `TIME_FMT`
+impl PartialSchema for ResaltTime {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::String)
+            .format(Some(SchemaFormat::KnownFormat(KnownFormat::DateTime)))
+            .into()
+    }
+}
+
+impl ToSchema for ResaltTime {}
+
 impl From<ResaltTime> for DateTime<Utc> {
     fn from(val: ResaltTime) -> Self {
         val.time
@@ -209,6 +230,13 @@ mod tests {
         assert_eq!(time2 - time, Duration::seconds(1));
     }
 
+    #[test]
+    fn test_time_from_jid() {
+        let time = ResaltTime::parse_from_jid("20200101120000123456").unwrap();
+        assert_eq!(time.to_string(), "2020-01-01T12:00:00.123456Z");
+        assert!(ResaltTime::parse_from_jid("req").is_err());
+    }
+
     #[test]
     fn test_time_serde() {
         let time = ResaltTime::parse_from_rfc3339("2020-01-01T00:00:00.000000Z").unwrap();
@@ -0,0 +1,44 @@
+use std::convert::Infallible;
+
+use axum::{
+    body::Body,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use resalt_models::ExportFormat;
+use serde_json::Value;
+
+/// Stream rows as a file download. Rows are produced and encoded one at a time as the
+/// body is read.
+pub(crate) fn export_response<R>(
+    format: ExportFormat,
+    filename: &str,
+    columns: Vec<String>,
+    rows: R,
+) -> Response
+where
+    R: Iterator<Item = Vec<Value>> + Send + 'static,
+{
+    let header = format.header(&columns);
+    let lines = header
+        .into_iter()
+        .chain(rows.map(move |values| format.row(&columns, values)));
+    let body = Body::from_stream(futures_util::stream::iter(lines.map(Ok::<_, Infallible>)));
+
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"{}.{}\"",
+                    filename,
+                    format.extension()
+                ),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
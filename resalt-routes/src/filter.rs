@@ -0,0 +1,74 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use log::*;
+use resalt_api::minion::pillar_redaction;
+use resalt_models::*;
+
+use crate::permission::*;
+
+/// Decode and parse a URL-encoded JSON filter list.
+pub(crate) fn parse_filter_query(filter: &Option<String>) -> Result<Vec<FilterNode>, StatusCode> {
+    let filter = match filter {
+        Some(filter) => match urlencoding::decode(filter.as_str()) {
+            Ok(filter) => filter.to_string(),
+            Err(e) => {
+                error!("Failed to decode filter: {}", e);
+                return Err(StatusCode::BAD_REQUEST);
+            }
+        },
+        None => return Ok(vec![]),
+    };
+
+    match serde_json::from_str(&filter) {
+        Ok(filters) => Ok(filters),
+        Err(e) => {
+            error!("Failed to parse filter: {}", e);
+            Err(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+/// Parse the `filter` JSON and `q` text query parameters into one filter list,
+/// where both must match if both are given. Text query errors are returned as
+/// a JSON body with the position of the error.
+pub(crate) fn parse_filter_params(
+    filter: &Option<String>,
+    q: &Option<String>,
+) -> Result<Vec<FilterNode>, Box<Response>> {
+    let mut filters =
+        parse_filter_query(filter).map_err(|status| Box::new(status.into_response()))?;
+    if let Some(q) = q {
+        match parse_query(q) {
+            Ok(query_filters) => filters.extend(query_filters),
+            Err(e) => {
+                warn!("Failed to parse query: {}", e);
+                return Err(Box::new((StatusCode::BAD_REQUEST, Json(e)).into_response()));
+            }
+        }
+    }
+    Ok(filters)
+}
+
+/// Check that the user may filter on the fields used, and return the redaction
+/// to apply to pillars before filtering on them.
+pub(crate) fn filter_pillar_redaction(
+    auth: &AuthStatus,
+    filters: &[FilterNode],
+) -> Result<Option<PillarRedaction>, StatusCode> {
+    if !filters
+        .iter()
+        .any(|f| f.uses_field_type(&FilterFieldType::Pillar))
+    {
+        return Ok(None);
+    }
+    if !has_resalt_permission(auth, P_MINION_PILLARS)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    match has_resalt_permission(auth, P_MINION_PILLARS_SECRETS)? {
+        true => Ok(None),
+        false => Ok(Some(pillar_redaction())),
+    }
+}
@@ -1,5 +1,8 @@
+mod export;
+mod filter;
 mod login;
 pub mod middleware;
+pub mod openapi;
 mod permission;
 pub mod route;
 pub mod state;
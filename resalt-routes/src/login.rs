@@ -24,17 +24,23 @@ pub async fn renew_token_salt_token(
         }
     };
 
-    // Create Salt session
-    let salt_token = match salt.login(&user.username, auth_token).await {
-        Ok(salt_token) => salt_token,
-        Err(e) => {
-            error!("update_token_salt_token salt login {:?}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    // Create a Salt session on every master, a master being down should not lock users out of the others
+    let mut salt_tokens = Vec::new();
+    for master in salt.masters() {
+        match salt.login(&master, &user.username, auth_token).await {
+            Ok(salt_token) => salt_tokens.push(salt_token),
+            Err(e) => error!(
+                "update_token_salt_token salt login on master {} {:?}",
+                master, e
+            ),
         }
-    };
+    }
+    if salt_tokens.is_empty() {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
 
-    // Update token with salt session
-    match data.set_authtoken_salttoken(auth_token, Some(&salt_token)) {
+    // Update token with salt sessions
+    match data.set_authtoken_salttoken(auth_token, &salt_tokens) {
         Ok(_) => {}
         Err(e) => {
             error!("update_token_salt_token update_salttoken {:?}", e);
@@ -46,10 +52,41 @@ pub async fn renew_token_salt_token(
         user_id: user_id.to_owned(),
         perms: user.perms,
         auth_token: auth_token.to_owned(),
-        salt_token: Some(salt_token),
+        salt_tokens,
     })
 }
 
+/// The Salt session of the user on `master`, logging in again if there is none,
+/// e.g. because the master was down when the user logged in.
+pub async fn salt_token_for(
+    data: &Storage,
+    salt: &SaltAPI,
+    auth: &AuthStatus,
+    master: &str,
+) -> Result<SaltToken, StatusCode> {
+    match auth.salt_token(master) {
+        Some(salt_token) => Ok(salt_token.clone()),
+        None => renew_salt_token_for(data, salt, auth, master).await,
+    }
+}
+
+/// Like [`salt_token_for`], but always renews the Salt sessions first.
+pub async fn renew_salt_token_for(
+    data: &Storage,
+    salt: &SaltAPI,
+    auth: &AuthStatus,
+    master: &str,
+) -> Result<SaltToken, StatusCode> {
+    let auth = renew_token_salt_token(data, salt, &auth.user_id, &auth.auth_token).await?;
+    match auth.salt_token(master) {
+        Some(salt_token) => Ok(salt_token.clone()),
+        None => {
+            error!("No salt token found for master {}", master);
+            Err(StatusCode::UNAUTHORIZED)
+        }
+    }
+}
+
 pub fn validate_auth_token(data: &Storage, token: &str) -> Result<Option<AuthStatus>, StatusCode> {
     if token.len() < 20 {
         return Ok(None);
@@ -84,12 +121,15 @@ pub fn validate_auth_token(data: &Storage, token: &str) -> Result<Option<AuthSta
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
+    if !user.is_active() {
+        return Ok(None);
+    }
 
     Ok(Some(AuthStatus {
         user_id: authtoken.user_id.clone(),
         perms: user.perms,
         auth_token: authtoken.id.clone(),
-        salt_token: authtoken.salt_token(),
+        salt_tokens: authtoken.salt_tokens(),
     }))
 }
 
@@ -118,6 +158,13 @@ pub fn auth_login_classic(
     if !verify_password(password, user_pass) {
         return Ok(None);
     }
+    if !user.is_active() {
+        warn!(
+            "Login attempt by disabled or expired user {}",
+            user.username
+        );
+        return Ok(None);
+    }
 
     Ok(Some(user))
 }
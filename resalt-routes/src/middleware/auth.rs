@@ -45,7 +45,7 @@ pub async fn middleware_auth(
             .split(";")
             .map(|s| s.trim().to_string())
             .collect::<Vec<String>>();
-        let header = parts.get(0).unwrap_or(&"".to_string()).to_string();
+        let header = parts.first().unwrap_or(&"".to_string()).to_string();
         // Check if header contains resalt-auth
         if header.contains("resalt-auth") {
             token = header.replace("resalt-auth=", "");
@@ -110,15 +110,13 @@ async fn resolve_auth_status(
         }
     };
 
-    // Check if salt_token has expired
-    let salt_token = match &auth_status.salt_token {
-        Some(salt_token) => salt_token,
-        None => {
-            return Ok(Some(auth_status));
-        }
-    };
+    // Check if any salt_token has expired
+    let expired = auth_status
+        .salt_tokens
+        .iter()
+        .any(|salt_token| salt_token.expired());
 
-    match salt_token.expired() {
+    match expired {
         true => {
             warn!(
                 "Salt token expired for {}! Attempting to renew...",
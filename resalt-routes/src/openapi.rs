@@ -0,0 +1,132 @@
+use resalt_models::{ExportFormat, JobSort, MinionSort};
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::route::{auth::*, noauth::*};
+
+/// Every route requires a logged in user, and the resalt permission in the
+/// `x-resalt-permission` extension of the operation, if any.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Resalt",
+        description = "Web-based administration and monitoring panel for SaltStack."
+    ),
+    paths(
+        route_config_get,
+        route_openapi_get,
+        route_login_post,
+        route_logout_post,
+        route_metrics_get,
+        route_token_post,
+        route_myself_get,
+        route_status_get,
+        route_minions_get,
+        route_minions_export_get,
+        route_minion_get,
+        route_minion_refresh_post,
+        route_minion_availability_get,
+        route_minion_history_get,
+        route_presets_get,
+        route_presets_post,
+        route_presets_nodegroups_get,
+        route_preset_get,
+        route_preset_put,
+        route_preset_delete,
+        route_grains_get,
+        route_grains_export_get,
+        route_packages_get,
+        route_package_get,
+        route_jobs_get,
+        route_jobs_post,
+        route_job_get,
+        route_events_get,
+        route_users_get,
+        route_users_post,
+        route_user_get,
+        route_user_delete,
+        route_user_password_post,
+        route_user_access_post,
+        route_user_preferences_post,
+        route_user_permissions_post,
+        route_user_permissions_delete,
+        route_user_permissions_grant_post,
+        route_keys_get,
+        route_key_accept_put,
+        route_key_reject_put,
+        route_key_delete_delete,
+        route_permissions_get,
+        route_permissions_post,
+        route_permission_get,
+        route_permission_put,
+        route_permission_delete,
+        route_permission_subgroup_post,
+        route_permission_subgroup_delete,
+        route_permission_grants_get,
+        route_permission_audit_get,
+        route_settings_import_post,
+        route_settings_export_get,
+    ),
+    // Schemas only referenced from query parameters are not collected automatically
+    components(schemas(ExportFormat, JobSort, MinionSort, NodegroupsFormat)),
+    modifiers(&SecuritySchemes),
+    security(("cookie" = []), ("query" = []))
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        // Same token as returned by /api/login, see middleware_auth
+        components.add_security_scheme(
+            "cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("resalt-auth"))),
+        );
+        components.add_security_scheme(
+            "query",
+            SecurityScheme::ApiKey(ApiKey::Query(ApiKeyValue::new("token"))),
+        );
+    }
+}
+
+pub fn openapi_json() -> String {
+    ApiDoc::openapi().to_pretty_json().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use utoipa::openapi::HttpMethod;
+
+    #[test]
+    fn test_openapi_permissions() {
+        let openapi = ApiDoc::openapi();
+        let permission = |path: &str, method: HttpMethod| {
+            let item = openapi.paths.get_path_operation(path, method).unwrap();
+            item.extensions
+                .as_ref()
+                .and_then(|e| e.get("x-resalt-permission"))
+                .cloned()
+        };
+        assert_eq!(
+            permission("/api/jobs", HttpMethod::Post),
+            Some(Value::from("run.live"))
+        );
+        assert_eq!(
+            permission("/api/keys/{state}/{id}/delete", HttpMethod::Delete),
+            Some(Value::from("saltkey.delete"))
+        );
+        assert_eq!(permission("/api/myself", HttpMethod::Get), None);
+        // Public routes override the default security requirement
+        let login = openapi
+            .paths
+            .get_path_operation("/api/login", HttpMethod::Post)
+            .unwrap();
+        assert_eq!(login.security.as_ref().map(|s| s.len()), Some(1));
+    }
+}
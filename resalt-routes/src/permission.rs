@@ -34,6 +34,7 @@ pub const P_RUN_TEMPLATE_GLOBAL: &str = "run.template.global"; // Can create glo
 pub const P_MINION_LIST: &str = "minion.list";
 pub const P_MINION_CONFORMITY: &str = "minion.conformity";
 pub const P_MINION_PILLARS: &str = "minion.pillars";
+pub const P_MINION_PILLARS_SECRETS: &str = "minion.pillars.secrets";
 pub const P_MINION_PACKAGES: &str = "minion.packages";
 pub const P_MINION_REFRESH: &str = "minion.refresh";
 #[allow(dead_code)]
@@ -67,6 +68,29 @@ pub fn has_resalt_permission(auth: &AuthStatus, permission: &str) -> Result<bool
     Ok(evaluate_resalt_permission(&perms, permission))
 }
 
+/// Check that the user may run the function on every one of the target minions.
+pub fn has_target_permission(
+    auth: &AuthStatus,
+    targets: &[String],
+    fun: &str,
+    args: &Vec<String>,
+    kwargs: &HashMap<String, String>,
+) -> Result<bool, StatusCode> {
+    let perms = match serde_json::from_str(&auth.perms) {
+        Ok(perms) => perms,
+        Err(e) => {
+            error!("{:?}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    if evaluate_resalt_permission(&perms, P_ADMIN_SUPERADMIN) {
+        return Ok(true);
+    }
+    Ok(targets
+        .iter()
+        .all(|target| evaluate_permission(&perms, target, fun, args, kwargs)))
+}
+
 pub(crate) fn evaluate_resalt_permission(permissions: &Value, permission: &str) -> bool {
     let args = Vec::new();
     let kwargs = HashMap::new();
@@ -84,6 +108,7 @@ fn salt_wrapped_regex(regex: &str) -> String {
     format!("^{}$", regex.replace("([a-zA-Z0-9])\\*", "$1.*"))
 }
 
+#[allow(clippy::ptr_arg)]
 fn evaluate_function(
     fun_section_perm: &Value,
     fun: &str,
@@ -120,7 +145,7 @@ fn evaluate_function(
                 for (i, value) in value.iter().enumerate() {
                     let regex = salt_wrapped_regex(value.as_str().unwrap());
                     let re = Regex::new(&regex).unwrap();
-                    if !re.is_match(&args[i]) {
+                    if !args.get(i).is_some_and(|arg| re.is_match(arg)) {
                         result = false;
                         break;
                     }
@@ -137,7 +162,7 @@ fn evaluate_function(
                         for (i, value) in value.iter().enumerate() {
                             let regex = salt_wrapped_regex(value.as_str().unwrap());
                             let re = Regex::new(&regex).unwrap();
-                            if !re.is_match(&args[i]) {
+                            if !args.get(i).is_some_and(|arg| re.is_match(arg)) {
                                 return false;
                             }
                         }
@@ -166,6 +191,7 @@ fn evaluate_function(
     false
 }
 
+#[allow(clippy::ptr_arg)]
 fn evaluate_target(
     target_section_perm: &Value,
     target: &str,
@@ -202,6 +228,7 @@ fn evaluate_target(
     false
 }
 
+#[allow(clippy::ptr_arg)]
 pub fn evaluate_permission(
     permissions: &Value,
     target: &str,
@@ -383,4 +410,47 @@ mod tests {
             .collect()
         ));
     }
+
+    #[test]
+    fn test_evaluate_permission_per_target() {
+        let perms = from_str(
+            r#"[
+                {
+                  "web.*": [
+                    {
+                      "state.apply": {
+                        "args": [
+                          "nginx"
+                        ],
+                        "kwargs": {}
+                      }
+                    }
+                  ]
+                }
+              ]"#,
+        )
+        .unwrap();
+        let targets = ["web01", "web02", "db01"];
+        let allowed: Vec<bool> = targets
+            .iter()
+            .map(|target| {
+                evaluate_permission(
+                    &perms,
+                    target,
+                    "state.apply",
+                    &vec!["nginx".to_string()],
+                    &HashMap::new(),
+                )
+            })
+            .collect();
+        assert_eq!(allowed, vec![true, true, false]);
+        // Fewer arguments than the permission lists must not match
+        assert!(!evaluate_permission(
+            &perms,
+            "web01",
+            "state.apply",
+            &vec![],
+            &HashMap::new()
+        ));
+    }
 }
@@ -6,9 +6,20 @@ use axum::{
     Extension, Json,
 };
 use resalt_api::event::get_events;
-use resalt_models::{AuthStatus, PaginateQuery};
+use resalt_models::{AuthStatus, Event, PaginateQuery};
 use resalt_storage::Storage;
 
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    tag = "events",
+    params(PaginateQuery),
+    responses(
+        (status = 200, description = "Events, oldest first", body = Vec<Event>),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_EVENT_LIST))),
+)]
 pub async fn route_events_get(
     query: Query<PaginateQuery>,
     State(data): State<Storage>,
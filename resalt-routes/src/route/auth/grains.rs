@@ -1,27 +1,114 @@
-use crate::permission::*;
+use crate::{
+    export::export_response,
+    filter::{filter_pillar_redaction, parse_filter_params},
+    permission::*,
+};
 use axum::{
     extract::{Query, State},
     http::StatusCode,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     Extension, Json,
 };
 use log::*;
-use resalt_api::grain::search_grains;
+use resalt_api::{grain::search_grains, minion::pillar_redaction};
 use resalt_models::*;
 use resalt_storage::Storage;
 use serde::Deserialize;
+use serde_json::Value;
+use utoipa::IntoParams;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct GrainsGetQuery {
+    /// URL-encoded JSONPath selecting the grain, e.g. `$.os`
     query: String,
-    filter: Option<String>, // URL-encoded JSON
+    /// URL-encoded JSON list of filters
+    filter: Option<String>,
+    /// Text query, e.g. `os:Ubuntu and not pkg.nginx`
+    q: Option<String>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/grains",
+    tag = "grains",
+    params(GrainsGetQuery),
+    responses(
+        (status = 200, description = "Grain value per minion", body = Object),
+        (status = 400, description = "Invalid filter or query"),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_GRAINEXPLORER))),
+)]
 pub async fn route_grains_get(
     query: Query<GrainsGetQuery>,
     State(data): State<Storage>,
     Extension(auth): Extension<AuthStatus>,
-) -> Result<impl IntoResponse, StatusCode> {
+) -> Result<Response, StatusCode> {
+    // Validate permission
+    if !has_resalt_permission(&auth, P_MINION_GRAINEXPLORER)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // Args
+    let path = match urlencoding::decode(query.query.as_str()) {
+        Ok(q) => q.to_string(),
+        Err(e) => {
+            error!("Failed to decode q: {}", e);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+    let filters = match parse_filter_params(&query.filter, &query.q) {
+        Ok(filters) => filters,
+        Err(response) => return Ok(*response),
+    };
+    filter_pillar_redaction(&auth, &filters)?;
+
+    let redaction = match has_resalt_permission(&auth, P_MINION_PILLARS_SECRETS)? {
+        true => None,
+        false => Some(pillar_redaction()),
+    };
+
+    // API
+    let results = search_grains(&data, path, filters, redaction.as_ref())?;
+
+    Ok(Json(results).into_response())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GrainsExportGetQuery {
+    /// URL-encoded JSONPath selecting the grain, e.g. `$.os`
+    query: String,
+    /// URL-encoded JSON list of filters
+    filter: Option<String>,
+    /// Text query, e.g. `os:Ubuntu and not pkg.nginx`
+    q: Option<String>,
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/grains/export",
+    tag = "grains",
+    params(GrainsExportGetQuery),
+    responses(
+        (
+            status = 200,
+            description = "Grain values as CSV or NDJSON",
+            content((String = "text/csv"), (String = "application/x-ndjson"))
+        ),
+        (status = 400, description = "Invalid filter or query"),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_GRAINEXPLORER))),
+)]
+pub async fn route_grains_export_get(
+    query: Query<GrainsExportGetQuery>,
+    State(data): State<Storage>,
+    Extension(auth): Extension<AuthStatus>,
+) -> Result<Response, StatusCode> {
     // Validate permission
     if !has_resalt_permission(&auth, P_MINION_GRAINEXPLORER)? {
         return Err(StatusCode::FORBIDDEN);
@@ -35,19 +122,35 @@ pub async fn route_grains_get(
             return Err(StatusCode::BAD_REQUEST);
         }
     };
-    let filter = match &query.filter {
-        Some(filter) => Some(match urlencoding::decode(filter.as_str()) {
-            Ok(filter) => filter.to_string(),
-            Err(e) => {
-                error!("Failed to decode filter: {}", e);
-                return Err(StatusCode::BAD_REQUEST);
-            }
-        }),
-        None => None,
+    let filters = match parse_filter_params(&query.filter, &query.q) {
+        Ok(filters) => filters,
+        Err(response) => return Ok(*response),
+    };
+    filter_pillar_redaction(&auth, &filters)?;
+
+    let redaction = match has_resalt_permission(&auth, P_MINION_PILLARS_SECRETS)? {
+        true => None,
+        false => Some(pillar_redaction()),
     };
 
     // API
-    let results = search_grains(&data, path, filter)?;
+    let results = search_grains(&data, path.clone(), filters, redaction.as_ref())?;
+
+    let mut results: Vec<(String, Value)> = results.into_iter().collect();
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    let rows = results.into_iter().map(|(minion_id, selected)| {
+        // Unwrap single selections, so they export as plain values
+        let selected = match selected {
+            Value::Array(mut selected) if selected.len() == 1 => selected.remove(0),
+            selected => selected,
+        };
+        vec![Value::String(minion_id), selected]
+    });
 
-    Ok(Json(results))
+    Ok(export_response(
+        query.format,
+        "grains",
+        vec!["id".to_string(), path],
+        rows,
+    ))
 }
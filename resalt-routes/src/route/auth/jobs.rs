@@ -1,27 +1,49 @@
-use crate::{login::renew_token_salt_token, permission::*};
+use crate::{
+    filter::filter_pillar_redaction,
+    login::{renew_salt_token_for, salt_token_for},
+    permission::*,
+};
 use axum::{
     extract::{Path, Query, State},
     http::StatusCode,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     Extension, Json,
 };
 use log::*;
-use resalt_api::job::{create_job, get_job, get_job_returns_by_job, get_jobs};
+use resalt_api::{
+    job::{create_job, get_job, get_job_masters, get_job_returns_by_job, get_jobs, set_job_preset},
+    minion::get_minions,
+    preset::get_minion_preset_filters,
+};
 use resalt_models::*;
 use resalt_salt::*;
 use resalt_storage::Storage;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct JobsListGetQuery {
     sort: Option<JobSort>,
     // Include fields from PaginateQuery
     #[serde(flatten)]
+    #[param(ignore)]
     paginate_query: PaginateQuery,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/jobs",
+    tag = "jobs",
+    params(JobsListGetQuery, PaginateQuery),
+    responses(
+        (status = 200, description = "Jobs", body = Vec<Job>),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_JOB_LIST))),
+)]
 pub async fn route_jobs_get(
     query: Query<JobsListGetQuery>,
     State(data): State<Storage>,
@@ -40,12 +62,16 @@ pub async fn route_jobs_get(
     Ok(Json(get_jobs(data, paginate, sort)?))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct JobRunRequest {
     client: SaltClientType,
-    #[serde(rename = "tgtType")]
+    #[serde(rename = "tgtType", default)]
     tgt_type: SaltTgtType,
+    #[serde(default)]
     tgt: String,
+    /// Target the minions currently matching this preset, instead of `tgt`.
+    #[serde(rename = "presetId", default)]
+    preset_id: Option<String>,
     fun: String,
     arg: Vec<Value>,
     kwarg: HashMap<String, String>,
@@ -100,60 +126,202 @@ pub fn map_client_to_runjob(request: JobRunRequest) -> SaltRunJob {
     }
 }
 
+/// Result of a job on one master: either the Salt API result, or why running it failed.
+#[derive(Serialize, ToSchema)]
+pub struct JobRunResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobRunQuery {
+    /// Always respond with the result on each master, even if only one master is configured.
+    #[serde(rename = "perMaster", default)]
+    per_master: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/jobs",
+    tag = "jobs",
+    params(JobRunQuery),
+    request_body = JobRunRequest,
+    responses(
+        (
+            status = 200,
+            description = "Result of the job on each master it ran on, keyed by master name. \
+                If only one master is configured and `perMaster` is not set, the Salt API result \
+                itself. Masters the job failed on have an `error` instead of a `job`.",
+            body = HashMap<String, JobRunResult>
+        ),
+        (status = 403, description = "Missing permission"),
+        (
+            status = 502,
+            description = "No master accepted the job, with the same body as a 200 if `perMaster` \
+                is set or more than one master is configured",
+            body = HashMap<String, JobRunResult>
+        ),
+    ),
+    extensions(("x-resalt-permission" = json!(P_RUN_LIVE))),
+)]
 pub async fn route_jobs_post(
+    query: Query<JobRunQuery>,
     State(salt): State<SaltAPI>,
     State(data): State<Storage>,
     Extension(auth): Extension<AuthStatus>,
-    Json(input): Json<JobRunRequest>,
-) -> Result<impl IntoResponse, StatusCode> {
+    Json(mut input): Json<JobRunRequest>,
+) -> Result<Response, StatusCode> {
     // Validate permission
     if !has_resalt_permission(&auth, P_RUN_LIVE)? {
         return Err(StatusCode::FORBIDDEN);
     }
 
-    let salt_token = match &auth.salt_token {
-        Some(salt_token) => salt_token,
-        None => {
-            error!("No salt token found");
-            return Err(StatusCode::UNAUTHORIZED);
-        }
-    };
+    let preset_id = input.preset_id.clone();
+    if let Some(preset_id) = &preset_id {
+        expand_preset_target(&data, &auth, preset_id, &mut input)?;
+    }
 
     let run_job = map_client_to_runjob(input);
 
     // API
-    match create_job(&salt, salt_token, &run_job).await {
-        Ok(job) => Ok(Json(job)),
+    let masters = salt.masters();
+    let mut results: HashMap<String, JobRunResult> = HashMap::new();
+    // A failing master does not keep the job from running on the others
+    for master in get_job_masters(&data, &masters, &run_job)? {
+        let result = match run_job_on_master(&data, &salt, &auth, &master, &run_job).await {
+            Ok(job) => {
+                // Only async jobs return their jid, synchronous jobs return the results directly
+                if let (Some(preset_id), Some(jid)) =
+                    (&preset_id, job.get("jid").and_then(|j| j.as_str()))
+                {
+                    if let Err(e) = set_job_preset(&data, jid, preset_id) {
+                        error!("Failed to set preset of job {}: {:?}", jid, e);
+                    }
+                }
+                JobRunResult {
+                    job: Some(job),
+                    error: None,
+                }
+            }
+            Err(e) => {
+                error!("route_jobs_post {} {}", master, e);
+                JobRunResult {
+                    job: None,
+                    error: Some(e),
+                }
+            }
+        };
+        results.insert(master, result);
+    }
+
+    let status = match results.values().any(|result| result.job.is_some()) {
+        true => StatusCode::OK,
+        false => StatusCode::BAD_GATEWAY,
+    };
+
+    // Keep the response of a single master as it was before jobs ran on several masters
+    if masters.len() == 1 && !query.per_master {
+        return match results.into_values().next().and_then(|result| result.job) {
+            Some(job) => Ok(Json(job).into_response()),
+            None => Err(StatusCode::BAD_GATEWAY),
+        };
+    }
+
+    Ok((status, Json(results)).into_response())
+}
+
+async fn run_job_on_master(
+    data: &Storage,
+    salt: &SaltAPI,
+    auth: &AuthStatus,
+    master: &str,
+    run_job: &SaltRunJob,
+) -> Result<Value, String> {
+    let salt_token = salt_token_for(data, salt, auth, master)
+        .await
+        .map_err(|status| format!("Failed to get Salt token: {}", status))?;
+    match create_job(salt, &salt_token, run_job).await {
+        Ok(job) => Ok(job),
         Err(SaltError::Unauthorized) => {
             if !salt_token.matured() {
-                error!("Salt token unauthorized, but not matured");
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                return Err("Salt token unauthorized, but not matured".to_string());
             }
             // TODO: Remove this complex logic, this is more of a hack
             error!("Salt token expired, renewing and retrying");
-            let auth =
-                renew_token_salt_token(&data, &salt, &auth.user_id, &auth.auth_token).await?;
-            match create_job(&salt, &auth.salt_token.unwrap(), &run_job).await {
-                Ok(job) => Ok(Json(job)),
-                Err(e) => {
-                    error!("route_jobs_post {:?}", e);
-                    Err(StatusCode::INTERNAL_SERVER_ERROR)
-                }
-            }
-        }
-        Err(e) => {
-            error!("route_jobs_post {:?}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            let salt_token = renew_salt_token_for(data, salt, auth, master)
+                .await
+                .map_err(|status| format!("Failed to renew Salt token: {}", status))?;
+            create_job(salt, &salt_token, run_job)
+                .await
+                .map_err(|e| e.to_string())
         }
+        Err(e) => Err(e.to_string()),
     }
 }
 
-#[derive(Serialize)]
+/// Replace the target of a job run with the list of minions currently matching the preset,
+/// and check that the user may run the function on every one of them.
+fn expand_preset_target(
+    data: &Storage,
+    auth: &AuthStatus,
+    preset_id: &str,
+    input: &mut JobRunRequest,
+) -> Result<(), StatusCode> {
+    if !matches!(
+        input.client,
+        SaltClientType::Local | SaltClientType::LocalAsync | SaltClientType::LocalBatch
+    ) {
+        warn!("Preset targets are only supported for local clients");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let filters = get_minion_preset_filters(data, preset_id)?;
+    let filter_redaction = filter_pillar_redaction(auth, &filters)?;
+    let minions = get_minions(data, filters, None, None, filter_redaction.as_ref())?;
+    if minions.is_empty() {
+        warn!("Preset {} does not match any minions", preset_id);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let targets: Vec<String> = minions.into_iter().map(|m| m.id).collect();
+
+    let args: Vec<String> = input
+        .arg
+        .iter()
+        .map(|arg| match arg {
+            Value::String(arg) => arg.clone(),
+            arg => arg.to_string(),
+        })
+        .collect();
+    if !has_target_permission(auth, &targets, &input.fun, &args, &input.kwarg)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    input.tgt = targets.join(",");
+    input.tgt_type = SaltTgtType::List;
+    Ok(())
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct JobGetResponse {
     job: Job,
     returns: Vec<JobReturn>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/jobs/{jid}",
+    tag = "jobs",
+    params(("jid" = String, Path, description = "Salt job ID")),
+    responses(
+        (status = 200, description = "The job and its returns", body = JobGetResponse),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "Job not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_JOB_LIST))),
+)]
 pub async fn route_job_get(
     Path(jid): Path<String>,
     State(data): State<Storage>,
@@ -1,16 +1,42 @@
-use crate::{login::renew_token_salt_token, permission::*};
+use crate::{
+    login::{renew_salt_token_for, salt_token_for},
+    permission::*,
+};
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
     Extension, Json,
 };
 use log::*;
-use resalt_api::key::{accept_key, delete_key, get_keys, reject_key};
+use resalt_api::{
+    key::{accept_key, delete_key, get_keys, reject_key},
+    minion::get_minion_master,
+};
 use resalt_models::*;
 use resalt_salt::SaltAPI;
 use resalt_storage::Storage;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct KeyMasterQuery {
+    /// The master holding the key, required for unknown minions if several masters are configured.
+    master: Option<String>,
+}
 
+#[utoipa::path(
+    get,
+    path = "/api/keys",
+    tag = "keys",
+    responses(
+        (status = 200, description = "Keys of all reachable masters", body = Vec<SaltMinionKey>),
+        (status = 401, description = "No master could be listed"),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_SALTKEY_LIST))),
+)]
 pub async fn route_keys_get(
     State(data): State<Storage>,
     State(salt): State<SaltAPI>,
@@ -21,44 +47,68 @@ pub async fn route_keys_get(
         return Err(StatusCode::FORBIDDEN);
     }
 
-    let salt_token = match &auth.salt_token {
-        Some(salt_token) => salt_token,
-        None => {
-            error!("No salt token found");
-            return Err(StatusCode::UNAUTHORIZED);
-        }
-    };
-
     // API
-    let keys = match get_keys(&salt, salt_token).await {
-        Ok(keys) => keys,
-        Err(e) => {
-            error!("get_keys {:?}", e);
-            // Try refresh salt token, and try again
-            let salt_token = renew_token_salt_token(&data, &salt, &auth.user_id, &auth.auth_token)
-                .await?
-                .salt_token;
-            match get_keys(&salt, &salt_token.unwrap()).await {
-                Ok(keys) => keys,
-                Err(e) => {
-                    error!("get_keys {:?}", e);
-                    return Err(StatusCode::UNAUTHORIZED);
+    let mut keys = Vec::new();
+    let mut listed_masters = Vec::new();
+    // Unreachable masters are skipped, as long as one can be listed
+    for master in salt.masters() {
+        let salt_token = match salt_token_for(&data, &salt, &auth, &master).await {
+            Ok(salt_token) => salt_token,
+            Err(_) => continue,
+        };
+        let master_keys = match get_keys(&salt, &salt_token).await {
+            Ok(keys) => keys,
+            Err(e) => {
+                error!("get_keys {} {:?}", master, e);
+                // Try refresh salt token, and try again
+                let salt_token = match renew_salt_token_for(&data, &salt, &auth, &master).await {
+                    Ok(salt_token) => salt_token,
+                    Err(_) => continue,
+                };
+                match get_keys(&salt, &salt_token).await {
+                    Ok(keys) => keys,
+                    Err(e) => {
+                        error!("get_keys {} {:?}", master, e);
+                        continue;
+                    }
                 }
             }
-        }
-    };
+        };
+        keys.extend(master_keys);
+        listed_masters.push(master);
+    }
+    if listed_masters.is_empty() {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
 
     // Clean out non-existing minions
-    data.prune_minions_without_key(&keys).map_err(|e| {
-        error!("prune_minions_without_key {:?}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    data.prune_minions_without_key(&keys, &listed_masters)
+        .map_err(|e| {
+            error!("prune_minions_without_key {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
 
     Ok(Json(keys))
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/keys/{state}/{id}/accept",
+    tag = "keys",
+    params(
+        ("state" = SaltKeyState, Path, description = "Current state of the key"),
+        ("id" = String, Path, description = "Minion ID"),
+        KeyMasterQuery,
+    ),
+    responses(
+        (status = 200, description = "Key accepted"),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_SALTKEY_ACCEPT))),
+)]
 pub async fn route_key_accept_put(
     Path((state, id)): Path<(SaltKeyState, String)>,
+    Query(query): Query<KeyMasterQuery>,
     State(data): State<Storage>,
     State(salt): State<SaltAPI>,
     Extension(auth): Extension<AuthStatus>,
@@ -68,24 +118,17 @@ pub async fn route_key_accept_put(
         return Err(StatusCode::FORBIDDEN);
     }
 
-    let salt_token = match &auth.salt_token {
-        Some(salt_token) => salt_token,
-        None => {
-            error!("No salt token found");
-            return Err(StatusCode::UNAUTHORIZED);
-        }
-    };
+    let master = get_minion_master(&data, &salt.masters(), &id, query.master.as_deref())?;
+    let salt_token = salt_token_for(&data, &salt, &auth, &master).await?;
 
     // API
-    match accept_key(&salt, salt_token, &state, &id).await {
+    match accept_key(&salt, &salt_token, &state, &id).await {
         Ok(()) => Ok(Json(())),
         Err(e) => {
             error!("accept_key {:?}", e);
             // Try refresh salt token, and try again
-            let salt_token = renew_token_salt_token(&data, &salt, &auth.user_id, &auth.auth_token)
-                .await?
-                .salt_token;
-            match accept_key(&salt, &salt_token.unwrap(), &state, &id).await {
+            let salt_token = renew_salt_token_for(&data, &salt, &auth, &master).await?;
+            match accept_key(&salt, &salt_token, &state, &id).await {
                 Ok(()) => Ok(Json(())),
                 Err(e) => {
                     error!("accept_key {:?}", e);
@@ -96,8 +139,24 @@ pub async fn route_key_accept_put(
     }
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/keys/{state}/{id}/reject",
+    tag = "keys",
+    params(
+        ("state" = SaltKeyState, Path, description = "Current state of the key"),
+        ("id" = String, Path, description = "Minion ID"),
+        KeyMasterQuery,
+    ),
+    responses(
+        (status = 200, description = "Key rejected"),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_SALTKEY_REJECT))),
+)]
 pub async fn route_key_reject_put(
     Path((state, id)): Path<(SaltKeyState, String)>,
+    Query(query): Query<KeyMasterQuery>,
     State(data): State<Storage>,
     State(salt): State<SaltAPI>,
     Extension(auth): Extension<AuthStatus>,
@@ -107,24 +166,17 @@ pub async fn route_key_reject_put(
         return Err(StatusCode::FORBIDDEN);
     }
 
-    let salt_token = match &auth.salt_token {
-        Some(salt_token) => salt_token,
-        None => {
-            error!("No salt token found");
-            return Err(StatusCode::UNAUTHORIZED);
-        }
-    };
+    let master = get_minion_master(&data, &salt.masters(), &id, query.master.as_deref())?;
+    let salt_token = salt_token_for(&data, &salt, &auth, &master).await?;
 
     // API
-    match reject_key(&salt, salt_token, &state, &id).await {
+    match reject_key(&salt, &salt_token, &state, &id).await {
         Ok(()) => Ok(Json(())),
         Err(e) => {
             error!("reject_key {:?}", e);
             // Try refresh salt token, and try again
-            let salt_token = renew_token_salt_token(&data, &salt, &auth.user_id, &auth.auth_token)
-                .await?
-                .salt_token;
-            match reject_key(&salt, &salt_token.unwrap(), &state, &id).await {
+            let salt_token = renew_salt_token_for(&data, &salt, &auth, &master).await?;
+            match reject_key(&salt, &salt_token, &state, &id).await {
                 Ok(()) => Ok(Json(())),
                 Err(e) => {
                     error!("reject_key {:?}", e);
@@ -135,8 +187,24 @@ pub async fn route_key_reject_put(
     }
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/keys/{state}/{id}/delete",
+    tag = "keys",
+    params(
+        ("state" = SaltKeyState, Path, description = "Current state of the key"),
+        ("id" = String, Path, description = "Minion ID"),
+        KeyMasterQuery,
+    ),
+    responses(
+        (status = 200, description = "Key deleted"),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_SALTKEY_DELETE))),
+)]
 pub async fn route_key_delete_delete(
     Path((state, id)): Path<(SaltKeyState, String)>,
+    Query(query): Query<KeyMasterQuery>,
     State(data): State<Storage>,
     State(salt): State<SaltAPI>,
     Extension(auth): Extension<AuthStatus>,
@@ -146,24 +214,17 @@ pub async fn route_key_delete_delete(
         return Err(StatusCode::FORBIDDEN);
     }
 
-    let salt_token = match &auth.salt_token {
-        Some(salt_token) => salt_token,
-        None => {
-            error!("No salt token found");
-            return Err(StatusCode::UNAUTHORIZED);
-        }
-    };
+    let master = get_minion_master(&data, &salt.masters(), &id, query.master.as_deref())?;
+    let salt_token = salt_token_for(&data, &salt, &auth, &master).await?;
 
     // API
-    match delete_key(&salt, salt_token, &state, &id).await {
+    match delete_key(&salt, &salt_token, &state, &id).await {
         Ok(()) => Ok(Json(())),
         Err(e) => {
             error!("delete_key {:?}", e);
             // Try refresh salt token, and try again
-            let salt_token = renew_token_salt_token(&data, &salt, &auth.user_id, &auth.auth_token)
-                .await?
-                .salt_token;
-            match delete_key(&salt, &salt_token.unwrap(), &state, &id).await {
+            let salt_token = renew_salt_token_for(&data, &salt, &auth, &master).await?;
+            match delete_key(&salt, &salt_token, &state, &id).await {
                 Ok(()) => Ok(Json(())),
                 Err(e) => {
                     error!("delete_key {:?}", e);
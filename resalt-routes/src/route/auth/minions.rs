@@ -1,90 +1,225 @@
-use crate::{login::renew_token_salt_token, permission::*};
+use crate::{
+    export::export_response,
+    filter::{filter_pillar_redaction, parse_filter_params},
+    login::{renew_salt_token_for, salt_token_for},
+    permission::*,
+};
 use axum::{
     extract::{Path, Query, State},
     http::StatusCode,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     Extension, Json,
 };
 use log::*;
-use resalt_api::minion::{get_minion, get_minions, refresh_minion};
+use resalt_api::minion::{
+    get_minion, get_minion_history, get_minion_master, get_minion_status_transitions, get_minions,
+    pillar_redaction, refresh_minion,
+};
 use resalt_models::*;
 use resalt_salt::{SaltAPI, SaltError};
 use resalt_storage::Storage;
 use serde::Deserialize;
+use serde_json::Value;
+use utoipa::IntoParams;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct MinionsListGetQuery {
-    filter: Option<String>, // URL-encoded JSON
+    /// URL-encoded JSON list of filters
+    filter: Option<String>,
+    /// Text query, e.g. `os:Ubuntu and not pkg.nginx`
+    q: Option<String>,
     sort: Option<MinionSort>,
     // Include fields from PaginateQuery
     #[serde(flatten)]
+    #[param(ignore)]
     paginate_query: PaginateQuery,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/minions",
+    tag = "minions",
+    params(MinionsListGetQuery, PaginateQuery),
+    responses(
+        (
+            status = 200,
+            description = "Minions, with the fields the user may not see removed",
+            body = Vec<Minion>
+        ),
+        (status = 400, description = "Invalid filter or query"),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_LIST))),
+)]
 pub async fn route_minions_get(
     query: Query<MinionsListGetQuery>,
     State(data): State<Storage>,
     Extension(auth): Extension<AuthStatus>,
-) -> Result<impl IntoResponse, StatusCode> {
+) -> Result<Response, StatusCode> {
     // Validate permission
     if !has_resalt_permission(&auth, P_MINION_LIST)? {
         return Err(StatusCode::FORBIDDEN);
     }
 
-    let filter = match &query.filter {
-        Some(filter) => Some(match urlencoding::decode(filter.as_str()) {
-            Ok(filter) => filter.to_string(),
-            Err(e) => {
-                error!("Failed to decode filter: {}", e);
-                return Err(StatusCode::BAD_REQUEST);
-            }
-        }),
-        None => None,
+    let filters = match parse_filter_params(&query.filter, &query.q) {
+        Ok(filters) => filters,
+        Err(response) => return Ok(*response),
     };
-
+    let filter_redaction = filter_pillar_redaction(&auth, &filters)?;
     let sort = query.sort.clone();
     // Pagination
     let paginate: Paginate = query.paginate_query.parse_query();
 
-    let filters: Vec<Filter> = match filter {
-        Some(filter) => match serde_json::from_str(&filter) {
-            Ok(filters) => filters,
-            Err(e) => {
-                error!("Failed to parse filter: {}", e);
-                return Err(StatusCode::BAD_REQUEST);
-            }
-        },
-        None => vec![],
-    };
-
-    let mut minions = match get_minions(&data, filters, sort, paginate) {
-        Ok(minions) => minions,
-        Err(e) => {
-            error!("{:?}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
+    let mut minions = get_minions(&data, filters, sort, paginate, filter_redaction.as_ref())?;
 
     // Validate extra permission
-    if !has_resalt_permission(&auth, P_MINION_CONFORMITY)? {
+    strip_minion_fields(&auth, &mut minions)?;
+
+    Ok(Json(minions).into_response())
+}
+
+/// Remove or redact the minion fields which the user is not permitted to see.
+fn strip_minion_fields(auth: &AuthStatus, minions: &mut [Minion]) -> Result<(), StatusCode> {
+    if !has_resalt_permission(auth, P_MINION_CONFORMITY)? {
         for minion in minions.iter_mut() {
             minion.conformity = None;
         }
     }
-    if !has_resalt_permission(&auth, P_MINION_PILLARS)? {
+    if !has_resalt_permission(auth, P_MINION_PILLARS)? {
         for minion in minions.iter_mut() {
             minion.pillars = None;
         }
+    } else if !has_resalt_permission(auth, P_MINION_PILLARS_SECRETS)? {
+        let redaction = pillar_redaction();
+        for minion in minions.iter_mut() {
+            redaction.redact_minion(minion);
+        }
     }
-    if !has_resalt_permission(&auth, P_MINION_PACKAGES)? {
+    if !has_resalt_permission(auth, P_MINION_PACKAGES)? {
         for minion in minions.iter_mut() {
             minion.pkgs = None;
         }
     }
+    Ok(())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MinionsExportGetQuery {
+    /// URL-encoded JSON list of filters
+    filter: Option<String>,
+    /// Text query, e.g. `os:Ubuntu and not pkg.nginx`
+    q: Option<String>,
+    sort: Option<MinionSort>,
+    /// URL-encoded JSON array of column names
+    columns: Option<String>,
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/minions/export",
+    tag = "minions",
+    params(MinionsExportGetQuery),
+    responses(
+        (
+            status = 200,
+            description = "Minions as CSV or NDJSON",
+            content((String = "text/csv"), (String = "application/x-ndjson"))
+        ),
+        (status = 400, description = "Invalid filter, query or columns"),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_LIST))),
+)]
+pub async fn route_minions_export_get(
+    query: Query<MinionsExportGetQuery>,
+    State(data): State<Storage>,
+    Extension(auth): Extension<AuthStatus>,
+) -> Result<Response, StatusCode> {
+    // Validate permission
+    if !has_resalt_permission(&auth, P_MINION_LIST)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let filters = match parse_filter_params(&query.filter, &query.q) {
+        Ok(filters) => filters,
+        Err(response) => return Ok(*response),
+    };
+    let filter_redaction = filter_pillar_redaction(&auth, &filters)?;
+    let columns: Vec<ExportColumn> = match &query.columns {
+        Some(columns) => {
+            let columns = match urlencoding::decode(columns.as_str()) {
+                Ok(columns) => columns.to_string(),
+                Err(e) => {
+                    error!("Failed to decode columns: {}", e);
+                    return Err(StatusCode::BAD_REQUEST);
+                }
+            };
+            let columns: Vec<String> = match serde_json::from_str(&columns) {
+                Ok(columns) => columns,
+                Err(e) => {
+                    error!("Failed to parse columns: {}", e);
+                    return Err(StatusCode::BAD_REQUEST);
+                }
+            };
+            match columns.iter().map(|c| c.parse()).collect() {
+                Ok(columns) => columns,
+                Err(e) => {
+                    error!("Failed to parse columns: {}", e);
+                    return Err(StatusCode::BAD_REQUEST);
+                }
+            }
+        }
+        None => ExportColumn::default_columns(),
+    };
+
+    let mut minions = get_minions(
+        &data,
+        filters,
+        query.sort.clone(),
+        None,
+        filter_redaction.as_ref(),
+    )?;
+
+    // Validate extra permission
+    strip_minion_fields(&auth, &mut minions)?;
+    let redaction = match has_resalt_permission(&auth, P_MINION_PILLARS_SECRETS)? {
+        true => None,
+        false => Some(pillar_redaction()),
+    };
+
+    let names = columns.iter().map(|c| c.name()).collect();
+    let rows = minions.into_iter().map(move |minion| {
+        let grains: Value =
+            serde_json::from_str(minion.grains.as_deref().unwrap_or("{}")).unwrap_or_default();
+        let grains = match &redaction {
+            Some(redaction) => redaction.redact_value(grains),
+            None => grains,
+        };
+        columns
+            .iter()
+            .map(|column| column.minion_value(&minion, &grains))
+            .collect()
+    });
 
-    Ok(Json(minions))
+    Ok(export_response(query.format, "minions", names, rows))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/minions/{minion_id}",
+    tag = "minions",
+    params(("minion_id" = String, Path, description = "Minion ID")),
+    responses(
+        (status = 200, description = "The minion", body = Minion),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "Minion not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_LIST))),
+)]
 pub async fn route_minion_get(
     Path(minion_id): Path<String>,
     State(data): State<Storage>,
@@ -95,7 +230,7 @@ pub async fn route_minion_get(
         return Err(StatusCode::FORBIDDEN);
     }
 
-    let minion = match get_minion(&data, &minion_id) {
+    let mut minion = match get_minion(&data, &minion_id) {
         Ok(Some(minion)) => minion,
         Ok(None) => {
             return Err(StatusCode::NOT_FOUND);
@@ -106,9 +241,139 @@ pub async fn route_minion_get(
         }
     };
 
+    // Validate extra permission
+    strip_minion_fields(&auth, std::slice::from_mut(&mut minion))?;
+
     Ok(Json(minion))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/minions/{minion_id}/availability",
+    tag = "minions",
+    params(("minion_id" = String, Path, description = "Minion ID"), PaginateQuery),
+    responses(
+        (
+            status = 200,
+            description = "Status transitions, oldest first",
+            body = Vec<MinionStatusTransition>
+        ),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_LIST))),
+)]
+pub async fn route_minion_availability_get(
+    Path(minion_id): Path<String>,
+    query: Query<PaginateQuery>,
+    State(data): State<Storage>,
+    Extension(auth): Extension<AuthStatus>,
+) -> Result<impl IntoResponse, StatusCode> {
+    // Validate permission
+    if !has_resalt_permission(&auth, P_MINION_LIST)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // Pagination
+    let paginate: Paginate = query.parse_query();
+
+    // API
+    get_minion_status_transitions(&data, &minion_id, paginate).map(Json)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MinionHistoryGetQuery {
+    /// Only show versions from this RFC 3339 timestamp onwards
+    since: Option<String>,
+    // Include fields from PaginateQuery
+    #[serde(flatten)]
+    #[param(ignore)]
+    paginate_query: PaginateQuery,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/minions/{minion_id}/history/{kind}",
+    tag = "minions",
+    description = "Pillars additionally require `minion.pillars`, and packages `minion.packages`.",
+    params(
+        ("minion_id" = String, Path, description = "Minion ID"),
+        ("kind" = MinionSnapshotKind, Path, description = "Which data to show the history of"),
+        MinionHistoryGetQuery,
+        PaginateQuery,
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Versions of the data, newest first",
+            body = Vec<MinionHistoryEntry>
+        ),
+        (status = 400, description = "Invalid timestamp"),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "Unknown kind"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_LIST))),
+)]
+pub async fn route_minion_history_get(
+    Path((minion_id, kind)): Path<(String, String)>,
+    query: Query<MinionHistoryGetQuery>,
+    State(data): State<Storage>,
+    Extension(auth): Extension<AuthStatus>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let kind: MinionSnapshotKind = match kind.parse() {
+        Ok(kind) => kind,
+        Err(_) => return Err(StatusCode::NOT_FOUND),
+    };
+
+    // Validate permission
+    if !has_resalt_permission(&auth, P_MINION_LIST)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let extra_permission = match kind {
+        MinionSnapshotKind::Grains => None,
+        MinionSnapshotKind::Pillars => Some(P_MINION_PILLARS),
+        MinionSnapshotKind::Pkgs => Some(P_MINION_PACKAGES),
+    };
+    if let Some(permission) = extra_permission {
+        if !has_resalt_permission(&auth, permission)? {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let since = match query.since.as_deref() {
+        Some(since) if !since.is_empty() => match ResaltTime::parse_from_rfc3339(since) {
+            Ok(since) => Some(since),
+            Err(e) => {
+                error!("Failed to parse since: {}", e);
+                return Err(StatusCode::BAD_REQUEST);
+            }
+        },
+        _ => None,
+    };
+
+    // Pagination
+    let paginate: Paginate = query.paginate_query.parse_query();
+
+    let redaction = match has_resalt_permission(&auth, P_MINION_PILLARS_SECRETS)? {
+        true => None,
+        false => Some(pillar_redaction()),
+    };
+
+    // API
+    get_minion_history(&data, &minion_id, kind, since, paginate, redaction.as_ref()).map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/minions/{minion_id}/refresh",
+    tag = "minions",
+    params(("minion_id" = String, Path, description = "Minion ID")),
+    responses(
+        (status = 200, description = "Refresh of grains, pillars and packages requested"),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_REFRESH))),
+)]
 pub async fn route_minion_refresh_post(
     Path(minion_id): Path<String>,
     State(salt): State<SaltAPI>,
@@ -120,16 +385,11 @@ pub async fn route_minion_refresh_post(
         return Err(StatusCode::FORBIDDEN);
     }
 
-    let salt_token = match &auth.salt_token {
-        Some(salt_token) => salt_token,
-        None => {
-            error!("No salt token found");
-            return Err(StatusCode::UNAUTHORIZED);
-        }
-    };
+    let master = get_minion_master(&data, &salt.masters(), &minion_id, None)?;
+    let salt_token = salt_token_for(&data, &salt, &auth, &master).await?;
 
     // API
-    match refresh_minion(&salt, salt_token, &minion_id).await {
+    match refresh_minion(&salt, &salt_token, &minion_id).await {
         Ok(()) => Ok(Json(())),
         Err(SaltError::Unauthorized) => {
             if !salt_token.matured() {
@@ -137,9 +397,8 @@ pub async fn route_minion_refresh_post(
                 return Err(StatusCode::INTERNAL_SERVER_ERROR);
             }
             error!("Salt token expired, renewing and retrying");
-            let auth =
-                renew_token_salt_token(&data, &salt, &auth.user_id, &auth.auth_token).await?;
-            match refresh_minion(&salt, &auth.salt_token.unwrap(), &minion_id).await {
+            let salt_token = renew_salt_token_for(&data, &salt, &auth, &master).await?;
+            match refresh_minion(&salt, &salt_token, &minion_id).await {
                 Ok(()) => Ok(Json(())),
                 Err(e) => {
                     error!("route_minion_refresh_post {:?}", e);
@@ -7,6 +7,7 @@ mod jobs;
 mod keys;
 mod minions;
 mod myself;
+mod packages;
 mod permissions;
 mod presets;
 mod settings;
@@ -22,6 +23,7 @@ pub use jobs::*;
 pub use keys::*;
 pub use minions::*;
 pub use myself::*;
+pub use packages::*;
 pub use permissions::*;
 pub use presets::*;
 pub use settings::*;
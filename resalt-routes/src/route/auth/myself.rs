@@ -4,9 +4,17 @@ use resalt_api::{
     permission::get_permission_groups_by_user_id,
     user::{get_preferences, get_user_by_id},
 };
-use resalt_models::AuthStatus;
+use resalt_models::{AuthStatus, PublicUser};
 use resalt_storage::Storage;
 
+#[utoipa::path(
+    get,
+    path = "/api/myself",
+    tag = "users",
+    responses(
+        (status = 200, description = "The logged in user", body = PublicUser),
+    ),
+)]
 pub async fn route_myself_get(
     State(data): State<Storage>,
     Extension(auth): Extension<AuthStatus>,
@@ -0,0 +1,85 @@
+use crate::permission::*;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use log::*;
+use resalt_api::package::{get_package, get_packages};
+use resalt_models::*;
+use resalt_storage::Storage;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+#[utoipa::path(
+    get,
+    path = "/api/packages",
+    tag = "packages",
+    responses(
+        (status = 200, description = "Installed packages across all minions", body = Vec<Package>),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_PACKAGES))),
+)]
+pub async fn route_packages_get(
+    State(data): State<Storage>,
+    Extension(auth): Extension<AuthStatus>,
+) -> Result<impl IntoResponse, StatusCode> {
+    // Validate permission
+    if !has_resalt_permission(&auth, P_MINION_PACKAGES)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // API
+    get_packages(&data).map(Json)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PackageGetQuery {
+    /// Only show versions matching this constraint, e.g. "<3.0.13"
+    version: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/packages/{name}",
+    tag = "packages",
+    params(("name" = String, Path, description = "Package name"), PackageGetQuery),
+    responses(
+        (status = 200, description = "The package", body = Package),
+        (status = 400, description = "Invalid version constraint"),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "Package not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_PACKAGES))),
+)]
+pub async fn route_package_get(
+    Path(name): Path<String>,
+    query: Query<PackageGetQuery>,
+    State(data): State<Storage>,
+    Extension(auth): Extension<AuthStatus>,
+) -> Result<impl IntoResponse, StatusCode> {
+    // Validate permission
+    if !has_resalt_permission(&auth, P_MINION_PACKAGES)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let constraint = match query.version.as_deref() {
+        Some(version) if !version.is_empty() => match version.parse() {
+            Ok(constraint) => Some(constraint),
+            Err(e) => {
+                error!("Failed to parse version constraint: {}", e);
+                return Err(StatusCode::BAD_REQUEST);
+            }
+        },
+        _ => None,
+    };
+
+    // API
+    match get_package(&data, &name, constraint)? {
+        Some(package) => Ok(Json(package)),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
@@ -7,13 +7,14 @@ use axum::{
 };
 use log::*;
 use resalt_api::permission::{
-    create_permission_group, delete_permission_group, get_permission_group_by_id,
+    create_permission_group, delete_permission_group, get_audit_records,
+    get_permission_group_by_id, get_permission_group_grants, get_permission_group_resolved_perms,
     get_permission_group_users, get_permission_groups, update_permission_group,
 };
 use resalt_models::*;
 use resalt_storage::Storage;
 use serde::Deserialize;
-use serde_json::Value;
+use utoipa::ToSchema;
 
 async fn get_group(data: &Storage, group_id: &str) -> Result<impl IntoResponse, StatusCode> {
     let permission_group = match get_permission_group_by_id(data, group_id) {
@@ -32,9 +33,21 @@ async fn get_group(data: &Storage, group_id: &str) -> Result<impl IntoResponse,
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
-    Ok(Json(permission_group.public(users)))
+    let resolved_perms = get_permission_group_resolved_perms(data, group_id)?;
+    Ok(Json(permission_group.public(users, resolved_perms)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/permissions",
+    tag = "permissions",
+    params(PaginateQuery),
+    responses(
+        (status = 200, description = "Permission groups", body = Vec<PublicPermissionGroup>),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_GROUP))),
+)]
 pub async fn route_permissions_get(
     query: Query<PaginateQuery>,
     State(data): State<Storage>,
@@ -57,7 +70,7 @@ pub async fn route_permissions_get(
         }
     };
 
-    let mut results: Vec<Value> = Vec::new();
+    let mut results: Vec<PublicPermissionGroup> = Vec::new();
     for group in permission_groups {
         let users = match get_permission_group_users(&data, &group.id) {
             Ok(users) => users,
@@ -66,16 +79,81 @@ pub async fn route_permissions_get(
                 return Err(StatusCode::INTERNAL_SERVER_ERROR);
             }
         };
-        results.push(group.public(users));
+        let resolved_perms = get_permission_group_resolved_perms(&data, &group.id)?;
+        results.push(group.public(users, resolved_perms));
     }
     Ok(Json(results))
 }
 
-#[derive(Deserialize)]
+#[utoipa::path(
+    get,
+    path = "/api/permissions/grants",
+    tag = "permissions",
+    responses(
+        (status = 200, description = "Temporary memberships which have not expired, soonest expiring first", body = Vec<PermissionGroupGrant>),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_GROUP))),
+)]
+pub async fn route_permission_grants_get(
+    State(data): State<Storage>,
+    Extension(auth): Extension<AuthStatus>,
+) -> Result<impl IntoResponse, StatusCode> {
+    // Validate permission
+    if !has_resalt_permission(&auth, P_ADMIN_GROUP)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // Expired ones may not have been revoked yet
+    let now = ResaltTime::now();
+    let grants: Vec<PermissionGroupGrant> = get_permission_group_grants(&data)?
+        .into_iter()
+        .filter(|grant| !grant.is_expired(now))
+        .collect();
+    Ok(Json(grants))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/permissions/audit",
+    tag = "permissions",
+    params(PaginateQuery),
+    responses(
+        (status = 200, description = "Changes to group memberships, newest first", body = Vec<AuditRecord>),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_GROUP))),
+)]
+pub async fn route_permission_audit_get(
+    query: Query<PaginateQuery>,
+    State(data): State<Storage>,
+    Extension(auth): Extension<AuthStatus>,
+) -> Result<impl IntoResponse, StatusCode> {
+    // Validate permission
+    if !has_resalt_permission(&auth, P_ADMIN_GROUP)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let paginate: Paginate = query.parse_query();
+    Ok(Json(get_audit_records(&data, paginate)?))
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct PermissionGroupCreateRequest {
     pub name: String,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/permissions",
+    tag = "permissions",
+    request_body = PermissionGroupCreateRequest,
+    responses(
+        (status = 200, description = "The created permission group", body = PublicPermissionGroup),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_GROUP))),
+)]
 pub async fn route_permissions_post(
     State(data): State<Storage>,
     Extension(auth): Extension<AuthStatus>,
@@ -109,9 +187,24 @@ pub async fn route_permissions_post(
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
-    Ok(Json(permission_group.public(permission_group_users)))
+    let resolved_perms = get_permission_group_resolved_perms(&data, &permission_group_id)?;
+    Ok(Json(
+        permission_group.public(permission_group_users, resolved_perms),
+    ))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/permissions/{id}",
+    tag = "permissions",
+    params(("id" = String, Path, description = "Permission group ID")),
+    responses(
+        (status = 200, description = "The permission group", body = PublicPermissionGroup),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "Permission group not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_GROUP))),
+)]
 pub async fn route_permission_get(
     Path(id): Path<String>,
     State(data): State<Storage>,
@@ -125,12 +218,25 @@ pub async fn route_permission_get(
     get_group(&data, &id).await
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct PermissionGroupUpdateRequest {
     pub name: String,
     pub perms: String, // JSON encoded array
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/permissions/{id}",
+    tag = "permissions",
+    params(("id" = String, Path, description = "Permission group ID")),
+    request_body = PermissionGroupUpdateRequest,
+    responses(
+        (status = 200, description = "The updated permission group", body = PublicPermissionGroup),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "Permission group not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_GROUP))),
+)]
 pub async fn route_permission_put(
     Path(id): Path<String>,
     State(data): State<Storage>,
@@ -165,6 +271,101 @@ pub async fn route_permission_put(
     get_group(&data, &id).await
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/permissions/{id}/subgroups/{subgroup_id}",
+    tag = "permissions",
+    description = "Members of the subgroup become members of the group too, and inherit its permissions.",
+    params(
+        ("id" = String, Path, description = "Permission group ID"),
+        ("subgroup_id" = String, Path, description = "Permission group ID to include"),
+    ),
+    responses(
+        (status = 200, description = "The updated permission group", body = PublicPermissionGroup),
+        (status = 400, description = "The subgroup already includes the group"),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "Permission group not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_GROUP))),
+)]
+pub async fn route_permission_subgroup_post(
+    Path((id, subgroup_id)): Path<(String, String)>,
+    State(data): State<Storage>,
+    Extension(auth): Extension<AuthStatus>,
+) -> Result<impl IntoResponse, StatusCode> {
+    // Validate permission
+    if !has_resalt_permission(&auth, P_ADMIN_GROUP)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let mut permission_group = match get_permission_group_by_id(&data, &id)? {
+        Some(permission_group) => permission_group,
+        None => return Err(StatusCode::NOT_FOUND),
+    };
+    if get_permission_group_by_id(&data, &subgroup_id)?.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    if !permission_group.subgroups.contains(&subgroup_id) {
+        permission_group.subgroups.push(subgroup_id);
+        update_permission_group(&data, &permission_group)?;
+    }
+
+    get_group(&data, &id).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/permissions/{id}/subgroups/{subgroup_id}",
+    tag = "permissions",
+    params(
+        ("id" = String, Path, description = "Permission group ID"),
+        ("subgroup_id" = String, Path, description = "Permission group ID to no longer include"),
+    ),
+    responses(
+        (status = 200, description = "The updated permission group", body = PublicPermissionGroup),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "Permission group not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_GROUP))),
+)]
+pub async fn route_permission_subgroup_delete(
+    Path((id, subgroup_id)): Path<(String, String)>,
+    State(data): State<Storage>,
+    Extension(auth): Extension<AuthStatus>,
+) -> Result<impl IntoResponse, StatusCode> {
+    // Validate permission
+    if !has_resalt_permission(&auth, P_ADMIN_GROUP)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let mut permission_group = match get_permission_group_by_id(&data, &id)? {
+        Some(permission_group) => permission_group,
+        None => return Err(StatusCode::NOT_FOUND),
+    };
+
+    if permission_group.subgroups.contains(&subgroup_id) {
+        permission_group
+            .subgroups
+            .retain(|other| other != &subgroup_id);
+        update_permission_group(&data, &permission_group)?;
+    }
+
+    get_group(&data, &id).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/permissions/{id}",
+    tag = "permissions",
+    params(("id" = String, Path, description = "Permission group ID")),
+    responses(
+        (status = 200, description = "The deleted permission group", body = PublicPermissionGroup),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "Permission group not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_GROUP))),
+)]
 pub async fn route_permission_delete(
     Path(id): Path<String>,
     State(data): State<Storage>,
@@ -1,19 +1,30 @@
-use crate::permission::*;
+use crate::{filter::filter_pillar_redaction, permission::*};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
     Extension, Json,
 };
 use log::*;
 use resalt_api::preset::{
     create_minion_preset, delete_minion_preset, get_minion_preset, get_minion_presets,
-    update_minion_preset,
+    get_nodegroups, update_minion_preset,
 };
-use resalt_models::{AuthStatus, MinionPreset};
+use resalt_models::{render_nodegroups_yaml, AuthStatus, FilterNode, MinionPreset};
 use resalt_storage::Storage;
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
+#[utoipa::path(
+    get,
+    path = "/api/presets",
+    tag = "presets",
+    responses(
+        (status = 200, description = "Minion presets", body = Vec<MinionPreset>),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_PRESETS_LIST))),
+)]
 pub async fn route_presets_get(
     State(data): State<Storage>,
     Extension(auth): Extension<AuthStatus>,
@@ -27,12 +38,109 @@ pub async fn route_presets_get(
     get_minion_presets(&data).map(Json)
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, ToSchema)]
+pub enum NodegroupsFormat {
+    #[default]
+    #[serde(rename = "yaml")]
+    Yaml,
+    #[serde(rename = "json")]
+    Json,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PresetsNodegroupsGetQuery {
+    /// Comma-separated preset IDs, all presets if not set
+    ids: Option<String>,
+    #[serde(default)]
+    format: NodegroupsFormat,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/presets/nodegroups",
+    tag = "presets",
+    params(PresetsNodegroupsGetQuery),
+    responses(
+        (
+            status = 200,
+            description = "Presets rendered as Salt nodegroups",
+            content((String = "application/yaml"), (Object = "application/json"))
+        ),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "Preset not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_PRESETS_LIST))),
+)]
+pub async fn route_presets_nodegroups_get(
+    query: Query<PresetsNodegroupsGetQuery>,
+    State(data): State<Storage>,
+    Extension(auth): Extension<AuthStatus>,
+) -> Result<Response, StatusCode> {
+    // Validate permission
+    if !has_resalt_permission(&auth, P_MINION_PRESETS_LIST)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let presets = match &query.ids {
+        Some(ids) => {
+            let mut presets: Vec<MinionPreset> = Vec::new();
+            for id in ids
+                .split(',')
+                .map(|id| id.trim())
+                .filter(|id| !id.is_empty())
+            {
+                match get_minion_preset(&data, id)? {
+                    Some(preset) => presets.push(preset),
+                    None => return Err(StatusCode::NOT_FOUND),
+                }
+            }
+            presets
+        }
+        None => get_minion_presets(&data)?,
+    };
+
+    // Validate extra permission, if any preset filters on pillars
+    let mut filters: Vec<FilterNode> = Vec::new();
+    for preset in &presets {
+        filters.extend(preset.filters().map_err(|e| {
+            error!("{}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?);
+    }
+    let redaction = filter_pillar_redaction(&auth, &filters)?;
+
+    // API
+    let nodegroups = get_nodegroups(&data, &presets, redaction.as_ref())?;
+
+    Ok(match query.format {
+        NodegroupsFormat::Yaml => (
+            [(header::CONTENT_TYPE, "application/yaml; charset=utf-8")],
+            render_nodegroups_yaml(&nodegroups),
+        )
+            .into_response(),
+        NodegroupsFormat::Json => Json(nodegroups).into_response(),
+    })
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct PresetsCreateRequest {
     name: String,
     filter: String,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/presets",
+    tag = "presets",
+    request_body = PresetsCreateRequest,
+    responses(
+        (status = 200, description = "The created preset", body = MinionPreset),
+        (status = 400, description = "Invalid name or filter"),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_PRESETS_MANAGE))),
+)]
 pub async fn route_presets_post(
     State(data): State<Storage>,
     Extension(auth): Extension<AuthStatus>,
@@ -64,6 +172,18 @@ pub async fn route_presets_post(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/presets/{preset_id}",
+    tag = "presets",
+    params(("preset_id" = String, Path, description = "Preset ID")),
+    responses(
+        (status = 200, description = "The preset", body = MinionPreset),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "Preset not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_PRESETS_LIST))),
+)]
 pub async fn route_preset_get(
     Path(preset_id): Path<String>,
     State(data): State<Storage>,
@@ -85,12 +205,26 @@ pub async fn route_preset_get(
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct PresetUpdateRequest {
     name: String,
     filter: String,
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/presets/{preset_id}",
+    tag = "presets",
+    params(("preset_id" = String, Path, description = "Preset ID")),
+    request_body = PresetUpdateRequest,
+    responses(
+        (status = 200, description = "The updated preset", body = MinionPreset),
+        (status = 400, description = "Invalid name or filter"),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "Preset not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_PRESETS_MANAGE))),
+)]
 pub async fn route_preset_put(
     Path(preset_id): Path<String>,
     State(data): State<Storage>,
@@ -126,6 +260,21 @@ pub async fn route_preset_put(
     update_minion_preset(&data, &preset).map(|_| Json(preset))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/presets/{preset_id}",
+    tag = "presets",
+    params(("preset_id" = String, Path, description = "Preset ID")),
+    responses(
+        (
+            status = 200,
+            description = "The deleted preset, or null if it did not exist",
+            body = Option<MinionPreset>
+        ),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_PRESETS_MANAGE))),
+)]
 pub async fn route_preset_delete(
     Path(preset_id): Path<String>,
     State(data): State<Storage>,
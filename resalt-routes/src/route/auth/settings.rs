@@ -1,24 +1,90 @@
 use crate::permission::*;
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension, Json};
-use resalt_api::setting::{export_backup, import_backup, DataDump};
-use resalt_models::AuthStatus;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use log::*;
+use resalt_api::{
+    minion::pillar_redaction,
+    setting::{export_backup, import_backup},
+};
+use resalt_models::{AuthStatus, DataDump, DataDumpImportSummary, DataDumpSection};
 use resalt_storage::Storage;
+use serde::Deserialize;
+use serde_json::Value;
+use utoipa::IntoParams;
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SettingsImportPostQuery {
+    /// Only summarize what the import would do
+    #[serde(rename = "dryRun", default)]
+    dry_run: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/settings/import",
+    tag = "settings",
+    description = "Backups of older versions are upgraded before they are imported.",
+    params(SettingsImportPostQuery),
+    request_body = DataDump,
+    responses(
+        (status = 200, description = "What was imported", body = DataDumpImportSummary),
+        (status = 400, description = "Invalid backup, or of a newer version"),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_SUPERADMIN))),
+)]
 pub async fn route_settings_import_post(
+    query: Query<SettingsImportPostQuery>,
     State(data): State<Storage>,
     Extension(auth): Extension<AuthStatus>,
-    Json(input): Json<DataDump>,
+    Json(input): Json<Value>,
 ) -> Result<impl IntoResponse, StatusCode> {
     // Validate permission
     if !has_resalt_permission(&auth, P_ADMIN_SUPERADMIN)? {
         return Err(StatusCode::FORBIDDEN);
     }
 
+    let dump = match DataDump::from_value(input) {
+        Ok(dump) => dump,
+        Err(e) => {
+            warn!("route_settings_import_post {}", e);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+
     // API
-    import_backup(&data, &input).map(|_| ())
+    import_backup(&data, &dump, query.dry_run).map(Json)
 }
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SettingsExportGetQuery {
+    #[serde(rename = "includeSecrets", default)]
+    include_secrets: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/settings/export",
+    tag = "settings",
+    params(SettingsExportGetQuery),
+    responses(
+        (
+            status = 200,
+            description = "Backup of users, groups, minions and presets",
+            body = DataDump
+        ),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_SUPERADMIN))),
+)]
 pub async fn route_settings_export_get(
+    query: Query<SettingsExportGetQuery>,
     State(data): State<Storage>,
     Extension(auth): Extension<AuthStatus>,
 ) -> Result<impl IntoResponse, StatusCode> {
@@ -27,6 +93,13 @@ pub async fn route_settings_export_get(
         return Err(StatusCode::FORBIDDEN);
     }
 
+    // Pillar secrets are redacted unless explicitly asked for
+    let redaction =
+        match query.include_secrets && has_resalt_permission(&auth, P_MINION_PILLARS_SECRETS)? {
+            true => None,
+            false => Some(pillar_redaction()),
+        };
+
     // API
-    export_backup(&data).map(Json)
+    export_backup(&data, &DataDumpSection::SETTINGS, redaction.as_ref()).map(Json)
 }
@@ -4,8 +4,22 @@ use resalt_models::*;
 use resalt_salt::SaltEventListenerStatus;
 use resalt_storage::Storage;
 
+#[utoipa::path(
+    get,
+    path = "/api/status",
+    tag = "status",
+    responses(
+        (
+            status = 200,
+            description = "Status of the Salt masters and the database",
+            body = SystemStatus
+        ),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_MINION_LIST))),
+)]
 pub async fn route_status_get(
-    State(_listener_status): State<SaltEventListenerStatus>,
+    State(listener_status): State<SaltEventListenerStatus>,
     State(_data): State<Storage>,
     Extension(auth): Extension<AuthStatus>,
 ) -> Result<impl IntoResponse, StatusCode> {
@@ -15,19 +29,18 @@ pub async fn route_status_get(
     }
 
     // API
-    Ok(Json(StorageStatus {
-        auth_tokens_total: -1,
-        auth_tokens_active: -1,
-        events_total: -1,
-        job_returns_total: -1,
-        jobs_total: -1,
-        minions_total: -1,
-        minions_success: -1,
-        minions_incorrect: -1,
-        minions_error: -1,
-        minions_unknown: -1,
-        permission_group_users_total: -1,
-        permission_groups_total: -1,
-        users_total: -1,
+    Ok(Json(SystemStatus {
+        salt: listener_status.connected(),
+        salt_masters: listener_status.masters(),
+        db: true,
+        db_auth_tokens_total: None,
+        db_auth_tokens_active: None,
+        db_events_total: None,
+        db_job_returns_total: None,
+        db_jobs_total: None,
+        db_minions_total: None,
+        db_permission_group_users_total: None,
+        db_permission_groups_total: None,
+        db_users_total: None,
     }))
 }
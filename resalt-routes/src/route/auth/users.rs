@@ -5,23 +5,38 @@ use axum::{
     response::IntoResponse,
     Extension, Json,
 };
+use chrono::Duration;
 use log::*;
 use resalt_api::{
     permission::{
-        add_user_to_group, get_permission_group_by_id, get_permission_groups_by_user_id,
-        is_user_member_of_group, remove_user_from_group,
+        add_audit_record, add_user_to_group, delete_permission_group_grant,
+        get_permission_group_by_id, get_permission_group_grant, get_permission_groups_by_user_id,
+        grant_user_group, is_user_member_of_group, remove_user_from_group,
     },
     user::{
         create_user, delete_user, get_preferences, get_user_by_id, get_user_by_username, get_users,
-        update_preferences, update_user,
+        set_user_access, update_preferences, update_user,
     },
 };
-use resalt_models::{AuthStatus, Paginate, PaginateQuery, Preferences};
+use resalt_models::{
+    AuthStatus, Paginate, PaginateQuery, PermissionGroupGrant, Preferences, PublicUser, ResaltTime,
+};
 use resalt_security::hash_password;
 use resalt_storage::Storage;
 use serde::Deserialize;
-use serde_json::Value;
-
+use utoipa::ToSchema;
+
+#[utoipa::path(
+    get,
+    path = "/api/users",
+    tag = "users",
+    params(PaginateQuery),
+    responses(
+        (status = 200, description = "Users", body = Vec<PublicUser>),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_USER_LIST))),
+)]
 pub async fn route_users_get(
     query: Query<PaginateQuery>,
     State(data): State<Storage>,
@@ -39,7 +54,7 @@ pub async fn route_users_get(
     let users = get_users(&data, paginate)?;
 
     // Map to "public" - for among other things - remove password
-    let mut results: Vec<Value> = Vec::new();
+    let mut results: Vec<PublicUser> = Vec::new();
     for user in users {
         results.push(user.public(
             get_permission_groups_by_user_id(&data, &user.id)?,
@@ -50,12 +65,24 @@ pub async fn route_users_get(
     Ok(Json(results))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct UserCreateRequest {
     pub username: String,
     pub email: Option<String>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/users",
+    tag = "users",
+    request_body = UserCreateRequest,
+    responses(
+        (status = 200, description = "The created user", body = PublicUser),
+        (status = 400, description = "Invalid username"),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_USER))),
+)]
 pub async fn route_users_post(
     State(data): State<Storage>,
     Extension(auth): Extension<AuthStatus>,
@@ -67,7 +94,7 @@ pub async fn route_users_post(
     }
 
     // Check if username is taken
-    if (get_user_by_username(&data, &input.username)?).is_none() {
+    if (get_user_by_username(&data, &input.username)?).is_some() {
         return Err(StatusCode::BAD_REQUEST);
     };
 
@@ -83,6 +110,19 @@ pub async fn route_users_post(
     Ok(Json(user))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/users/{user_id}",
+    tag = "users",
+    description = "Users may always fetch themselves.",
+    params(("user_id" = String, Path, description = "User ID")),
+    responses(
+        (status = 200, description = "The user", body = PublicUser),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "User not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_USER_LIST))),
+)]
 pub async fn route_user_get(
     Path(user_id): Path<String>,
     State(data): State<Storage>,
@@ -108,6 +148,18 @@ pub async fn route_user_get(
     Ok(Json(user))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/users/{user_id}",
+    tag = "users",
+    params(("user_id" = String, Path, description = "User ID")),
+    responses(
+        (status = 200, description = "User deleted"),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "User not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_USER))),
+)]
 pub async fn route_user_delete(
     Path(user_id): Path<String>,
     State(data): State<Storage>,
@@ -130,11 +182,25 @@ pub async fn route_user_delete(
     Ok(Json(()))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct UserPostPasswordData {
     password: String,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/users/{user_id}/password",
+    tag = "users",
+    description = "Changing your own password requires `user.password` instead.",
+    params(("user_id" = String, Path, description = "User ID")),
+    request_body = UserPostPasswordData,
+    responses(
+        (status = 200, description = "Password changed"),
+        (status = 400, description = "Password is shorter than 8 characters"),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_USER))),
+)]
 pub async fn route_user_password_post(
     Path(user_id): Path<String>,
     State(data): State<Storage>,
@@ -173,6 +239,70 @@ pub async fn route_user_password_post(
     Ok(Json(()))
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct UserPostAccessData {
+    disabled: bool,
+    #[serde(rename = "expiresAt", default)]
+    expires_at: Option<ResaltTime>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users/{user_id}/access",
+    tag = "users",
+    description = "Disabled and expired users can not log in, and their sessions are ended.",
+    params(("user_id" = String, Path, description = "User ID")),
+    request_body = UserPostAccessData,
+    responses(
+        (status = 200, description = "Access changed", body = PublicUser),
+        (status = 400, description = "Tried to change own access"),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "User not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_USER))),
+)]
+pub async fn route_user_access_post(
+    Path(user_id): Path<String>,
+    State(data): State<Storage>,
+    Extension(auth): Extension<AuthStatus>,
+    Json(input): Json<UserPostAccessData>,
+) -> Result<impl IntoResponse, StatusCode> {
+    // Validate permission
+    if !has_resalt_permission(&auth, P_ADMIN_USER)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // Don't allow locking out self
+    if auth.user_id == user_id {
+        warn!("Tried to change own access: {}", user_id);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut user = match get_user_by_id(&data, &user_id)? {
+        Some(user) => user,
+        None => return Err(StatusCode::NOT_FOUND),
+    };
+    set_user_access(&data, &mut user, input.disabled, input.expires_at)?;
+
+    let permission_groups = get_permission_groups_by_user_id(&data, &user.id)?;
+    let preferences = get_preferences(&data, &user.id)?.unwrap_or_default();
+    Ok(Json(user.public(permission_groups, preferences)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users/{user_id}/preferences",
+    tag = "users",
+    description = "Users may always change their own preferences.",
+    params(("user_id" = String, Path, description = "User ID")),
+    request_body = Preferences,
+    responses(
+        (status = 200, description = "Preferences saved"),
+        (status = 400, description = "Invalid preferences"),
+        (status = 403, description = "Missing permission"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_USER))),
+)]
 pub async fn route_user_preferences_post(
     Path(user_id): Path<String>,
     State(data): State<Storage>,
@@ -208,6 +338,21 @@ pub async fn route_user_preferences_post(
     Ok(Json(()))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/users/{user_id}/permissions/{group_id}",
+    tag = "users",
+    params(
+        ("user_id" = String, Path, description = "User ID"),
+        ("group_id" = String, Path, description = "Permission group ID"),
+    ),
+    responses(
+        (status = 200, description = "User added to the group"),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "User or group not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_GROUP))),
+)]
 pub async fn route_user_permissions_post(
     Path((user_id, group_id)): Path<(String, String)>,
     State(data): State<Storage>,
@@ -238,10 +383,104 @@ pub async fn route_user_permissions_post(
     if !is_user_member_of_group(&data, &user_id, &group_id)? {
         add_user_to_group(&data, &user.id, &permission_group.id)?;
     }
+    // Make a temporary membership permanent
+    if let Some(grant) = get_permission_group_grant(&data, &user_id, &group_id)? {
+        delete_permission_group_grant(&data, &grant.id)?;
+        add_audit_record(
+            &data,
+            "grant.permanent",
+            Some(&auth.user_id),
+            Some(&user_id),
+            Some(&group_id),
+            "Temporary membership made permanent".to_string(),
+        )?;
+    }
 
     Ok(Json(()))
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct UserPermissionGrantRequest {
+    /// Seconds until the membership is revoked.
+    duration: i64,
+    reason: String,
+}
+
+/// Temporary memberships can not last longer than this.
+const GRANT_MAX_DURATION: i64 = 366 * 24 * 60 * 60;
+
+#[utoipa::path(
+    post,
+    path = "/api/users/{user_id}/permissions/{group_id}/grant",
+    tag = "users",
+    description = "Add a user to a group temporarily, e.g. for an incident. Granting again extends the membership.",
+    params(
+        ("user_id" = String, Path, description = "User ID"),
+        ("group_id" = String, Path, description = "Permission group ID"),
+    ),
+    request_body = UserPermissionGrantRequest,
+    responses(
+        (status = 200, description = "User added to the group until the grant expires", body = PermissionGroupGrant),
+        (status = 400, description = "Invalid duration, missing reason, or the user is a permanent member"),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "User or group not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_GROUP))),
+)]
+pub async fn route_user_permissions_grant_post(
+    Path((user_id, group_id)): Path<(String, String)>,
+    State(data): State<Storage>,
+    Extension(auth): Extension<AuthStatus>,
+    Json(input): Json<UserPermissionGrantRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    // Validate permission
+    if !has_resalt_permission(&auth, P_ADMIN_GROUP)? {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if input.duration <= 0 || input.duration > GRANT_MAX_DURATION {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let reason = input.reason.trim();
+    if reason.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // Check if user and group exist
+    if get_user_by_id(&data, &user_id)?.is_none()
+        || get_permission_group_by_id(&data, &group_id)?.is_none()
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let expires_at = ResaltTime::now() + Duration::seconds(input.duration);
+    let grant = grant_user_group(
+        &data,
+        &user_id,
+        &group_id,
+        expires_at,
+        reason,
+        &auth.user_id,
+    )?;
+
+    Ok(Json(grant))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/users/{user_id}/permissions/{group_id}",
+    tag = "users",
+    params(
+        ("user_id" = String, Path, description = "User ID"),
+        ("group_id" = String, Path, description = "Permission group ID"),
+    ),
+    responses(
+        (status = 200, description = "User removed from the group"),
+        (status = 403, description = "Missing permission"),
+        (status = 404, description = "User or group not found"),
+    ),
+    extensions(("x-resalt-permission" = json!(P_ADMIN_GROUP))),
+)]
 pub async fn route_user_permissions_delete(
     Path((user_id, group_id)): Path<(String, String)>,
     State(data): State<Storage>,
@@ -269,9 +508,60 @@ pub async fn route_user_permissions_delete(
     };
 
     // Remove user from group
+    let grant = get_permission_group_grant(&data, &user_id, &group_id)?;
     if is_user_member_of_group(&data, &user_id, &group_id)? {
         remove_user_from_group(&data, &user.id, &permission_group.id)?;
     }
+    if let Some(grant) = grant {
+        add_audit_record(
+            &data,
+            "grant.revoked",
+            Some(&auth.user_id),
+            Some(&user_id),
+            Some(&group_id),
+            format!(
+                "Membership revoked before {}, granted for: {}",
+                grant.expires_at, grant.reason
+            ),
+        )?;
+    }
 
     Ok(Json(()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_route_users_post_username_taken() {
+        let path = std::env::temp_dir()
+            .join(format!("resalt-routes-users-{}", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let _ = std::fs::remove_dir_all(&path);
+        let data = Storage::connect(&format!("files:{}", path)).await.unwrap();
+        let auth = AuthStatus {
+            user_id: "admin".to_string(),
+            perms: r#"[{"@resalt": ["admin.superadmin"]}]"#.to_string(),
+            auth_token: String::new(),
+            salt_tokens: vec![],
+        };
+        let create = |username: &str| {
+            route_users_post(
+                State(data.clone()),
+                Extension(auth.clone()),
+                Json(UserCreateRequest {
+                    username: username.to_string(),
+                    email: None,
+                }),
+            )
+        };
+
+        assert!(create("alice").await.is_ok());
+        assert!(create("bob").await.is_ok());
+        assert_eq!(create("alice").await.err(), Some(StatusCode::BAD_REQUEST));
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
@@ -2,7 +2,21 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Json},
 };
+use resalt_api::config::ApiConfig;
 
+#[utoipa::path(
+    get,
+    path = "/api/config",
+    tag = "auth",
+    responses(
+        (
+            status = 200,
+            description = "Public configuration of this Resalt instance",
+            body = ApiConfig
+        ),
+    ),
+    security(()),
+)]
 pub async fn route_config_get() -> Result<impl IntoResponse, StatusCode> {
     // API
     match resalt_api::config::get_config(true).await {
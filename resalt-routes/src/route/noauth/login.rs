@@ -9,16 +9,17 @@ use resalt_models::User;
 use resalt_salt::SaltAPI;
 use resalt_storage::Storage;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::login::{auth_login_classic, renew_token_salt_token};
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct LoginRequest {
     username: String,
     password: String,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, ToSchema)]
 struct LoginResponse {
     #[serde(rename = "userId")]
     user_id: String,
@@ -26,6 +27,21 @@ struct LoginResponse {
     expiry: u64,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (
+            status = 200,
+            description = "Logged in, the token is also set as the `resalt-auth` cookie",
+            body = LoginResponse
+        ),
+        (status = 401, description = "Invalid username or password"),
+    ),
+    security(()),
+)]
 pub async fn route_login_post(
     headers: HeaderMap,
     State(data): State<Storage>,
@@ -41,6 +57,14 @@ pub async fn route_login_post(
 
         // Fetch user to see if they exist
         match data.get_user_by_username(&username) {
+            // User EXISTS, but may not log in
+            Ok(Some(user)) if !user.is_active() => {
+                warn!(
+                    "Forwarded login by disabled or expired user {}",
+                    user.username
+                );
+                return Err(StatusCode::UNAUTHORIZED);
+            }
             // User EXISTS
             Ok(Some(user)) => user,
             // User DOES NOT exist, but we are in AuthForward, so create user
@@ -114,12 +138,17 @@ pub async fn route_login_post(
     };
 
     // Set cookie
+    let secure = if *ResaltConfig::HTTP_TLS_ENABLED {
+        "; Secure"
+    } else {
+        ""
+    };
     let mut headers = HeaderMap::new();
     headers.insert(
         "Set-Cookie",
         format!(
-            "resalt-auth={}; Path=/; HttpOnly; SameSite=Strict",
-            authtoken.id
+            "resalt-auth={}; Path=/; HttpOnly; SameSite=Strict{}",
+            authtoken.id, secure
         )
         .parse()
         .unwrap(),
@@ -2,18 +2,36 @@ use axum::http::HeaderMap;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::Json;
+use resalt_config::ResaltConfig;
 
+#[utoipa::path(
+    post,
+    path = "/api/logout",
+    tag = "auth",
+    responses(
+        (status = 200, description = "The `resalt-auth` cookie is cleared"),
+    ),
+    security(()),
+)]
 pub async fn route_logout_post() -> Result<impl IntoResponse, StatusCode> {
     // TODO: Check if user is logged in
     // TODO: Invalidate the token in Storage
 
     // Unset cookie
+    let secure = if *ResaltConfig::HTTP_TLS_ENABLED {
+        "; Secure"
+    } else {
+        ""
+    };
     let mut headers = HeaderMap::new();
     headers.insert(
         "Set-Cookie",
-        "resalt-auth=; Path=/; HttpOnly; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
-            .parse()
-            .unwrap(),
+        format!(
+            "resalt-auth=; Path=/; HttpOnly; Expires=Thu, 01 Jan 1970 00:00:00 GMT{}",
+            secure
+        )
+        .parse()
+        .unwrap(),
     );
 
     // Return
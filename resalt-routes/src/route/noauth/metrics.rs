@@ -4,6 +4,21 @@ use resalt_models::StorageStatus;
 use resalt_salt::SaltEventListenerStatus;
 use resalt_storage::Storage;
 
+#[utoipa::path(
+    get,
+    path = "/api/metrics",
+    tag = "status",
+    responses(
+        (
+            status = 200,
+            description = "Prometheus metrics",
+            content_type = "text/plain",
+            body = String
+        ),
+        (status = 404, description = "Metrics are disabled"),
+    ),
+    security(()),
+)]
 pub async fn route_metrics_get(
     State(listener_status): State<SaltEventListenerStatus>,
     State(_data): State<Storage>,
@@ -36,17 +51,51 @@ pub async fn route_metrics_get(
         users_total: -1,
     });
 
-    let salt: bool;
-    {
-        salt = *listener_status.connected.lock().unwrap();
-    }
-
     // Print Prometheus metrics
     let mut result = String::new();
 
+    let masters = listener_status.masters();
     result.push_str("# HELP resalt-salt_api_up Salt API is up\n");
     result.push_str("# TYPE resalt-salt_api_up gauge\n");
-    result.push_str(&format!("resalt-salt_api_up {}\n", i32::from(salt)));
+    for master in &masters {
+        result.push_str(&format!(
+            "resalt-salt_api_up{{master=\"{}\"}} {}\n",
+            master.name,
+            i32::from(master.connected)
+        ));
+    }
+
+    result.push_str(
+        "# HELP resalt-salt_api_disconnects_total Number of times the event stream was lost\n",
+    );
+    result.push_str("# TYPE resalt-salt_api_disconnects_total counter\n");
+    for master in &masters {
+        result.push_str(&format!(
+            "resalt-salt_api_disconnects_total{{master=\"{}\"}} {}\n",
+            master.name, master.disconnects
+        ));
+    }
+
+    result.push_str("# HELP resalt-salt_api_disconnected_seconds_total Time spent disconnected before reconnecting\n");
+    result.push_str("# TYPE resalt-salt_api_disconnected_seconds_total counter\n");
+    for master in &masters {
+        result.push_str(&format!(
+            "resalt-salt_api_disconnected_seconds_total{{master=\"{}\"}} {}\n",
+            master.name, master.disconnected_seconds
+        ));
+    }
+
+    result.push_str(
+        "# HELP resalt-salt_api_last_event_timestamp Unix time of the last event received\n",
+    );
+    result.push_str("# TYPE resalt-salt_api_last_event_timestamp gauge\n");
+    for master in &masters {
+        result.push_str(&format!(
+            "resalt-salt_api_last_event_timestamp{{master=\"{}\"}} {}\n",
+            master.name,
+            master.last_event.map(|t| t.timestamp()).unwrap_or(0)
+        ));
+    }
 
     result.push_str("# HELP resalt-db_up Database is up\n");
     result.push_str("# TYPE resalt-db_up gauge\n");
@@ -2,10 +2,12 @@ mod config;
 mod login;
 mod logout;
 mod metrics;
+mod openapi;
 mod token;
 
 pub use config::*;
 pub use login::*;
 pub use logout::*;
 pub use metrics::*;
+pub use openapi::*;
 pub use token::*;
@@ -0,0 +1,19 @@
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+
+use crate::openapi::openapi_json;
+
+#[utoipa::path(
+    get,
+    path = "/api/openapi.json",
+    tag = "auth",
+    responses(
+        (status = 200, description = "This OpenAPI document", body = Object),
+    ),
+    security(()),
+)]
+pub async fn route_openapi_get() -> Result<impl IntoResponse, StatusCode> {
+    Ok(([(header::CONTENT_TYPE, "application/json")], openapi_json()))
+}
@@ -5,15 +5,31 @@ use resalt_salt::RESALT_SALT_SYSTEM_SERVICE_USERNAME;
 use resalt_storage::Storage;
 use serde::Deserialize;
 use serde_json::{json, Value};
+use utoipa::ToSchema;
 
 use crate::login::validate_auth_token;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct TokenValidateRequest {
     username: String,
     password: String,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/token",
+    tag = "auth",
+    description = "Validates a Resalt token for the Salt `rest` external authentication.",
+    request_body(
+        content = TokenValidateRequest,
+        content_type = "application/x-www-form-urlencoded",
+    ),
+    responses(
+        (status = 200, description = "Salt eauth permissions of the token", body = Object),
+        (status = 401, description = "Invalid token"),
+    ),
+    security(()),
+)]
 pub async fn route_token_post(
     State(data): State<Storage>,
     Form(input): Form<TokenValidateRequest>,
@@ -25,7 +41,11 @@ pub async fn route_token_post(
     debug!("Token validation for {:?} with token {:?}", username, token);
 
     if username == RESALT_SALT_SYSTEM_SERVICE_USERNAME {
-        if token == *ResaltConfig::SALT_API_SYSTEM_SERVICE_TOKEN {
+        // Each master may log in with its own system service token
+        if ResaltConfig::SALT_MASTERS
+            .iter()
+            .any(|master| master.token == token)
+        {
             info!("System service token OK");
             return Ok(Json(json!([
                 ".*".to_string(),
@@ -3,27 +3,107 @@ use async_stream::stream;
 use futures::StreamExt;
 use futures_core::stream;
 use log::*;
-use reqwest::StatusCode;
-use resalt_config::ResaltConfig;
-use resalt_models::{SaltKeyState, SaltMinionKey, SaltRunJob, SaltToken};
-use serde_json::{json, Value};
-use std::{collections::HashMap, time::Duration};
+use reqwest::{Certificate, Identity, StatusCode};
+use resalt_config::{ResaltConfig, SaltMaster};
+use resalt_models::{ResaltTime, SaltKeyState, SaltMinionKey, SaltRunJob, SaltToken};
+use serde_json::{json, Map, Value};
+use std::{collections::HashMap, error::Error, sync::Arc, time::Duration};
 
 const X_AUTH_TOKEN: &str = "X-Auth-Token";
+/// Time format understood by the time arguments of the `jobs` runners, in the master's (UTC) time.
+const SALT_RUNNER_TIME_FMT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Join an error with all its sources, as reqwest hides the cause (e.g. a TLS failure) behind a generic message.
+fn error_chain(e: &dyn Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        // Some errors already include their source in their own message
+        let part = e.to_string();
+        if !message.contains(&part) {
+            message = format!("{}: {}", message, part);
+        }
+        source = e.source();
+    }
+    message
+}
+
+fn request_error(e: &reqwest::Error) -> SaltError {
+    let message = error_chain(e);
+    let lower = message.to_lowercase();
+    if ["certificate", "ssl", "tls", "handshake"]
+        .iter()
+        .any(|word| lower.contains(word))
+    {
+        SaltError::TlsError(message)
+    } else {
+        SaltError::RequestError(message)
+    }
+}
 
-pub fn create_reqwest_client() -> reqwest::Client {
+fn read_tls_file(what: &str, path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("Failed reading Salt API {} \"{}\": {}", what, path, e))
+}
+
+/// Build the Salt API client of a master, failing on unreadable or invalid CA and client certificates.
+pub fn create_reqwest_client(master: &SaltMaster) -> Result<reqwest::Client, String> {
     let mut builder = reqwest::ClientBuilder::new();
-    if *ResaltConfig::SALT_API_TLS_SKIPVERIFY {
+    if master.tls_skipverify {
         builder = builder.danger_accept_invalid_certs(true);
     }
+    let ca_path = master.tls_ca.as_str();
+    if !ca_path.is_empty() {
+        let certs =
+            Certificate::from_pem_bundle(&read_tls_file("CA bundle", ca_path)?).map_err(|e| {
+                format!(
+                    "Invalid Salt API CA bundle \"{}\": {}",
+                    ca_path,
+                    error_chain(&e)
+                )
+            })?;
+        if certs.is_empty() {
+            return Err(format!(
+                "Salt API CA bundle \"{}\" contains no certificates",
+                ca_path
+            ));
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    let cert_path = master.tls_client_cert.as_str();
+    let key_path = master.tls_client_key.as_str();
+    if !cert_path.is_empty() {
+        let cert = read_tls_file("client certificate", cert_path)?;
+        let key = read_tls_file("client key", key_path)?;
+        let identity = Identity::from_pkcs8_pem(&cert, &key).map_err(|e| {
+            format!(
+                "Invalid Salt API client certificate \"{}\" or key \"{}\" (the key must be PKCS#8): {}",
+                cert_path,
+                key_path,
+                error_chain(&e)
+            )
+        })?;
+        builder = builder.identity(identity);
+    }
     builder = builder.connect_timeout(Duration::from_secs(5));
     // builder = builder.timeout(Duration::from_secs(5)); // No timeout, as it breaks SSE
-    builder.build().unwrap()
+    builder
+        .build()
+        .map_err(|e| format!("Failed creating Salt API client: {}", error_chain(&e)))
+}
+
+struct SaltMasterClient {
+    config: SaltMaster,
+    client: reqwest::Client,
 }
 
+/// Client for the Salt API of every configured master.
+///
+/// Requests are sent to the master which issued the [`SaltToken`] they are made with.
 #[derive(Clone)]
 pub struct SaltAPI {
-    client: reqwest::Client,
+    masters: Arc<Vec<SaltMasterClient>>,
 }
 
 impl Default for SaltAPI {
@@ -33,16 +113,73 @@ impl Default for SaltAPI {
 }
 
 impl SaltAPI {
+    pub fn try_new() -> Result<Self, String> {
+        Self::try_with_masters(ResaltConfig::SALT_MASTERS.clone())
+    }
+
+    pub fn try_with_masters(masters: Vec<SaltMaster>) -> Result<Self, String> {
+        let masters = masters
+            .into_iter()
+            .map(|config| {
+                let client = create_reqwest_client(&config)
+                    .map_err(|e| format!("Salt master \"{}\": {}", config.name, e))?;
+                Ok(SaltMasterClient { config, client })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Self {
+            masters: Arc::new(masters),
+        })
+    }
+
+    /// Like [`SaltAPI::try_new`], but on invalid TLS config falls back to a client
+    /// with default TLS settings, so requests fail verification instead of skipping it.
     pub fn new() -> Self {
+        let masters = ResaltConfig::SALT_MASTERS
+            .iter()
+            .map(|config| {
+                let client = create_reqwest_client(config).unwrap_or_else(|e| {
+                    error!("Salt master \"{}\": {}", config.name, e);
+                    reqwest::ClientBuilder::new()
+                        .connect_timeout(Duration::from_secs(5))
+                        .build()
+                        .unwrap()
+                });
+                SaltMasterClient {
+                    config: config.clone(),
+                    client,
+                }
+            })
+            .collect();
         Self {
-            client: create_reqwest_client(),
+            masters: Arc::new(masters),
         }
     }
-    pub async fn login(&self, username: &str, authtoken: &str) -> Result<SaltToken, SaltError> {
-        let url = format!("{}/login", &ResaltConfig::SALT_API_URL.clone());
+
+    /// Names of the masters, in configured order.
+    pub fn masters(&self) -> Vec<String> {
+        self.masters.iter().map(|m| m.config.name.clone()).collect()
+    }
+
+    /// Tokens issued before multiple masters were supported have no master, and belong to the first.
+    fn master(&self, name: &str) -> Result<&SaltMasterClient, SaltError> {
+        match name.is_empty() {
+            true => self.masters.first(),
+            false => self.masters.iter().find(|m| m.config.name == name),
+        }
+        .ok_or_else(|| SaltError::UnknownMaster(name.to_owned()))
+    }
+
+    pub async fn login(
+        &self,
+        master: &str,
+        username: &str,
+        authtoken: &str,
+    ) -> Result<SaltToken, SaltError> {
+        let master = self.master(master)?;
+        let url = format!("{}/login", master.config.url);
         // Send POST request to Salt API for auth token
         // This will contact us back on the /token endpoint to validate auth token
-        let res = match self
+        let res = match master
             .client
             .post(&url)
             .json(&json!({
@@ -56,7 +193,7 @@ impl SaltAPI {
             Ok(res) => res,
             Err(e) => {
                 error!("login_err {:?}", e);
-                return Err(SaltError::RequestError(e.to_string()));
+                return Err(request_error(&e));
             }
         };
 
@@ -107,35 +244,48 @@ impl SaltAPI {
         if salt_token.perms.is_object() {
             salt_token.perms = json!(Vec::<String>::new());
         }
+        salt_token.master = master.config.name.clone();
 
         debug!("login {:?}", salt_token);
 
         Ok(salt_token)
     }
 
-    pub fn listen_events(&self, salt_token: &SaltToken) -> impl stream::Stream<Item = SaltEvent> {
+    /// Connect to the event bus of the master which issued `salt_token`.
+    ///
+    /// Fails if the connection can't be established, the stream ends when the connection is lost.
+    pub async fn listen_events(
+        &self,
+        salt_token: &SaltToken,
+    ) -> Result<impl stream::Stream<Item = SaltEvent>, SaltError> {
+        let master = self.master(&salt_token.master)?;
         let url = format!(
             "{}/events?salt_token={}",
-            ResaltConfig::SALT_API_URL.clone(),
-            salt_token.token.clone()
+            master.config.url, salt_token.token
         );
 
-        let client = self.client.clone();
-        stream! {
-            debug!("Connecting to SSE stream: {}", &url);
-            let res = match client
-                .get(&url)
-                .header("Accept", "text/event-stream")
-                .send()
-                .await
-            {
-                Ok(res) => res,
-                Err(e) => {
-                    error!("Failed to connect to SSE stream: {}", e);
-                    return;
-                }
-            };
+        debug!("Connecting to SSE stream: {}", &url);
+        let res = match master
+            .client
+            .get(&url)
+            .header("Accept", "text/event-stream")
+            .send()
+            .await
+        {
+            Ok(res) => res,
+            Err(e) => {
+                error!("Failed to connect to SSE stream: {}", error_chain(&e));
+                return Err(request_error(&e));
+            }
+        };
+        if res.status() == StatusCode::UNAUTHORIZED {
+            return Err(SaltError::Unauthorized);
+        }
+        if res.status() != StatusCode::OK {
+            return Err(SaltError::FailedRequest);
+        }
 
+        Ok(stream! {
             // Parse ServerSideEvents
             //
             // Important part is "tag" and "data".
@@ -252,7 +402,7 @@ impl SaltAPI {
                 }
             }
             debug!("SSE stream closed by ending loop");
-        }
+        })
     }
 
     pub async fn run_job(
@@ -260,14 +410,14 @@ impl SaltAPI {
         salt_token: &SaltToken,
         run_job: &SaltRunJob,
     ) -> Result<Value, SaltError> {
-        let url: &str = &ResaltConfig::SALT_API_URL;
+        let master = self.master(&salt_token.master)?;
         let data = json!(run_job);
 
         debug!("run_job data {:?}", data.to_string());
 
-        let res = match self
+        let res = match master
             .client
-            .post(url)
+            .post(&master.config.url)
             .header(X_AUTH_TOKEN, salt_token.token.clone())
             .json(&data)
             .send()
@@ -275,7 +425,7 @@ impl SaltAPI {
         {
             Ok(res) => res,
             Err(e) => {
-                return Err(SaltError::RequestError(e.to_string()));
+                return Err(request_error(&e));
             }
         };
 
@@ -408,6 +558,7 @@ impl SaltAPI {
                     id: host.clone(),
                     state: SaltKeyState::Rejected,
                     finger: finger.as_str().unwrap().to_owned(),
+                    master: salt_token.master.clone(),
                 });
             }
         }
@@ -417,6 +568,7 @@ impl SaltAPI {
                     id: host.clone(),
                     state: SaltKeyState::Denied,
                     finger: finger.as_str().unwrap().to_owned(),
+                    master: salt_token.master.clone(),
                 });
             }
         }
@@ -426,6 +578,7 @@ impl SaltAPI {
                     id: host.clone(),
                     state: SaltKeyState::Pending,
                     finger: finger.as_str().unwrap().to_owned(),
+                    master: salt_token.master.clone(),
                 });
             }
         }
@@ -435,6 +588,7 @@ impl SaltAPI {
                     id: host.clone(),
                     state: SaltKeyState::Accepted,
                     finger: finger.as_str().unwrap().to_owned(),
+                    master: salt_token.master.clone(),
                 });
             }
         }
@@ -598,6 +752,101 @@ impl SaltAPI {
         Ok(())
     }
 
+    /// Ask the master which minions respond, using the `manage.status` runner.
+    ///
+    /// Returns the IDs of all minions which are up.
+    pub async fn get_minions_up(&self, salt_token: &SaltToken) -> Result<Vec<String>, SaltError> {
+        let data = self
+            .run_job(
+                salt_token,
+                &SaltRunJob::Runner {
+                    fun: "manage.status".to_owned(),
+                    arg: None,
+                    kwarg: None,
+                },
+            )
+            .await?;
+        let up = match data.get("up") {
+            Some(up) => match up.as_array() {
+                Some(up) => up,
+                None => {
+                    return Err(SaltError::MissingExpectedDataError(
+                        "get_minions_up: up is not array".to_owned(),
+                    ));
+                }
+            },
+            None => {
+                return Err(SaltError::MissingExpectedDataError(
+                    "get_minions_up: missing up".to_owned(),
+                ));
+            }
+        };
+        Ok(up
+            .iter()
+            .filter_map(|id| id.as_str().map(|id| id.to_owned()))
+            .collect())
+    }
+
+    /// List the jobs published between `start` and `end`, using the `jobs.list_jobs` runner.
+    ///
+    /// Returns the job info (`Function`, `Arguments`, `Target`, `User`, ...) by jid.
+    pub async fn list_jobs(
+        &self,
+        salt_token: &SaltToken,
+        start: ResaltTime,
+        end: ResaltTime,
+    ) -> Result<Map<String, Value>, SaltError> {
+        let mut kwarg = HashMap::new();
+        kwarg.insert(
+            "start_time".to_owned(),
+            start.format(SALT_RUNNER_TIME_FMT).to_string(),
+        );
+        kwarg.insert(
+            "end_time".to_owned(),
+            end.format(SALT_RUNNER_TIME_FMT).to_string(),
+        );
+        let data = self
+            .run_job(
+                salt_token,
+                &SaltRunJob::Runner {
+                    fun: "jobs.list_jobs".to_owned(),
+                    arg: None,
+                    kwarg: Some(kwarg),
+                },
+            )
+            .await?;
+        match data {
+            Value::Object(jobs) => Ok(jobs),
+            _ => Err(SaltError::MissingExpectedDataError(
+                "list_jobs: return is not object".to_owned(),
+            )),
+        }
+    }
+
+    /// Fetch the returns of a job by minion ID, using the `jobs.lookup_jid` runner.
+    pub async fn lookup_jid(
+        &self,
+        salt_token: &SaltToken,
+        jid: &str,
+    ) -> Result<Map<String, Value>, SaltError> {
+        let data = self
+            .run_job(
+                salt_token,
+                &SaltRunJob::Runner {
+                    fun: "jobs.lookup_jid".to_owned(),
+                    arg: Some(vec![Value::String(jid.to_owned())]),
+                    kwarg: None,
+                },
+            )
+            .await?;
+        match data {
+            Value::Object(returns) => Ok(returns),
+            _ => Err(SaltError::MissingExpectedDataError(
+                "lookup_jid: return is not object".to_owned(),
+            )),
+        }
+    }
+
     async fn run_local_async(
         &self,
         salt_token: &SaltToken,
@@ -0,0 +1,84 @@
+use super::{SaltAPI, RESALT_SALT_SYSTEM_SERVICE_USERNAME};
+use log::*;
+use resalt_config::{ResaltConfig, SaltMaster};
+use resalt_models::ResaltTime;
+use resalt_storage::Storage;
+use std::time::Duration;
+
+/// Periodically records minion status transitions, and optionally probes
+/// all minions through the system service token to refresh their last seen time.
+pub struct SaltAvailabilityMonitor {
+    api: SaltAPI,
+    storage: Storage,
+}
+
+impl SaltAvailabilityMonitor {
+    pub fn new(storage: Storage) -> Self {
+        Self {
+            api: SaltAPI::new(),
+            storage,
+        }
+    }
+
+    async fn probe(&self, master: &SaltMaster) {
+        let salt_token = match self
+            .api
+            .login(
+                &master.name,
+                RESALT_SALT_SYSTEM_SERVICE_USERNAME,
+                &master.token,
+            )
+            .await
+        {
+            Ok(token) => token,
+            Err(err) => {
+                error!(
+                    "Failed to refresh probe token for master {}: {:?}",
+                    master.name, err
+                );
+                return;
+            }
+        };
+
+        let minions_up = match self.api.get_minions_up(&salt_token).await {
+            Ok(minions_up) => minions_up,
+            Err(err) => {
+                error!(
+                    "Failed to probe minions of master {}: {:?}",
+                    master.name, err
+                );
+                return;
+            }
+        };
+        debug!(
+            "Probe found {} minions up on master {}",
+            minions_up.len(),
+            master.name
+        );
+
+        let time = ResaltTime::now();
+        for minion_id in minions_up {
+            if let Err(e) = self.storage.set_minion_last_seen(&minion_id, time) {
+                error!("Failed updating minion last seen {:?}", e);
+            }
+            if let Err(e) = self.storage.set_minion_master(&minion_id, &master.name) {
+                error!("Failed updating minion master {:?}", e);
+            }
+        }
+    }
+
+    pub async fn start(&self) {
+        let interval = Duration::from_secs((*ResaltConfig::MINION_PROBE_INTERVAL).max(1));
+        loop {
+            if *ResaltConfig::MINION_PROBE_ENABLED {
+                for master in ResaltConfig::SALT_MASTERS.iter() {
+                    self.probe(master).await;
+                }
+            }
+            if let Err(e) = self.storage.refresh_minion_statuses() {
+                error!("Failed refreshing minion statuses {:?}", e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
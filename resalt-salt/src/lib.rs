@@ -1,7 +1,9 @@
 mod api;
+mod availability;
 mod listener;
 mod model;
 
 pub use api::*;
+pub use availability::*;
 pub use listener::*;
 pub use model::*;
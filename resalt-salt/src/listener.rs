@@ -1,507 +1,904 @@
-use super::SaltAPI;
+use super::{SaltAPI, SaltEvent};
+use chrono::Duration as ChronoDuration;
 use futures::{pin_mut, StreamExt};
 use log::*;
-use resalt_config::ResaltConfig;
-use resalt_models::{ResaltTime, SaltToken};
+use rand::Rng;
+use resalt_config::SaltMaster;
+use resalt_models::{ResaltTime, SaltMasterStatus, SaltToken};
 use resalt_storage::Storage;
-use serde_json::Value;
-use std::sync::{Arc, Mutex};
+use serde_json::{json, Map, Value};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 pub const RESALT_SALT_SYSTEM_SERVICE_USERNAME: &str = "$superadmin/svc/resalt$";
 
-#[derive(Debug, Clone)]
+/// Reconnect delays double on every failed attempt, up to this maximum.
+const RECONNECT_DELAY_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(60);
+/// Jobs published this long before the last event are backfilled too, as their returns may have been missed.
+const BACKFILL_MARGIN_SECONDS: i64 = 300;
+/// Outages longer than this are only backfilled for this last part.
+const BACKFILL_MAX_SECONDS: i64 = 24 * 60 * 60;
+
+/// Delay before the next reconnect, after `failures` consecutive failed attempts.
+///
+/// The delay is randomized between half and all of the backoff, so listeners
+/// don't reconnect in lockstep after a master restarts.
+fn reconnect_delay(failures: u32) -> Duration {
+    let backoff = RECONNECT_DELAY_MIN
+        .saturating_mul(2u32.saturating_pow(failures))
+        .min(RECONNECT_DELAY_MAX);
+    backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+}
+
+/// Connection status of the event listener of every master.
+#[derive(Debug, Clone, Default)]
 pub struct SaltEventListenerStatus {
-    pub connected: Arc<Mutex<bool>>,
+    masters: Arc<Mutex<Vec<SaltMasterStatus>>>,
 }
 
+impl SaltEventListenerStatus {
+    pub fn new(masters: &[String]) -> Self {
+        let masters = masters
+            .iter()
+            .map(|name| SaltMasterStatus {
+                name: name.clone(),
+                connected: false,
+                error: None,
+                last_event: None,
+                disconnects: 0,
+                disconnected_seconds: 0,
+            })
+            .collect();
+        Self {
+            masters: Arc::new(Mutex::new(masters)),
+        }
+    }
+
+    /// Status of every master, in configured order.
+    pub fn masters(&self) -> Vec<SaltMasterStatus> {
+        self.masters.lock().unwrap().clone()
+    }
+
+    /// Whether the listener of every master is connected.
+    pub fn connected(&self) -> bool {
+        let masters = self.masters.lock().unwrap();
+        !masters.is_empty() && masters.iter().all(|master| master.connected)
+    }
+
+    fn update(&self, master: &str, update: impl FnOnce(&mut SaltMasterStatus)) {
+        if let Some(status) = self
+            .masters
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|status| status.name == master)
+        {
+            update(status);
+        }
+    }
+
+    fn set_connected(&self, master: &str, connected: bool) {
+        self.update(master, |status| status.connected = connected);
+    }
+
+    /// The last reason the listener failed to connect, cleared once connected.
+    fn set_error(&self, master: &str, error: Option<String>) {
+        self.update(master, |status| status.error = error);
+    }
+
+    fn set_last_event(&self, master: &str, time: ResaltTime) {
+        self.update(master, |status| status.last_event = Some(time));
+    }
+
+    fn last_event(&self, master: &str) -> Option<ResaltTime> {
+        let masters = self.masters.lock().unwrap();
+        masters
+            .iter()
+            .find(|status| status.name == master)
+            .and_then(|status| status.last_event)
+    }
+
+    fn record_disconnect(&self, master: &str) {
+        self.update(master, |status| {
+            status.connected = false;
+            status.disconnects += 1;
+        });
+    }
+
+    fn record_reconnect(&self, master: &str, disconnected: ChronoDuration) {
+        self.update(master, |status| {
+            status.disconnected_seconds += disconnected.num_seconds().max(0) as u64;
+        });
+    }
+}
+
+/// Listens to the event bus of a single master.
 pub struct SaltEventListener {
-    api: SaltAPI,
+    /// `Err` if the Salt API client config is invalid, e.g. an unreadable CA bundle.
+    api: Result<SaltAPI, String>,
+    master: SaltMaster,
     storage: Storage,
     status: SaltEventListenerStatus,
 }
 
 impl SaltEventListener {
-    pub fn new(storage: Storage, status: SaltEventListenerStatus) -> Self {
+    pub fn new(storage: Storage, status: SaltEventListenerStatus, master: SaltMaster) -> Self {
         Self {
-            api: SaltAPI::new(),
+            api: SaltAPI::try_with_masters(vec![master.clone()]),
+            master,
             storage,
             status,
         }
     }
 
-    async fn refresh_token(&self) -> Option<SaltToken> {
-        match self
-            .api
+    async fn refresh_token(&self, api: &SaltAPI) -> Option<SaltToken> {
+        match api
             .login(
+                &self.master.name,
                 RESALT_SALT_SYSTEM_SERVICE_USERNAME,
-                &ResaltConfig::SALT_API_SYSTEM_SERVICE_TOKEN,
+                &self.master.token,
             )
             .await
         {
             Ok(token) => Some(token),
             Err(err) => {
-                error!("Failed to refresh token: {:?}", err);
+                error!(
+                    "Failed to refresh token for master {}: {:?}",
+                    self.master.name, err
+                );
+                self.status
+                    .set_error(&self.master.name, Some(err.to_string()));
                 None
             }
         }
     }
 
-    async fn listen(&self) {
-        let salt_token = match self.refresh_token().await {
-            Some(token) => token,
+    /// Any event from a minion is proof of life, and tells which master it is connected to.
+    fn set_minion_seen(&self, minion_id: &str, time: ResaltTime) {
+        if let Err(e) = self.storage.set_minion_last_seen(minion_id, time) {
+            error!("Failed updating minion last seen {:?}", e);
+        }
+        if let Err(e) = self.storage.set_minion_master(minion_id, &self.master.name) {
+            error!("Failed updating minion master {:?}", e);
+        }
+    }
+
+    /// Store an event, and update the jobs and minions it concerns.
+    ///
+    /// `replayed` events are reconstructed by backfilling and may be older than what is
+    /// already known, so they only record jobs and returns, and leave the master's last
+    /// event, the minions' last seen time and their grains, pillars, packages and
+    /// conformity as they are.
+    fn handle_event(&self, event: SaltEvent, replayed: bool) {
+        debug!("{:?}", event);
+
+        // Unpack string to JSON structure
+        let data: Value = match serde_json::from_str(&event.data) {
+            Ok(data) => data,
+            Err(err) => {
+                error!("Failed to parse event data: {:?}", err);
+                return;
+            }
+        };
+        let data = match data.get("data") {
+            Some(data) => data,
             None => {
-                error!("Failed to refresh listener token");
+                error!("Failed to get data from event data");
+                return;
+            }
+        };
+        let data = match data.as_object() {
+            Some(data) => data,
+            None => {
+                error!("Failed to get data as object");
                 return;
             }
         };
 
-        let stream = self.api.listen_events(&salt_token);
-        pin_mut!(stream);
+        // Unpack timestamp
+        let time = match data.get("_stamp") {
+            Some(time) => match time.as_str() {
+                Some(time) => match ResaltTime::parse_from_rfc3339(time) {
+                    Ok(time) => time,
+                    Err(err) => {
+                        error!("Failed to parse timestamp: {:?} {}", err, time);
+                        return;
+                    }
+                },
+                None => {
+                    error!("Failed to get timestamp from event data");
+                    return;
+                }
+            },
+            None => {
+                error!("Failed to get timestamp from event data");
+                return;
+            }
+        };
 
-        {
-            // Make sure lock is released after setting status
-            *self.status.connected.lock().unwrap() = true;
+        if !replayed {
+            self.status.set_last_event(&self.master.name, time);
         }
 
-        while let Some(event) = stream.next().await {
-            debug!("{:?}", event);
-
-            // Unpack string to JSON structure
-            let data: Value = match serde_json::from_str(&event.data) {
-                Ok(data) => data,
+        // Insert event into database
+        let event_id =
+            match self
+                .storage
+                .insert_event(event.tag.clone(), event.data, time, &self.master.name)
+            {
+                Ok(uuid) => uuid,
                 Err(err) => {
-                    error!("Failed to parse event data: {:?}", err);
-                    continue;
+                    error!("Failed to insert event: {:?}", err);
+                    return;
                 }
             };
-            let data = match data.get("data") {
-                Some(data) => data,
+
+        // Check tag type
+        let tag_parts: Vec<&str> = event.tag.split('/').collect();
+        if tag_parts.len() == 4
+            && tag_parts[0] == "salt"
+            && tag_parts[1] == "job"
+            && tag_parts[3] == "new"
+        {
+            let jid = tag_parts[2].to_string();
+            let user = match data.get("user") {
+                Some(user) => user.as_str().map(|s| s.to_string()),
                 None => {
-                    error!("Failed to get data from event data");
-                    continue;
+                    error!("Failed to get user from event data");
+                    return;
                 }
             };
-            let data = match data.as_object() {
-                Some(data) => data,
+
+            // Insert job into database
+            match self
+                .storage
+                .insert_job(jid, user, Some(event_id), time, &self.master.name)
+            {
+                Ok(_) => (),
+                Err(err) => error!("Failed to insert job: {:?}", err),
+            }
+        } else if tag_parts.len() == 5
+            && tag_parts[0] == "salt"
+            && tag_parts[1] == "job"
+            && tag_parts[3] == "ret"
+        {
+            let jid = tag_parts[2].to_string();
+            let minion_id = tag_parts[4].to_string();
+            let fun = match data.get("fun") {
+                Some(fun) => match fun.as_str() {
+                    Some(fun) => fun,
+                    None => {
+                        error!("Failed to get function from event data");
+                        return;
+                    }
+                },
                 None => {
-                    error!("Failed to get data as object");
-                    continue;
+                    error!("Failed to get function from event data");
+                    return;
                 }
             };
-
-            // Unpack timestamp
-            let time = match data.get("_stamp") {
-                Some(time) => match time.as_str() {
-                    Some(time) => match ResaltTime::parse_from_rfc3339(time) {
-                        Ok(time) => time,
-                        Err(err) => {
-                            error!("Failed to parse timestamp: {:?} {}", err, time);
-                            continue;
-                        }
-                    },
+            let fun_args = match data.get("fun_args") {
+                Some(fun_args) => match fun_args.as_array() {
+                    Some(fun_args) => fun_args,
                     None => {
-                        error!("Failed to get timestamp from event data");
-                        continue;
+                        error!("Failed to get function arguments from event data");
+                        return;
                     }
                 },
                 None => {
-                    error!("Failed to get timestamp from event data");
-                    continue;
+                    error!("Failed to get function arguments from event data");
+                    return;
                 }
             };
 
-            // Insert event into database
-            let event_id = match self
-                .storage
-                .insert_event(event.tag.clone(), event.data, time)
-            {
-                Ok(uuid) => uuid,
+            // Insert job return into database
+            match self.storage.get_job_by_jid(&jid) {
+                Ok(job) => match job {
+                    Some(job) => {
+                        let job_id = job.id;
+                        match self.storage.insert_job_return(
+                            jid,
+                            job_id,
+                            event_id,
+                            minion_id.clone(),
+                            time,
+                        ) {
+                            Ok(_) => (),
+                            Err(err) => error!("Failed to insert job return: {:?}", err),
+                        }
+                    }
+                    None => {
+                        warn!("Failed to get job by jid: {}", jid);
+                    }
+                },
                 Err(err) => {
-                    error!("Failed to insert event: {:?}", err);
-                    continue;
+                    error!("Failed to get job by jid: {:?}", err);
                 }
             };
 
-            // Check tag type
-            let tag_parts: Vec<&str> = event.tag.split('/').collect();
-            if tag_parts.len() == 4
-                && tag_parts[0] == "salt"
-                && tag_parts[1] == "job"
-                && tag_parts[3] == "new"
-            {
-                let jid = tag_parts[2].to_string();
-                let user = match data.get("user") {
-                    Some(user) => user.as_str().map(|s| s.to_string()),
-                    None => {
-                        error!("Failed to get user from event data");
-                        continue;
-                    }
-                };
+            if replayed {
+                return;
+            }
 
-                // Insert job into database
-                match self.storage.insert_job(jid, user, Some(event_id), time) {
-                    Ok(_) => (),
-                    Err(err) => error!("Failed to insert job: {:?}", err),
-                }
-            } else if tag_parts.len() == 5
-                && tag_parts[0] == "salt"
-                && tag_parts[1] == "job"
-                && tag_parts[3] == "ret"
-            {
-                let jid = tag_parts[2].to_string();
-                let minion_id = tag_parts[4].to_string();
-                let fun = match data.get("fun") {
-                    Some(fun) => match fun.as_str() {
-                        Some(fun) => fun,
-                        None => {
-                            error!("Failed to get function from event data");
-                            continue;
-                        }
-                    },
-                    None => {
-                        error!("Failed to get function from event data");
-                        continue;
-                    }
-                };
-                let fun_args = match data.get("fun_args") {
-                    Some(fun_args) => match fun_args.as_array() {
-                        Some(fun_args) => fun_args,
-                        None => {
-                            error!("Failed to get function arguments from event data");
-                            continue;
-                        }
-                    },
-                    None => {
-                        error!("Failed to get function arguments from event data");
-                        continue;
-                    }
-                };
-
-                // Insert job return into database
-                match self.storage.get_job_by_jid(&jid) {
-                    Ok(job) => match job {
-                        Some(job) => {
-                            let job_id = job.id;
-                            match self
-                                .storage
-                                .insert_job_return(jid, job_id, event_id, minion_id, time)
-                            {
-                                Ok(_) => (),
-                                Err(err) => error!("Failed to insert job return: {:?}", err),
+            // Any return is proof of life from the minion
+            self.set_minion_seen(&minion_id, time);
+
+            debug!("salt event job fun: {:?}", fun);
+            match fun {
+                "grains.items" => {
+                    let minion_id = match data.get("id") {
+                        Some(minion_id) => match minion_id.as_str() {
+                            Some(minion_id) => minion_id.to_string(),
+                            None => {
+                                error!("Failed to get minion ID from event data");
+                                return;
                             }
-                        }
+                        },
                         None => {
-                            warn!("Failed to get job by jid: {}", jid);
+                            error!("Failed to get minion ID from event data");
+                            return;
                         }
-                    },
-                    Err(err) => {
-                        error!("Failed to get job by jid: {:?}", err);
-                    }
-                };
-
-                debug!("salt event job fun: {:?}", fun);
-                match fun {
-                    "grains.items" => {
-                        let minion_id = match data.get("id") {
-                            Some(minion_id) => match minion_id.as_str() {
-                                Some(minion_id) => minion_id.to_string(),
-                                None => {
-                                    error!("Failed to get minion ID from event data");
-                                    continue;
+                    };
+                    let (grains, os_type) = match data.get("return") {
+                        Some(grains) => match grains.as_object() {
+                            Some(grains) => match serde_json::to_string(grains) {
+                                Ok(grains_str) => {
+                                    // Parse grains as JSON, and fetch osfullname+osrelease as os_type.
+                                    let osfullname = grains
+                                        .get("osfullname")
+                                        .map(|s| s.as_str().unwrap_or("Unknown"))
+                                        .unwrap_or("Unknown");
+                                    let osrelease = grains
+                                        .get("osrelease")
+                                        .map(|s| s.as_str().unwrap_or(""))
+                                        .unwrap_or("");
+                                    let os_type =
+                                        format!("{} {}", osfullname, osrelease).trim().to_string();
+                                    (grains_str, os_type)
                                 }
-                            },
-                            None => {
-                                error!("Failed to get minion ID from event data");
-                                continue;
-                            }
-                        };
-                        let (grains, os_type) = match data.get("return") {
-                            Some(grains) => match grains.as_object() {
-                                Some(grains) => match serde_json::to_string(grains) {
-                                    Ok(grains_str) => {
-                                        // Parse grains as JSON, and fetch osfullname+osrelease as os_type.
-                                        let osfullname = grains
-                                            .get("osfullname")
-                                            .map(|s| s.as_str().unwrap_or("Unknown"))
-                                            .unwrap_or("Unknown");
-                                        let osrelease = grains
-                                            .get("osrelease")
-                                            .map(|s| s.as_str().unwrap_or(""))
-                                            .unwrap_or("");
-                                        let os_type = format!("{} {}", osfullname, osrelease)
-                                            .trim()
-                                            .to_string();
-                                        (grains_str, os_type)
-                                    }
-                                    Err(err) => {
-                                        error!("Failed to serialize grains: {:?}", err);
-                                        continue;
-                                    }
-                                },
-                                None => {
-                                    error!("Failed to get grains from event data");
-                                    continue;
+                                Err(err) => {
+                                    error!("Failed to serialize grains: {:?}", err);
+                                    return;
                                 }
                             },
                             None => {
                                 error!("Failed to get grains from event data");
-                                continue;
+                                return;
                             }
-                        };
-                        match self
-                            .storage
-                            .set_minion_grains(&minion_id, time, grains, os_type)
-                        {
-                            Ok(_) => {}
-                            Err(e) => error!("Failed updating minion grains {:?}", e),
+                        },
+                        None => {
+                            error!("Failed to get grains from event data");
+                            return;
                         }
+                    };
+                    match self
+                        .storage
+                        .set_minion_grains(&minion_id, time, grains, os_type)
+                    {
+                        Ok(_) => {}
+                        Err(e) => error!("Failed updating minion grains {:?}", e),
                     }
-                    "pillar.items" => {
-                        let minion_id = match data.get("id") {
-                            Some(minion_id) => match minion_id.as_str() {
-                                Some(minion_id) => minion_id.to_string(),
-                                None => {
-                                    error!("Failed to get minion ID from event data");
-                                    continue;
-                                }
-                            },
+                }
+                "pillar.items" => {
+                    let minion_id = match data.get("id") {
+                        Some(minion_id) => match minion_id.as_str() {
+                            Some(minion_id) => minion_id.to_string(),
                             None => {
                                 error!("Failed to get minion ID from event data");
-                                continue;
+                                return;
                             }
-                        };
-                        let pillar = match data.get("return") {
-                            Some(pillar) => match pillar.as_object() {
-                                Some(pillar) => match serde_json::to_string(pillar) {
-                                    Ok(pillar) => pillar,
-                                    Err(err) => {
-                                        error!("Failed to serialize pillar: {:?}", err);
-                                        continue;
-                                    }
-                                },
-                                None => {
-                                    error!("Failed to get pillar from event data");
-                                    continue;
+                        },
+                        None => {
+                            error!("Failed to get minion ID from event data");
+                            return;
+                        }
+                    };
+                    let pillar = match data.get("return") {
+                        Some(pillar) => match pillar.as_object() {
+                            Some(pillar) => match serde_json::to_string(pillar) {
+                                Ok(pillar) => pillar,
+                                Err(err) => {
+                                    error!("Failed to serialize pillar: {:?}", err);
+                                    return;
                                 }
                             },
                             None => {
                                 error!("Failed to get pillar from event data");
-                                continue;
+                                return;
                             }
-                        };
-                        match self.storage.set_minion_pillars(&minion_id, time, pillar) {
-                            Ok(_) => {}
-                            Err(e) => error!("Failed updating minion pillar {:?}", e),
+                        },
+                        None => {
+                            error!("Failed to get pillar from event data");
+                            return;
                         }
+                    };
+                    match self.storage.set_minion_pillars(&minion_id, time, pillar) {
+                        Ok(_) => {}
+                        Err(e) => error!("Failed updating minion pillar {:?}", e),
                     }
-                    "pkg.list_pkgs" => {
-                        let minion_id = match data.get("id") {
-                            Some(minion_id) => match minion_id.as_str() {
-                                Some(minion_id) => minion_id.to_string(),
-                                None => {
-                                    error!("Failed to get minion ID from event data");
-                                    continue;
-                                }
-                            },
+                }
+                "pkg.list_pkgs" => {
+                    let minion_id = match data.get("id") {
+                        Some(minion_id) => match minion_id.as_str() {
+                            Some(minion_id) => minion_id.to_string(),
                             None => {
                                 error!("Failed to get minion ID from event data");
-                                continue;
+                                return;
                             }
-                        };
-                        let pkgs = match data.get("return") {
-                            Some(pkgs) => match pkgs.as_object() {
-                                Some(pkgs) => match serde_json::to_string(pkgs) {
-                                    Ok(pkgs) => pkgs,
-                                    Err(err) => {
-                                        error!("Failed to serialize pkgs: {:?}", err);
-                                        continue;
-                                    }
-                                },
-                                None => {
-                                    error!("Failed to get pkgs from event data");
-                                    continue;
+                        },
+                        None => {
+                            error!("Failed to get minion ID from event data");
+                            return;
+                        }
+                    };
+                    let pkgs = match data.get("return") {
+                        Some(pkgs) => match pkgs.as_object() {
+                            Some(pkgs) => match serde_json::to_string(pkgs) {
+                                Ok(pkgs) => pkgs,
+                                Err(err) => {
+                                    error!("Failed to serialize pkgs: {:?}", err);
+                                    return;
                                 }
                             },
                             None => {
                                 error!("Failed to get pkgs from event data");
-                                continue;
+                                return;
                             }
-                        };
-                        match self.storage.set_minion_pkgs(&minion_id, time, pkgs) {
-                            Ok(_) => {}
-                            Err(e) => error!("Failed updating minion pkgs {:?}", e),
+                        },
+                        None => {
+                            error!("Failed to get pkgs from event data");
+                            return;
                         }
+                    };
+                    match self.storage.set_minion_pkgs(&minion_id, time, pkgs) {
+                        Ok(_) => {}
+                        Err(e) => error!("Failed updating minion pkgs {:?}", e),
                     }
-                    "state.apply" | "state.highstate" => {
-                        // Check if empty args, or if empty args but test=True is only argument.
-                        // If so, then we can assume this is a highstate run.
-                        let only_arg_is_test_true = match fun_args.get(0) {
-                            Some(arg) => match arg.is_string() {
-                                // If the arg is a string, check if it's test=True
-                                true => match arg.as_str() {
-                                    Some(arg) => arg.to_lowercase() == "test=true",
-                                    None => false,
-                                },
-                                // If the arg is an object, check if it's test: True
-                                false => match arg.is_object() {
-                                    true => match arg.get("test") {
-                                        // Value can be both string or bool
-                                        Some(test) => match test.is_string() {
-                                            true => match test.as_str() {
-                                                Some(test) => test.to_lowercase() == "true",
-                                                None => false,
-                                            },
-                                            false => match test.is_boolean() {
-                                                true => test.as_bool().unwrap_or(false),
-                                                false => false,
-                                            },
+                }
+                "state.apply" | "state.highstate" => {
+                    // Check if empty args, or if empty args but test=True is only argument.
+                    // If so, then we can assume this is a highstate run.
+                    let only_arg_is_test_true = match fun_args.first() {
+                        Some(arg) => match arg.is_string() {
+                            // If the arg is a string, check if it's test=True
+                            true => match arg.as_str() {
+                                Some(arg) => arg.to_lowercase() == "test=true",
+                                None => false,
+                            },
+                            // If the arg is an object, check if it's test: True
+                            false => match arg.is_object() {
+                                true => match arg.get("test") {
+                                    // Value can be both string or bool
+                                    Some(test) => match test.is_string() {
+                                        true => match test.as_str() {
+                                            Some(test) => test.to_lowercase() == "true",
+                                            None => false,
+                                        },
+                                        false => match test.is_boolean() {
+                                            true => test.as_bool().unwrap_or(false),
+                                            false => false,
                                         },
-                                        None => false,
                                     },
-                                    false => false,
+                                    None => false,
                                 },
+                                false => false,
                             },
-                            None => false,
-                        };
-                        let no_args = fun_args.is_empty();
-                        let is_highstate = no_args || only_arg_is_test_true;
-                        if !is_highstate {
-                            continue;
-                        }
-                        let retcode = match data.get("retcode") {
-                            Some(retcode) => match retcode.as_i64() {
-                                Some(retcode) => retcode,
-                                None => {
-                                    error!("Failed to get retcode from event data");
-                                    continue;
-                                }
-                            },
+                        },
+                        None => false,
+                    };
+                    let no_args = fun_args.is_empty();
+                    let is_highstate = no_args || only_arg_is_test_true;
+                    if !is_highstate {
+                        return;
+                    }
+                    let retcode = match data.get("retcode") {
+                        Some(retcode) => match retcode.as_i64() {
+                            Some(retcode) => retcode,
                             None => {
                                 error!("Failed to get retcode from event data");
-                                continue;
+                                return;
                             }
-                        };
-                        if retcode == 1 {
-                            continue;
+                        },
+                        None => {
+                            error!("Failed to get retcode from event data");
+                            return;
                         }
+                    };
+                    if retcode == 1 {
+                        return;
+                    }
 
-                        let minion_id = match data.get("id") {
-                            Some(minion_id) => match minion_id.as_str() {
-                                Some(minion_id) => minion_id.to_string(),
-                                None => {
-                                    error!("Failed to get minion ID from event data");
-                                    continue;
-                                }
-                            },
+                    let minion_id = match data.get("id") {
+                        Some(minion_id) => match minion_id.as_str() {
+                            Some(minion_id) => minion_id.to_string(),
                             None => {
                                 error!("Failed to get minion ID from event data");
-                                continue;
+                                return;
                             }
-                        };
+                        },
+                        None => {
+                            error!("Failed to get minion ID from event data");
+                            return;
+                        }
+                    };
 
-                        // Loop over return's and count success/incorrect/error
-                        let mut success = 0;
-                        let mut incorrect = 0;
-                        let mut error = 0;
-
-                        let ret = match data.get("return") {
-                            Some(ret) => match ret.as_object() {
-                                Some(ret) => ret,
-                                None => {
-                                    error!("Failed to get return from event data");
-                                    continue;
-                                }
-                            },
+                    // Loop over return's and count success/incorrect/error
+                    let mut success = 0;
+                    let mut incorrect = 0;
+                    let mut error = 0;
+
+                    let ret = match data.get("return") {
+                        Some(ret) => match ret.as_object() {
+                            Some(ret) => ret,
                             None => {
                                 error!("Failed to get return from event data");
-                                continue;
+                                return;
                             }
+                        },
+                        None => {
+                            error!("Failed to get return from event data");
+                            return;
+                        }
+                    };
+                    for item in ret.values() {
+                        let item = match item.as_object() {
+                            Some(item) => item,
+                            None => continue,
                         };
-                        for item in ret.values() {
-                            let item = match item.as_object() {
-                                Some(item) => item,
-                                None => continue,
-                            };
-                            match item.get("result") {
-                                Some(result) => match result.as_bool() {
-                                    Some(true) => success += 1,
-                                    Some(false) => error += 1,
-                                    None => incorrect += 1, // test=True mode, result will be Null
-                                },
-                                None => {
-                                    error!("Failed to get result from event data");
-                                    continue;
-                                }
+                        match item.get("result") {
+                            Some(result) => match result.as_bool() {
+                                Some(true) => success += 1,
+                                Some(false) => error += 1,
+                                None => incorrect += 1, // test=True mode, result will be Null
+                            },
+                            None => {
+                                error!("Failed to get result from event data");
+                                continue;
                             }
                         }
+                    }
 
-                        let conformity = match serde_json::to_string(ret) {
-                            Ok(conformity) => conformity,
-                            Err(err) => {
-                                error!("Failed to serialize conformity: {:?}", err);
-                                continue;
-                            }
-                        };
-                        match self.storage.set_minion_conformity(
-                            &minion_id, time, conformity, success, incorrect, error,
-                        ) {
-                            Ok(_) => {}
-                            Err(e) => error!("Failed updating minion conformity {:?}", e),
+                    let conformity = match serde_json::to_string(ret) {
+                        Ok(conformity) => conformity,
+                        Err(err) => {
+                            error!("Failed to serialize conformity: {:?}", err);
+                            return;
                         }
+                    };
+                    match self.storage.set_minion_conformity(
+                        &minion_id, time, conformity, success, incorrect, error,
+                    ) {
+                        Ok(_) => {}
+                        Err(e) => error!("Failed updating minion conformity {:?}", e),
                     }
-                    _ => {}
                 }
-            } else if event.tag == "salt/auth" {
-                let result = match data.get("result") {
-                    Some(result) => match result.as_bool() {
-                        Some(result) => result,
-                        None => {
-                            error!("Failed to get result from event data");
-                            continue;
-                        }
-                    },
+                _ => {}
+            }
+        } else if event.tag == "salt/auth" {
+            let result = match data.get("result") {
+                Some(result) => match result.as_bool() {
+                    Some(result) => result,
                     None => {
                         error!("Failed to get result from event data");
-                        continue;
+                        return;
                     }
-                };
-                if !result {
-                    continue;
+                },
+                None => {
+                    error!("Failed to get result from event data");
+                    return;
                 }
+            };
+            if !result {
+                return;
+            }
 
-                let minion_id = match data.get("id") {
-                    Some(minion_id) => match minion_id.as_str() {
-                        Some(minion_id) => minion_id.to_string(),
-                        None => {
-                            error!("Failed to get minion ID from event data");
-                            continue;
-                        }
-                    },
+            let minion_id = match data.get("id") {
+                Some(minion_id) => match minion_id.as_str() {
+                    Some(minion_id) => minion_id.to_string(),
                     None => {
                         error!("Failed to get minion ID from event data");
+                        return;
+                    }
+                },
+                None => {
+                    error!("Failed to get minion ID from event data");
+                    return;
+                }
+            };
+            if !replayed {
+                self.set_minion_seen(&minion_id, time);
+            }
+        } else {
+            //warn!("Unhandled event: {:?}", event);
+        }
+    }
+
+    /// Reconstruct the jobs and returns published while disconnected, by replaying them as events.
+    async fn backfill(&self, api: &SaltAPI, salt_token: &SaltToken, since: ResaltTime) {
+        let until = ResaltTime::now();
+        let since = since.max(until + ChronoDuration::seconds(-BACKFILL_MAX_SECONDS))
+            + ChronoDuration::seconds(-BACKFILL_MARGIN_SECONDS);
+        let jobs = match api.list_jobs(salt_token, since, until).await {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                error!(
+                    "Failed to list jobs of master {} to backfill: {}",
+                    self.master.name, e
+                );
+                return;
+            }
+        };
+        info!(
+            "Backfilling {} jobs of master {} since {}",
+            jobs.len(),
+            self.master.name,
+            since
+        );
+
+        for (jid, info) in jobs {
+            // Only jids generated by Salt are timestamps, e.g. not those of scheduled jobs
+            let time = match ResaltTime::parse_from_jid(&jid) {
+                Ok(time) => time,
+                Err(_) => continue,
+            };
+            let returned_minions: Vec<String> = match self.storage.get_job_by_jid(&jid) {
+                Ok(Some(job)) => match self.storage.get_job_returns_by_job(&job) {
+                    Ok(returns) => returns.into_iter().map(|r| r.minion_id).collect(),
+                    Err(e) => {
+                        error!("Failed to get job returns: {:?}", e);
                         continue;
                     }
-                };
-                match self.storage.set_minion_last_seen(&minion_id, time) {
-                    Ok(_) => {}
-                    Err(e) => error!("Failed updating minion last seen {:?}", e),
+                },
+                Ok(None) => {
+                    self.handle_event(backfill_new_event(&jid, &info, time), true);
+                    Vec::new()
                 }
-            } else {
-                //warn!("Unhandled event: {:?}", event);
+                Err(e) => {
+                    error!("Failed to get job by jid: {:?}", e);
+                    continue;
+                }
+            };
+
+            let returns = match api.lookup_jid(salt_token, &jid).await {
+                Ok(returns) => returns,
+                Err(e) => {
+                    error!("Failed to look up job {} to backfill: {}", jid, e);
+                    continue;
+                }
+            };
+            for event in backfill_return_events(&jid, &info, returns, &returned_minions, time) {
+                self.handle_event(event, true);
+            }
+        }
+    }
+
+    /// Listen until the stream ends. Returns whether the connection was established.
+    ///
+    /// `disconnected_at` is when a previous connection was lost, if any,
+    /// in which case the events missed since are backfilled.
+    async fn listen(&self, api: &SaltAPI, disconnected_at: Option<ResaltTime>) -> bool {
+        let salt_token = match self.refresh_token(api).await {
+            Some(token) => token,
+            None => {
+                error!("Failed to refresh listener token");
+                return false;
+            }
+        };
+
+        let stream = match api.listen_events(&salt_token).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                error!(
+                    "Failed to connect to events of master {}: {}",
+                    self.master.name, e
+                );
+                self.status
+                    .set_error(&self.master.name, Some(e.to_string()));
+                return false;
             }
+        };
+        pin_mut!(stream);
+
+        self.status.set_connected(&self.master.name, true);
+        self.status.set_error(&self.master.name, None);
+
+        if let Some(disconnected_at) = disconnected_at {
+            let disconnected = ResaltTime::now() - disconnected_at;
+            info!(
+                "Reconnected to master {} after {}s",
+                self.master.name,
+                disconnected.num_seconds()
+            );
+            self.status
+                .record_reconnect(&self.master.name, disconnected);
+            // New events are buffered by the stream meanwhile
+            let since = self
+                .status
+                .last_event(&self.master.name)
+                .unwrap_or(disconnected_at);
+            self.backfill(api, &salt_token, since).await;
         }
 
-        warn!("Salt event stream ended! Reconnecting stream...");
+        while let Some(event) = stream.next().await {
+            self.handle_event(event, false);
+        }
+
+        warn!(
+            "Salt event stream of master {} ended! Reconnecting stream...",
+            self.master.name
+        );
+        true
     }
 
     pub async fn start(&self) {
+        let api = match &self.api {
+            Ok(api) => api,
+            Err(e) => {
+                // Certificate config is only read at startup, so retrying won't help
+                error!("Salt event listener not started: {}", e);
+                self.status.set_error(&self.master.name, Some(e.clone()));
+                return;
+            }
+        };
+        let mut failures: u32 = 0;
+        let mut disconnected_at = None;
         loop {
-            self.listen().await;
-            {
-                // Make sure lock is released after setting status
-                *self.status.connected.lock().unwrap() = false;
+            if self.listen(api, disconnected_at).await {
+                failures = 0;
+                // Keep the start of the outage until reconnected
+                disconnected_at = Some(ResaltTime::now());
+                self.status.record_disconnect(&self.master.name);
+            } else {
+                failures = failures.saturating_add(1);
             }
-            std::thread::sleep(std::time::Duration::from_secs(1));
+            let delay = reconnect_delay(failures);
+            info!(
+                "Reconnecting to master {} in {:.1}s",
+                self.master.name,
+                delay.as_secs_f64()
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Rebuild the `salt/job/<jid>/new` event of a job listed by `jobs.list_jobs`.
+fn backfill_new_event(jid: &str, info: &Value, time: ResaltTime) -> SaltEvent {
+    let field = |name: &str| info.get(name).cloned().unwrap_or(Value::Null);
+    let mut data = Map::new();
+    data.insert("jid".to_owned(), json!(jid));
+    data.insert("fun".to_owned(), field("Function"));
+    data.insert("arg".to_owned(), backfill_args(info));
+    data.insert("tgt".to_owned(), field("Target"));
+    data.insert("tgt_type".to_owned(), field("Target-type"));
+    data.insert("user".to_owned(), field("User"));
+    backfill_event(format!("salt/job/{}/new", jid), data, time)
+}
+
+/// Rebuild the `salt/job/<jid>/ret/<minion>` events of the minions in `returns`
+/// (from `jobs.lookup_jid`) which are not in `returned_minions` yet.
+///
+/// The runner does not tell the exit code, so the events have no `retcode`.
+fn backfill_return_events(
+    jid: &str,
+    info: &Value,
+    returns: Map<String, Value>,
+    returned_minions: &[String],
+    time: ResaltTime,
+) -> Vec<SaltEvent> {
+    let fun = info.get("Function").cloned().unwrap_or(Value::Null);
+    let args = backfill_args(info);
+    returns
+        .into_iter()
+        .filter(|(minion_id, _)| !returned_minions.contains(minion_id))
+        .map(|(minion_id, ret)| {
+            let mut data = Map::new();
+            data.insert("id".to_owned(), json!(minion_id));
+            data.insert("jid".to_owned(), json!(jid));
+            data.insert("fun".to_owned(), fun.clone());
+            data.insert("fun_args".to_owned(), args.clone());
+            data.insert("return".to_owned(), ret);
+            backfill_event(format!("salt/job/{}/ret/{}", jid, minion_id), data, time)
+        })
+        .collect()
+}
+
+fn backfill_args(info: &Value) -> Value {
+    match info.get("Arguments") {
+        Some(Value::Array(args)) => Value::Array(args.clone()),
+        _ => json!([]),
+    }
+}
+
+/// Build an event the way Salt publishes it, for events replayed by backfilling.
+fn backfill_event(tag: String, mut data: Map<String, Value>, time: ResaltTime) -> SaltEvent {
+    data.insert("_stamp".to_owned(), json!(time.to_string()));
+    SaltEvent {
+        data: json!({ "tag": tag, "data": data }).to_string(),
+        tag,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event_data(event: &SaltEvent) -> Value {
+        let data: Value = serde_json::from_str(&event.data).unwrap();
+        assert_eq!(data["tag"], json!(event.tag));
+        data["data"].clone()
+    }
+
+    #[test]
+    fn test_reconnect_delay() {
+        for _ in 0..100 {
+            let delay = reconnect_delay(0);
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_secs(1));
+            let delay = reconnect_delay(3);
+            assert!(delay >= Duration::from_secs(4) && delay <= Duration::from_secs(8));
+            let delay = reconnect_delay(u32::MAX);
+            assert!(delay >= RECONNECT_DELAY_MAX / 2 && delay <= RECONNECT_DELAY_MAX);
         }
     }
+
+    #[test]
+    fn test_backfill_events() {
+        let jid = "20240102030405123456";
+        let time = ResaltTime::parse_from_jid(jid).unwrap();
+        let info = json!({
+            "Function": "state.apply",
+            "Arguments": ["nginx"],
+            "Target": "web*",
+            "Target-type": "glob",
+            "User": "admin",
+        });
+
+        let event = backfill_new_event(jid, &info, time);
+        assert_eq!(event.tag, format!("salt/job/{}/new", jid));
+        assert_eq!(
+            event_data(&event),
+            json!({
+                "jid": jid,
+                "fun": "state.apply",
+                "arg": ["nginx"],
+                "tgt": "web*",
+                "tgt_type": "glob",
+                "user": "admin",
+                "_stamp": time.to_string(),
+            })
+        );
+
+        // Minions which already returned are not replayed
+        let returns = json!({ "web01": true, "web02": false })
+            .as_object()
+            .unwrap()
+            .clone();
+        let events = backfill_return_events(jid, &info, returns, &["web01".to_string()], time);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].tag, format!("salt/job/{}/ret/web02", jid));
+        assert_eq!(
+            event_data(&events[0]),
+            json!({
+                "id": "web02",
+                "jid": jid,
+                "fun": "state.apply",
+                "fun_args": ["nginx"],
+                "return": false,
+                "_stamp": time.to_string(),
+            })
+        );
+
+        // Missing arguments are replayed as none
+        let events = backfill_return_events(
+            jid,
+            &json!({ "Function": "test.ping" }),
+            json!({ "web01": true }).as_object().unwrap().clone(),
+            &[],
+            time,
+        );
+        assert_eq!(event_data(&events[0])["fun_args"], json!([]));
+    }
 }
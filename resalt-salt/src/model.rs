@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Clone, Debug, Default)]
 pub struct SaltEvent {
     pub tag: String,
@@ -10,6 +12,30 @@ pub enum SaltError {
     Forbidden,     // 403
     FailedRequest, // Anything NOT 200
     RequestError(String),
+    /// Certificate or handshake failure, e.g. an untrusted Salt API certificate
+    TlsError(String),
     ResponseParseError(Option<String>),
     MissingExpectedDataError(String),
+    /// The token or request refers to a master which is not configured
+    UnknownMaster(String),
+}
+
+impl fmt::Display for SaltError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaltError::Unauthorized => write!(f, "Unauthorized by Salt API"),
+            SaltError::Forbidden => write!(f, "Forbidden by Salt API"),
+            SaltError::FailedRequest => write!(f, "Salt API request failed"),
+            SaltError::RequestError(e) => write!(f, "Salt API request error: {}", e),
+            SaltError::TlsError(e) => write!(f, "Salt API TLS error: {}", e),
+            SaltError::ResponseParseError(Some(e)) => {
+                write!(f, "Failed parsing Salt API response: {}", e)
+            }
+            SaltError::ResponseParseError(None) => write!(f, "Failed parsing Salt API response"),
+            SaltError::MissingExpectedDataError(e) => {
+                write!(f, "Salt API response is missing data: {}", e)
+            }
+            SaltError::UnknownMaster(name) => write!(f, "Unknown Salt master \"{}\"", name),
+        }
+    }
 }
@@ -1,43 +1,78 @@
 use axum::{
     middleware::{from_fn, from_fn_with_state},
     routing::{delete, get, post, put},
-    Router, ServiceExt,
+    Router,
 };
 use env_logger::{init_from_env, Env};
-use resalt_config::ResaltConfig;
+use log::{error, info};
+use resalt_config::{validate_config, ResaltConfig};
+use resalt_models::ResaltTime;
 use resalt_routes::middleware::*;
 use resalt_routes::route::auth::*;
 use resalt_routes::route::noauth::*;
 use resalt_routes::state::*;
-use resalt_salt::{SaltAPI, SaltEventListener, SaltEventListenerStatus};
+use resalt_salt::{SaltAPI, SaltAvailabilityMonitor, SaltEventListener, SaltEventListenerStatus};
 use resalt_storage::Storage;
 use resalt_update::update_loop;
-use std::{
-    error::Error,
-    net::SocketAddr,
-    sync::{Arc, Mutex},
-};
-use tokio::{net::TcpListener, task};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{error::Error, net::SocketAddr, time::Duration};
+use tokio::task;
 use tower::Layer;
 
-fn start_salt_websocket_thread(db: Storage) -> SaltEventListenerStatus {
-    let listener_status: SaltEventListenerStatus = SaltEventListenerStatus {
-        connected: Arc::new(Mutex::new(false)),
-    };
-    let salt_listener_status = listener_status.clone();
-    std::thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        let ls = task::LocalSet::new();
-        ls.block_on(&rt, async {
-            // Wait a few seconds before starting SSE, so web server gets time to start
-            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-            let salt_ws = SaltEventListener::new(db.clone(), salt_listener_status);
-            salt_ws.start().await;
+mod tls;
+
+/// How often expired temporary memberships are looked for.
+const GRANT_EXPIRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Start one event listener thread per Salt master.
+fn start_salt_websocket_threads(db: Storage) -> SaltEventListenerStatus {
+    let masters = ResaltConfig::SALT_MASTERS.clone();
+    let names: Vec<String> = masters.iter().map(|master| master.name.clone()).collect();
+    let listener_status = SaltEventListenerStatus::new(&names);
+    for master in masters {
+        let db = db.clone();
+        let salt_listener_status = listener_status.clone();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            let ls = task::LocalSet::new();
+            ls.block_on(&rt, async {
+                // Wait a few seconds before starting SSE, so web server gets time to start
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                let salt_ws = SaltEventListener::new(db, salt_listener_status, master);
+                salt_ws.start().await;
+            });
         });
-    });
+    }
     listener_status
 }
 
+fn start_minion_availability_monitor(db: Storage) -> task::JoinHandle<()> {
+    task::spawn(async move {
+        let monitor = SaltAvailabilityMonitor::new(db);
+        monitor.start().await;
+    })
+}
+
+/// Revoke temporary group memberships as they expire.
+fn start_grant_expiry_monitor(db: Storage) -> task::JoinHandle<()> {
+    task::spawn(async move {
+        loop {
+            match db.expire_permission_group_grants(ResaltTime::now()) {
+                Ok(expired) => {
+                    for grant in expired {
+                        info!(
+                            "Revoked expired membership of {} in {}",
+                            grant.user_id, grant.group_id
+                        );
+                    }
+                }
+                Err(e) => error!("Failed revoking expired memberships {:?}", e),
+            }
+            tokio::time::sleep(GRANT_EXPIRY_INTERVAL).await;
+        }
+    })
+}
+
 async fn start_server(
     db: Storage,
     listener_status: SaltEventListenerStatus,
@@ -53,17 +88,30 @@ async fn start_server(
         .route("/myself", get(route_myself_get))
         .route("/status", get(route_status_get))
         .route("/minions", get(route_minions_get))
+        .route("/minions/export", get(route_minions_export_get))
         .route("/minions/:minion_id", get(route_minion_get))
         .route(
             "/minions/:minion_id/refresh",
             post(route_minion_refresh_post),
         )
+        .route(
+            "/minions/:minion_id/availability",
+            get(route_minion_availability_get),
+        )
+        .route(
+            "/minions/:minion_id/history/:kind",
+            get(route_minion_history_get),
+        )
         .route("/presets", get(route_presets_get))
         .route("/presets", post(route_presets_post))
+        .route("/presets/nodegroups", get(route_presets_nodegroups_get))
         .route("/presets/:preset_id", get(route_preset_get))
         .route("/presets/:preset_id", put(route_preset_put))
         .route("/presets/:preset_id", delete(route_preset_delete))
         .route("/grains", get(route_grains_get))
+        .route("/grains/export", get(route_grains_export_get))
+        .route("/packages", get(route_packages_get))
+        .route("/packages/:name", get(route_package_get))
         .route("/jobs", get(route_jobs_get))
         .route("/jobs", post(route_jobs_post))
         .route("/jobs/:jid", get(route_job_get))
@@ -73,6 +121,7 @@ async fn start_server(
         .route("/users/:user_id", get(route_user_get))
         .route("/users/:user_id", delete(route_user_delete))
         .route("/users/:user_id/password", post(route_user_password_post))
+        .route("/users/:user_id/access", post(route_user_access_post))
         .route(
             "/users/:user_id/preferences",
             post(route_user_preferences_post),
@@ -85,15 +134,29 @@ async fn start_server(
             "/users/:user_id/permissions/:group_id",
             delete(route_user_permissions_delete),
         )
+        .route(
+            "/users/:user_id/permissions/:group_id/grant",
+            post(route_user_permissions_grant_post),
+        )
         .route("/keys", get(route_keys_get))
         .route("/keys/:state/:id/accept", put(route_key_accept_put))
         .route("/keys/:state/:id/reject", put(route_key_reject_put))
         .route("/keys/:state/:id/delete", delete(route_key_delete_delete))
         .route("/permissions", get(route_permissions_get))
         .route("/permissions", post(route_permissions_post))
+        .route("/permissions/grants", get(route_permission_grants_get))
+        .route("/permissions/audit", get(route_permission_audit_get))
         .route("/permissions/:id", get(route_permission_get))
         .route("/permissions/:id", put(route_permission_put))
         .route("/permissions/:id", delete(route_permission_delete))
+        .route(
+            "/permissions/:id/subgroups/:subgroup_id",
+            post(route_permission_subgroup_post),
+        )
+        .route(
+            "/permissions/:id/subgroups/:subgroup_id",
+            delete(route_permission_subgroup_delete),
+        )
         .route("/settings/import", post(route_settings_import_post))
         .route("/settings/export", get(route_settings_export_get))
         .route_layer(from_fn_with_state(shared_state.clone(), middleware_auth))
@@ -105,6 +168,7 @@ async fn start_server(
         .route("/login", post(route_login_post))
         .route("/logout", post(route_logout_post))
         .route("/metrics", get(route_metrics_get))
+        .route("/openapi.json", get(route_openapi_get))
         .route("/token", post(route_token_post));
 
     let app = Router::new()
@@ -124,21 +188,82 @@ async fn start_server(
     let logging = from_fn_with_state(shared_state, middleware_logging);
     let app = logging.layer(app);
 
-    let socket = SocketAddr::from(([0, 0, 0, 0], *ResaltConfig::HTTP_PORT));
-    let listener = TcpListener::bind(socket).await?;
-    axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .await?;
+    // Wrap in a Router, which converts the hyper request body for the middleware layers
+    let app = Router::new()
+        .fallback_service(app)
+        .into_make_service_with_connect_info::<SocketAddr>();
+    let tls_config = match *ResaltConfig::HTTP_TLS_ENABLED {
+        true => Some(tls::load_tls_config().await?),
+        false => None,
+    };
+    let mut servers = task::JoinSet::new();
+    for listener in bind_listeners(*ResaltConfig::HTTP_PORT)? {
+        match &tls_config {
+            Some(config) => servers
+                .spawn(axum_server::from_tcp_rustls(listener, config.clone()).serve(app.clone())),
+            None => servers.spawn(axum_server::from_tcp(listener).serve(app.clone())),
+        };
+    }
+    if let Some(config) = tls_config {
+        tls::start_tls_reloader(config)?;
+        if *ResaltConfig::HTTP_REDIRECT_PORT != 0 {
+            let redirect = tls::redirect_router().into_make_service();
+            for listener in bind_listeners(*ResaltConfig::HTTP_REDIRECT_PORT)? {
+                servers.spawn(axum_server::from_tcp(listener).serve(redirect.clone()));
+            }
+        }
+    }
+
+    // Serve until any listener fails
+    if let Some(result) = servers.join_next().await {
+        result??;
+    }
 
     Ok(())
 }
 
+/// Bind the configured IPv4 and IPv6 addresses on `port`.
+fn bind_listeners(port: u16) -> Result<Vec<std::net::TcpListener>, Box<dyn Error>> {
+    let mut addrs: Vec<SocketAddr> = Vec::new();
+    if let Some(ip) = *ResaltConfig::HTTP_BIND_IPV4 {
+        addrs.push(SocketAddr::from((ip, port)));
+    }
+    if let Some(ip) = *ResaltConfig::HTTP_BIND_IPV6 {
+        addrs.push(SocketAddr::from((ip, port)));
+    }
+
+    let mut listeners = Vec::new();
+    for addr in addrs {
+        let bind = || -> std::io::Result<std::net::TcpListener> {
+            let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+            // Otherwise "::" also claims the port on IPv4, conflicting with the IPv4 listener
+            if addr.is_ipv6() {
+                socket.set_only_v6(true)?;
+            }
+            socket.set_reuse_address(true)?;
+            socket.set_nonblocking(true)?;
+            socket.bind(&addr.into())?;
+            socket.listen(1024)?;
+            Ok(socket.into())
+        };
+        listeners.push(bind().map_err(|e| format!("Failed binding {}: {}", addr, e))?);
+        info!("Listening on {}", addr);
+    }
+    Ok(listeners)
+}
+
 async fn run() -> Result<(), Box<dyn Error>> {
     // Logging
     init_from_env(Env::new().default_filter_or("Debug"));
 
+    // Config
+    if let Err(errors) = validate_config() {
+        for e in &errors {
+            error!("Invalid config: {}", e);
+        }
+        return Err(format!("{} invalid config value(s)", errors.len()).into());
+    }
+
     // Database
     let db: Storage = Storage::init_db().await;
 
@@ -146,7 +271,13 @@ async fn run() -> Result<(), Box<dyn Error>> {
     let _update_loop = task::spawn(update_loop());
 
     // Salt WebSocket Thread
-    let listener_status = start_salt_websocket_thread(db.clone());
+    let listener_status = start_salt_websocket_threads(db.clone());
+
+    // Minion Availability Monitor
+    let _availability_monitor = start_minion_availability_monitor(db.clone());
+
+    // Temporary Membership Expiry
+    let _grant_expiry_monitor = start_grant_expiry_monitor(db.clone());
 
     // Web Server
     start_server(db, listener_status).await?;
@@ -0,0 +1,119 @@
+use axum::{
+    http::{header::HOST, HeaderMap, StatusCode, Uri},
+    response::Redirect,
+    Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use log::{error, info};
+use resalt_config::ResaltConfig;
+use std::{error::Error, time::Duration, time::SystemTime};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    task,
+};
+
+/// How often the certificate and key files are checked for changes.
+const TLS_RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+pub async fn load_tls_config() -> Result<RustlsConfig, Box<dyn Error>> {
+    // Ring is the only provider compiled in, so this only fails if already installed
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    RustlsConfig::from_pem_file(&*ResaltConfig::HTTP_TLS_CERT, &*ResaltConfig::HTTP_TLS_KEY)
+        .await
+        .map_err(|e| format!("Failed loading TLS certificate: {}", e).into())
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Reload the certificate and key when either file changes, or on SIGHUP.
+/// If reloading fails, the previous certificate keeps being served.
+pub fn start_tls_reloader(config: RustlsConfig) -> Result<task::JoinHandle<()>, Box<dyn Error>> {
+    let mut sighup = signal(SignalKind::hangup())?;
+    Ok(task::spawn(async move {
+        let cert = ResaltConfig::HTTP_TLS_CERT.as_str();
+        let key = ResaltConfig::HTTP_TLS_KEY.as_str();
+        let mut last_modified = (modified(cert), modified(key));
+        let mut interval = tokio::time::interval(TLS_RELOAD_CHECK_INTERVAL);
+        loop {
+            let reason = tokio::select! {
+                _ = interval.tick() => {
+                    let current = (modified(cert), modified(key));
+                    if current == last_modified {
+                        continue;
+                    }
+                    last_modified = current;
+                    "file changed"
+                }
+                _ = sighup.recv() => "SIGHUP",
+            };
+            match config.reload_from_pem_file(cert, key).await {
+                Ok(_) => info!("Reloaded TLS certificate ({})", reason),
+                Err(e) => error!("Failed reloading TLS certificate ({}): {}", reason, e),
+            }
+        }
+    }))
+}
+
+/// Router answering every plain HTTP request with a redirect to HTTPS.
+pub fn redirect_router() -> Router {
+    Router::new().fallback(route_redirect_https)
+}
+
+async fn route_redirect_https(headers: HeaderMap, uri: Uri) -> Result<Redirect, StatusCode> {
+    let host = headers
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    Ok(Redirect::permanent(&https_url(
+        host,
+        &uri,
+        *ResaltConfig::HTTP_PORT,
+    )))
+}
+
+fn https_url(host: &str, uri: &Uri, port: u16) -> String {
+    // Strip the HTTP port from the host, keeping IPv6 brackets intact
+    let host = match host.rsplit_once(':') {
+        Some((name, port))
+            if !port.is_empty()
+                && port.chars().all(|c| c.is_ascii_digit())
+                && (!name.contains(':') || name.ends_with(']')) =>
+        {
+            name
+        }
+        _ => host,
+    };
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    match port {
+        443 => format!("https://{}{}", host, path),
+        port => format!("https://{}:{}{}", host, port, path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_https_url() {
+        let uri: Uri = "/minions?limit=10".parse().unwrap();
+        assert_eq!(
+            https_url("resalt.local:80", &uri, 443),
+            "https://resalt.local/minions?limit=10"
+        );
+        assert_eq!(
+            https_url("resalt.local", &uri, 8443),
+            "https://resalt.local:8443/minions?limit=10"
+        );
+        assert_eq!(
+            https_url("[::1]:8080", &Uri::from_static("/"), 443),
+            "https://[::1]/"
+        );
+        assert_eq!(
+            https_url("[::1]", &Uri::from_static("/"), 443),
+            "https://[::1]/"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Round trips of the typed client against a server using the files backend.
+
+use std::{
+    collections::HashMap,
+    net::TcpListener,
+    process::{Child, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use resalt_client::{JobRunRequest, ResaltClient, ResaltClientError};
+use resalt_models::{SaltClientType, SaltTgtType};
+use resalt_storage::Storage;
+
+/// Tests run in parallel, so each server gets its own database directory.
+static SERVER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A server process, killed when dropped, with its database directory.
+struct TestServer {
+    child: Child,
+    db_path: String,
+    url: String,
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.db_path);
+    }
+}
+
+impl TestServer {
+    async fn start() -> TestServer {
+        let db_path = std::env::temp_dir()
+            .join(format!(
+                "resalt-client-test-{}-{}",
+                std::process::id(),
+                SERVER_COUNT.fetch_add(1, Ordering::SeqCst)
+            ))
+            .to_string_lossy()
+            .to_string();
+        let _ = std::fs::remove_dir_all(&db_path);
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let child = Command::new(env!("CARGO_BIN_EXE_resalt-server"))
+            .env("RESALT_DATABASE_TYPE", "files")
+            .env("RESALT_DATABASE_HOST", &db_path)
+            .env("RESALT_HTTP_PORT", port.to_string())
+            // Blocking storage calls would otherwise stall small runtimes
+            .env("TOKIO_WORKER_THREADS", "4")
+            // Nothing listens there, the tests don't need Salt
+            .env("RESALT_SALT_API_URL", "http://127.0.0.1:9")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let server = TestServer {
+            child,
+            db_path,
+            url: format!("http://127.0.0.1:{}", port),
+        };
+
+        let client = ResaltClient::new(&server.url);
+        for _ in 0..300 {
+            if client.config().await.is_ok() {
+                return server;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("Server did not start");
+    }
+
+    /// Create a superadmin directly in the database, returning a client logged in as them.
+    /// Logging in through the API would need Salt.
+    async fn admin_client(&self) -> ResaltClient {
+        let data = Storage::connect(&format!("files:{}", self.db_path))
+            .await
+            .unwrap();
+        let perms = r#"[".*", "@runner", "@wheel", {"@resalt": ["admin.superadmin"]}]"#;
+        let group_id = data
+            .create_permission_group(None, "$superadmins", Some(perms.to_string()))
+            .unwrap();
+        let user = data
+            .create_user_hashed(
+                None,
+                "admin".to_string(),
+                None,
+                "[]".to_string(),
+                None,
+                None,
+            )
+            .unwrap();
+        data.insert_permission_group_user(&user.id, &group_id)
+            .unwrap();
+        data.refresh_user_permissions(&user.id).unwrap();
+        let token = data.create_authtoken(user.id).unwrap();
+        ResaltClient::new(&self.url).with_token(&token.id)
+    }
+}
+
+#[tokio::test]
+async fn test_client_round_trip() {
+    let server = TestServer::start().await;
+    let client = server.admin_client().await;
+
+    let myself = client.myself().await.unwrap();
+    assert_eq!(myself.username, "admin");
+
+    // Users, whose usernames must be unique
+    let user = client
+        .create_user("alice", Some("alice@example.com"))
+        .await
+        .unwrap();
+    assert_eq!(client.user(&user.id).await.unwrap().username, "alice");
+    assert!(matches!(
+        client.create_user("alice", None).await,
+        Err(ResaltClientError::BadRequest(_))
+    ));
+    let mut usernames: Vec<String> = client
+        .users(None)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|u| u.username)
+        .collect();
+    usernames.sort();
+    assert_eq!(usernames, vec!["admin", "alice"]);
+
+    // Group memberships
+    let group = client.create_group("operators").await.unwrap();
+    client.add_user_to_group(&user.id, &group.id).await.unwrap();
+    let user = client.user(&user.id).await.unwrap();
+    assert_eq!(user.permission_groups.len(), 1);
+    assert_eq!(user.permission_groups[0].name, "operators");
+    let group = client.group(&group.id).await.unwrap();
+    assert_eq!(group.users.len(), 1);
+    assert_eq!(group.users[0].username, "alice");
+
+    client.delete_user(&user.id).await.unwrap();
+    assert!(matches!(
+        client.user(&user.id).await,
+        Err(ResaltClientError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test_run_job_without_masters() {
+    let server = TestServer::start().await;
+    let client = server.admin_client().await;
+
+    // Salt is unreachable, so no master accepts the job
+    let job = JobRunRequest {
+        client: SaltClientType::Local,
+        tgt_type: SaltTgtType::Glob,
+        tgt: "*".to_string(),
+        preset_id: None,
+        fun: "test.ping".to_string(),
+        arg: vec![],
+        kwarg: HashMap::new(),
+        batch_size: String::new(),
+    };
+    assert!(matches!(
+        client.run_job(&job).await,
+        Err(ResaltClientError::ServerError(502))
+    ));
+
+    // Also without asking for the result on each master
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/jobs", server.url))
+        .header("Cookie", format!("resalt-auth={}", client.token().unwrap()))
+        .json(&job)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_GATEWAY);
+}
@@ -123,7 +123,13 @@ impl StorageImpl for StorageFiles {
 
     fn keys(&self, pattern: &str) -> Result<Vec<String>, String> {
         let keys = self.list_file_names("kv")?;
-        let pattern = pattern.replace("*", ".*");
+        // Glob semantics like Redis KEYS, so "user:*" does not match "permission_group_user:..."
+        let pattern = pattern
+            .split('*')
+            .map(regex::escape)
+            .collect::<Vec<String>>()
+            .join(".*");
+        let pattern = format!("^{}$", pattern);
         let regex = regex::Regex::new(&pattern).map_err(|e| format!("{:?}", e))?;
         let keys = keys
             .into_iter()
@@ -170,3 +176,35 @@ impl StorageImpl for StorageFiles {
 //         cleanup_temp_storage(&data.1);
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use resalt_models::StorageImpl;
+
+    use crate::StorageFiles;
+
+    #[test]
+    fn test_keys_glob_is_anchored() {
+        let path = std::env::temp_dir()
+            .join(format!("resalt-files-keys-{}", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let _ = std::fs::remove_dir_all(&path);
+        let data = StorageFiles::connect(&path).unwrap();
+        data.set("user:u1:username", "alice").unwrap();
+        data.set("permission_group_user:g1:u1", "1").unwrap();
+        data.set("user:u1", "x").unwrap();
+
+        let mut keys = data.keys("user:*").unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["user:u1", "user:u1:username"]);
+        assert_eq!(
+            data.keys("user:*:username").unwrap(),
+            vec!["user:u1:username"]
+        );
+        // Regex characters in the pattern are literal
+        assert!(data.keys("user.u1").unwrap().is_empty());
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -9,7 +9,7 @@ pub struct StorageRedis {
 
 impl StorageRedis {
     pub async fn connect(database_url: &str) -> Result<Self, String> {
-        let client = Client::open(database_url).unwrap();
+        let client = Client::open(database_url).map_err(|e| format!("{:?}", e))?;
         let pool = Pool::builder().build(client);
 
         match pool {
@@ -39,13 +39,17 @@ impl StorageImpl for StorageRedis {
 
     fn set(&self, key: &str, value: &str) -> Result<(), String> {
         let mut connection = self.create_connection()?;
-        connection.set(key, value).map_err(|e| format!("{:?}", e))?;
+        connection
+            .set::<_, _, ()>(key, value)
+            .map_err(|e| format!("{:?}", e))?;
         Ok(())
     }
 
     fn del(&self, key: &str) -> Result<(), String> {
         let mut connection = self.create_connection()?;
-        connection.del(key).map_err(|e| format!("{:?}", e))?;
+        connection
+            .del::<_, ()>(key)
+            .map_err(|e| format!("{:?}", e))?;
         Ok(())
     }
 
@@ -0,0 +1,325 @@
+//! Encryption at rest of sensitive fields.
+//!
+//! Every value is encrypted with its own random data key, which is stored next to it,
+//! encrypted with the configured master key. Values record which master key that was,
+//! so earlier keys keep working for reading until [`Storage::reencrypt`] has rewritten
+//! everything with the current key.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use resalt_config::ResaltConfig;
+use resalt_models::StorageImpl;
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    digest::{digest, SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::Storage;
+
+/// Keys of the encrypted values, where `*` matches one segment of a key.
+const ENCRYPTED_KEYS: [&str; 4] = [
+    "user:*:password",
+    "auth_token:*:saltToken",
+    "minion:*:pillars",
+    "minion_snapshot:*:pillars:*:data",
+];
+
+/// Marks an encrypted value, followed by `<key id>$<data key>$<data>`.
+const ENCRYPTED_PREFIX: &str = "$resalt-enc$1$";
+
+fn is_encrypted_key(key: &str) -> bool {
+    ENCRYPTED_KEYS.iter().any(|pattern| {
+        let mut parts = key.split(':');
+        pattern
+            .split(':')
+            .all(|segment| matches!(parts.next(), Some(part) if segment == "*" || segment == part))
+            && parts.next().is_none()
+    })
+}
+
+/// Encrypt with AES-256-GCM, prepending the random nonce.
+fn seal(key: &[u8], aad: &str, plaintext: &[u8], rng: &SystemRandom) -> Result<Vec<u8>, String> {
+    let key = UnboundKey::new(&AES_256_GCM, key).map_err(|_| "Invalid encryption key")?;
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut nonce)
+        .map_err(|_| "Failed to generate a nonce".to_string())?;
+    let mut data = plaintext.to_vec();
+    LessSafeKey::new(key)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(aad.as_bytes()),
+            &mut data,
+        )
+        .map_err(|_| "Failed to encrypt".to_string())?;
+    Ok([nonce.as_slice(), &data].concat())
+}
+
+fn open(key: &[u8], aad: &str, sealed: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, data) = sealed.split_at(NONCE_LEN);
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key).ok()?);
+    let mut data = data.to_vec();
+    let plaintext = key
+        .open_in_place(
+            Nonce::try_assume_unique_for_key(nonce).ok()?,
+            Aad::from(aad.as_bytes()),
+            &mut data,
+        )
+        .ok()?;
+    Some(plaintext.to_vec())
+}
+
+#[derive(Clone)]
+struct MasterKey {
+    /// Short hash of the key, identifying it in encrypted values.
+    id: String,
+    key: Vec<u8>,
+}
+
+impl MasterKey {
+    fn from_hex(hex: &str) -> Result<MasterKey, String> {
+        let hex = hex.trim();
+        if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("Encryption keys must be 64 hex characters".to_string());
+        }
+        let key: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        let id = digest(&SHA256, &key).as_ref()[..4]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        Ok(MasterKey { id, key })
+    }
+}
+
+/// The current master key, used for writing, and earlier ones still accepted for reading.
+#[derive(Clone, Default)]
+pub(crate) struct Keyring {
+    current: Option<MasterKey>,
+    old: Vec<MasterKey>,
+}
+
+impl Keyring {
+    fn new(current: &str, old: &[String]) -> Result<Keyring, String> {
+        let current = match current.is_empty() {
+            true => None,
+            false => Some(MasterKey::from_hex(current)?),
+        };
+        let old = old
+            .iter()
+            .map(|key| MasterKey::from_hex(key))
+            .collect::<Result<Vec<MasterKey>, String>>()?;
+        Ok(Keyring { current, old })
+    }
+
+    /// The keys in `RESALT_DATABASE_ENCRYPTION_KEY` and `RESALT_DATABASE_ENCRYPTION_OLD_KEYS`.
+    pub(crate) fn from_config() -> Result<Keyring, String> {
+        Keyring::new(
+            &ResaltConfig::DATABASE_ENCRYPTION_KEY,
+            &ResaltConfig::DATABASE_ENCRYPTION_OLD_KEYS,
+        )
+    }
+
+    /// Whether a stored value is written as it would be now: encrypted with the current
+    /// key, or in plaintext if encryption is disabled.
+    fn is_current(&self, value: &str) -> bool {
+        let key_id = value
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .and_then(|rest| rest.split('$').next());
+        match (&self.current, key_id) {
+            (Some(current), Some(key_id)) => current.id == key_id,
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// Encrypt the value of `key` with the current key, or leave it if there is none.
+    fn encrypt(&self, key: &str, value: &str) -> Result<String, String> {
+        let master = match &self.current {
+            Some(master) => master,
+            None => return Ok(value.to_string()),
+        };
+        let rng = SystemRandom::new();
+        let mut data_key = [0u8; 32];
+        rng.fill(&mut data_key)
+            .map_err(|_| "Failed to generate a data key".to_string())?;
+        let sealed_data_key = seal(&master.key, key, &data_key, &rng)?;
+        let sealed_value = seal(&data_key, key, value.as_bytes(), &rng)?;
+        Ok(format!(
+            "{}{}${}${}",
+            ENCRYPTED_PREFIX,
+            master.id,
+            STANDARD.encode(sealed_data_key),
+            STANDARD.encode(sealed_value)
+        ))
+    }
+
+    /// Decrypt the value of `key` with whichever key encrypted it. Plaintext values,
+    /// written before encryption was enabled, are returned as is.
+    fn decrypt(&self, key: &str, value: &str) -> Result<String, String> {
+        let rest = match value.strip_prefix(ENCRYPTED_PREFIX) {
+            Some(rest) => rest,
+            None => return Ok(value.to_string()),
+        };
+        let (key_id, sealed_data_key, sealed_value) = match rest.split('$').collect::<Vec<_>>()[..]
+        {
+            [key_id, sealed_data_key, sealed_value] => (key_id, sealed_data_key, sealed_value),
+            _ => return Err(format!("Malformed encrypted value of {}", key)),
+        };
+        let master = match self
+            .current
+            .iter()
+            .chain(self.old.iter())
+            .find(|master| master.id == key_id)
+        {
+            Some(master) => master,
+            None => {
+                return Err(format!(
+                    "{} is encrypted with unknown key {}, configure it in RESALT_DATABASE_ENCRYPTION_OLD_KEYS",
+                    key, key_id
+                ))
+            }
+        };
+        let plaintext = STANDARD
+            .decode(sealed_data_key)
+            .ok()
+            .and_then(|sealed| open(&master.key, key, &sealed))
+            .and_then(|data_key| {
+                let sealed = STANDARD.decode(sealed_value).ok()?;
+                open(&data_key, key, &sealed)
+            });
+        match plaintext.map(String::from_utf8) {
+            Some(Ok(plaintext)) => Ok(plaintext),
+            _ => Err(format!("Failed to decrypt {}", key)),
+        }
+    }
+}
+
+/// Encrypts sensitive values on their way into the database, and decrypts them on the way out.
+#[derive(Clone)]
+pub(crate) struct EncryptedStorage {
+    inner: Box<dyn StorageImpl>,
+    keyring: Keyring,
+}
+
+impl EncryptedStorage {
+    pub(crate) fn new(inner: Box<dyn StorageImpl>, keyring: Keyring) -> EncryptedStorage {
+        EncryptedStorage { inner, keyring }
+    }
+}
+
+impl StorageImpl for EncryptedStorage {
+    fn clone(&self) -> Box<dyn StorageImpl> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, String> {
+        match self.inner.get(key)? {
+            Some(value) if is_encrypted_key(key) => self.keyring.decrypt(key, &value).map(Some),
+            value => Ok(value),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), String> {
+        match is_encrypted_key(key) {
+            true => self.inner.set(key, &self.keyring.encrypt(key, value)?),
+            false => self.inner.set(key, value),
+        }
+    }
+
+    fn del(&self, key: &str) -> Result<(), String> {
+        self.inner.del(key)
+    }
+
+    fn keys(&self, pattern: &str) -> Result<Vec<String>, String> {
+        self.inner.keys(pattern)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReencryptCounts {
+    /// Sensitive fields in the database.
+    pub fields: usize,
+    /// Fields which were not yet written with the current key.
+    pub rewritten: usize,
+}
+
+impl Storage {
+    /// Rewrite every sensitive field which is not encrypted with the current key, after
+    /// rotating keys, or enabling or disabling encryption. Nothing is written on a `dry_run`.
+    pub fn reencrypt(&self, dry_run: bool) -> Result<ReencryptCounts, String> {
+        let mut counts = ReencryptCounts::default();
+        for pattern in ENCRYPTED_KEYS {
+            for key in self.s.keys(pattern)? {
+                // Redis patterns also match across segments
+                if !is_encrypted_key(&key) {
+                    continue;
+                }
+                let stored = match self.s.inner.get(&key)? {
+                    Some(stored) => stored,
+                    None => continue,
+                };
+                counts.fields += 1;
+                if self.s.keyring.is_current(&stored) {
+                    continue;
+                }
+                counts.rewritten += 1;
+                if !dry_run {
+                    let value = self.s.keyring.decrypt(&key, &stored)?;
+                    self.s.set(&key, &value)?;
+                }
+            }
+        }
+        Ok(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_1: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    const KEY_2: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+    #[test]
+    fn test_is_encrypted_key() {
+        assert!(is_encrypted_key("user:usr_1:password"));
+        assert!(is_encrypted_key("minion_snapshot:m1:pillars:snap_1:data"));
+        assert!(!is_encrypted_key("minion_snapshot:m1:grains:snap_1:data"));
+        assert!(!is_encrypted_key("user:usr_1:preferences:password"));
+        assert!(!is_encrypted_key("minion:m1:grains"));
+    }
+
+    #[test]
+    fn test_encrypt_rotate() {
+        let key = "minion:m1:pillars";
+        let keyring = Keyring::new(KEY_1, &[]).unwrap();
+        let encrypted = keyring.encrypt(key, "{\"secret\":1}").unwrap();
+        assert!(encrypted.starts_with(ENCRYPTED_PREFIX));
+        assert!(keyring.is_current(&encrypted));
+        assert_eq!(keyring.decrypt(key, &encrypted).unwrap(), "{\"secret\":1}");
+        // Bound to its key, and random per value
+        assert!(keyring.decrypt("minion:m2:pillars", &encrypted).is_err());
+        assert_ne!(keyring.encrypt(key, "{\"secret\":1}").unwrap(), encrypted);
+        // Plaintext from before encryption was enabled
+        assert!(!keyring.is_current("{}"));
+        assert_eq!(keyring.decrypt(key, "{}").unwrap(), "{}");
+
+        let rotated = Keyring::new(KEY_2, &[KEY_1.to_string()]).unwrap();
+        assert!(!rotated.is_current(&encrypted));
+        assert_eq!(rotated.decrypt(key, &encrypted).unwrap(), "{\"secret\":1}");
+        assert!(Keyring::new(KEY_2, &[])
+            .unwrap()
+            .decrypt(key, &encrypted)
+            .is_err());
+
+        let disabled = Keyring::new("", &[KEY_1.to_string()]).unwrap();
+        assert_eq!(disabled.encrypt(key, "{}").unwrap(), "{}");
+        assert!(disabled.is_current("{}"));
+        assert!(Keyring::new("nope", &[]).is_err());
+    }
+}
@@ -8,47 +8,75 @@ use resalt_storage_redis::StorageRedis;
 use serde::Serialize;
 use serde_json::Value;
 
+mod encryption;
+mod migrate;
+
+pub use encryption::ReencryptCounts;
+use encryption::{EncryptedStorage, Keyring};
+pub use migrate::*;
+
 #[derive(Clone)]
 pub struct Storage {
-    s: Box<dyn StorageImpl>,
+    s: EncryptedStorage,
 }
 
 impl Storage {
     pub async fn init_db() -> Storage {
-        let db_type = &ResaltConfig::DATABASE_TYPE.clone();
-        let db_type = db_type.as_str();
-        debug!("Database type: \"{}\"", db_type);
-        let storage: Box<dyn StorageImpl> = match db_type {
-            "files" => {
-                let path: String = ResaltConfig::DATABASE_HOST.clone();
-                debug!("Database path: \"{}\"", path);
-                Box::new(
-                    StorageFiles::connect(&path)
-                        .unwrap_or_else(|_| panic!("Error connecting to {}", &path)),
-                )
-            }
-            "redis" => {
-                let database_url = format!(
-                    "redis://{}:{}@{}:{}/{}",
-                    *ResaltConfig::DATABASE_USERNAME,
-                    *ResaltConfig::DATABASE_PASSWORD,
-                    *ResaltConfig::DATABASE_HOST,
-                    *ResaltConfig::DATABASE_PORT,
-                    *ResaltConfig::DATABASE_DATABASE
-                );
-                debug!("Database URL: \"{}\"", database_url);
-                Box::new(
-                    StorageRedis::connect(&database_url)
-                        .await
-                        .unwrap_or_else(|_| panic!("Error connecting to {}", &database_url)),
-                )
-            }
-            _ => panic!(),
+        let database_url = Storage::config_database_url();
+        debug!("Database URL: \"{}\"", database_url);
+        Storage::connect(&database_url)
+            .await
+            .unwrap_or_else(|e| panic!("Error connecting to {}: {}", &database_url, e))
+    }
+
+    /// The database configured with the `RESALT_DATABASE_*` settings, as a URL for
+    /// [`Storage::connect`].
+    pub fn config_database_url() -> String {
+        match ResaltConfig::DATABASE_TYPE.as_str() {
+            "files" => format!("files:{}", *ResaltConfig::DATABASE_HOST),
+            "redis" => format!(
+                "redis://{}:{}@{}:{}/{}",
+                *ResaltConfig::DATABASE_USERNAME,
+                *ResaltConfig::DATABASE_PASSWORD,
+                *ResaltConfig::DATABASE_HOST,
+                *ResaltConfig::DATABASE_PORT,
+                *ResaltConfig::DATABASE_DATABASE
+            ),
+            db_type => panic!("Unsupported database type \"{}\"", db_type),
+        }
+    }
+
+    /// Connect to `files:<path>` or `redis://<username>:<password>@<host>:<port>/<database>`,
+    /// encrypting sensitive fields with the configured keys.
+    pub async fn connect(database_url: &str) -> Result<Storage, String> {
+        let storage: Box<dyn StorageImpl> = match database_url.split_once(':') {
+            Some(("files", path)) => {
+                let path = path.strip_prefix("//").unwrap_or(path);
+                Box::new(StorageFiles::connect(path)?)
+            }
+            Some(("redis", _)) | Some(("rediss", _)) => {
+                Box::new(StorageRedis::connect(database_url).await?)
+            }
+            _ => {
+                return Err(format!(
+                    "Unsupported database URL \"{}\", expected files:<path> or redis://...",
+                    database_url
+                ))
+            }
         };
-        Storage { s: storage }
+        Ok(Storage {
+            s: EncryptedStorage::new(storage, Keyring::from_config()?),
+        })
     }
 
-    pub fn prune_minions_without_key(&self, keys: &Vec<SaltMinionKey>) -> Result<(), String> {
+    /// Delete minions without a key on any master. Only minions of `listed_masters`
+    /// (or without a known master) are considered, so an unreachable master does not
+    /// wipe its minions.
+    pub fn prune_minions_without_key(
+        &self,
+        keys: &Vec<SaltMinionKey>,
+        listed_masters: &[String],
+    ) -> Result<(), String> {
         let mut minions: Vec<Minion> = match self.list_minions(Vec::new(), None, Paginate::None) {
             Ok(minions) => minions,
             Err(e) => {
@@ -58,6 +86,11 @@ impl Storage {
         };
         let mut minions_to_delete: Vec<Minion> = Vec::new();
         for minion in minions.iter_mut() {
+            if let Some(master) = &minion.master {
+                if !listed_masters.contains(master) {
+                    continue;
+                }
+            }
             let mut found = false;
             for key in keys {
                 if minion.id == key.id {
@@ -90,24 +123,15 @@ impl Storage {
             }
         };
         info!("Groups: {:?}", groups);
-        let mut perms: Vec<Value> = Vec::new();
-        for group in groups {
-            // Parse group.perms as json array
-            let serdegroup: serde_json::Value = match serde_json::from_str(&group.perms) {
-                Ok(serdegroup) => serdegroup,
-                Err(e) => {
-                    error!("{:?}", e);
-                    return Err(e.to_string());
-                }
-            };
-            let group_perms = match serdegroup.as_array() {
-                Some(group_perms) => group_perms,
-                None => continue,
-            };
-            for group_perm in group_perms {
-                perms.push(group_perm.clone());
+        // Including the permissions inherited from groups which include these
+        let group_ids: Vec<String> = groups.into_iter().map(|group| group.id).collect();
+        let perms = match self.permission_group_tree()?.effective_perms(&group_ids) {
+            Ok(perms) => perms,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(e);
             }
-        }
+        };
         let perms = Value::Array(perms);
         let perms = match serde_json::to_string(&perms) {
             Ok(perms) => perms,
@@ -162,10 +186,7 @@ impl Storage {
     }
 
     fn save_object(&self, prefix: &str, obj: &impl Serialize) -> Result<(), String> {
-        let map = match self.obj_to_map(obj) {
-            Ok(map) => map,
-            Err(e) => return Err(e),
-        };
+        let map = self.obj_to_map(obj)?;
         for (key, value) in map.iter() {
             match self.s.set(&format!("{}:{}", prefix, key), value) {
                 Ok(_) => (),
@@ -176,19 +197,13 @@ impl Storage {
     }
 
     fn read_map(&self, prefix: &str) -> Result<Option<HashMap<String, String>>, String> {
-        let keys = match self.s.keys(&format!("{}:*", prefix)) {
-            Ok(keys) => keys,
-            Err(e) => return Err(e),
-        };
+        let keys = self.s.keys(&format!("{}:*", prefix))?;
         if keys.is_empty() {
             return Ok(None);
         }
         let mut map: HashMap<String, String> = HashMap::new();
         for key in keys {
-            let value = match self.s.get(&key) {
-                Ok(value) => value,
-                Err(e) => return Err(e),
-            };
+            let value = self.s.get(&key)?;
             let value = match value {
                 Some(value) => value,
                 None => continue,
@@ -204,10 +219,7 @@ impl Storage {
         prefix: &str,
     ) -> Result<Option<T>, String> {
         info!("Reading object: {}", prefix);
-        let map = match self.read_map(prefix) {
-            Ok(map) => map,
-            Err(e) => return Err(e),
-        };
+        let map = self.read_map(prefix)?;
         let map = match map {
             Some(map) => map,
             None => return Ok(None),
@@ -224,10 +236,7 @@ impl Storage {
     }
 
     fn delete_object(&self, prefix: &str) -> Result<(), String> {
-        let keys = match self.s.keys(&format!("{}:*", prefix)) {
-            Ok(keys) => keys,
-            Err(e) => return Err(e),
-        };
+        let keys = self.s.keys(&format!("{}:*", prefix))?;
         for key in keys {
             match self.s.del(&key) {
                 Ok(_) => (),
@@ -242,10 +251,7 @@ impl Storage {
     }
 
     fn keys_depth(&self, pattern: &str, depth: usize) -> Result<Vec<String>, String> {
-        let keys = match self.s.keys(pattern) {
-            Ok(keys) => keys,
-            Err(e) => return Err(e),
-        };
+        let keys = self.s.keys(pattern)?;
         debug!("Keys({}): {:?}", pattern, keys);
         let mut result: HashSet<String> = HashSet::new();
         for key in keys {
@@ -279,12 +285,14 @@ impl Storage {
         tag: String,
         data: String,
         timestamp: ResaltTime,
+        master: &str,
     ) -> Result<String, String> {
         let event = Event {
             id: Storage::id("evnt"),
             tag,
             data,
             timestamp,
+            master: Some(master.to_owned()),
         };
         self.save_object(&format!("event:{}", event.id), &event)?;
         Ok(event.id)
@@ -294,11 +302,13 @@ impl Storage {
         self.read_object(&format!("event:{}", id))
     }
 
+    /// Save an event as is, e.g. when restoring a backup.
+    pub fn set_event(&self, event: &Event) -> Result<(), String> {
+        self.save_object(&format!("event:{}", event.id), event)
+    }
+
     pub fn list_events(&self, paginate: Paginate) -> Result<Vec<Event>, String> {
-        let keys = match self.keys_depth("event:*", 2) {
-            Ok(keys) => keys,
-            Err(e) => return Err(e),
-        };
+        let keys = self.keys_depth("event:*", 2)?;
 
         // Read events
         let mut events: Vec<Event> = Vec::new();
@@ -312,7 +322,7 @@ impl Storage {
         }
 
         // Sort by timestamp
-        events.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        events.sort_by_key(|a| a.timestamp);
 
         // Pagination
         if let Some((limit, offset)) = paginate {
@@ -336,6 +346,7 @@ impl Storage {
         user: Option<String>,
         event_id: Option<String>,
         timestamp: ResaltTime,
+        master: &str,
     ) -> Result<(), String> {
         let job = Job {
             id: jid.clone(),
@@ -343,24 +354,48 @@ impl Storage {
             jid: jid.clone(), // TODO: remove, id = jid
             user,
             event_id,
+            preset_id: None,
+            master: Some(master.to_owned()),
         };
         self.save_object(&format!("job:{}", job.id), &job)
     }
 
+    /// Record the preset a job was targeted at. Kept apart from the job object,
+    /// as the job itself is only created once its event arrives from Salt.
+    pub fn set_job_preset(&self, jid: &str, preset_id: &str) -> Result<(), String> {
+        self.s.set(&format!("job_preset:{}", jid), preset_id)
+    }
+
+    fn read_job(&self, jid: &str) -> Result<Option<Job>, String> {
+        let mut job: Job = match self.read_object(&format!("job:{}", jid))? {
+            Some(job) => job,
+            None => return Ok(None),
+        };
+        job.preset_id = self.s.get(&format!("job_preset:{}", jid))?;
+        Ok(Some(job))
+    }
+
     pub fn get_job_by_jid(&self, jid: &str) -> Result<Option<Job>, String> {
-        self.read_object(&format!("job:{}", jid))
+        self.read_job(jid)
+    }
+
+    /// Save a job as is, e.g. when restoring a backup.
+    pub fn set_job(&self, job: &Job) -> Result<(), String> {
+        self.save_object(&format!("job:{}", job.id), job)?;
+        match &job.preset_id {
+            Some(preset_id) => self.set_job_preset(&job.jid, preset_id),
+            None => Ok(()),
+        }
     }
 
     pub fn list_jobs(&self, sort: Option<JobSort>, paginate: Paginate) -> Result<Vec<Job>, String> {
-        let keys = match self.keys_depth("job:*", 2) {
-            Ok(keys) => keys,
-            Err(e) => return Err(e),
-        };
+        let keys = self.keys_depth("job:*", 2)?;
 
         // Read jobs
         let mut jobs: Vec<Job> = Vec::new();
         for key in keys {
-            let job = match self.read_object(&key) {
+            let jid = key.trim_start_matches("job:");
+            let job = match self.read_job(jid) {
                 Ok(Some(job)) => job,
                 Ok(None) => continue,
                 Err(e) => return Err(e),
@@ -408,11 +443,34 @@ impl Storage {
         self.save_object(&format!("job_return:{}", job_return.id), &job_return)
     }
 
+    pub fn get_job_return(&self, id: &str) -> Result<Option<JobReturn>, String> {
+        self.read_object(&format!("job_return:{}", id))
+    }
+
+    /// Save a job return as is, e.g. when restoring a backup.
+    pub fn set_job_return(&self, job_return: &JobReturn) -> Result<(), String> {
+        self.save_object(&format!("job_return:{}", job_return.id), job_return)
+    }
+
+    pub fn list_job_returns(&self) -> Result<Vec<JobReturn>, String> {
+        let keys = self.keys_depth("job_return:*", 2)?;
+
+        // Read job returns
+        let mut job_returns: Vec<JobReturn> = Vec::new();
+        for key in keys {
+            let job_return = match self.read_object(&key) {
+                Ok(Some(job_return)) => job_return,
+                Ok(None) => continue,
+                Err(e) => return Err(e),
+            };
+            job_returns.push(job_return);
+        }
+
+        Ok(job_returns)
+    }
+
     pub fn get_job_returns_by_job(&self, job: &Job) -> Result<Vec<JobReturn>, String> {
-        let keys = match self.keys_depth("job_return:*", 2) {
-            Ok(keys) => keys,
-            Err(e) => return Err(e),
-        };
+        let keys = self.keys_depth("job_return:*", 2)?;
 
         // Read job returns
         let mut job_returns: Vec<JobReturn> = Vec::new();
@@ -448,6 +506,7 @@ impl Storage {
             id: id.unwrap_or_else(|| Storage::id("perm")),
             name: name.to_string(),
             perms: perms.unwrap_or_else(|| "[]".to_string()),
+            subgroups: Vec::new(),
         };
         self.save_object(
             &format!("permission_group:{}", permission_group.id),
@@ -481,10 +540,7 @@ impl Storage {
         &self,
         paginate: Paginate,
     ) -> Result<Vec<PermissionGroup>, String> {
-        let keys = match self.keys_depth("permission_group:*", 2) {
-            Ok(keys) => keys,
-            Err(e) => return Err(e),
-        };
+        let keys = self.keys_depth("permission_group:*", 2)?;
 
         // Read permission groups
         let mut permission_groups: Vec<PermissionGroup> = Vec::new();
@@ -509,14 +565,32 @@ impl Storage {
         Ok(permission_groups)
     }
 
+    /// All permission groups, to resolve nested groups.
+    pub fn permission_group_tree(&self) -> Result<PermissionGroupTree, String> {
+        Ok(PermissionGroupTree::new(self.list_permission_groups(None)?))
+    }
+
+    /// IDs of the members of a group and of every group it includes, who all get its
+    /// permissions.
+    pub fn list_transitive_user_ids_by_permission_group_id(
+        &self,
+        group_id: &str,
+    ) -> Result<Vec<String>, String> {
+        let group_ids = self.permission_group_tree()?.descendants(group_id);
+        let mut user_ids: Vec<String> = Vec::new();
+        for (user_id, member_group_id) in self.list_permission_group_users()? {
+            if group_ids.contains(&member_group_id) && !user_ids.contains(&user_id) {
+                user_ids.push(user_id);
+            }
+        }
+        Ok(user_ids)
+    }
+
     pub fn list_permission_groups_by_user_id(
         &self,
         user_id: &str,
     ) -> Result<Vec<PermissionGroup>, String> {
-        let keys = match self.keys_depth("permission_group_user:*", 3) {
-            Ok(keys) => keys,
-            Err(e) => return Err(e),
-        };
+        let keys = self.keys_depth("permission_group_user:*", 3)?;
 
         // Read permission groups
         let mut permission_groups: Vec<PermissionGroup> = Vec::new();
@@ -557,11 +631,25 @@ impl Storage {
         self.s.set(&key, "")
     }
 
+    /// All group memberships, as pairs of user ID and group ID.
+    pub fn list_permission_group_users(&self) -> Result<Vec<(String, String)>, String> {
+        let keys = self.keys_depth("permission_group_user:*", 3)?;
+        let mut memberships: Vec<(String, String)> = Vec::new();
+        for key in keys {
+            let user_id_group_id: Vec<&str> = key.split(':').collect();
+            if user_id_group_id.len() != 3 {
+                continue;
+            }
+            memberships.push((
+                user_id_group_id[1].to_string(),
+                user_id_group_id[2].to_string(),
+            ));
+        }
+        Ok(memberships)
+    }
+
     pub fn list_users_by_permission_group_id(&self, group_id: &str) -> Result<Vec<User>, String> {
-        let keys = match self.keys_depth("permission_group_user:*", 2) {
-            Ok(keys) => keys,
-            Err(e) => return Err(e),
-        };
+        let keys = self.keys_depth("permission_group_user:*", 3)?;
 
         // Read users
         let mut users: Vec<User> = Vec::new();
@@ -589,11 +677,171 @@ impl Storage {
         user_id: &str,
         group_id: &str,
     ) -> Result<(), String> {
+        // Memberships are a single key, not an object with fields
         let key = format!("permission_group_user:{}:{}", user_id, group_id);
-        match self.delete_object(&key) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
+        self.s.del(&key)
+    }
+
+    //
+    // Permission Group Grants
+    //
+
+    pub fn insert_permission_group_grant(
+        &self,
+        user_id: &str,
+        group_id: &str,
+        reason: &str,
+        granted_by: &str,
+        expires_at: ResaltTime,
+    ) -> Result<PermissionGroupGrant, String> {
+        let grant = PermissionGroupGrant {
+            id: Storage::id("grant"),
+            user_id: user_id.to_string(),
+            group_id: group_id.to_string(),
+            reason: reason.to_string(),
+            granted_by: granted_by.to_string(),
+            granted_at: ResaltTime::now(),
+            expires_at,
+        };
+        self.set_permission_group_grant(&grant)?;
+        Ok(grant)
+    }
+
+    pub fn set_permission_group_grant(&self, grant: &PermissionGroupGrant) -> Result<(), String> {
+        self.save_object(&format!("permission_group_grant:{}", grant.id), grant)
+    }
+
+    /// All temporary memberships, including expired ones not yet revoked, soonest
+    /// expiring first.
+    pub fn list_permission_group_grants(&self) -> Result<Vec<PermissionGroupGrant>, String> {
+        let keys = self.keys_depth("permission_group_grant:*", 2)?;
+
+        let mut grants: Vec<PermissionGroupGrant> = Vec::new();
+        for key in keys {
+            let grant = match self.read_object(&key) {
+                Ok(Some(grant)) => grant,
+                Ok(None) => continue,
+                Err(e) => return Err(e),
+            };
+            grants.push(grant);
+        }
+        grants.sort_by_key(|grant| grant.expires_at);
+        Ok(grants)
+    }
+
+    pub fn get_permission_group_grant(
+        &self,
+        user_id: &str,
+        group_id: &str,
+    ) -> Result<Option<PermissionGroupGrant>, String> {
+        Ok(self
+            .list_permission_group_grants()?
+            .into_iter()
+            .find(|grant| grant.user_id == user_id && grant.group_id == group_id))
+    }
+
+    pub fn delete_permission_group_grant(&self, id: &str) -> Result<(), String> {
+        self.delete_object(&format!("permission_group_grant:{}", id))
+    }
+
+    /// Revoke the temporary memberships which expired by `now`, and record it in the
+    /// audit log. Returns the revoked grants; those failing to be revoked are logged
+    /// and retried on the next call.
+    pub fn expire_permission_group_grants(
+        &self,
+        now: ResaltTime,
+    ) -> Result<Vec<PermissionGroupGrant>, String> {
+        let mut expired: Vec<PermissionGroupGrant> = Vec::new();
+        for grant in self.list_permission_group_grants()? {
+            if !grant.is_expired(now) {
+                continue;
+            }
+            match self.expire_permission_group_grant(&grant) {
+                Ok(()) => expired.push(grant),
+                Err(e) => error!("Failed revoking expired grant {}: {:?}", grant.id, e),
+            }
+        }
+        Ok(expired)
+    }
+
+    fn expire_permission_group_grant(&self, grant: &PermissionGroupGrant) -> Result<(), String> {
+        // Audit first, so a revocation is never left unrecorded
+        self.insert_audit_record(
+            "grant.expired",
+            None,
+            Some(&grant.user_id),
+            Some(&grant.group_id),
+            format!(
+                "Membership expired at {}, granted for: {}",
+                grant.expires_at, grant.reason
+            ),
+        )?;
+        if self.is_user_member_of_group(&grant.user_id, &grant.group_id)? {
+            self.delete_permission_group_user(&grant.user_id, &grant.group_id)?;
+        }
+        self.delete_permission_group_grant(&grant.id)?;
+        // The user may have been deleted in the meantime
+        if self.get_user_by_id(&grant.user_id)?.is_some() {
+            self.refresh_user_permissions(&grant.user_id)?;
+        }
+        Ok(())
+    }
+
+    //
+    // Audit
+    //
+
+    pub fn insert_audit_record(
+        &self,
+        action: &str,
+        actor_id: Option<&str>,
+        user_id: Option<&str>,
+        group_id: Option<&str>,
+        message: String,
+    ) -> Result<String, String> {
+        let record = AuditRecord {
+            id: Storage::id("audit"),
+            timestamp: ResaltTime::now(),
+            action: action.to_string(),
+            actor_id: actor_id.map(str::to_string),
+            user_id: user_id.map(str::to_string),
+            group_id: group_id.map(str::to_string),
+            message,
+        };
+        self.set_audit_record(&record)?;
+        Ok(record.id)
+    }
+
+    /// Save an audit record as is, e.g. when migrating.
+    pub fn set_audit_record(&self, record: &AuditRecord) -> Result<(), String> {
+        self.save_object(&format!("audit:{}", record.id), record)
+    }
+
+    /// Audit records, newest first.
+    pub fn list_audit_records(&self, paginate: Paginate) -> Result<Vec<AuditRecord>, String> {
+        let keys = self.keys_depth("audit:*", 2)?;
+
+        let mut records: Vec<AuditRecord> = Vec::new();
+        for key in keys {
+            let record = match self.read_object(&key) {
+                Ok(Some(record)) => record,
+                Ok(None) => continue,
+                Err(e) => return Err(e),
+            };
+            records.push(record);
         }
+        records.sort_by_key(|record| std::cmp::Reverse(record.timestamp));
+
+        // Pagination
+        if let Some((limit, offset)) = paginate {
+            records = records
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect();
+        }
+
+        Ok(records)
     }
 
     //
@@ -602,14 +850,11 @@ impl Storage {
 
     pub fn list_minions(
         &self,
-        filters: Vec<Filter>,
+        filters: Vec<FilterNode>,
         sort: Option<MinionSort>,
         paginate: Paginate,
     ) -> Result<Vec<Minion>, String> {
-        let keys = match self.keys_depth("minion:*", 2) {
-            Ok(keys) => keys,
-            Err(e) => return Err(e),
-        };
+        let keys = self.keys_depth("minion:*", 2)?;
 
         // Read minions
         let mut minions: Vec<Minion> = Vec::new();
@@ -619,11 +864,11 @@ impl Storage {
                 Ok(None) => continue,
                 Err(e) => return Err(e),
             };
-            minions.push(minion);
+            minions.push(Storage::with_minion_status(minion));
         }
 
         // Filter
-        filter_minions(&mut minions, &filters);
+        filter_minions(&mut minions, &filters).map_err(|e| e.to_string())?;
 
         // Sort
         if let Some(sort) = sort {
@@ -643,7 +888,20 @@ impl Storage {
     }
 
     pub fn get_minion_by_id(&self, id: &str) -> Result<Option<Minion>, String> {
-        self.read_object(&format!("minion:{}", id))
+        Ok(self
+            .read_object(&format!("minion:{}", id))?
+            .map(Storage::with_minion_status))
+    }
+
+    /// Derive `status` from `last_seen` using the configured thresholds.
+    fn with_minion_status(mut minion: Minion) -> Minion {
+        minion.status = MinionStatus::from_last_seen(
+            minion.last_seen,
+            ResaltTime::now(),
+            *ResaltConfig::MINION_STALE_THRESHOLD,
+            *ResaltConfig::MINION_OFFLINE_THRESHOLD,
+        );
+        minion
     }
 
     pub fn set_minion(&self, minion: Minion) -> Result<(), String> {
@@ -652,11 +910,16 @@ impl Storage {
 
     pub fn set_minion_last_seen(&self, minion_id: &str, time: ResaltTime) -> Result<(), String> {
         let key = format!("minion:{}:id", minion_id);
-        self.s.set(&key, &minion_id)?;
+        self.s.set(&key, minion_id)?;
         let key = format!("minion:{}:lastSeen", minion_id);
         self.s.set(&key, &time.to_string())
     }
 
+    pub fn set_minion_master(&self, minion_id: &str, master: &str) -> Result<(), String> {
+        let key = format!("minion:{}:master", minion_id);
+        self.s.set(&key, master)
+    }
+
     pub fn set_minion_grains(
         &self,
         minion_id: &str,
@@ -665,6 +928,7 @@ impl Storage {
         os_type: String,
     ) -> Result<(), String> {
         self.set_minion_last_seen(minion_id, time)?;
+        self.insert_minion_snapshot(minion_id, MinionSnapshotKind::Grains, time, &grains)?;
         let key = format!("minion:{}:grains", minion_id);
         self.s.set(&key, &grains)?;
         let key = format!("minion:{}:osType", minion_id);
@@ -680,6 +944,7 @@ impl Storage {
         pillars: String,
     ) -> Result<(), String> {
         self.set_minion_last_seen(minion_id, time)?;
+        self.insert_minion_snapshot(minion_id, MinionSnapshotKind::Pillars, time, &pillars)?;
         let key = format!("minion:{}:pillars", minion_id);
         self.s.set(&key, &pillars)?;
         let key = format!("minion:{}:lastUpdatedPillars", minion_id);
@@ -693,6 +958,9 @@ impl Storage {
         pkgs: String,
     ) -> Result<(), String> {
         self.set_minion_last_seen(minion_id, time)?;
+        self.insert_minion_snapshot(minion_id, MinionSnapshotKind::Pkgs, time, &pkgs)?;
+        let previous = self.s.get(&format!("minion:{}:pkgs", minion_id))?;
+        self.update_package_index(minion_id, previous.as_deref(), Some(&pkgs))?;
         let key = format!("minion:{}:pkgs", minion_id);
         self.s.set(&key, &pkgs)?;
         let key = format!("minion:{}:lastUpdatedPkgs", minion_id);
@@ -722,12 +990,417 @@ impl Storage {
     }
 
     pub fn delete_minion(&self, id: &str) -> Result<(), String> {
+        let pkgs = self.s.get(&format!("minion:{}:pkgs", id))?;
+        self.update_package_index(id, pkgs.as_deref(), None)?;
+        for kind in [
+            MinionSnapshotKind::Grains,
+            MinionSnapshotKind::Pillars,
+            MinionSnapshotKind::Pkgs,
+        ] {
+            for key in self.minion_snapshot_keys(id, kind)? {
+                self.delete_object(&key)?;
+            }
+            let latest_key = Storage::minion_snapshot_latest_key(id, kind);
+            if self.s.get(&latest_key)?.is_some() {
+                self.s.del(&latest_key)?;
+            }
+        }
+        for transition in self.list_minion_status_transitions(id, None)? {
+            self.delete_object(&format!("minion_status:{}:{}", id, transition.id))?;
+        }
         match self.delete_object(&format!("minion:{}", id)) {
             Ok(_) => Ok(()),
             Err(e) => Err(e),
         }
     }
 
+    //
+    // Minion Snapshots
+    //
+
+    /// Store a new version of a minion data blob, unless it is unchanged from the latest version,
+    /// and prune the oldest versions past the configured retention.
+    pub fn insert_minion_snapshot(
+        &self,
+        minion_id: &str,
+        kind: MinionSnapshotKind,
+        time: ResaltTime,
+        data: &str,
+    ) -> Result<(), String> {
+        if let Some(latest) = self.get_latest_minion_snapshot(minion_id, kind)? {
+            if MinionSnapshotDiff::compute(Some(&latest.data), data).is_empty() {
+                return Ok(());
+            }
+        }
+        let snapshot = MinionSnapshot {
+            id: Storage::id("snap"),
+            minion_id: minion_id.to_string(),
+            kind,
+            timestamp: time,
+            data: data.to_string(),
+        };
+        self.set_minion_snapshot(&snapshot)?;
+        self.s.set(
+            &Storage::minion_snapshot_latest_key(minion_id, kind),
+            &snapshot.id,
+        )?;
+        self.prune_minion_snapshots(minion_id, kind, *ResaltConfig::MINION_SNAPSHOT_RETENTION)
+    }
+
+    /// The latest version of a minion data blob.
+    pub fn get_latest_minion_snapshot(
+        &self,
+        minion_id: &str,
+        kind: MinionSnapshotKind,
+    ) -> Result<Option<MinionSnapshot>, String> {
+        match self
+            .s
+            .get(&Storage::minion_snapshot_latest_key(minion_id, kind))?
+        {
+            Some(id) => self.read_object(&format!("minion_snapshot:{}:{}:{}", minion_id, kind, id)),
+            // Not tracked for snapshots stored before, or copied by a migration
+            None => Ok(self.list_minion_snapshots(minion_id, kind)?.pop()),
+        }
+    }
+
+    /// Delete the oldest versions of a minion data blob, keeping the latest `keep`, or all
+    /// of them if 0.
+    fn prune_minion_snapshots(
+        &self,
+        minion_id: &str,
+        kind: MinionSnapshotKind,
+        keep: u64,
+    ) -> Result<(), String> {
+        let keys = self.minion_snapshot_keys(minion_id, kind)?;
+        if keep == 0 || keys.len() as u64 <= keep {
+            return Ok(());
+        }
+        // Only read the timestamps, not the data
+        let mut timestamps: Vec<(ResaltTime, String)> = Vec::new();
+        for key in keys {
+            let timestamp = match self.s.get(&format!("{}:timestamp", key))? {
+                Some(timestamp) => {
+                    serde_json::from_value(Value::String(timestamp)).map_err(|e| e.to_string())?
+                }
+                None => continue,
+            };
+            timestamps.push((timestamp, key));
+        }
+        timestamps.sort();
+        let excess = timestamps.len().saturating_sub(keep as usize);
+        for (_, key) in timestamps.into_iter().take(excess) {
+            self.delete_object(&key)?;
+        }
+        Ok(())
+    }
+
+    fn minion_snapshot_latest_key(minion_id: &str, kind: MinionSnapshotKind) -> String {
+        format!("minion_snapshot_latest:{}:{}", minion_id, kind)
+    }
+
+    fn minion_snapshot_key(snapshot: &MinionSnapshot) -> String {
+        format!(
+            "minion_snapshot:{}:{}:{}",
+            snapshot.minion_id, snapshot.kind, snapshot.id
+        )
+    }
+
+    fn minion_snapshot_keys(
+        &self,
+        minion_id: &str,
+        kind: MinionSnapshotKind,
+    ) -> Result<Vec<String>, String> {
+        self.keys_depth(&format!("minion_snapshot:{}:{}:*", minion_id, kind), 4)
+    }
+
+    /// Save a snapshot as is, e.g. when migrating to another database.
+    pub fn set_minion_snapshot(&self, snapshot: &MinionSnapshot) -> Result<(), String> {
+        self.save_object(&Storage::minion_snapshot_key(snapshot), snapshot)
+    }
+
+    /// List all versions of a minion data blob, oldest first.
+    pub fn list_minion_snapshots(
+        &self,
+        minion_id: &str,
+        kind: MinionSnapshotKind,
+    ) -> Result<Vec<MinionSnapshot>, String> {
+        let keys = self.minion_snapshot_keys(minion_id, kind)?;
+
+        // Read snapshots
+        let mut snapshots: Vec<MinionSnapshot> = Vec::new();
+        for key in keys {
+            let snapshot = match self.read_object(&key) {
+                Ok(Some(snapshot)) => snapshot,
+                Ok(None) => continue,
+                Err(e) => return Err(e),
+            };
+            snapshots.push(snapshot);
+        }
+
+        // Sort by timestamp
+        snapshots.sort_by_key(|s| s.timestamp);
+
+        Ok(snapshots)
+    }
+
+    //
+    // Package Index
+    //
+
+    /// Package names may contain characters which are special in key patterns
+    /// (e.g. `libstdc++6`, `libc6:amd64`), so they are escaped in keys.
+    fn package_key_name(name: &str) -> String {
+        let mut escaped = String::with_capacity(name.len());
+        for byte in name.bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+                escaped.push(byte as char);
+            } else {
+                escaped.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        escaped
+    }
+
+    fn package_key_name_decode(escaped: &str) -> String {
+        let bytes = escaped.as_bytes();
+        let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'%' && i + 2 < bytes.len() {
+                if let Ok(byte) = u8::from_str_radix(&escaped[i + 1..i + 3], 16) {
+                    decoded.push(byte);
+                    i += 3;
+                    continue;
+                }
+            }
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+        String::from_utf8_lossy(&decoded).to_string()
+    }
+
+    /// Bring the package index of a minion in line with its latest `pkg.list_pkgs` return.
+    /// Entries are only written when they differ, so unchanged returns are cheap.
+    fn update_package_index(
+        &self,
+        minion_id: &str,
+        previous: Option<&str>,
+        pkgs: Option<&str>,
+    ) -> Result<(), String> {
+        let parse = |pkgs: Option<&str>| -> serde_json::Map<String, Value> {
+            match pkgs.map(serde_json::from_str::<Value>) {
+                Some(Ok(Value::Object(pkgs))) => pkgs,
+                _ => serde_json::Map::new(),
+            }
+        };
+        let previous = parse(previous);
+        let pkgs = parse(pkgs);
+
+        for name in previous.keys() {
+            if !pkgs.contains_key(name) {
+                self.s.del(&format!(
+                    "pkg_index:{}:{}",
+                    Storage::package_key_name(name),
+                    minion_id
+                ))?;
+            }
+        }
+        for (name, version) in pkgs.iter() {
+            let key = format!(
+                "pkg_index:{}:{}",
+                Storage::package_key_name(name),
+                minion_id
+            );
+            let version = split_package_versions(version).join(",");
+            if self.s.get(&key)?.as_deref() != Some(version.as_str()) {
+                self.s.set(&key, &version)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_package_index(&self, pattern: &str) -> Result<Vec<Package>, String> {
+        let keys = self.s.keys(pattern)?;
+
+        let mut rows: Vec<(String, String, String)> = Vec::new();
+        for key in keys {
+            let mut parts = key.splitn(3, ':');
+            let (name, minion_id) = match (parts.next(), parts.next(), parts.next()) {
+                (Some("pkg_index"), Some(name), Some(minion_id)) => (name, minion_id),
+                _ => continue,
+            };
+            let versions = match self.s.get(&key)? {
+                Some(versions) => versions,
+                None => continue,
+            };
+            let name = Storage::package_key_name_decode(name);
+            for version in versions.split(',').filter(|v| !v.is_empty()) {
+                rows.push((name.clone(), minion_id.to_string(), version.to_string()));
+            }
+        }
+
+        Ok(group_packages(rows))
+    }
+
+    pub fn list_packages(&self) -> Result<Vec<Package>, String> {
+        self.read_package_index("pkg_index:*")
+    }
+
+    pub fn get_package(&self, name: &str) -> Result<Option<Package>, String> {
+        let pattern = format!("pkg_index:{}:*", Storage::package_key_name(name));
+        Ok(self
+            .read_package_index(&pattern)?
+            .into_iter()
+            .find(|package| package.name == name))
+    }
+
+    //
+    // Minion Status Transitions
+    //
+
+    pub fn insert_minion_status_transition(
+        &self,
+        minion_id: &str,
+        from: MinionStatus,
+        to: MinionStatus,
+        timestamp: ResaltTime,
+    ) -> Result<(), String> {
+        let transition = MinionStatusTransition {
+            id: Storage::id("mst"),
+            minion_id: minion_id.to_string(),
+            timestamp,
+            from,
+            to,
+        };
+        self.save_object(
+            &format!("minion_status:{}:{}", minion_id, transition.id),
+            &transition,
+        )
+    }
+
+    /// Save a status transition as is, e.g. when migrating to another database.
+    pub fn set_minion_status_transition(
+        &self,
+        transition: &MinionStatusTransition,
+    ) -> Result<(), String> {
+        self.save_object(
+            &format!("minion_status:{}:{}", transition.minion_id, transition.id),
+            transition,
+        )
+    }
+
+    /// List the status transitions of a minion, oldest first.
+    pub fn list_minion_status_transitions(
+        &self,
+        minion_id: &str,
+        paginate: Paginate,
+    ) -> Result<Vec<MinionStatusTransition>, String> {
+        let keys = self.keys_depth(&format!("minion_status:{}:*", minion_id), 3)?;
+
+        // Read transitions
+        let mut transitions: Vec<MinionStatusTransition> = Vec::new();
+        for key in keys {
+            let transition = match self.read_object(&key) {
+                Ok(Some(transition)) => transition,
+                Ok(None) => continue,
+                Err(e) => return Err(e),
+            };
+            transitions.push(transition);
+        }
+
+        // Sort by timestamp
+        transitions.sort_by_key(|t| t.timestamp);
+
+        // Pagination
+        if let Some((limit, offset)) = paginate {
+            transitions = transitions
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect();
+        }
+
+        Ok(transitions)
+    }
+
+    /// Delete the status transitions of a minion older than the configured retention,
+    /// always keeping the latest one.
+    fn prune_minion_status_transitions(
+        &self,
+        minion_id: &str,
+        now: ResaltTime,
+    ) -> Result<(), String> {
+        let retention = *ResaltConfig::MINION_STATUS_RETENTION;
+        if retention == 0 {
+            return Ok(());
+        }
+        let cutoff = now.timestamp() - retention as i64;
+        let mut transitions = self.list_minion_status_transitions(minion_id, None)?;
+        transitions.pop();
+        for transition in transitions {
+            if transition.timestamp.timestamp() >= cutoff {
+                break;
+            }
+            self.delete_object(&format!("minion_status:{}:{}", minion_id, transition.id))?;
+        }
+        Ok(())
+    }
+
+    fn set_minion_status(
+        &self,
+        minion_id: &str,
+        status: MinionStatus,
+        since: ResaltTime,
+    ) -> Result<(), String> {
+        let key = format!("minion:{}:recordedStatus", minion_id);
+        self.s.set(&key, &status.to_string())?;
+        let key = format!("minion:{}:statusSince", minion_id);
+        self.s.set(&key, &since.to_string())
+    }
+
+    /// Compare the derived status of every minion against the status recorded on it,
+    /// and record a new transition for each minion whose status has changed.
+    pub fn refresh_minion_statuses(&self) -> Result<(), String> {
+        let minions = self.list_minions(Vec::new(), None, Paginate::None)?;
+        for minion in minions {
+            if minion.recorded_status == Some(minion.status) {
+                continue;
+            }
+            let timestamp = minion.status.entered_at(
+                minion.last_seen,
+                *ResaltConfig::MINION_STALE_THRESHOLD,
+                *ResaltConfig::MINION_OFFLINE_THRESHOLD,
+            );
+            let previous = match minion.recorded_status {
+                Some(previous) => previous,
+                // Minions whose status was only kept as transitions, before it was recorded
+                None => match self.list_minion_status_transitions(&minion.id, None)?.pop() {
+                    Some(last) if last.to == minion.status => {
+                        self.set_minion_status(&minion.id, last.to, last.timestamp)?;
+                        continue;
+                    }
+                    Some(last) => last.to,
+                    None => MinionStatus::default(),
+                },
+            };
+            if previous != minion.status {
+                debug!(
+                    "Minion {} changed status from {} to {}",
+                    minion.id, previous, minion.status
+                );
+                self.insert_minion_status_transition(
+                    &minion.id,
+                    previous,
+                    minion.status,
+                    timestamp,
+                )?;
+                self.prune_minion_status_transitions(&minion.id, ResaltTime::now())?;
+            }
+            self.set_minion_status(&minion.id, minion.status, timestamp)?;
+        }
+        Ok(())
+    }
+
     //
     // Minion Presets
     //
@@ -769,10 +1442,7 @@ impl Storage {
     }
 
     pub fn list_minion_presets(&self) -> Result<Vec<MinionPreset>, String> {
-        let keys = match self.keys_depth("minion_preset:*", 2) {
-            Ok(keys) => keys,
-            Err(e) => return Err(e),
-        };
+        let keys = self.keys_depth("minion_preset:*", 2)?;
 
         // Read minion presets
         let mut minion_presets: Vec<MinionPreset> = Vec::new();
@@ -818,6 +1488,8 @@ impl Storage {
             perms,
             last_login,
             email,
+            disabled: false,
+            expires_at: None,
         };
         self.save_object(&format!("user:{}", user.id), &user)?;
         Ok(user)
@@ -829,12 +1501,9 @@ impl Storage {
 
     pub fn get_user_by_username(&self, username: &str) -> Result<Option<User>, String> {
         // Look for user:<userId>:username
-        let keys = match self.s.keys(&format!("user:*:username")) {
-            Ok(keys) => keys,
-            Err(e) => return Err(e),
-        };
+        let keys = self.s.keys("user:*:username")?;
         for key in keys {
-            if self.s.get(&key).unwrap_or_else(|_| None) == Some(username.to_string()) {
+            if self.s.get(&key).unwrap_or(None) == Some(username.to_string()) {
                 let user_id_username: Vec<&str> = key.split(':').collect();
                 if user_id_username.len() != 3 {
                     continue;
@@ -846,17 +1515,33 @@ impl Storage {
     }
 
     pub fn set_user(&self, user: &User) -> Result<(), String> {
-        self.save_object(&format!("user:{}", user.id), user)
+        self.save_object(&format!("user:{}", user.id), user)?;
+        // Empty fields are not saved, so clearing the expiry must delete it
+        let key = format!("user:{}:expiresAt", user.id);
+        if user.expires_at.is_none() && self.s.get(&key)?.is_some() {
+            self.s.del(&key)?;
+        }
+        Ok(())
     }
 
     pub fn set_user_last_login(&self, user_id: &str, time: ResaltTime) -> Result<(), String> {
         let key = format!("user:{}:id", user_id);
-        self.s.set(&key, &user_id)?;
+        self.s.set(&key, user_id)?;
         let key = format!("user:{}:lastLogin", user_id);
         self.s.set(&key, &time.to_string())
     }
 
+    /// Delete a user, with their group memberships, temporary or not, and tokens.
     pub fn delete_user(&self, id: &str) -> Result<(), String> {
+        for group in self.list_permission_groups_by_user_id(id)? {
+            self.delete_permission_group_user(id, &group.id)?;
+        }
+        for grant in self.list_permission_group_grants()? {
+            if grant.user_id == id {
+                self.delete_permission_group_grant(&grant.id)?;
+            }
+        }
+        self.delete_authtokens_by_user_id(id)?;
         match self.delete_object(&format!("user:{}", id)) {
             Ok(_) => Ok(()),
             Err(e) => Err(e),
@@ -864,10 +1549,7 @@ impl Storage {
     }
 
     pub fn list_users(&self, paginate: Paginate) -> Result<Vec<User>, String> {
-        let keys = match self.keys_depth("user:*", 2) {
-            Ok(keys) => keys,
-            Err(e) => return Err(e),
-        };
+        let keys = self.keys_depth("user:*", 2)?;
 
         // Read users
         let mut users: Vec<User> = Vec::new();
@@ -918,25 +1600,349 @@ impl Storage {
         self.read_object(&format!("auth_token:{}", id))
     }
 
+    /// Save an auth token as is, e.g. when restoring a backup.
+    pub fn set_authtoken(&self, auth_token: &AuthToken) -> Result<(), String> {
+        self.save_object(&format!("auth_token:{}", auth_token.id), auth_token)
+    }
+
+    pub fn list_authtokens(&self) -> Result<Vec<AuthToken>, String> {
+        let keys = self.keys_depth("auth_token:*", 2)?;
+
+        // Read auth tokens
+        let mut auth_tokens: Vec<AuthToken> = Vec::new();
+        for key in keys {
+            let auth_token = match self.read_object(&key) {
+                Ok(Some(auth_token)) => auth_token,
+                Ok(None) => continue,
+                Err(e) => return Err(e),
+            };
+            auth_tokens.push(auth_token);
+        }
+
+        Ok(auth_tokens)
+    }
+
+    /// Revoke every token of a user, ending their sessions. Returns how many there were.
+    pub fn delete_authtokens_by_user_id(&self, user_id: &str) -> Result<usize, String> {
+        let auth_tokens: Vec<AuthToken> = self
+            .list_authtokens()?
+            .into_iter()
+            .filter(|auth_token| auth_token.user_id == user_id)
+            .collect();
+        for auth_token in &auth_tokens {
+            self.delete_object(&format!("auth_token:{}", auth_token.id))?;
+        }
+        Ok(auth_tokens.len())
+    }
+
     pub fn set_authtoken_salttoken(
         &self,
         auth_token: &str,
-        salt_token: Option<&SaltToken>,
+        salt_tokens: &[SaltToken],
     ) -> Result<(), String> {
         // Check if auth token exists
         let key = format!("auth_token:{}:id", auth_token);
-        if self.s.get(&key).unwrap_or_else(|_| None).is_none() {
+        if self.s.get(&key).unwrap_or(None).is_none() {
             return Err("Auth token does not exist".to_string());
         }
 
-        let salt_token_str = salt_token
-            .as_ref()
-            .map(|st| serde_json::to_string(st).unwrap());
+        let salt_token_str = serde_json::to_string(salt_tokens).map_err(|e| e.to_string())?;
 
-        // Update authtoken with salt_token
+        // Update authtoken with salt_tokens
         self.s.set(
             &format!("auth_token:{}:saltToken", auth_token),
-            &salt_token_str.unwrap_or_else(|| "".to_string()),
+            &salt_token_str,
         )
     }
 }
+
+/// A files backend in a new temporary directory, which is removed on drop.
+#[cfg(test)]
+pub(crate) struct TestStorage {
+    pub storage: Storage,
+    path: String,
+}
+
+#[cfg(test)]
+impl TestStorage {
+    pub fn new(name: &str) -> TestStorage {
+        let path = std::env::temp_dir()
+            .join(format!("resalt-{}-{}", name, uuid::Uuid::new_v4()))
+            .to_string_lossy()
+            .to_string();
+        let storage = Storage {
+            s: EncryptedStorage::new(
+                Box::new(StorageFiles::connect(&path).unwrap()),
+                Keyring::from_config().unwrap(),
+            ),
+        };
+        TestStorage { storage, path }
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestStorage {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> ResaltTime {
+        ResaltTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    /// A user with a temporary membership of a new group, expiring at `expires_at`.
+    fn grant(data: &Storage, expires_at: &str) -> PermissionGroupGrant {
+        let user = data
+            .create_user_hashed(
+                None,
+                "alice".to_string(),
+                None,
+                "[]".to_string(),
+                None,
+                None,
+            )
+            .unwrap();
+        let group_id = data
+            .create_permission_group(None, "ops", Some(r#"["test.*"]"#.to_string()))
+            .unwrap();
+        data.insert_permission_group_user(&user.id, &group_id)
+            .unwrap();
+        data.refresh_user_permissions(&user.id).unwrap();
+        data.insert_permission_group_grant(
+            &user.id,
+            &group_id,
+            "incident",
+            "admin",
+            time(expires_at),
+        )
+        .unwrap()
+    }
+
+    fn audit_actions(data: &Storage) -> Vec<String> {
+        data.list_audit_records(None)
+            .unwrap()
+            .into_iter()
+            .map(|record| record.action)
+            .collect()
+    }
+
+    #[test]
+    fn test_expire_permission_group_grants() {
+        let test = TestStorage::new("grant-expiry");
+        let data = &test.storage;
+        let grant = grant(data, "2024-01-01T12:00:00Z");
+
+        // Not expired yet
+        let expired = data
+            .expire_permission_group_grants(time("2024-01-01T11:59:59Z"))
+            .unwrap();
+        assert!(expired.is_empty());
+        assert!(data
+            .is_user_member_of_group(&grant.user_id, &grant.group_id)
+            .unwrap());
+
+        let expired = data
+            .expire_permission_group_grants(time("2024-01-01T12:00:01Z"))
+            .unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].id, grant.id);
+        assert!(!data
+            .is_user_member_of_group(&grant.user_id, &grant.group_id)
+            .unwrap());
+        assert!(data.list_permission_group_grants().unwrap().is_empty());
+        assert_eq!(audit_actions(data), vec!["grant.expired"]);
+        let user = data.get_user_by_id(&grant.user_id).unwrap().unwrap();
+        assert_eq!(user.perms, "[]");
+
+        // Revoked only once
+        let expired = data
+            .expire_permission_group_grants(time("2024-01-02T00:00:00Z"))
+            .unwrap();
+        assert!(expired.is_empty());
+        assert_eq!(audit_actions(data).len(), 1);
+    }
+
+    #[test]
+    fn test_expire_extended_grant() {
+        let test = TestStorage::new("grant-extend");
+        let data = &test.storage;
+        let mut grant = grant(data, "2024-01-01T12:00:00Z");
+        grant.expires_at = time("2024-01-01T18:00:00Z");
+        data.set_permission_group_grant(&grant).unwrap();
+
+        let expired = data
+            .expire_permission_group_grants(time("2024-01-01T13:00:00Z"))
+            .unwrap();
+        assert!(expired.is_empty());
+        assert!(data
+            .is_user_member_of_group(&grant.user_id, &grant.group_id)
+            .unwrap());
+
+        let expired = data
+            .expire_permission_group_grants(time("2024-01-01T18:00:01Z"))
+            .unwrap();
+        assert_eq!(expired.len(), 1);
+        assert!(!data
+            .is_user_member_of_group(&grant.user_id, &grant.group_id)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_expire_permanent_membership() {
+        let test = TestStorage::new("grant-permanent");
+        let data = &test.storage;
+        let grant = grant(data, "2024-01-01T12:00:00Z");
+        data.delete_permission_group_grant(&grant.id).unwrap();
+
+        let expired = data
+            .expire_permission_group_grants(time("2024-01-02T00:00:00Z"))
+            .unwrap();
+        assert!(expired.is_empty());
+        assert!(data
+            .is_user_member_of_group(&grant.user_id, &grant.group_id)
+            .unwrap());
+        assert!(audit_actions(data).is_empty());
+    }
+
+    #[test]
+    fn test_refresh_minion_statuses() {
+        let test = TestStorage::new("minion-status");
+        let data = &test.storage;
+        let transitions = |minion_id: &str| -> Vec<(MinionStatus, MinionStatus)> {
+            data.list_minion_status_transitions(minion_id, None)
+                .unwrap()
+                .into_iter()
+                .map(|t| (t.from, t.to))
+                .collect()
+        };
+
+        data.set_minion_last_seen("web01", time("2024-01-01T00:00:00Z"))
+            .unwrap();
+        data.refresh_minion_statuses().unwrap();
+        let minion = data.get_minion_by_id("web01").unwrap().unwrap();
+        assert_eq!(minion.recorded_status, Some(MinionStatus::Offline));
+        assert_eq!(minion.status_since, Some(time("2024-01-01T01:00:00Z")));
+        assert_eq!(
+            transitions("web01"),
+            vec![(MinionStatus::NeverSeen, MinionStatus::Offline)]
+        );
+
+        // Unchanged, so nothing is recorded
+        data.refresh_minion_statuses().unwrap();
+        assert_eq!(transitions("web01").len(), 1);
+
+        // The transition past the retention is pruned once it is not the latest
+        data.set_minion_last_seen("web01", ResaltTime::now())
+            .unwrap();
+        data.refresh_minion_statuses().unwrap();
+        let minion = data.get_minion_by_id("web01").unwrap().unwrap();
+        assert_eq!(minion.recorded_status, Some(MinionStatus::Online));
+        assert_eq!(
+            transitions("web01"),
+            vec![(MinionStatus::Offline, MinionStatus::Online)]
+        );
+
+        // Minions with transitions but no recorded status take it from the last transition
+        data.set_minion_last_seen("db01", time("2024-01-01T00:00:00Z"))
+            .unwrap();
+        data.insert_minion_status_transition(
+            "db01",
+            MinionStatus::Online,
+            MinionStatus::Offline,
+            time("2024-01-01T02:00:00Z"),
+        )
+        .unwrap();
+        data.refresh_minion_statuses().unwrap();
+        let minion = data.get_minion_by_id("db01").unwrap().unwrap();
+        assert_eq!(minion.recorded_status, Some(MinionStatus::Offline));
+        assert_eq!(minion.status_since, Some(time("2024-01-01T02:00:00Z")));
+        assert_eq!(transitions("db01").len(), 1);
+    }
+
+    #[test]
+    fn test_insert_minion_snapshot() {
+        let test = TestStorage::new("minion-snapshot");
+        let data = &test.storage;
+        let kind = MinionSnapshotKind::Grains;
+        let insert = |time: &str, grains: &str| {
+            data.insert_minion_snapshot("web01", kind, self::time(time), grains)
+                .unwrap()
+        };
+        let versions = || -> Vec<String> {
+            data.list_minion_snapshots("web01", kind)
+                .unwrap()
+                .into_iter()
+                .map(|snapshot| snapshot.data)
+                .collect()
+        };
+
+        insert("2024-01-01T00:00:00Z", r#"{"os":"Debian"}"#);
+        insert("2024-01-02T00:00:00Z", r#"{"os":"Debian"}"#);
+        insert("2024-01-03T00:00:00Z", r#"{"os":"Ubuntu"}"#);
+        assert_eq!(versions(), vec![r#"{"os":"Debian"}"#, r#"{"os":"Ubuntu"}"#]);
+        let latest = data.get_latest_minion_snapshot("web01", kind).unwrap();
+        assert_eq!(latest.unwrap().timestamp, time("2024-01-03T00:00:00Z"));
+
+        // Without the latest snapshot tracked, it is looked up from all snapshots
+        data.s
+            .del(&Storage::minion_snapshot_latest_key("web01", kind))
+            .unwrap();
+        insert("2024-01-04T00:00:00Z", r#"{"os":"Ubuntu"}"#);
+        insert("2024-01-05T00:00:00Z", r#"{"os":"Alpine"}"#);
+        assert_eq!(versions().len(), 3);
+
+        data.prune_minion_snapshots("web01", kind, 2).unwrap();
+        assert_eq!(versions(), vec![r#"{"os":"Ubuntu"}"#, r#"{"os":"Alpine"}"#]);
+        data.prune_minion_snapshots("web01", kind, 0).unwrap();
+        assert_eq!(versions().len(), 2);
+
+        data.delete_minion("web01").unwrap();
+        assert!(versions().is_empty());
+        assert!(data
+            .get_latest_minion_snapshot("web01", kind)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_list_users_by_permission_group_id() {
+        let test = TestStorage::new("group-users");
+        let data = &test.storage;
+        let user = |username: &str| {
+            data.create_user_hashed(
+                None,
+                username.to_string(),
+                None,
+                "[]".to_string(),
+                None,
+                None,
+            )
+            .unwrap()
+        };
+        let alice = user("alice");
+        let bob = user("bob");
+        let ops = data.create_permission_group(None, "ops", None).unwrap();
+        let dev = data.create_permission_group(None, "dev", None).unwrap();
+        data.insert_permission_group_user(&alice.id, &ops).unwrap();
+        data.insert_permission_group_user(&bob.id, &ops).unwrap();
+        data.insert_permission_group_user(&bob.id, &dev).unwrap();
+
+        let usernames = |group_id: &str| {
+            let mut usernames: Vec<String> = data
+                .list_users_by_permission_group_id(group_id)
+                .unwrap()
+                .into_iter()
+                .map(|user| user.username)
+                .collect();
+            usernames.sort();
+            usernames
+        };
+        assert_eq!(usernames(&ops), vec!["alice", "bob"]);
+        assert_eq!(usernames(&dev), vec!["bob"]);
+    }
+}
@@ -0,0 +1,455 @@
+use std::fmt;
+
+use log::*;
+use resalt_models::{
+    AuditRecord, Event, JobReturn, MinionSnapshot, MinionSnapshotKind, Paginate, StorageImpl,
+};
+
+use crate::Storage;
+
+/// Large object types are read and copied this many at a time, instead of all at once.
+#[cfg(not(test))]
+const MIGRATION_BATCH_SIZE: usize = 500;
+/// Small enough for tests to span several batches.
+#[cfg(test)]
+const MIGRATION_BATCH_SIZE: usize = 8;
+
+const SNAPSHOT_KINDS: [MinionSnapshotKind; 3] = [
+    MinionSnapshotKind::Grains,
+    MinionSnapshotKind::Pillars,
+    MinionSnapshotKind::Pkgs,
+];
+
+/// The object types copied by [`Storage::migrate_to`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MigrationKind {
+    Users,
+    Preferences,
+    PermissionGroups,
+    Memberships,
+    Grants,
+    AuditRecords,
+    AuthTokens,
+    Minions,
+    MinionSnapshots,
+    MinionStatusTransitions,
+    MinionPresets,
+    Jobs,
+    JobReturns,
+    Events,
+}
+
+impl MigrationKind {
+    /// In the order they are migrated.
+    pub const ALL: [MigrationKind; 14] = [
+        MigrationKind::Users,
+        MigrationKind::Preferences,
+        MigrationKind::PermissionGroups,
+        MigrationKind::Memberships,
+        MigrationKind::Grants,
+        MigrationKind::AuditRecords,
+        MigrationKind::AuthTokens,
+        MigrationKind::Minions,
+        MigrationKind::MinionSnapshots,
+        MigrationKind::MinionStatusTransitions,
+        MigrationKind::MinionPresets,
+        MigrationKind::Jobs,
+        MigrationKind::JobReturns,
+        MigrationKind::Events,
+    ];
+}
+
+impl fmt::Display for MigrationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MigrationKind::Users => "users",
+            MigrationKind::Preferences => "preferences",
+            MigrationKind::PermissionGroups => "permissionGroups",
+            MigrationKind::Memberships => "memberships",
+            MigrationKind::Grants => "grants",
+            MigrationKind::AuditRecords => "auditRecords",
+            MigrationKind::AuthTokens => "authTokens",
+            MigrationKind::Minions => "minions",
+            MigrationKind::MinionSnapshots => "minionSnapshots",
+            MigrationKind::MinionStatusTransitions => "minionStatusTransitions",
+            MigrationKind::MinionPresets => "minionPresets",
+            MigrationKind::Jobs => "jobs",
+            MigrationKind::JobReturns => "jobReturns",
+            MigrationKind::Events => "events",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MigrationCounts {
+    /// Objects in the source database.
+    pub source: usize,
+    pub created: usize,
+    pub updated: usize,
+    /// Already identical in the destination, e.g. copied by an interrupted earlier run.
+    pub skipped: usize,
+    /// Missing or different in the destination after being copied.
+    pub mismatched: usize,
+    /// Objects in the destination database after the migration.
+    pub destination: usize,
+}
+
+impl MigrationCounts {
+    /// Every object reads back the same from the destination. The destination may
+    /// hold more objects than the source, but never fewer.
+    pub fn verified(&self) -> bool {
+        self.mismatched == 0 && self.destination >= self.source
+    }
+}
+
+/// Create or update each object in the destination, unless it is identical already.
+fn copy_objects<T: PartialEq>(
+    counts: &mut MigrationCounts,
+    objects: &[T],
+    mut existing: impl FnMut(&T) -> Result<Option<T>, String>,
+    mut write: impl FnMut(&T) -> Result<(), String>,
+    dry_run: bool,
+) -> Result<(), String> {
+    for object in objects {
+        counts.source += 1;
+        // Objects half-written by an interrupted run may not parse, and are just rewritten
+        let current = existing(object).unwrap_or_else(|e| {
+            debug!("Failed reading object from destination: {}", e);
+            None
+        });
+        match current {
+            Some(existing) if existing == *object => {
+                counts.skipped += 1;
+                continue;
+            }
+            Some(_) => counts.updated += 1,
+            None => counts.created += 1,
+        }
+        if dry_run {
+            continue;
+        }
+        write(object)?;
+        // Read back what was written, rather than trusting the count of objects
+        match existing(object) {
+            Ok(Some(written)) if written == *object => {}
+            _ => counts.mismatched += 1,
+        }
+    }
+    Ok(())
+}
+
+/// Like [`copy_objects`], reading the objects of `keys` from the source in batches.
+fn copy_keys<T: PartialEq>(
+    counts: &mut MigrationCounts,
+    keys: &[String],
+    mut read: impl FnMut(&str) -> Result<Option<T>, String>,
+    mut existing: impl FnMut(&T) -> Result<Option<T>, String>,
+    mut write: impl FnMut(&T) -> Result<(), String>,
+    dry_run: bool,
+) -> Result<(), String> {
+    for batch in keys.chunks(MIGRATION_BATCH_SIZE) {
+        let mut objects = Vec::with_capacity(batch.len());
+        for key in batch {
+            if let Some(object) = read(key)? {
+                objects.push(object);
+            }
+        }
+        copy_objects(counts, &objects, &mut existing, &mut write, dry_run)?;
+    }
+    Ok(())
+}
+
+impl Storage {
+    /// Copy every object to `to` through the storage API, one object type at a time.
+    ///
+    /// Objects which are already identical in `to` are skipped, so an interrupted
+    /// migration is resumed by running it again. Nothing is written on a `dry_run`.
+    /// `progress` is called as each object type is done.
+    pub fn migrate_to(
+        &self,
+        to: &Storage,
+        dry_run: bool,
+        mut progress: impl FnMut(MigrationKind, &MigrationCounts),
+    ) -> Result<Vec<(MigrationKind, MigrationCounts)>, String> {
+        let mut result = Vec::new();
+        for kind in MigrationKind::ALL {
+            let mut counts = MigrationCounts::default();
+            self.migrate_kind(to, kind, &mut counts, dry_run)?;
+            counts.destination = to.count_objects(kind)?;
+            progress(kind, &counts);
+            result.push((kind, counts));
+        }
+        Ok(result)
+    }
+
+    fn migrate_kind(
+        &self,
+        to: &Storage,
+        kind: MigrationKind,
+        counts: &mut MigrationCounts,
+        dry_run: bool,
+    ) -> Result<(), String> {
+        match kind {
+            MigrationKind::Users => copy_objects(
+                counts,
+                &self.list_users(None)?,
+                |user| to.get_user_by_id(&user.id),
+                |user| to.set_user(user),
+                dry_run,
+            ),
+            MigrationKind::Preferences => {
+                for user in self.list_users(None)? {
+                    if let Some(preferences) = self.get_preferences(&user.id)? {
+                        copy_objects(
+                            counts,
+                            &[preferences],
+                            |_| to.get_preferences(&user.id),
+                            |preferences| to.set_preferences(&user.id, preferences),
+                            dry_run,
+                        )?;
+                    }
+                }
+                Ok(())
+            }
+            MigrationKind::PermissionGroups => copy_objects(
+                counts,
+                &self.list_permission_groups(None)?,
+                |group| to.get_permission_group_by_id(&group.id),
+                |group| to.update_permission_group(group),
+                dry_run,
+            ),
+            MigrationKind::Memberships => copy_objects(
+                counts,
+                &self.list_permission_group_users()?,
+                |(user_id, group_id)| {
+                    Ok(to
+                        .is_user_member_of_group(user_id, group_id)?
+                        .then(|| (user_id.clone(), group_id.clone())))
+                },
+                |(user_id, group_id)| to.insert_permission_group_user(user_id, group_id),
+                dry_run,
+            ),
+            MigrationKind::Grants => copy_objects(
+                counts,
+                &self.list_permission_group_grants()?,
+                |grant| to.read_object(&format!("permission_group_grant:{}", grant.id)),
+                |grant| to.set_permission_group_grant(grant),
+                dry_run,
+            ),
+            MigrationKind::AuditRecords => copy_keys(
+                counts,
+                &self.keys_depth("audit:*", 2)?,
+                |key| self.read_object(key),
+                |record: &AuditRecord| to.read_object(&format!("audit:{}", record.id)),
+                |record| to.set_audit_record(record),
+                dry_run,
+            ),
+            MigrationKind::AuthTokens => copy_objects(
+                counts,
+                &self.list_authtokens()?,
+                |auth_token| to.get_authtoken_by_id(&auth_token.id),
+                |auth_token| to.set_authtoken(auth_token),
+                dry_run,
+            ),
+            MigrationKind::Minions => copy_objects(
+                counts,
+                &self.list_minions(Vec::new(), None, Paginate::None)?,
+                |minion| to.get_minion_by_id(&minion.id),
+                |minion| {
+                    // The package index is derived from the packages of each minion
+                    let previous = to.s.get(&format!("minion:{}:pkgs", minion.id))?;
+                    to.update_package_index(
+                        &minion.id,
+                        previous.as_deref(),
+                        minion.pkgs.as_deref(),
+                    )?;
+                    to.set_minion(minion.clone())
+                },
+                dry_run,
+            ),
+            MigrationKind::MinionSnapshots => {
+                for minion in self.list_minions(Vec::new(), None, Paginate::None)? {
+                    for snapshot_kind in SNAPSHOT_KINDS {
+                        copy_keys(
+                            counts,
+                            &self.minion_snapshot_keys(&minion.id, snapshot_kind)?,
+                            |key| self.read_object(key),
+                            |snapshot: &MinionSnapshot| {
+                                to.read_object(&Storage::minion_snapshot_key(snapshot))
+                            },
+                            |snapshot| to.set_minion_snapshot(snapshot),
+                            dry_run,
+                        )?;
+                    }
+                }
+                Ok(())
+            }
+            MigrationKind::MinionStatusTransitions => {
+                for minion in self.list_minions(Vec::new(), None, Paginate::None)? {
+                    copy_objects(
+                        counts,
+                        &self.list_minion_status_transitions(&minion.id, None)?,
+                        |transition| {
+                            to.read_object(&format!(
+                                "minion_status:{}:{}",
+                                transition.minion_id, transition.id
+                            ))
+                        },
+                        |transition| to.set_minion_status_transition(transition),
+                        dry_run,
+                    )?;
+                }
+                Ok(())
+            }
+            MigrationKind::MinionPresets => copy_objects(
+                counts,
+                &self.list_minion_presets()?,
+                |preset| to.get_minion_preset_by_id(&preset.id),
+                |preset| to.update_minion_preset(preset),
+                dry_run,
+            ),
+            MigrationKind::Jobs => copy_keys(
+                counts,
+                &self.keys_depth("job:*", 2)?,
+                |key| self.read_job(key.trim_start_matches("job:")),
+                |job| to.get_job_by_jid(&job.jid),
+                |job| to.set_job(job),
+                dry_run,
+            ),
+            MigrationKind::JobReturns => copy_keys(
+                counts,
+                &self.keys_depth("job_return:*", 2)?,
+                |key| self.read_object(key),
+                |job_return: &JobReturn| to.get_job_return(&job_return.id),
+                |job_return| to.set_job_return(job_return),
+                dry_run,
+            ),
+            MigrationKind::Events => copy_keys(
+                counts,
+                &self.keys_depth("event:*", 2)?,
+                |key| self.read_object(key),
+                |event: &Event| to.get_event(&event.id),
+                |event| to.set_event(event),
+                dry_run,
+            ),
+        }
+    }
+
+    /// Count the objects of a type, the same way [`Storage::migrate_to`] lists them.
+    pub fn count_objects(&self, kind: MigrationKind) -> Result<usize, String> {
+        let count = match kind {
+            MigrationKind::Users => self.list_users(None)?.len(),
+            MigrationKind::Preferences => {
+                let mut count = 0;
+                for user in self.list_users(None)? {
+                    if self.get_preferences(&user.id)?.is_some() {
+                        count += 1;
+                    }
+                }
+                count
+            }
+            MigrationKind::PermissionGroups => self.list_permission_groups(None)?.len(),
+            MigrationKind::Memberships => self.list_permission_group_users()?.len(),
+            MigrationKind::Grants => self.list_permission_group_grants()?.len(),
+            MigrationKind::AuditRecords => self.keys_depth("audit:*", 2)?.len(),
+            MigrationKind::AuthTokens => self.list_authtokens()?.len(),
+            MigrationKind::Minions => self.list_minions(Vec::new(), None, Paginate::None)?.len(),
+            MigrationKind::MinionSnapshots => {
+                let mut count = 0;
+                for minion in self.list_minions(Vec::new(), None, Paginate::None)? {
+                    for snapshot_kind in SNAPSHOT_KINDS {
+                        count += self.minion_snapshot_keys(&minion.id, snapshot_kind)?.len();
+                    }
+                }
+                count
+            }
+            MigrationKind::MinionStatusTransitions => {
+                let mut count = 0;
+                for minion in self.list_minions(Vec::new(), None, Paginate::None)? {
+                    count += self.list_minion_status_transitions(&minion.id, None)?.len();
+                }
+                count
+            }
+            MigrationKind::MinionPresets => self.list_minion_presets()?.len(),
+            MigrationKind::Jobs => self.keys_depth("job:*", 2)?.len(),
+            MigrationKind::JobReturns => self.keys_depth("job_return:*", 2)?.len(),
+            MigrationKind::Events => self.keys_depth("event:*", 2)?.len(),
+        };
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestStorage;
+    use resalt_models::ResaltTime;
+
+    fn counts(result: &[(MigrationKind, MigrationCounts)], kind: MigrationKind) -> MigrationCounts {
+        result.iter().find(|(k, _)| *k == kind).unwrap().1
+    }
+
+    #[test]
+    fn test_migrate_and_resume() {
+        let source = TestStorage::new("migrate-source");
+        let destination = TestStorage::new("migrate-destination");
+        let (from, to) = (&source.storage, &destination.storage);
+
+        from.create_user_hashed(
+            None,
+            "alice".to_string(),
+            None,
+            "[]".to_string(),
+            None,
+            None,
+        )
+        .unwrap();
+        // More events than fit in one batch
+        let events = MIGRATION_BATCH_SIZE * 2 + 4;
+        for i in 0..events {
+            from.insert_event(
+                format!("salt/test/{}", i),
+                "{}".to_string(),
+                ResaltTime::now(),
+                "default",
+            )
+            .unwrap();
+        }
+
+        // A dry run writes nothing
+        let result = from.migrate_to(to, true, |_, _| {}).unwrap();
+        assert_eq!(counts(&result, MigrationKind::Events).created, events);
+        assert_eq!(to.count_objects(MigrationKind::Events).unwrap(), 0);
+
+        let result = from.migrate_to(to, false, |_, _| {}).unwrap();
+        let event_counts = counts(&result, MigrationKind::Events);
+        assert_eq!(event_counts.source, events);
+        assert_eq!(event_counts.created, events);
+        assert_eq!(event_counts.destination, events);
+        assert!(result.iter().all(|(_, counts)| counts.verified()));
+
+        // Interrupt: lose some events, and leave one half-written
+        let mut copied = to.list_events(None).unwrap();
+        for event in copied.drain(..3) {
+            to.delete_object(&format!("event:{}", event.id)).unwrap();
+        }
+        to.s.set(&format!("event:{}:tag", copied[0].id), "salt/half")
+            .unwrap();
+        assert_eq!(to.count_objects(MigrationKind::Events).unwrap(), events - 3);
+
+        // Resume
+        let result = from.migrate_to(to, false, |_, _| {}).unwrap();
+        let event_counts = counts(&result, MigrationKind::Events);
+        assert_eq!(event_counts.created, 3);
+        assert_eq!(event_counts.updated, 1);
+        assert_eq!(event_counts.skipped, events - 4);
+        assert_eq!(event_counts.destination, events);
+        assert_eq!(counts(&result, MigrationKind::Users).skipped, 1);
+        assert!(result.iter().all(|(_, counts)| counts.verified()));
+        assert_eq!(
+            to.list_events(None).unwrap(),
+            from.list_events(None).unwrap()
+        );
+    }
+}